@@ -0,0 +1,72 @@
+//! Throughput of each compiler phase on small, medium and large programs.
+//!
+//! Run with `cargo bench`; pass a name to run only matching cases, e.g.
+//! `cargo bench -- large`. The harness is std-only, so it works offline:
+//! each case runs for about a second and reports the median time per
+//! iteration and the source throughput.
+
+use harbor::codegen::{Backend, NodeBackend};
+use harbor::lexer::Lexer;
+use harbor::parser::Parser;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const SMALL: &str = include_str!("programs/small.hb");
+const MEDIUM: &str = include_str!("programs/medium.hb");
+
+/// Measure for roughly this long per case.
+const TARGET: Duration = Duration::from_secs(1);
+
+/// Many copies of the small program's definitions, renamed apart, followed
+/// by the medium server: a few thousand lines.
+fn large_program() -> String {
+    let mut src = String::new();
+    for i in 0..200 {
+        src.push_str(&SMALL.replace("fizzbuzz", &format!("fizzbuzz_{}", i)));
+        src.push('\n');
+    }
+    src.push_str(MEDIUM);
+    src
+}
+
+fn bench(filter: &Option<String>, name: &str, bytes: usize, mut f: impl FnMut()) {
+    if filter.as_ref().is_some_and(|f| !name.contains(f.as_str())) {
+        return;
+    }
+    f(); // warm up
+    let mut samples = Vec::new();
+    let start = Instant::now();
+    while start.elapsed() < TARGET || samples.len() < 5 {
+        let t = Instant::now();
+        f();
+        samples.push(t.elapsed());
+    }
+    samples.sort();
+    let median = samples[samples.len() / 2];
+    let mb_per_s = bytes as f64 / median.as_secs_f64() / 1_000_000.0;
+    println!("{:<20} {:>12.3?} {:>10.1} MB/s  ({} iterations)", name, median, mb_per_s, samples.len());
+}
+
+fn main() {
+    // `cargo bench` passes `--bench`; anything else is a name filter
+    let filter = std::env::args().skip(1).find(|a| !a.starts_with("--"));
+    let large = large_program();
+
+    for (size, src) in [("small", SMALL), ("medium", MEDIUM), ("large", large.as_str())] {
+        let tokens = Lexer::new(src).tokenize().expect("benchmark program lexes");
+        let ast = Parser::new(tokens.clone()).parse().expect("benchmark program parses");
+
+        bench(&filter, &format!("lex/{}", size), src.len(), || {
+            black_box(Lexer::new(black_box(src)).tokenize().unwrap());
+        });
+        bench(&filter, &format!("parse/{}", size), src.len(), || {
+            black_box(Parser::new(black_box(tokens.clone())).parse().unwrap());
+        });
+        bench(&filter, &format!("codegen/{}", size), src.len(), || {
+            black_box(NodeBackend.generate(black_box(&ast)));
+        });
+        bench(&filter, &format!("compile/{}", size), src.len(), || {
+            black_box(harbor::compile(black_box(src)));
+        });
+    }
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// The whole pipeline, so codegen sees every tree the parser accepts.
+fuzz_target!(|src: &str| {
+    let _ = harbor::compile(src);
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use harbor::lexer::Lexer;
+use libfuzzer_sys::fuzz_target;
+
+// Arbitrary bytes, decoded lossily so invalid UTF-8 still reaches the lexer
+// as replacement characters. Errors are fine; panics are bugs.
+fuzz_target!(|data: &[u8]| {
+    let src = String::from_utf8_lossy(data);
+    let _ = Lexer::new(&src).tokenize();
+});
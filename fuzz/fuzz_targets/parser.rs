@@ -0,0 +1,13 @@
+#![no_main]
+
+use harbor::lexer::Lexer;
+use harbor::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+// Whatever the lexer accepts must parse or fail with a diagnostic, with and
+// without comment trivia kept.
+fuzz_target!(|src: &str| {
+    let Ok(tokens) = Lexer::new(src).tokenize() else { return };
+    let _ = Parser::with_comments(tokens.clone()).parse();
+    let _ = Parser::new(tokens).parse();
+});
@@ -0,0 +1,231 @@
+//! Non-Harbor files imported as values: `import "config.json" as config`
+//! gives the parsed JSON, any other file its text. Both are read at build
+//! time and compiled into the program as literals, so nothing is read from
+//! disk when it runs.
+
+use crate::ast::Expr;
+use std::path::Path;
+
+/// JSON nested deeper than this is rejected, like deeply nested code.
+const MAX_DEPTH: usize = 100;
+
+/// The value of the asset at `path`.
+pub fn load(path: &Path) -> Result<Expr, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read '{}': {}", path.display(), e))?;
+    if path.extension().is_some_and(|ext| ext == "json") {
+        parse_json(&text).map_err(|e| format!("Invalid JSON at {}:{}", path.display(), e))
+    } else {
+        Ok(Expr::String(escape(&text)))
+    }
+}
+
+/// `text` as the contents of a Harbor string literal, which keep their
+/// escapes for JavaScript.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Parse JSON text into the literal expression it denotes. Errors start
+/// with the `line:col` they were found at.
+pub fn parse_json(text: &str) -> Result<Expr, String> {
+    let mut parser = JsonParser { chars: text.char_indices().peekable(), text, depth: 0 };
+    let value = parser.value()?;
+    parser.skip_whitespace();
+    match parser.chars.peek() {
+        None => Ok(value),
+        Some(&(i, _)) => Err(parser.error_at(i, "unexpected text after the value")),
+    }
+}
+
+struct JsonParser<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    text: &'a str,
+    depth: usize,
+}
+
+impl JsonParser<'_> {
+    fn error_at(&self, offset: usize, msg: &str) -> String {
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count() + 1;
+        let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+        format!("{}:{}: {}", line, col, msg)
+    }
+
+    fn error(&mut self, msg: &str) -> String {
+        let offset = self.chars.peek().map_or(self.text.len(), |&(i, _)| i);
+        self.error_at(offset, msg)
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.peek().is_some_and(|&(_, c)| matches!(c, ' ' | '\t' | '\n' | '\r')) {
+            self.chars.next();
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some(&(_, c)) if c == expected => {
+                self.chars.next();
+                Ok(())
+            }
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn value(&mut self) -> Result<Expr, String> {
+        self.skip_whitespace();
+        let Some(&(_, c)) = self.chars.peek() else { return Err(self.error("expected a value")) };
+        match c {
+            '{' | '[' => {
+                self.depth += 1;
+                if self.depth > MAX_DEPTH {
+                    return Err(self.error(&format!("nested too deeply (more than {} levels)", MAX_DEPTH)));
+                }
+                let value = if c == '{' { self.object() } else { self.array() };
+                self.depth -= 1;
+                value
+            }
+            '"' => Ok(Expr::String(escape(&self.string()?))),
+            't' => self.keyword("true", Expr::Bool(true)),
+            'f' => self.keyword("false", Expr::Bool(false)),
+            'n' => self.keyword("null", Expr::None),
+            '-' | '0'..='9' => self.number(),
+            _ => Err(self.error("expected a value")),
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Expr) -> Result<Expr, String> {
+        for expected in word.chars() {
+            match self.chars.peek() {
+                Some(&(_, c)) if c == expected => {
+                    self.chars.next();
+                }
+                _ => return Err(self.error("expected a value")),
+            }
+        }
+        Ok(value)
+    }
+
+    fn number(&mut self) -> Result<Expr, String> {
+        let start = self.chars.peek().map_or(self.text.len(), |&(i, _)| i);
+        let mut end = start;
+        while let Some(&(i, c)) = self.chars.peek() {
+            if !matches!(c, '-' | '+' | '.' | 'e' | 'E' | '0'..='9') {
+                break;
+            }
+            end = i + c.len_utf8();
+            self.chars.next();
+        }
+        self.text[start..end].parse::<f64>()
+            .ok()
+            .filter(|n| n.is_finite())
+            .map(|n| Expr::Number(n, None))
+            .ok_or_else(|| self.error_at(start, "invalid number"))
+    }
+
+    /// A string literal's value, with escapes decoded.
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut out = String::new();
+        loop {
+            let Some((_, c)) = self.chars.next() else { return Err(self.error("unterminated string")) };
+            match c {
+                '"' => return Ok(out),
+                '\\' => {
+                    let Some((_, escaped)) = self.chars.next() else { return Err(self.error("unterminated string")) };
+                    match escaped {
+                        '"' | '\\' | '/' => out.push(escaped),
+                        'b' => out.push('\u{8}'),
+                        'f' => out.push('\u{c}'),
+                        'n' => out.push('\n'),
+                        'r' => out.push('\r'),
+                        't' => out.push('\t'),
+                        'u' => {
+                            let high = self.hex4()?;
+                            let code = if (0xD800..0xDC00).contains(&high) {
+                                // A surrogate pair spells one character
+                                self.expect('\\')?;
+                                self.expect('u')?;
+                                let low = self.hex4()?;
+                                0x10000 + ((high - 0xD800) << 10) + (low.wrapping_sub(0xDC00) & 0x3FF)
+                            } else {
+                                high
+                            };
+                            out.push(char::from_u32(code).unwrap_or('\u{FFFD}'));
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    }
+                }
+                c if (c as u32) < 0x20 => return Err(self.error("control character in string")),
+                c => out.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self.chars.next().and_then(|(_, c)| c.to_digit(16));
+            code = code * 16 + digit.ok_or_else(|| self.error("invalid \\u escape"))?;
+        }
+        Ok(code)
+    }
+
+    fn object(&mut self) -> Result<Expr, String> {
+        self.expect('{')?;
+        let mut fields = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek().is_some_and(|&(_, c)| c == '}') {
+            self.chars.next();
+            return Ok(Expr::Object(fields));
+        }
+        loop {
+            self.skip_whitespace();
+            let key = escape(&self.string()?);
+            self.expect(':')?;
+            let value = self.value()?;
+            // A repeated key keeps its last value, as in JSON.parse
+            fields.retain(|(k, _): &(String, Expr)| *k != key);
+            fields.push((key, value));
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, '}')) => return Ok(Expr::Object(fields)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Expr, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.chars.peek().is_some_and(|&(_, c)| c == ']') {
+            self.chars.next();
+            return Ok(Expr::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.chars.next() {
+                Some((_, ',')) => continue,
+                Some((_, ']')) => return Ok(Expr::Array(items)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::lexer::Span;
+
 
 #[derive(Debug, Clone)]
 pub enum FStringExprPart {
@@ -19,6 +21,11 @@ pub enum Stmt {
     Expression(Expr),
     Print(Vec<Expr>),
     Pass,
+    /// Source comment; only produced by `Parser::with_comments`.
+    Comment(String),
+    /// The source line of the statement after it; only produced by
+    /// `Parser::with_lines`.
+    Line(usize),
 
     If {
         condition: Expr,
@@ -42,6 +49,8 @@ pub enum Stmt {
         name: String,
         args: Vec<String>,
         body: Vec<Stmt>,
+        /// Marked `@sync`: a plain function that callers don't wait for.
+        sync: bool,
     },
     Return(Option<Expr>),
 
@@ -52,55 +61,488 @@ pub enum Stmt {
 
     Try {
         body: Vec<Stmt>,
-        except_var: Option<String>,
-        except_body: Vec<Stmt>,
+        handlers: Vec<ExceptClause>,
+    },
+    /// `raise <error>`, e.g. `raise ValueError("bad input")`.
+    Raise(Expr),
+    /// `assert <condition>[, <message>]`: raise AssertionError if false.
+    Assert {
+        condition: Expr,
+        message: Option<Expr>,
     },
 
     Import {
         path: String,
         alias: Option<String>,
+        /// The `require` path, once `resolver::resolve_imports` has found
+        /// the module.
+        resolved: Option<String>,
+        span: Span,
     },
+    /// `from "x" import a, b as c`.
     FromImport {
         path: String,
-        names: Vec<String>,
+        names: Vec<NameAs>,
+        resolved: Option<String>,
+        span: Span,
     },
+    /// `export def`/`class`/assignment, `export import "x" as name`, or
+    /// `export from "x" import a, b` to re-export another module's names.
     Export(Box<Stmt>),
+    /// `export total, count as item_count`: export names the module has
+    /// already defined or imported, optionally under other names.
+    ExportNames(Vec<NameAs>),
+    /// `export default <expr>`: the module's `default` export.
+    ExportDefault(Expr),
 
     // Harbor-specific
-    Server {
-        port: Expr,
-        routes: Vec<Route>,
-    },
+    Server(Box<Server>),
+    /// `respond [status] [html|text|json] <value>`. Without a content type,
+    /// dicts and lists go out as JSON and anything else as text.
     Respond {
-        status: Option<u16>,
+        status: Option<Status>,
+        content_type: Option<ContentType>,
         value: Expr,
     },
+    /// `respond [status] file <path>`: stream a file from disk.
+    RespondFile {
+        status: Option<Status>,
+        path: Expr,
+    },
     Fetch {
         url: Expr,
+        options: Vec<(String, Expr)>,
+        body: Vec<Stmt>,
+    },
+    /// `stream:` — answer with server-sent events from the block.
+    Stream {
+        body: Vec<Stmt>,
+    },
+    /// `emit [event,] data` inside a `stream:` block.
+    Emit {
+        event: Option<Expr>,
+        data: Expr,
+    },
+    /// `spawn:` — run the block in the background.
+    Spawn {
         body: Vec<Stmt>,
     },
+    /// `every <n> "<unit>":` — run the block on a fixed interval.
+    Every {
+        count: Expr,
+        unit_ms: u64,
+        body: Vec<Stmt>,
+    },
+    /// `cron "<schedule>":` — run the block on a five-field cron schedule.
+    Cron {
+        schedule: String,
+        body: Vec<Stmt>,
+    },
+}
+
+impl Stmt {
+    /// The statement blocks nested directly inside this statement, not
+    /// counting server routes and hooks.
+    pub fn bodies_mut(&mut self) -> Vec<&mut Vec<Stmt>> {
+        match self {
+            Stmt::If { then_body, elif_branches, else_body, .. } => {
+                let mut bodies = vec![then_body];
+                bodies.extend(elif_branches.iter_mut().map(|(_, body)| body));
+                bodies.extend(else_body.as_mut());
+                bodies
+            }
+            Stmt::Try { body, handlers } => {
+                let mut bodies = vec![body];
+                bodies.extend(handlers.iter_mut().map(|h| &mut h.body));
+                bodies
+            }
+            Stmt::ForIn { body, .. } | Stmt::While { body, .. } | Stmt::Func { body, .. }
+            | Stmt::Fetch { body, .. } | Stmt::Stream { body } | Stmt::Spawn { body }
+            | Stmt::Every { body, .. } | Stmt::Cron { body, .. } => vec![body],
+            Stmt::Class { methods, .. } => vec![methods],
+            Stmt::Export(inner) => inner.bodies_mut(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// A name in an import or export list, with the name it goes by after an
+/// `as`: `parse_date as pd`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NameAs {
+    pub name: String,
+    pub alias: Option<String>,
+}
+
+impl NameAs {
+    pub fn new(name: &str) -> NameAs {
+        NameAs { name: name.to_string(), alias: None }
+    }
+
+    /// The alias if there is one, otherwise the name itself.
+    pub fn renamed(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// One `except` clause: `except e:`, `except KeyError:`, or
+/// `except KeyError, IndexError as e:`. A clause without types catches
+/// everything and must come last.
+#[derive(Debug, Clone)]
+pub struct ExceptClause {
+    pub types: Vec<String>,
+    pub var: Option<String>,
+    pub body: Vec<Stmt>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Expr {
     String(String),
     FString(Vec<FStringExprPart>),
-    Number(f64),
+    /// `html f"..."`: markup whose interpolated values are HTML-escaped.
+    Html(Vec<FStringExprPart>),
+    /// A number and, when it came from a literal, the literal as written.
+    Number(f64, Option<String>),
+    /// An integer literal past 2^53, kept exact as a BigInt.
+    BigInt(String),
     Bool(bool),
     None,
     Ident(String),
     Member(Box<Expr>, String),
     Object(Vec<(String, Expr)>),
+    /// A dict literal with a key that isn't a string (`{1: "one"}`), which
+    /// makes a Map-backed `dict`.
+    Dict(Vec<(Expr, Expr)>),
     Array(Vec<Expr>),
     Binary(Box<Expr>, String, Box<Expr>),
     Unary(String, Box<Expr>),
     Index(Box<Expr>, Box<Expr>),
     Call(Box<Expr>, Vec<Expr>),
+    /// `name=value` in a call's arguments.
+    Keyword(String, Box<Expr>),
+    /// `spawn f(x)`: start a call without waiting; evaluates to its task.
+    Spawn(Box<Expr>),
+    /// `await task`: wait for a stored task (or any promise) to finish.
+    Await(Box<Expr>),
+    /// `fetch(url, timeout=5)` / `fetch url` used as a value: the response.
+    Fetch {
+        url: Box<Expr>,
+        options: Vec<(String, Expr)>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Server {
+    pub port: Expr,
+    /// Where the port is written (the `server` keyword when it's left out).
+    pub port_span: Span,
+    pub tls: Option<Tls>,
+    /// `log requests ["format"]`; the inner value is the custom format.
+    pub log: Option<Option<String>>,
+    pub limit: Option<RateLimit>,
+    pub auth: Option<Auth>,
+    /// `health "<path>"`: a liveness endpoint answered ahead of hooks.
+    pub health: Option<String>,
+    /// `metrics "<path>"`: Prometheus metrics for this server.
+    pub metrics: Option<String>,
+    /// `headers:` block of `"Name": value` lines, set on every response.
+    pub headers: Vec<(String, Expr)>,
+    pub routes: Vec<RouteItem>,
+    /// `before:` hooks, run ahead of every request; may `respond` early.
+    pub before: Vec<Stmt>,
+    /// `after:` hooks, run once the request has been handled.
+    pub after: Vec<Stmt>,
+    /// `on_shutdown:` hooks, run after in-flight requests drain on SIGINT/SIGTERM.
+    pub on_shutdown: Vec<Stmt>,
+    /// `on_error <name>:`: the error variable and the block run when a
+    /// handler throws.
+    pub on_error: Option<(String, Vec<Stmt>)>,
+    /// `not_found:` block, run when no route matched.
+    pub not_found: Vec<Stmt>,
+}
+
+/// `tls cert <path> key <path>` on a server line.
+#[derive(Debug, Clone)]
+pub struct Tls {
+    pub cert: Expr,
+    pub key: Expr,
+    pub span: Span,
+}
+
+/// `limit <count> per "<unit>"`: at most `count` requests per client IP
+/// in each window.
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub count: Expr,
+    pub window_ms: u64,
+}
+
+/// `require_auth basic <users>` or `require_auth bearer <secret>`.
+///
+/// Basic auth checks against a dict of username to password; bearer tokens
+/// are HS256 JWTs signed with the secret. Either way the request continues
+/// with `req.user` set, or gets a 401.
+#[derive(Debug, Clone)]
+pub struct Auth {
+    pub scheme: AuthScheme,
+    pub secret: Expr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AuthScheme {
+    Basic,
+    Bearer,
+}
+
+/// The status in `respond <status> <value>`: a code (`201`), its name
+/// (`created`), or `status <expr>` for one worked out at runtime.
+#[derive(Debug, Clone)]
+pub enum Status {
+    Code(u16),
+    Named(String),
+    Expr(Expr),
+}
+
+/// The names `respond` accepts in place of a status code.
+pub const STATUS_NAMES: &[(&str, u16)] = &[
+    ("ok", 200),
+    ("created", 201),
+    ("accepted", 202),
+    ("no_content", 204),
+    ("moved_permanently", 301),
+    ("found", 302),
+    ("see_other", 303),
+    ("not_modified", 304),
+    ("temporary_redirect", 307),
+    ("permanent_redirect", 308),
+    ("bad_request", 400),
+    ("unauthorized", 401),
+    ("forbidden", 403),
+    ("not_found", 404),
+    ("method_not_allowed", 405),
+    ("conflict", 409),
+    ("gone", 410),
+    ("unprocessable", 422),
+    ("too_many_requests", 429),
+    ("server_error", 500),
+    ("not_implemented", 501),
+    ("bad_gateway", 502),
+    ("unavailable", 503),
+];
+
+impl Status {
+    /// The code a status name stands for: `not_found` is 404.
+    pub fn code_for(name: &str) -> Option<u16> {
+        STATUS_NAMES.iter().find(|(n, _)| *n == name).map(|(_, code)| *code)
+    }
+}
+
+/// The content type named in `respond html page` and the like.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContentType {
+    Html,
+    Text,
+    Json,
+}
+
+impl ContentType {
+    pub fn from_name(name: &str) -> Option<ContentType> {
+        match name {
+            "html" => Some(ContentType::Html),
+            "text" => Some(ContentType::Text),
+            "json" => Some(ContentType::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ContentType::Html => "html",
+            ContentType::Text => "text",
+            ContentType::Json => "json",
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct Route {
     pub method: String,
     pub path: String,
+    /// Where the path is written.
+    pub span: Span,
     pub body: Vec<Stmt>,
+    pub before: Vec<Stmt>,
+    pub after: Vec<Stmt>,
+    pub limit: Option<RateLimit>,
+    pub auth: Option<Auth>,
+    /// `cache <seconds>` on GET routes.
+    pub cache: Option<Expr>,
+    /// `expect <schema>`: the shape the request body must have.
+    pub expect: Option<Schema>,
+    /// `headers:` for this route's responses, set after the server's.
+    pub headers: Vec<(String, Expr)>,
+    /// `-> name`: a function that handles the request instead of `body`. It
+    /// is called with `req`, and what it returns is the response.
+    pub handler: Option<Expr>,
+}
+
+/// A body shape for `expect`, written as a dict literal of type names,
+/// e.g. `{"name": str, "tags": [str], "address": {"city": str}}`.
+#[derive(Debug, Clone)]
+pub enum Schema {
+    /// `str`, `int`, `float`, `bool`, `list`, `dict` or `any`.
+    Type(String),
+    List(Box<Schema>),
+    Dict(Vec<(String, Schema)>),
+}
+
+impl Schema {
+    pub const TYPES: [&'static str; 7] = ["str", "int", "float", "bool", "list", "dict", "any"];
+
+    pub fn from_expr(expr: &Expr) -> Result<Schema, String> {
+        match expr {
+            Expr::Ident(name) if Self::TYPES.contains(&name.as_str()) => Ok(Schema::Type(name.clone())),
+            Expr::Ident(name) => Err(format!("Unknown type '{}' in expect (use {})", name, Self::TYPES.join(", "))),
+            Expr::Array(items) if items.len() == 1 => Ok(Schema::List(Box::new(Schema::from_expr(&items[0])?))),
+            Expr::Object(fields) => fields.iter()
+                .map(|(key, value)| Ok((key.clone(), Schema::from_expr(value)?)))
+                .collect::<Result<_, String>>()
+                .map(Schema::Dict),
+            _ => Err("Expected a type name, [type] or dict of types in expect".to_string()),
+        }
+    }
+}
+
+/// An entry in a server block: a route, or a `group "/prefix":` of entries.
+#[derive(Debug, Clone)]
+pub enum RouteItem {
+    Route(Box<Route>),
+    Group {
+        prefix: String,
+        items: Vec<RouteItem>,
+    },
+    /// Source comment; only produced by `Parser::with_comments`.
+    Comment(String),
+}
+
+impl Server {
+    /// All routes in definition order, with group prefixes applied.
+    pub fn flat_routes(&self) -> Vec<Route> {
+        let mut routes = Vec::new();
+        flatten_routes(&self.routes, "", &mut routes);
+        routes
+    }
+}
+
+fn flatten_routes(items: &[RouteItem], prefix: &str, out: &mut Vec<Route>) {
+    for item in items {
+        match item {
+            RouteItem::Route(route) => {
+                let mut route = (**route).clone();
+                route.path = join_route_path(prefix, &route.path);
+                out.push(route);
+            }
+            RouteItem::Group { prefix: group, items } => {
+                flatten_routes(items, &join_route_path(prefix, group), out);
+            }
+            RouteItem::Comment(_) => {}
+        }
+    }
+}
+
+/// Join a group prefix and a route path: `"/api" + "/users"` is
+/// `"/api/users"`, and `"/api" + "/"` is `"/api"`.
+fn join_route_path(prefix: &str, path: &str) -> String {
+    let prefix = prefix.trim_end_matches('/');
+    let path = path.trim_start_matches('/');
+    if path.is_empty() {
+        if prefix.is_empty() { "/".to_string() } else { prefix.to_string() }
+    } else {
+        format!("{}/{}", prefix, path)
+    }
+}
+
+/// One `/`-separated piece of a route path.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Static(String),
+    Param { name: String, kind: ParamKind },
+}
+
+/// Constraint on a path parameter: `:id`, `:id(int)`, or `:id|\d+`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParamKind {
+    Any,
+    Int,
+    Float,
+    Uuid,
+    Pattern(String),
+}
+
+/// Split a route path into segments, validating parameter syntax.
+pub fn parse_route_path(path: &str) -> Result<Vec<PathSegment>, String> {
+    let mut segments = Vec::new();
+    for part in path.split('/').filter(|p| !p.is_empty()) {
+        let param = match part.strip_prefix(':') {
+            Some(param) => param,
+            None => {
+                segments.push(PathSegment::Static(part.to_string()));
+                continue;
+            }
+        };
+        let (name, kind) = if let Some((name, pattern)) = param.split_once('|') {
+            // Harbor strings keep escapes verbatim; a regex wants `\\` as `\`.
+            (name, ParamKind::Pattern(pattern.replace("\\\\", "\\")))
+        } else if let Some((name, rest)) = param.split_once('(') {
+            let ty = rest.strip_suffix(')')
+                .ok_or_else(|| format!("Unclosed type in path parameter ':{}'", param))?;
+            let kind = match ty {
+                "int" => ParamKind::Int,
+                "float" => ParamKind::Float,
+                "str" => ParamKind::Any,
+                "uuid" => ParamKind::Uuid,
+                _ => return Err(format!("Unknown path parameter type '{}' (expected int, float, str, or uuid)", ty)),
+            };
+            (name, kind)
+        } else {
+            (param, ParamKind::Any)
+        };
+        if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("Invalid path parameter name '{}'", name));
+        }
+        segments.push(PathSegment::Param { name: name.to_string(), kind });
+    }
+    Ok(segments)
+}
+
+/// Check a five-field cron schedule (minute hour day month weekday). Each
+/// field is a comma list of `*`, `n` or `a-b`, optionally with `/step`.
+pub fn validate_cron(schedule: &str) -> Result<(), String> {
+    const FIELDS: [(&str, u32, u32); 5] = [("minute", 0, 59), ("hour", 0, 23), ("day", 1, 31), ("month", 1, 12), ("weekday", 0, 7)];
+    let fields: Vec<&str> = schedule.split_whitespace().collect();
+    if fields.len() != FIELDS.len() {
+        return Err(format!("Cron schedule '{}' needs 5 fields (minute hour day month weekday)", schedule));
+    }
+    for (field, (name, min, max)) in fields.iter().zip(FIELDS) {
+        for part in field.split(',') {
+            let (range, step) = match part.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (part, None),
+            };
+            if step.is_some_and(|s| !matches!(s.parse::<u32>(), Ok(n) if n > 0)) {
+                return Err(format!("Invalid step in cron {} field '{}'", name, field));
+            }
+            if range == "*" {
+                continue;
+            }
+            let bounds: Vec<&str> = range.splitn(2, '-').collect();
+            for bound in bounds {
+                match bound.parse::<u32>() {
+                    Ok(n) if (min..=max).contains(&n) => {}
+                    _ => return Err(format!("Invalid cron {} field '{}' (expected {}-{})", name, field, min, max)),
+                }
+            }
+        }
+    }
+    Ok(())
 }
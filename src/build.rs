@@ -0,0 +1,121 @@
+//! `harbor build`: compile many files at once, each next to its source.
+//!
+//! A file that fails doesn't stop the others. Diagnostics are printed
+//! grouped by the file they're in, once however many of the files import
+//! it, then a summary line, and the exit status is 1 if any file failed.
+
+use harbor::cache::Cache;
+use harbor::resolver::normalize;
+use std::path::{Path, PathBuf};
+
+/// Build every `.hb` file in `paths`, looking through directories the way
+/// `harbor dev` watches them. Returns the exit status.
+pub fn run(paths: &[String], options: &harbor::BuildOptions, use_cache: bool) -> i32 {
+    let mut files: Vec<PathBuf> = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        let mut found = Vec::new();
+        if path.is_dir() {
+            crate::dev::collect_sources(path, &mut found);
+            found.sort();
+        } else {
+            found.push(path.to_path_buf());
+        }
+        // `./a.hb` and `a.hb` are one file
+        for file in found {
+            let file = file.strip_prefix(".").map(Path::to_path_buf).unwrap_or(file);
+            if !files.contains(&file) {
+                files.push(file);
+            }
+        }
+    }
+    if files.is_empty() {
+        eprintln!("Error: No .hb files in {}", paths.join(", "));
+        return 1;
+    }
+
+    // A module's diagnostics come up in every build that imports it, so
+    // they're collected by the file they're in and counted once
+    let mut report: Vec<FileReport> = files.iter().map(|file| FileReport::new(file)).collect();
+    for file in &files {
+        let input = file.display().to_string();
+        let output = crate::adjacent_js_path(&input);
+        let cache = Cache::for_source(&input);
+        match harbor::build_file(&input, &output, use_cache.then_some(&cache), options) {
+            Ok(info) => {
+                for warning in info.warnings {
+                    let report = FileReport::find(&mut report, warning.file.as_deref().unwrap_or(file));
+                    add(&mut report.warnings, warning.diagnostic.to_string());
+                }
+            }
+            Err(error) => {
+                let report = FileReport::find(&mut report, error.file.as_deref().unwrap_or(file));
+                for line in error.message.lines() {
+                    add(&mut report.errors, line.to_string());
+                }
+            }
+        }
+    }
+
+    let (mut failed, mut errors, mut warnings) = (0, 0, 0);
+    for file in &report {
+        if !file.errors.is_empty() {
+            failed += 1;
+        }
+        if file.errors.is_empty() && file.warnings.is_empty() {
+            continue;
+        }
+        println!("{}", crate::display_path(&file.path));
+        for line in file.errors.iter().chain(&file.warnings) {
+            println!("  {}", line);
+        }
+        errors += file.errors.len();
+        warnings += file.warnings.len();
+    }
+
+    if failed > 0 {
+        println!("\n{}, {}, {}", count(failed, "file failed", "files failed"), count(errors, "error", "errors"), count(warnings, "warning", "warnings"));
+        return 1;
+    }
+    println!("\nBuilt {}, {}", count(files.len(), "file", "files"), count(warnings, "warning", "warnings"));
+    0
+}
+
+/// The errors and warnings in one file, one line each.
+struct FileReport {
+    path: PathBuf,
+    /// `path` made absolute, to tell whether two paths are the same file.
+    key: PathBuf,
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+impl FileReport {
+    fn new(path: &Path) -> FileReport {
+        let key = normalize(&std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf()));
+        FileReport { path: path.to_path_buf(), key, errors: Vec::new(), warnings: Vec::new() }
+    }
+
+    /// The report for `path`, added at the end if it's an imported module
+    /// that wasn't one of the files named.
+    fn find<'a>(reports: &'a mut Vec<FileReport>, path: &Path) -> &'a mut FileReport {
+        let new = FileReport::new(path);
+        match reports.iter().position(|report| report.key == new.key) {
+            Some(i) => &mut reports[i],
+            None => {
+                reports.push(new);
+                reports.last_mut().expect("just pushed")
+            }
+        }
+    }
+}
+
+fn add(lines: &mut Vec<String>, line: String) {
+    if !lines.contains(&line) {
+        lines.push(line);
+    }
+}
+
+fn count(n: usize, one: &str, many: &str) -> String {
+    format!("{} {}", n, if n == 1 { one } else { many })
+}
@@ -0,0 +1,134 @@
+//! On-disk build cache under `.harbor/cache`.
+//!
+//! An entry is the generated JavaScript, its source map and the dev server
+//! port, so a hit skips lexing, parsing and codegen entirely. Keys hash the source, the
+//! compiler version and the build options, so changing any of them misses.
+//! Builds with warnings aren't cached: those depend on the filesystem (e.g.
+//! missing TLS files) and must be re-checked each time. An entry also
+//! records the Harbor modules and asset files the source imports, with
+//! hashes of their contents: assets are compiled in, and the names taken
+//! from modules are checked at build time.
+
+use crate::BuildInfo;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// The cache for sources in `project_dir`.
+    pub fn new(project_dir: &Path) -> Self {
+        Self { dir: project_dir.join(".harbor").join("cache") }
+    }
+
+    /// The cache for the directory holding `source_path`.
+    pub fn for_source(source_path: &str) -> Self {
+        let dir = Path::new(source_path).parent().filter(|p| !p.as_os_str().is_empty());
+        Self::new(dir.unwrap_or(Path::new(".")))
+    }
+
+    /// Key for `source` built with `options`.
+    pub fn key(source: &str, options: &str) -> String {
+        let compiler = compiler_stamp();
+        let parts = [compiler.as_str(), options, source];
+        // Two FNV-1a passes with different offsets, for a 128-bit key
+        let hash = |offset: u64| {
+            let mut h = offset;
+            for part in parts {
+                for &b in part.as_bytes().iter().chain(&[0xff]) {
+                    h ^= b as u64;
+                    h = h.wrapping_mul(0x100000001b3);
+                }
+            }
+            h
+        };
+        format!("{:016x}{:016x}", hash(0xcbf29ce484222325), hash(0x6c62272e07bb0142))
+    }
+
+    /// Copy the cached JavaScript for `key` and its source map to `output`,
+    /// returning its info.
+    pub fn restore(&self, key: &str, output: &Path) -> Option<BuildInfo> {
+        let meta = fs::read_to_string(self.dir.join(format!("{}.meta", key))).ok()?;
+        let info = parse_meta(&meta)?;
+        fs::copy(self.dir.join(format!("{}.js", key)), output).ok()?;
+        let map = self.dir.join(format!("{}.js.map", key));
+        if map.exists() {
+            fs::copy(map, crate::map_path(output)).ok()?;
+        }
+        Some(info)
+    }
+
+    /// Store the freshly written `output` and its info under `key`, unless
+    /// the build had warnings.
+    pub fn save(&self, key: &str, output: &Path, info: &BuildInfo) -> io::Result<()> {
+        if !info.warnings.is_empty() {
+            return Ok(());
+        }
+        let meta = format_meta(info)?;
+        fs::create_dir_all(&self.dir)?;
+        fs::copy(output, self.dir.join(format!("{}.js", key)))?;
+        // Inline source maps have no file of their own
+        if crate::map_path(output).exists() {
+            fs::copy(crate::map_path(output), self.dir.join(format!("{}.js.map", key)))?;
+        }
+        // The meta file goes last: an entry without one is never restored
+        fs::write(self.dir.join(format!("{}.meta", key)), meta)
+    }
+}
+
+/// The compiler version plus the running executable's modification time, so
+/// rebuilding the compiler (even without a version bump) starts afresh.
+fn compiler_stamp() -> String {
+    let built = std::env::current_exe()
+        .and_then(fs::metadata)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map_or(0, |d| d.as_nanos());
+    format!("{} {}", env!("CARGO_PKG_VERSION"), built)
+}
+
+/// The meta file is a `port <n>` (or `port -`) line, then an
+/// `import <hash> <path>` line per imported module and an
+/// `asset <hash> <path>` line per asset.
+fn format_meta(info: &BuildInfo) -> io::Result<String> {
+    let mut meta = match info.port {
+        Some(port) => format!("port {}\n", port),
+        None => "port -\n".to_string(),
+    };
+    for (kind, paths) in [("import", &info.imports), ("asset", &info.assets)] {
+        for path in paths {
+            let source = fs::read_to_string(path)?;
+            meta.push_str(&format!("{} {} {}\n", kind, Cache::key(&source, ""), path.display()));
+        }
+    }
+    Ok(meta)
+}
+
+/// Read a meta file back, or `None` if it's malformed or an imported file
+/// has changed since.
+fn parse_meta(meta: &str) -> Option<BuildInfo> {
+    let mut lines = meta.lines();
+    let port = match lines.next()?.strip_prefix("port ")? {
+        "-" => None,
+        n => Some(n.parse().ok()?),
+    };
+    let mut info = BuildInfo { warnings: Vec::new(), port, imports: Vec::new(), assets: Vec::new() };
+    for line in lines {
+        let (kind, rest) = line.split_once(' ')?;
+        let (hash, path) = rest.split_once(' ')?;
+        let source = fs::read_to_string(path).ok()?;
+        if Cache::key(&source, "") != hash {
+            return None;
+        }
+        match kind {
+            "import" => info.imports.push(PathBuf::from(path)),
+            "asset" => info.assets.push(PathBuf::from(path)),
+            _ => return None,
+        }
+    }
+    Some(info)
+}
@@ -0,0 +1,231 @@
+//! Compile-time checks that give warnings. These run from the CLI and
+//! aren't part of the filesystem-free `compile` used by the WASM build;
+//! `missing_tls_files` looks at the filesystem.
+
+use crate::ast::{parse_route_path, Expr, ParamKind, PathSegment, Route, Server, Stmt};
+use crate::diagnostics::Diagnostic;
+use std::path::Path;
+
+/// Warn about `tls cert`/`key` files that don't exist relative to the
+/// current directory, which is where Node will look for them.
+pub fn missing_tls_files(stmts: &[Stmt]) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for stmt in stmts {
+        let tls = match stmt {
+            Stmt::Server(server) => match &server.tls {
+                Some(tls) => tls,
+                None => continue,
+            },
+            _ => continue,
+        };
+        for (label, expr) in [("certificate", &tls.cert), ("key", &tls.key)] {
+            if let Expr::String(path) = expr {
+                if !Path::new(path).exists() {
+                    diags.push(Diagnostic::warning("W001", format!("TLS {} file '{}' not found", label, path), tls.span));
+                }
+            }
+        }
+    }
+    diags
+}
+
+/// Warn about server ports that can't be a number, or can be left without
+/// one: `env("PORT")` with no default is None wherever PORT isn't set.
+pub fn server_ports(stmts: &[Stmt]) -> Vec<Diagnostic> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Server(server) => port_problem(&server.port).map(|message| Diagnostic::warning("W004", message, server.port_span)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn port_problem(port: &Expr) -> Option<String> {
+    match port {
+        Expr::Number(n, _) if n.fract() != 0.0 || !(0.0..=65535.0).contains(n) => {
+            Some(format!("Server port {} isn't a whole number from 0 to 65535", n))
+        }
+        Expr::String(text) => match text.trim().parse::<u16>() {
+            Ok(_) => None,
+            Err(_) => Some(format!("Server port \"{}\" isn't a number", text)),
+        },
+        Expr::Bool(_) | Expr::None | Expr::Array(_) | Expr::Object(_) | Expr::Dict(_) | Expr::Html(_) => {
+            Some("Server port can't be a number".to_string())
+        }
+        Expr::Call(callee, args) => {
+            let name = match &**callee {
+                Expr::Ident(name) if name == "env" => "env",
+                Expr::Member(object, method) if matches!(&**object, Expr::Ident(name) if name == "env") && method == "get" => "env.get",
+                _ => return None,
+            };
+            match args.as_slice() {
+                [Expr::String(var)] => Some(format!(
+                    "{}(\"{}\") has no default, so the server has no port where {} isn't set (e.g. env(\"{}\", 8080))",
+                    name, var, var, var
+                )),
+                [_, default] => port_problem(default),
+                _ => None,
+            }
+        }
+        Expr::Member(object, var) if matches!(&**object, Expr::Ident(name) if name == "env") => Some(format!(
+            "env.{} is None where {} isn't set, leaving the server without a port (e.g. env(\"{}\", 8080))",
+            var, var, var
+        )),
+        _ => None,
+    }
+}
+
+/// Warn about a route whose path is a letter or two away from a route's
+/// with another method, like `get "/users"` and `post "/user"`, when
+/// nothing else uses either spelling for that method.
+pub fn similar_route_paths(stmts: &[Stmt]) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for server in servers(stmts) {
+        let routes: Vec<(Route, String)> = server.flat_routes().into_iter()
+            .map(|route| {
+                let shape = path_shape(&route.path);
+                (route, shape)
+            })
+            .collect();
+        for (i, (route, shape)) in routes.iter().enumerate() {
+            // A path only one method uses, which an earlier route's path for
+            // another method almost matches
+            if routes.iter().any(|(other, other_shape)| other_shape == shape && other.method != route.method) {
+                continue;
+            }
+            let close = routes[..i].iter().find(|(other, other_shape)| {
+                other.method != route.method
+                    && !routes.iter().any(|(r, s)| s == other_shape && r.method == route.method)
+                    && is_misspelling(shape, other_shape)
+            });
+            if let Some((other, _)) = close {
+                diags.push(Diagnostic::warning("W005", format!(
+                    "{} \"{}\" is almost the path of {} \"{}\" (line {}); if they're meant to be the same, one is misspelled",
+                    route.method, route.path, other.method, other.path, other.span.line
+                ), route.span));
+            }
+        }
+    }
+    diags
+}
+
+/// Warn about routes that can never run: an earlier route for the same
+/// method matches every path they do, or `health`/`metrics` answers it.
+pub fn unreachable_routes(stmts: &[Stmt]) -> Vec<Diagnostic> {
+    let mut diags = Vec::new();
+    for server in servers(stmts) {
+        let routes = server.flat_routes();
+        for (i, route) in routes.iter().enumerate() {
+            let endpoint = [("health", &server.health), ("metrics", &server.metrics)].into_iter()
+                .find(|(_, path)| route.method == "GET" && path.as_deref() == Some(route.path.as_str()));
+            if let Some((directive, _)) = endpoint {
+                diags.push(Diagnostic::warning("W006", format!(
+                    "GET \"{}\" can never run: the server's `{}` endpoint answers it first",
+                    route.path, directive
+                ), route.span));
+                continue;
+            }
+            let earlier = routes[..i].iter().find(|earlier| earlier.method == route.method && covers(&earlier.path, &route.path));
+            if let Some(earlier) = earlier {
+                diags.push(Diagnostic::warning("W006", format!(
+                    "{} \"{}\" can never run: {} \"{}\" (line {}) comes first and matches every path it does",
+                    route.method, route.path, earlier.method, earlier.path, earlier.span.line
+                ), route.span));
+            }
+        }
+    }
+    diags
+}
+
+fn servers(stmts: &[Stmt]) -> impl Iterator<Item = &Server> {
+    stmts.iter().filter_map(|stmt| match stmt {
+        Stmt::Server(server) => Some(&**server),
+        _ => None,
+    })
+}
+
+/// A path with its parameters' names left out, so `/users/:id` and
+/// `/users/:user_id` are the same path.
+fn path_shape(path: &str) -> String {
+    let segments = parse_route_path(path).unwrap_or_default();
+    let parts: Vec<String> = segments.iter().map(|segment| match segment {
+        PathSegment::Static(text) => text.clone(),
+        PathSegment::Param { kind, .. } => format!(":{:?}", kind),
+    }).collect();
+    format!("/{}", parts.join("/"))
+}
+
+/// Whether two path shapes differ in a single piece, by a letter or two of
+/// a word long enough that the change is likely a typo rather than another
+/// name (`/user` and `/users`, but not `/v1` and `/v2`).
+fn is_misspelling(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<&str>, Vec<&str>) = (a.split('/').collect(), b.split('/').collect());
+    if a.len() != b.len() {
+        return false;
+    }
+    let differing: Vec<(&str, &str)> = a.into_iter().zip(b).filter(|(x, y)| x != y).collect();
+    match differing.as_slice() {
+        [(x, y)] => !x.starts_with(':') && !y.starts_with(':') && x.len().min(y.len()) >= 4 && edit_distance(x, y) <= 2,
+        _ => false,
+    }
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != *cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(row[j + 1] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// Whether every request path `later` matches, `earlier` matches too.
+fn covers(earlier: &str, later: &str) -> bool {
+    let (Ok(first), Ok(second)) = (parse_route_path(earlier), parse_route_path(later)) else { return false };
+    let is_static = |segments: &[PathSegment]| segments.iter().all(|s| matches!(s, PathSegment::Static(_)));
+    match (is_static(&first), is_static(&second)) {
+        // Paths without parameters are compared as written
+        (true, true) => earlier == later,
+        (true, false) => false,
+        // A pattern only matches a path without a trailing or doubled `/`
+        (false, true) => later == path_shape(later) && segments_cover(&first, &second),
+        (false, false) => segments_cover(&first, &second),
+    }
+}
+
+fn segments_cover(earlier: &[PathSegment], later: &[PathSegment]) -> bool {
+    earlier.len() == later.len() && earlier.iter().zip(later).all(|(e, l)| match (e, l) {
+        (PathSegment::Static(a), PathSegment::Static(b)) => a == b,
+        (PathSegment::Static(_), PathSegment::Param { .. }) => false,
+        (PathSegment::Param { kind: ParamKind::Any, .. }, _) => true,
+        (PathSegment::Param { kind: a, .. }, PathSegment::Param { kind: b, .. }) => a == b || (*a == ParamKind::Float && *b == ParamKind::Int),
+        (PathSegment::Param { kind, .. }, PathSegment::Static(text)) => matches_kind(kind, text),
+    })
+}
+
+/// Whether a `:name(kind)` parameter matches the literal segment `text`.
+fn matches_kind(kind: &ParamKind, text: &str) -> bool {
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let int = |s: &str| digits(s.strip_prefix('-').unwrap_or(s));
+    match kind {
+        ParamKind::Any => true,
+        ParamKind::Int => int(text),
+        ParamKind::Float => match text.split_once('.') {
+            Some((whole, fraction)) => int(whole) && digits(fraction),
+            None => int(text),
+        },
+        ParamKind::Uuid => {
+            let groups: Vec<&str> = text.split('-').collect();
+            groups.len() == 5 && groups.iter().zip([8, 4, 4, 4, 12]).all(|(g, n)| g.len() == n && g.chars().all(|c| c.is_ascii_hexdigit()))
+        }
+        // Can't tell which literals a custom pattern matches
+        ParamKind::Pattern(_) => false,
+    }
+}
@@ -1,53 +1,614 @@
 use crate::ast::*;
+use crate::numbers::{self, Kind};
+use crate::resolver;
+use crate::scoping;
+use crate::sourcemap;
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::io;
+
+/// Method names with Python semantics in the runtime (`__method`).
+const PYTHON_METHODS: &[&str] = &[
+    "strip", "lstrip", "rstrip", "split", "join", "replace", "startswith", "endswith",
+    "upper", "lower", "find", "count", "zfill", "title", "index",
+    "append", "pop", "insert", "remove", "extend", "clear", "copy", "sort",
+    "get", "update", "setdefault", "decode", "hex",
+];
+
+/// Builtins that return lists, which `for` can loop over directly.
+const ITERATING_BUILTINS: &[&str] = &["range", "enumerate", "sorted", "reversed", "keys", "values", "items", "unique", "flatten", "chunk"];
+
+/// A code generation target.
+///
+/// Backends turn the parsed program into source for a particular JavaScript
+/// runtime. Only `runtime_prelude`, `write_stmt` and `emit_expr` are required;
+/// `generate_to` stitches them together and can be overridden by targets that
+/// don't want the async IIFE wrapper.
+pub trait Backend {
+    /// Builtins and helpers emitted ahead of the program body.
+    fn runtime_prelude(&self) -> String;
+
+    /// Append a single statement at the given indentation to `out`. Nested
+    /// statements are written into the same buffer rather than returned.
+    fn write_stmt(&self, out: &mut String, stmt: &Stmt, req_name: &str, indent: &str);
+
+    /// Emit a single expression.
+    fn emit_expr(&self, expr: &Expr, req_name: &str) -> String;
+
+    /// A single statement as a string.
+    fn emit_stmt(&self, stmt: &Stmt, req_name: &str, indent: &str) -> String {
+        let mut out = String::new();
+        self.write_stmt(&mut out, stmt, req_name, indent);
+        out
+    }
+
+    /// Write the program to `out` one top-level statement at a time, so the
+    /// whole output is never held in memory at once.
+    fn generate_to(&self, stmts: &[Stmt], out: &mut dyn io::Write) -> io::Result<()> {
+        out.write_all(self.runtime_prelude().as_bytes())?;
+
+        // Wrap in async IIFE
+        out.write_all(b"(async () => {\n")?;
+
+        // Function declarations are hoisted, so their exports can be set
+        // before anything else runs. A module that imports this one while
+        // it's still loading (a circular import) then finds them.
+        let hoisted = hoisted_exports(stmts);
+        for (exported, name) in &hoisted {
+            out.write_all(format!("  module.exports.{} = {};\n", exported, name).as_bytes())?;
+        }
+
+        SYNC_FUNCTIONS.set(sync_functions(stmts));
+        let mut buf = String::new();
+        in_scope(&[], stmts, || {
+            for stmt in stmts {
+                buf.clear();
+                match stmt {
+                    Stmt::Export(inner) if matches!(**inner, Stmt::Func { .. }) => self.write_stmt(&mut buf, inner, "null", "  "),
+                    Stmt::ExportNames(names) => {
+                        let rest: Vec<NameAs> = names.iter().filter(|n| !hoisted.iter().any(|(exported, _)| exported == n.renamed())).cloned().collect();
+                        if !rest.is_empty() {
+                            self.write_stmt(&mut buf, &Stmt::ExportNames(rest), "null", "  ");
+                        }
+                    }
+                    _ => self.write_stmt(&mut buf, stmt, "null", "  "),
+                }
+                out.write_all(buf.as_bytes())?;
+            }
+            Ok::<(), io::Error>(())
+        })?;
+
+        out.write_all(b"})().catch(__uncaught);\n")
+    }
+
+    fn generate(&self, stmts: &[Stmt]) -> String {
+        let mut out = Vec::new();
+        self.generate_to(stmts, &mut out).expect("writing to a Vec cannot fail");
+        String::from_utf8(out).expect("generated code is UTF-8")
+    }
+}
+
+/// The exports of the module-level functions in `stmts`, as (exported
+/// name, function) pairs: `export def`, and `export f as g` of a function.
+fn hoisted_exports(stmts: &[Stmt]) -> Vec<(String, String)> {
+    let mut functions = HashSet::new();
+    for stmt in stmts {
+        if let Stmt::Func { name, .. } = stmt {
+            functions.insert(name.as_str());
+        } else if let Stmt::Export(inner) = stmt {
+            if let Stmt::Func { name, .. } = &**inner {
+                functions.insert(name.as_str());
+            }
+        }
+    }
+    // A name that's also assigned may not hold the function when exported
+    for stmt in stmts {
+        if let Stmt::Set { target: Expr::Ident(name), .. } = stmt {
+            functions.remove(name.as_str());
+        }
+    }
+    let mut hoisted = Vec::new();
+    for stmt in stmts {
+        match stmt {
+            Stmt::Export(inner) => {
+                if let Stmt::Func { name, .. } = &**inner {
+                    hoisted.push((name.clone(), name.clone()));
+                }
+            }
+            Stmt::ExportNames(names) => {
+                for name in names.iter().filter(|n| functions.contains(n.name.as_str())) {
+                    hoisted.push((name.renamed().to_string(), name.name.clone()));
+                }
+            }
+            _ => {}
+        }
+    }
+    hoisted
+}
 
-pub struct CodeGen;
+/// The default backend: CommonJS for Node.js.
+pub struct NodeBackend;
+
+thread_local! {
+    /// Set while generating a class constructor, which JS won't let be
+    /// async, or a `@sync` function: nothing generated there may `await`.
+    static IN_SYNC: Cell<bool> = const { Cell::new(false) };
+    /// How the `@sync` functions in reach are called (`f`, `this.m`), which
+    /// callers don't wait for.
+    static SYNC_FUNCTIONS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+    /// The function bodies being generated, innermost last.
+    static SCOPES: RefCell<Vec<scoping::Scope>> = const { RefCell::new(Vec::new()) };
+    /// Variables the enclosing loops of this function declare with `let`,
+    /// which assignments mustn't declare again with `var`.
+    static LOOP_LETS: RefCell<HashSet<String>> = RefCell::new(HashSet::new());
+}
+
+/// Generate the function (or module) with `params` whose statements are
+/// `body`, whose loops see only their own function's variables.
+fn in_scope<T>(params: &[String], body: &[Stmt], generate: impl FnOnce() -> T) -> T {
+    SCOPES.with(|scopes| scopes.borrow_mut().push(scoping::Scope::of(params, body)));
+    let outer = LOOP_LETS.take();
+    let result = generate();
+    LOOP_LETS.set(outer);
+    SCOPES.with(|scopes| scopes.borrow_mut().pop());
+    result
+}
+
+/// Generate with `IN_SYNC` set to `value`, restoring it after.
+fn in_sync<T>(value: bool, generate: impl FnOnce() -> T) -> T {
+    let outer = IN_SYNC.replace(value);
+    let result = generate();
+    IN_SYNC.set(outer);
+    result
+}
+
+/// Whether a call to `callee` reaches a `@sync` function: a method on
+/// `this`, or a module-level function no enclosing function rebinds.
+fn is_sync_function(callee: &str) -> bool {
+    SYNC_FUNCTIONS.with(|names| names.borrow().contains(callee))
+        && SCOPES.with(|scopes| !scopes.borrow().iter().skip(1).any(|scope| scope.binds(callee)))
+}
+
+/// The module-level `@sync` functions in `stmts`.
+fn sync_functions(stmts: &[Stmt]) -> HashSet<String> {
+    stmts
+        .iter()
+        .filter_map(|stmt| match stmt {
+            Stmt::Func { name, sync: true, .. } => Some(name.clone()),
+            Stmt::Export(inner) => match &**inner {
+                Stmt::Func { name, sync: true, .. } => Some(name.clone()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
 
-impl CodeGen {
-    pub fn generate(stmts: &[Stmt]) -> String {
+impl Backend for NodeBackend {
+    fn runtime_prelude(&self) -> String {
         let mut output = String::new();
 
         // ─── Runtime Header (Python-like builtins) ───
         output.push_str("const http = require(\"http\");\n");
         output.push_str("const https = require(\"https\");\n");
         output.push_str("const readline = require(\"readline\");\n");
-        output.push_str("const __fs = require(\"fs/promises\");\n\n");
+        output.push_str("const __fs = require(\"fs/promises\");\n");
+        output.push_str("const __crypto = require(\"crypto\");\n");
+        output.push_str("const __childProcess = require(\"child_process\");\n");
+        output.push_str("const __path = require(\"path\");\n\n");
 
         // File system
         output.push_str("const fs = {\n");
         output.push_str("  read: (path) => __fs.readFile(path, 'utf-8'),\n");
-        output.push_str("  write: (path, content) => __fs.writeFile(path, String(content))\n");
+        output.push_str("  write: (path, content) => __fs.writeFile(path, String(content)),\n");
+        output.push_str("  append: (path, content) => __fs.appendFile(path, String(content)),\n");
+        output.push_str("  read_bytes: (path) => __fs.readFile(path),\n");
+        output.push_str("  write_bytes: (path, data) => __fs.writeFile(path, data),\n");
+        output.push_str("  exists: (path) => __fs.access(path).then(() => true, () => false),\n");
+        output.push_str("  listdir: async (path = \".\") => (await __fs.readdir(path)).sort(),\n");
+        output.push_str("  mkdir: (path) => __fs.mkdir(path, { recursive: true }).then(() => null),\n");
+        output.push_str("  // Files, or directories with everything in them\n");
+        output.push_str("  remove: (path) => __fs.rm(path, { recursive: true }),\n");
+        output.push_str("  copy: (src, dest) => __fs.cp(src, dest, { recursive: true }),\n");
+        output.push_str("  stat: async (path) => {\n");
+        output.push_str("    const st = await __fs.stat(path);\n");
+        output.push_str("    return { size: st.size, modified: st.mtimeMs / 1000, is_file: st.isFile(), is_dir: st.isDirectory() };\n");
+        output.push_str("  },\n");
+        output.push_str("};\n\n");
+        // JSON, with Python's separators: json.dumps(obj, indent=2, sort_keys=True)
+        output.push_str("const json = {\n");
+        output.push_str("  dumps: (value, ...args) => {\n");
+        output.push_str("    const [[indentArg = null], kw] = __splitKwargs(args);\n");
+        output.push_str("    const indent = kw.indent ?? indentArg;\n");
+        output.push_str("    const dump = (v, depth) => {\n");
+        output.push_str("      if (v != null && typeof v.toJSON === \"function\") v = v.toJSON();\n");
+        output.push_str("      if (typeof v === \"bigint\") return String(v);\n");
+        output.push_str("      if (v === null || typeof v !== \"object\") return JSON.stringify(v) ?? \"null\";\n");
+        output.push_str("      const isList = Array.isArray(v);\n");
+        output.push_str("      let names = isList ? [] : Object.keys(v).filter((k) => v[k] !== undefined && typeof v[k] !== \"function\");\n");
+        output.push_str("      if (kw.sort_keys) names = names.sort();\n");
+        output.push_str("      const entries = isList\n");
+        output.push_str("        ? v.map((x) => dump(x, depth + 1))\n");
+        output.push_str("        : names.map((k) => JSON.stringify(k) + \": \" + dump(v[k], depth + 1));\n");
+        output.push_str("      const [open, close] = isList ? [\"[\", \"]\"] : [\"{\", \"}\"];\n");
+        output.push_str("      if (entries.length === 0) return open + close;\n");
+        output.push_str("      if (indent == null) return open + entries.join(\", \") + close;\n");
+        output.push_str("      const pad = (n) => \"\\n\" + \" \".repeat(indent * n);\n");
+        output.push_str("      return open + pad(depth + 1) + entries.join(\",\" + pad(depth + 1)) + pad(depth) + close;\n");
+        output.push_str("    };\n");
+        output.push_str("    return dump(value, 0);\n");
+        output.push_str("  },\n");
+        output.push_str("  loads: (text) => {\n");
+        output.push_str("    try {\n");
+        output.push_str("      return JSON.parse(text);\n");
+        output.push_str("    } catch (e) {\n");
+        output.push_str("      throw new ValueError(`Invalid JSON: ${e.message}`, { kind: \"json\" });\n");
+        output.push_str("    }\n");
+        output.push_str("  },\n");
         output.push_str("};\n\n");
 
         // Python-like builtins
         output.push_str("const len = (obj) => {\n");
         output.push_str("  if (obj == null) return 0;\n");
-        output.push_str("  if (typeof obj === 'string' || Array.isArray(obj)) return obj.length;\n");
-        output.push_str("  if (typeof obj === 'object') return Object.keys(obj).length;\n");
+        output.push_str("  if (typeof obj === 'string' || Array.isArray(obj) || ArrayBuffer.isView(obj)) return obj.length;\n");
+        output.push_str("  if (obj instanceof Map || obj instanceof Set) return obj.size;\n");
+        output.push_str("  if (typeof obj === 'object' || __isDict(obj)) return Object.keys(obj).length;\n");
         output.push_str("  return 0;\n");
         output.push_str("};\n");
-        output.push_str("const str = (x) => String(x);\n");
-        output.push_str("const int = (x) => parseInt(x, 10);\n");
+        // print, f-strings and str() use a class's __str__ (or __repr__), and
+        // show None, booleans, lists and dicts the way Python does
+        output.push_str("const __tostr = async (x) => {\n");
+        output.push_str("  if (typeof x === \"bigint\") return String(x);\n");
+        output.push_str("  if (x == null || typeof x === \"boolean\") return __reprScalar(x);\n");
+        output.push_str("  if (typeof x !== \"object\") return x;\n");
+        output.push_str("  if (Buffer.isBuffer(x)) return __bytesRepr(x);\n");
+        output.push_str("  if (typeof x.__str__ === \"function\") return String(await x.__str__());\n");
+        output.push_str("  if (typeof x.__repr__ === \"function\") return String(await x.__repr__());\n");
+        output.push_str("  if (Array.isArray(x) || x instanceof Map || __isDict(x)) return __repr(x);\n");
+        output.push_str("  return x;\n");
+        output.push_str("};\n");
+        // How a value is written in Harbor source: strings quoted, None/True/False,
+        // and lists and dicts with their items written the same way
+        output.push_str("const __reprScalar = (x) => {\n");
+        output.push_str("  if (x == null) return \"None\";\n");
+        output.push_str("  if (typeof x === \"boolean\") return x ? \"True\" : \"False\";\n");
+        output.push_str("  if (typeof x === \"string\") {\n");
+        output.push_str("    const quote = x.includes(\"'\") && !x.includes(\"\\\"\") ? \"\\\"\" : \"'\";\n");
+        output.push_str("    const escaped = x.replace(/\\\\/g, \"\\\\\\\\\").replace(/\\n/g, \"\\\\n\").replaceAll(quote, `\\\\${quote}`);\n");
+        output.push_str("    return `${quote}${escaped}${quote}`;\n");
+        output.push_str("  }\n");
+        output.push_str("  if (Buffer.isBuffer(x)) return __bytesRepr(x);\n");
+        output.push_str("  return String(x);\n");
+        output.push_str("};\n");
+        output.push_str("const __repr = async (x, seen = new Set()) => {\n");
+        output.push_str("  if (x == null || typeof x !== \"object\" || Buffer.isBuffer(x)) return __reprScalar(x);\n");
+        output.push_str("  if (typeof x.__repr__ === \"function\") return String(await x.__repr__());\n");
+        output.push_str("  if (seen.has(x)) return Array.isArray(x) ? \"[...]\" : \"{...}\";\n");
+        output.push_str("  const inner = new Set(seen).add(x);\n");
+        output.push_str("  if (Array.isArray(x)) return `[${(await Promise.all(x.map((item) => __repr(item, inner)))).join(\", \")}]`;\n");
+        output.push_str("  if (x instanceof Map || __isDict(x)) {\n");
+        output.push_str("    const entries = x instanceof Map ? [...x] : Object.entries(x);\n");
+        output.push_str("    const items = await Promise.all(entries.map(async ([k, v]) => `${await __repr(k, inner)}: ${await __repr(v, inner)}`));\n");
+        output.push_str("    return `{${items.join(\", \")}}`;\n");
+        output.push_str("  }\n");
+        output.push_str("  return `<${x.constructor?.name ?? \"object\"} object>`;\n");
+        output.push_str("};\n");
+        output.push_str("const str = async (x) => String(await __tostr(x));\n");
+        // The same for @sync functions and constructors, which can't wait:
+        // a class's __str__ or __repr__ must be @sync too
+        output.push_str("const __callSync = (x, method) => {\n");
+        output.push_str("  const text = x[method]();\n");
+        output.push_str("  if (text instanceof Promise) throw new TypeError(`'${__typeName(x)}'.${method} is async; mark it @sync to show it without waiting`);\n");
+        output.push_str("  return String(text);\n");
+        output.push_str("};\n");
+        output.push_str("const __tostrSync = (x) => {\n");
+        output.push_str("  if (typeof x === \"bigint\") return String(x);\n");
+        output.push_str("  if (x == null || typeof x === \"boolean\") return __reprScalar(x);\n");
+        output.push_str("  if (typeof x !== \"object\") return x;\n");
+        output.push_str("  if (Buffer.isBuffer(x)) return __bytesRepr(x);\n");
+        output.push_str("  if (typeof x.__str__ === \"function\") return __callSync(x, \"__str__\");\n");
+        output.push_str("  if (typeof x.__repr__ === \"function\") return __callSync(x, \"__repr__\");\n");
+        output.push_str("  if (Array.isArray(x) || x instanceof Map || __isDict(x)) return __reprSync(x);\n");
+        output.push_str("  return x;\n");
+        output.push_str("};\n");
+        output.push_str("const __reprSync = (x, seen = new Set()) => {\n");
+        output.push_str("  if (x == null || typeof x !== \"object\" || Buffer.isBuffer(x)) return __reprScalar(x);\n");
+        output.push_str("  if (typeof x.__repr__ === \"function\") return __callSync(x, \"__repr__\");\n");
+        output.push_str("  if (seen.has(x)) return Array.isArray(x) ? \"[...]\" : \"{...}\";\n");
+        output.push_str("  const inner = new Set(seen).add(x);\n");
+        output.push_str("  if (Array.isArray(x)) return `[${x.map((item) => __reprSync(item, inner)).join(\", \")}]`;\n");
+        output.push_str("  if (x instanceof Map || __isDict(x)) {\n");
+        output.push_str("    const entries = x instanceof Map ? [...x] : Object.entries(x);\n");
+        output.push_str("    return `{${entries.map(([k, v]) => `${__reprSync(k, inner)}: ${__reprSync(v, inner)}`).join(\", \")}}`;\n");
+        output.push_str("  }\n");
+        output.push_str("  return `<${x.constructor?.name ?? \"object\"} object>`;\n");
+        output.push_str("};\n");
+        // bytes(x): binary data as a Buffer, from text (encoded as UTF-8 unless
+        // an encoding is given), numbers 0-255, other bytes, or a size to fill
+        // with zeros. It prints like Python's, as b'...'
+        output.push_str("const __encoding = (name) => String(name).toLowerCase().replace(\"-\", \"\");\n");
+        output.push_str("const bytes = (source = 0, encoding = \"utf-8\") => {\n");
+        output.push_str("  if (typeof source === \"number\") return Buffer.alloc(source);\n");
+        output.push_str("  if (typeof source === \"string\") return Buffer.from(source, __encoding(encoding));\n");
+        output.push_str("  if (ArrayBuffer.isView(source)) return Buffer.from(source);\n");
+        output.push_str("  const list = Array.from(source);\n");
+        output.push_str("  if (!list.every((n) => Number.isInteger(n) && n >= 0 && n < 256)) throw new ValueError(\"bytes must be in range(0, 256)\");\n");
+        output.push_str("  return Buffer.from(list);\n");
+        output.push_str("};\n");
+        output.push_str("bytes.fromhex = (text) => {\n");
+        output.push_str("  const digits = String(text).replace(/\\s+/g, \"\");\n");
+        output.push_str("  if (!/^([0-9a-f]{2})*$/i.test(digits)) throw new ValueError(`non-hexadecimal number found in fromhex() arg: ${JSON.stringify(text)}`);\n");
+        output.push_str("  return Buffer.from(digits, \"hex\");\n");
+        output.push_str("};\n");
+        output.push_str("const __bytesRepr = (b) => {\n");
+        output.push_str("  const escapes = { 9: \"\\\\t\", 10: \"\\\\n\", 13: \"\\\\r\", 39: \"\\\\'\", 92: \"\\\\\\\\\" };\n");
+        output.push_str("  const chars = Array.from(b, (n) => escapes[n] ?? (n >= 32 && n < 127 ? String.fromCharCode(n) : `\\\\x${n.toString(16).padStart(2, \"0\")}`));\n");
+        output.push_str("  return `b'${chars.join(\"\")}'`;\n");
+        output.push_str("};\n");
+        // `html f"..."` escapes what it interpolates, except markup built the
+        // same way, so fragments nest without being escaped twice
+        output.push_str("class __SafeHtml {\n");
+        output.push_str("  constructor(text) { this.text = text; }\n");
+        output.push_str("  __str__() { return this.text; }\n");
+        output.push_str("  toString() { return this.text; }\n");
+        output.push_str("  toJSON() { return this.text; }\n");
+        output.push_str("}\n");
+        output.push_str("const __htmlEntities = { \"&\": \"&amp;\", \"<\": \"&lt;\", \">\": \"&gt;\", \"\\\"\": \"&quot;\", \"'\": \"&#39;\" };\n");
+        output.push_str("const __escapeHtml = (x) => x instanceof __SafeHtml ? x.text : String(x).replace(/[&<>\"']/g, (c) => __htmlEntities[c]);\n");
+        output.push_str("const __htmlPart = async (x) => __escapeHtml(x instanceof __SafeHtml ? x : await __tostr(x));\n");
+        output.push_str("const __htmlPartSync = (x) => __escapeHtml(x instanceof __SafeHtml ? x : __tostrSync(x));\n");
+        // int() parses like Python's (bases, underscores, ValueError on junk);
+        // past 2^53 it returns a BigInt so no digits are lost
+        output.push_str("const __MAX_SAFE = BigInt(Number.MAX_SAFE_INTEGER);\n");
+        output.push_str("const int = (x, ...args) => {\n");
+        output.push_str("  if (typeof x === \"bigint\") return x;\n");
+        output.push_str("  if (typeof x === \"number\" || typeof x === \"boolean\") {\n");
+        output.push_str("    if (!Number.isFinite(Number(x))) throw new ValueError(`cannot convert float ${x} to integer`);\n");
+        output.push_str("    return Math.trunc(Number(x));\n");
+        output.push_str("  }\n");
+        output.push_str("  const [[given], kw] = __splitKwargs(args);\n");
+        output.push_str("  const base = kw.base ?? given ?? 10;\n");
+        output.push_str("  const match = /^([+-]?)([0-9a-z]+(?:_[0-9a-z]+)*)$/i.exec(String(x).trim());\n");
+        output.push_str("  let value = 0n;\n");
+        output.push_str("  for (const ch of match ? match[2].replace(/_/g, \"\") : \"\") {\n");
+        output.push_str("    const digit = parseInt(ch, 36);\n");
+        output.push_str("    if (digit >= base) { value = null; break; }\n");
+        output.push_str("    value = value * BigInt(base) + BigInt(digit);\n");
+        output.push_str("  }\n");
+        output.push_str("  if (!match || value === null) throw new ValueError(`invalid literal for int() with base ${base}: '${x}'`);\n");
+        output.push_str("  if (match[1] === \"-\") value = -value;\n");
+        output.push_str("  return value >= -__MAX_SAFE && value <= __MAX_SAFE ? Number(value) : value;\n");
+        output.push_str("};\n");
+        output.push_str("const bigint = (x, ...args) => BigInt(int(x, ...args));\n");
+        // BigInt division truncates; Python's // floors
+        output.push_str("const __bigFloorDiv = (a, b) => (a % b !== 0n && (a < 0n) !== (b < 0n) ? a / b - 1n : a / b);\n");
         output.push_str("const float = (x) => parseFloat(x);\n");
         output.push_str("const bool = (x) => Boolean(x);\n");
         output.push_str("const type = (x) => typeof x;\n");
         output.push_str("const abs = (x) => Math.abs(x);\n");
         output.push_str("const round = (x) => Math.round(x);\n");
-        output.push_str("const sorted = (arr) => [...arr].sort();\n");
+        // sorted/min/max order values like Python and accept key=, reverse=, default=
+        output.push_str("const sorted = async (items, ...args) => {\n");
+        output.push_str("  const list = __isDict(items) || items instanceof Map ? keys(items) : Array.from(items);\n");
+        output.push_str("  await __listMethods.sort(list, ...args);\n");
+        output.push_str("  return list;\n");
+        output.push_str("};\n");
         output.push_str("const reversed = (arr) => [...arr].reverse();\n");
         output.push_str("const sum = (arr) => arr.reduce((a, b) => a + b, 0);\n");
-        output.push_str("const min = (...a) => a.length === 1 && Array.isArray(a[0]) ? Math.min(...a[0]) : Math.min(...a);\n");
-        output.push_str("const max = (...a) => a.length === 1 && Array.isArray(a[0]) ? Math.max(...a[0]) : Math.max(...a);\n");
+        output.push_str("const __extreme = async (name, sign, args) => {\n");
+        output.push_str("  const [values, kw] = __splitKwargs(args);\n");
+        output.push_str("  const items = values.length === 1 ? (__isDict(values[0]) || values[0] instanceof Map ? keys(values[0]) : Array.from(values[0])) : values;\n");
+        output.push_str("  if (items.length === 0) {\n");
+        output.push_str("    if (\"default\" in kw) return kw.default;\n");
+        output.push_str("    throw new ValueError(`${name}() arg is an empty sequence`);\n");
+        output.push_str("  }\n");
+        output.push_str("  const keys = kw.key ? await Promise.all(items.map((x) => kw.key(x))) : items;\n");
+        output.push_str("  let best = 0;\n");
+        output.push_str("  for (let i = 1; i < items.length; i++) if (sign * __compare(keys[i], keys[best]) > 0) best = i;\n");
+        output.push_str("  return items[best];\n");
+        output.push_str("};\n");
+        output.push_str("const min = (...args) => __extreme(\"min\", -1, args);\n");
+        output.push_str("const max = (...args) => __extreme(\"max\", 1, args);\n");
         output.push_str("const enumerate = (arr) => arr.map((v, i) => [i, v]);\n");
         output.push_str("const zip = (...arrays) => arrays[0].map((_, i) => arrays.map(a => a[i]));\n");
         output.push_str("const any = (arr) => arr.some(Boolean);\n");
         output.push_str("const all = (arr) => arr.every(Boolean);\n");
-        output.push_str("const keys = (obj) => Object.keys(obj);\n");
-        output.push_str("const values = (obj) => Object.values(obj);\n");
-        output.push_str("const items = (obj) => Object.entries(obj);\n");
+        output.push_str("const keys = (obj) => (obj instanceof Map ? [...Map.prototype.keys.call(obj)] : Object.keys(obj));\n");
+        output.push_str("const values = (obj) => (obj instanceof Map ? [...Map.prototype.values.call(obj)] : Object.values(obj));\n");
+        output.push_str("const items = (obj) => (obj instanceof Map ? [...Map.prototype.entries.call(obj)] : Object.entries(obj));\n");
         output.push_str("const isinstance = (obj, cls) => obj instanceof cls;\n");
         output.push_str("const chr = (n) => String.fromCharCode(n);\n");
-        output.push_str("const ord = (c) => c.charCodeAt(0);\n\n");
+        output.push_str("const ord = (c) => c.charCodeAt(0);\n");
+        // Reshaping data: unique keeps the first of each value; count_by and
+        // group_by make a dict keyed by what `key` returns (a dict() one if a
+        // key isn't a string); flatten joins one level of nested lists; chunk
+        // splits a list into lists of n
+        output.push_str("const unique = async (items) => [...new Set(await __iter(items))];\n");
+        output.push_str("const __keyed = async (items, args, add) => {\n");
+        output.push_str("  const [[given], kw] = __splitKwargs(args);\n");
+        output.push_str("  const key = given ?? kw.key ?? ((x) => x);\n");
+        output.push_str("  const groups = new Map();\n");
+        output.push_str("  for (const item of await __iter(items)) {\n");
+        output.push_str("    const k = await key(item);\n");
+        output.push_str("    groups.set(k, add(groups.get(k), item));\n");
+        output.push_str("  }\n");
+        output.push_str("  return [...groups.keys()].every((k) => typeof k === \"string\") ? Object.fromEntries(groups) : new __Dict(groups);\n");
+        output.push_str("};\n");
+        output.push_str("const count_by = (items, ...args) => __keyed(items, args, (n = 0) => n + 1);\n");
+        output.push_str("const group_by = (items, ...args) => __keyed(items, args, (list = [], item) => (list.push(item), list));\n");
+        output.push_str("const flatten = async (items) => Array.from(await __iter(items)).flatMap((x) => (Array.isArray(x) ? x : [x]));\n");
+        output.push_str("const chunk = async (items, n) => {\n");
+        output.push_str("  if (!Number.isInteger(n) || n < 1) throw new ValueError(`chunk size must be a positive whole number, not ${n}`);\n");
+        output.push_str("  const list = Array.from(await __iter(items));\n");
+        output.push_str("  const chunks = [];\n");
+        output.push_str("  for (let i = 0; i < list.length; i += n) chunks.push(list.slice(i, i + n));\n");
+        output.push_str("  return chunks;\n");
+        output.push_str("};\n\n");
 
+        // math module
+        output.push_str("const math = {\n");
+        output.push_str("  pi: Math.PI, e: Math.E, tau: 2 * Math.PI, inf: Infinity, nan: NaN,\n");
+        output.push_str("  sqrt: (x) => {\n");
+        output.push_str("    if (x < 0) throw new ValueError(\"math domain error\");\n");
+        output.push_str("    return Math.sqrt(x);\n");
+        output.push_str("  },\n");
+        output.push_str("  floor: Math.floor, ceil: Math.ceil, trunc: Math.trunc, fabs: Math.abs,\n");
+        output.push_str("  pow: Math.pow, exp: Math.exp, hypot: Math.hypot,\n");
+        output.push_str("  log: (x, base) => {\n");
+        output.push_str("    if (x <= 0) throw new ValueError(\"math domain error\");\n");
+        output.push_str("    return base === undefined ? Math.log(x) : Math.log(x) / Math.log(base);\n");
+        output.push_str("  },\n");
+        output.push_str("  log2: Math.log2, log10: Math.log10,\n");
+        output.push_str("  sin: Math.sin, cos: Math.cos, tan: Math.tan,\n");
+        output.push_str("  asin: Math.asin, acos: Math.acos, atan: Math.atan, atan2: Math.atan2,\n");
+        output.push_str("  degrees: (x) => (x * 180) / Math.PI, radians: (x) => (x * Math.PI) / 180,\n");
+        output.push_str("  isnan: Number.isNaN, isinf: (x) => x === Infinity || x === -Infinity, isfinite: Number.isFinite,\n");
+        output.push_str("  gcd: (...xs) => xs.reduce((a, b) => { a = Math.abs(a); b = Math.abs(b); while (b) [a, b] = [b, a % b]; return a; }, 0),\n");
+        output.push_str("  factorial: (n) => {\n");
+        output.push_str("    if (!Number.isInteger(n) || n < 0) throw new ValueError(\"factorial() not defined for negative or non-integer values\");\n");
+        output.push_str("    let r = 1;\n");
+        output.push_str("    for (let i = 2; i <= n; i++) r *= i;\n");
+        output.push_str("    return r;\n");
+        output.push_str("  },\n");
+        output.push_str("  isclose: (a, b, ...args) => {\n");
+        output.push_str("    const { rel_tol = 1e-9, abs_tol = 0 } = __splitKwargs(args)[1];\n");
+        output.push_str("    return Math.abs(a - b) <= Math.max(rel_tol * Math.max(Math.abs(a), Math.abs(b)), abs_tol);\n");
+        output.push_str("  },\n");
+        output.push_str("};\n\n");
+        // Path manipulation; path.ext("a/b.txt") is ".txt"
+        output.push_str("const path = {\n");
+        output.push_str("  join: (...parts) => __path.join(...parts.map(String)),\n");
+        output.push_str("  basename: (p) => __path.basename(p),\n");
+        output.push_str("  dirname: (p) => __path.dirname(p),\n");
+        output.push_str("  ext: (p) => __path.extname(p),\n");
+        output.push_str("  abs: (p) => __path.resolve(p),\n");
+        output.push_str("  sep: __path.sep,\n");
+        output.push_str("};\n\n");
+        // CSV: rows are dicts keyed by the header line; values are read as strings
+        output.push_str("const __csvParse = (text) => {\n");
+        output.push_str("  const records = [];\n");
+        output.push_str("  let record = [], field = \"\", quoted = false;\n");
+        output.push_str("  for (let i = 0; i < text.length; i++) {\n");
+        output.push_str("    const c = text[i];\n");
+        output.push_str("    if (quoted) {\n");
+        output.push_str("      if (c === '\"' && text[i + 1] === '\"') { field += '\"'; i++; }\n");
+        output.push_str("      else if (c === '\"') quoted = false;\n");
+        output.push_str("      else field += c;\n");
+        output.push_str("    } else if (c === '\"') quoted = true;\n");
+        output.push_str("    else if (c === \",\") { record.push(field); field = \"\"; }\n");
+        output.push_str("    else if (c === \"\\n\" || c === \"\\r\") {\n");
+        output.push_str("      if (c === \"\\r\" && text[i + 1] === \"\\n\") i++;\n");
+        output.push_str("      record.push(field);\n");
+        output.push_str("      records.push(record);\n");
+        output.push_str("      record = [];\n");
+        output.push_str("      field = \"\";\n");
+        output.push_str("    } else field += c;\n");
+        output.push_str("  }\n");
+        output.push_str("  if (field !== \"\" || record.length) records.push([...record, field]);\n");
+        output.push_str("  const [header = [], ...rows] = records.filter((r) => r.length > 1 || r[0] !== \"\");\n");
+        output.push_str("  return rows.map((r) => Object.fromEntries(header.map((name, i) => [name, r[i] ?? \"\"])));\n");
+        output.push_str("};\n");
+        output.push_str("const __csvField = (value) => {\n");
+        output.push_str("  const text = value == null ? \"\" : String(value);\n");
+        output.push_str("  return /[\",\\r\\n]/.test(text) ? `\"${text.replace(/\"/g, '\"\"')}\"` : text;\n");
+        output.push_str("};\n");
+        output.push_str("const __csvFormat = (rows) => {\n");
+        output.push_str("  const header = [...new Set(rows.flatMap((row) => Object.keys(row)))];\n");
+        output.push_str("  const lines = [header, ...rows.map((row) => header.map((name) => row[name]))];\n");
+        output.push_str("  return lines.map((line) => line.map(__csvField).join(\",\")).join(\"\\n\") + \"\\n\";\n");
+        output.push_str("};\n");
+        output.push_str("const csv = {\n");
+        output.push_str("  read: async (path) => __csvParse(await __fs.readFile(path, \"utf-8\")),\n");
+        output.push_str("  write: (path, rows) => __fs.writeFile(path, __csvFormat(rows)),\n");
+        output.push_str("  parse: __csvParse,\n");
+        output.push_str("  format: __csvFormat,\n");
+        output.push_str("};\n\n");
+        // Process environment: env.get("PORT", "8080") or env.PORT as text, or
+        // env("PORT", 8080), which converts to the default's type: a number
+        // default gives a number (ValueError if it isn't one), a bool default
+        // reads 1/true/yes/on as True
+        output.push_str("const __envValue = (name, fallback = null) => {\n");
+        output.push_str("  const value = process.env[name];\n");
+        output.push_str("  if (value === undefined) return fallback;\n");
+        output.push_str("  if (typeof fallback === \"number\") {\n");
+        output.push_str("    if (value.trim() === \"\" || !Number.isFinite(Number(value))) throw new ValueError(`environment variable ${name} should be a number, not ${JSON.stringify(value)}`);\n");
+        output.push_str("    return Number(value);\n");
+        output.push_str("  }\n");
+        output.push_str("  if (typeof fallback === \"boolean\") return [\"1\", \"true\", \"yes\", \"on\"].includes(value.trim().toLowerCase());\n");
+        output.push_str("  return value;\n");
+        output.push_str("};\n");
+        output.push_str("const env = new Proxy(__envValue, {\n");
+        output.push_str("  get: (_, name) => (name === \"get\" ? (key, fallback = null) => process.env[key] ?? fallback : process.env[name]),\n");
+        output.push_str("  has: (_, name) => name in process.env,\n");
+        output.push_str("  ownKeys: () => Reflect.ownKeys(process.env),\n");
+        output.push_str("  getOwnPropertyDescriptor: (_, name) => Object.getOwnPropertyDescriptor(process.env, name),\n");
+        output.push_str("  getPrototypeOf: () => Object.prototype,\n");
+        output.push_str("});\n");
+        output.push_str("const os = {\n");
+        output.push_str("  // Like Python's sys.argv: the script path, then its arguments\n");
+        output.push_str("  args: process.argv.slice(1),\n");
+        output.push_str("  platform: process.platform,\n");
+        output.push_str("  cwd: () => process.cwd(),\n");
+        output.push_str("};\n");
+        output.push_str("const exit = (code = 0) => process.exit(code);\n\n");
+        // Hashing, HMAC and random tokens; digests are hex strings
+        output.push_str("const __bytesOf = (data) => (Buffer.isBuffer(data) ? data : Buffer.from(String(data)));\n");
+        output.push_str("const crypto = {\n");
+        output.push_str("  hash: (data, algorithm = \"sha256\") => __crypto.createHash(algorithm).update(__bytesOf(data)).digest(\"hex\"),\n");
+        output.push_str("  sha256: (data) => crypto.hash(data, \"sha256\"),\n");
+        output.push_str("  sha1: (data) => crypto.hash(data, \"sha1\"),\n");
+        output.push_str("  md5: (data) => crypto.hash(data, \"md5\"),\n");
+        output.push_str("  hmac: (key, data, algorithm = \"sha256\") => __crypto.createHmac(algorithm, __bytesOf(key)).update(__bytesOf(data)).digest(\"hex\"),\n");
+        output.push_str("  random_bytes: (n) => __crypto.randomBytes(n),\n");
+        output.push_str("  token_hex: (n = 32) => __crypto.randomBytes(n).toString(\"hex\"),\n");
+        output.push_str("  // Constant-time comparison, for checking signatures\n");
+        output.push_str("  safe_equal: (a, b) => __safeEqual(a, b),\n");
+        output.push_str("};\n");
+        output.push_str("const base64 = {\n");
+        output.push_str("  encode: (data) => __bytesOf(data).toString(\"base64\"),\n");
+        output.push_str("  decode: (text) => Buffer.from(String(text), \"base64\").toString(\"utf-8\"),\n");
+        output.push_str("  decode_bytes: (text) => Buffer.from(String(text), \"base64\"),\n");
+        output.push_str("  urlsafe_encode: (data) => __bytesOf(data).toString(\"base64url\"),\n");
+        output.push_str("  urlsafe_decode: (text) => Buffer.from(String(text), \"base64url\").toString(\"utf-8\"),\n");
+        output.push_str("  urlsafe_decode_bytes: (text) => Buffer.from(String(text), \"base64url\"),\n");
+        output.push_str("};\n");
+        output.push_str("const uuid = () => __crypto.randomUUID();\n\n");
+        // Shell commands: shell.run(cmd) collects output; shell.stream(cmd) passes it
+        // through (or line by line to a callback). check=True raises on failure
+        output.push_str("const __shellFailed = (cmd, code) =>\n");
+        output.push_str("  new ShellError(`Command failed with exit code ${code}: ${cmd}`, { code });\n");
+        output.push_str("const shell = {\n");
+        output.push_str("  run: (cmd, ...args) => new Promise((resolve, reject) => {\n");
+        output.push_str("    const { cwd, check = false } = __splitKwargs(args)[1];\n");
+        output.push_str("    const child = __childProcess.spawn(cmd, { shell: true, cwd });\n");
+        output.push_str("    let stdout = \"\", stderr = \"\";\n");
+        output.push_str("    child.stdout.on(\"data\", (chunk) => { stdout += chunk; });\n");
+        output.push_str("    child.stderr.on(\"data\", (chunk) => { stderr += chunk; });\n");
+        output.push_str("    child.on(\"error\", reject);\n");
+        output.push_str("    child.on(\"close\", (code) => {\n");
+        output.push_str("      if (check && code !== 0) reject(__shellFailed(cmd, code));\n");
+        output.push_str("      else resolve({ code, stdout, stderr, ok: code === 0 });\n");
+        output.push_str("    });\n");
+        output.push_str("  }),\n");
+        output.push_str("  stream: (cmd, ...args) => new Promise((resolve, reject) => {\n");
+        output.push_str("    const [[onLine], { cwd, check = false }] = __splitKwargs(args);\n");
+        output.push_str("    const child = __childProcess.spawn(cmd, { shell: true, cwd, stdio: onLine ? [\"inherit\", \"pipe\", \"pipe\"] : \"inherit\" });\n");
+        output.push_str("    let pending = Promise.resolve();\n");
+        output.push_str("    if (onLine) {\n");
+        output.push_str("      for (const source of [child.stdout, child.stderr]) {\n");
+        output.push_str("        readline.createInterface({ input: source }).on(\"line\", (line) => {\n");
+        output.push_str("          pending = pending.then(() => onLine(line));\n");
+        output.push_str("        });\n");
+        output.push_str("      }\n");
+        output.push_str("    }\n");
+        output.push_str("    child.on(\"error\", reject);\n");
+        output.push_str("    child.on(\"close\", async (code) => {\n");
+        output.push_str("      await pending;\n");
+        output.push_str("      if (check && code !== 0) reject(__shellFailed(cmd, code));\n");
+        output.push_str("      else resolve(code);\n");
+        output.push_str("    });\n");
+        output.push_str("  }),\n");
+        output.push_str("};\n\n");
         // range() function
         output.push_str("const range = (...args) => {\n");
         output.push_str("  let start = 0, end, step = 1;\n");
@@ -60,102 +621,1218 @@ impl CodeGen {
         output.push_str("  return r;\n");
         output.push_str("};\n\n");
 
-        // input() function
-        output.push_str("const input = (msg) => new Promise(resolve => {\n");
-        output.push_str("  const rl = readline.createInterface({ input: process.stdin, output: process.stdout });\n");
-        output.push_str("  rl.question(String(msg || ''), ans => {\n");
-        output.push_str("    rl.close();\n");
-        output.push_str("    resolve(ans);\n");
-        output.push_str("  });\n");
-        output.push_str("});\n\n");
-
-        // Membership test helper for 'in' / 'not in'
+        // Standard input, read a line at a time by one shared reader, so lines
+        // piped in aren't lost between calls. It only reads while something is
+        // waiting, and gives null once the input ends
+        output.push_str("const __stdin = {\n");
+        output.push_str("  lines: [],\n");
+        output.push_str("  waiting: [],\n");
+        output.push_str("  ended: false,\n");
+        output.push_str("  reader: null,\n");
+        output.push_str("  next() {\n");
+        output.push_str("    if (this.lines.length) return Promise.resolve(this.lines.shift());\n");
+        output.push_str("    if (this.ended) return Promise.resolve(null);\n");
+        output.push_str("    if (!this.reader) {\n");
+        output.push_str("      this.reader = readline.createInterface({ input: process.stdin, terminal: false });\n");
+        output.push_str("      this.reader.on(\"line\", (line) => {\n");
+        output.push_str("        const resolve = this.waiting.shift();\n");
+        output.push_str("        if (resolve) resolve(line);\n");
+        output.push_str("        else this.lines.push(line);\n");
+        output.push_str("        if (!this.waiting.length) this.reader.pause();\n");
+        output.push_str("      });\n");
+        output.push_str("      this.reader.on(\"close\", () => {\n");
+        output.push_str("        this.ended = true;\n");
+        output.push_str("        for (const resolve of this.waiting.splice(0)) resolve(null);\n");
+        output.push_str("      });\n");
+        output.push_str("    }\n");
+        output.push_str("    this.reader.resume();\n");
+        output.push_str("    return new Promise((resolve) => this.waiting.push(resolve));\n");
+        output.push_str("  },\n");
+        output.push_str("};\n");
+        // input(prompt) reads a line (None at the end of input); input.int and
+        // input.float ask again at a terminal until the answer parses, and
+        // raise ValueError for piped input; input.all and input.lines take the
+        // rest of stdin
+        output.push_str("const input = (msg = \"\") => {\n");
+        output.push_str("  if (msg !== \"\" && msg != null) process.stdout.write(String(msg));\n");
+        output.push_str("  return __stdin.next();\n");
+        output.push_str("};\n");
+        output.push_str("const __inputNumber = (parse, what) => async (msg = \"\") => {\n");
+        output.push_str("  for (;;) {\n");
+        output.push_str("    const text = await input(msg);\n");
+        output.push_str("    if (text === null) throw new ValueError(`expected ${what}, but the input ended`);\n");
+        output.push_str("    const value = parse(text.trim());\n");
+        output.push_str("    if (value !== null) return value;\n");
+        output.push_str("    if (!process.stdin.isTTY) throw new ValueError(`expected ${what}, got ${JSON.stringify(text)}`);\n");
+        output.push_str("    process.stdout.write(`Please enter ${what}.\\n`);\n");
+        output.push_str("  }\n");
+        output.push_str("};\n");
+        output.push_str("input.int = __inputNumber((text) => (/^[+-]?\\d+$/.test(text) ? Number(text) : null), \"a whole number\");\n");
+        output.push_str("input.float = __inputNumber((text) => (text !== \"\" && Number.isFinite(Number(text)) ? Number(text) : null), \"a number\");\n");
+        output.push_str("input.lines = async () => {\n");
+        output.push_str("  const lines = [];\n");
+        output.push_str("  for (let line; (line = await __stdin.next()) !== null;) lines.push(line);\n");
+        output.push_str("  return lines;\n");
+        output.push_str("};\n");
+        output.push_str("input.all = async () => (await input.lines()).join(\"\\n\");\n\n");
+
+        // Python string methods. Calls to these names go through __method, so
+        // strings get Python semantics while other objects keep their own methods
+        output.push_str("const __stripChars = (s, chars, left, right) => {\n");
+        output.push_str("  const strip = chars == null ? /\\s/ : { test: (c) => chars.includes(c) };\n");
+        output.push_str("  let start = 0, end = s.length;\n");
+        output.push_str("  while (left && start < end && strip.test(s[start])) start++;\n");
+        output.push_str("  while (right && end > start && strip.test(s[end - 1])) end--;\n");
+        output.push_str("  return s.slice(start, end);\n");
+        output.push_str("};\n");
+        output.push_str("const __strMethods = {\n");
+        output.push_str("  strip: (s, chars) => __stripChars(s, chars, true, true),\n");
+        output.push_str("  lstrip: (s, chars) => __stripChars(s, chars, true, false),\n");
+        output.push_str("  rstrip: (s, chars) => __stripChars(s, chars, false, true),\n");
+        output.push_str("  split: (s, sep, maxsplit = -1) => {\n");
+        output.push_str("    const parts = sep == null ? s.trim().split(/\\s+/).filter(Boolean) : s.split(sep);\n");
+        output.push_str("    if (maxsplit < 0 || parts.length <= maxsplit + 1) return parts;\n");
+        output.push_str("    const head = parts.slice(0, maxsplit);\n");
+        output.push_str("    const rest = sep == null ? s.trim().split(/\\s+/).slice(maxsplit).join(\" \") : parts.slice(maxsplit).join(sep);\n");
+        output.push_str("    return [...head, rest];\n");
+        output.push_str("  },\n");
+        output.push_str("  join: (s, items) => Array.from(items).join(s),\n");
+        output.push_str("  replace: (s, old, replacement, count = -1) => {\n");
+        output.push_str("    const parts = s.split(old);\n");
+        output.push_str("    if (count < 0 || parts.length <= count + 1) return parts.join(replacement);\n");
+        output.push_str("    return parts.slice(0, count + 1).join(replacement) + old + parts.slice(count + 1).join(old);\n");
+        output.push_str("  },\n");
+        output.push_str("  startswith: (s, prefix, start = 0) => [].concat(prefix).some((p) => s.startsWith(p, start)),\n");
+        output.push_str("  endswith: (s, suffix) => [].concat(suffix).some((p) => s.endsWith(p)),\n");
+        output.push_str("  upper: (s) => s.toUpperCase(),\n");
+        output.push_str("  lower: (s) => s.toLowerCase(),\n");
+        output.push_str("  find: (s, sub, start = 0) => s.indexOf(sub, start),\n");
+        output.push_str("  count: (s, sub) => (sub === \"\" ? s.length + 1 : s.split(sub).length - 1),\n");
+        output.push_str("  zfill: (s, width) => {\n");
+        output.push_str("    const sign = s[0] === \"-\" || s[0] === \"+\" ? s[0] : \"\";\n");
+        output.push_str("    return sign + s.slice(sign.length).padStart(width - sign.length, \"0\");\n");
+        output.push_str("  },\n");
+        output.push_str("  title: (s) => s.toLowerCase().replace(/[a-z]+/gi, (w) => w[0].toUpperCase() + w.slice(1)),\n");
+        output.push_str("  index: (s, sub, start = 0) => {\n");
+        output.push_str("    const i = s.indexOf(sub, start);\n");
+        output.push_str("    if (i === -1) throw new ValueError(\"substring not found\");\n");
+        output.push_str("    return i;\n");
+        output.push_str("  },\n");
+        output.push_str("};\n");
+        // Keyword arguments (`f(x, key=g)`) arrive as one trailing __kw({...}) object
+        output.push_str("class __Kwargs {\n");
+        output.push_str("  constructor(values) { Object.assign(this, values); }\n");
+        output.push_str("}\n");
+        output.push_str("const __kw = (values) => new __Kwargs(values);\n");
+        output.push_str("const __splitKwargs = (args) => {\n");
+        output.push_str("  const last = args[args.length - 1];\n");
+        output.push_str("  return last instanceof __Kwargs ? [args.slice(0, -1), last] : [args, {}];\n");
+        output.push_str("};\n");
+        // Python ordering: numbers numerically, strings by code point, lists element-wise
+        output.push_str("const __compare = (a, b) => {\n");
+        output.push_str("  if (Array.isArray(a) && Array.isArray(b)) {\n");
+        output.push_str("    for (let i = 0; i < Math.min(a.length, b.length); i++) {\n");
+        output.push_str("      const c = __compare(a[i], b[i]);\n");
+        output.push_str("      if (c !== 0) return c;\n");
+        output.push_str("    }\n");
+        output.push_str("    return a.length - b.length;\n");
+        output.push_str("  }\n");
+        output.push_str("  if (typeof a === \"number\" && typeof b === \"number\") return a - b;\n");
+        output.push_str("  return a < b ? -1 : a > b ? 1 : 0;\n");
+        output.push_str("};\n");
+        output.push_str("const __listIndex = (list, item) => {\n");
+        output.push_str("  const i = list.indexOf(item);\n");
+        output.push_str("  if (i === -1) throw new ValueError(`${JSON.stringify(item)} is not in list`);\n");
+        output.push_str("  return i;\n");
+        output.push_str("};\n");
+        output.push_str("const __listMethods = {\n");
+        output.push_str("  append: (list, item) => { list.push(item); return null; },\n");
+        output.push_str("  pop: (list, i = -1) => {\n");
+        output.push_str("    if (list.length === 0) throw new IndexError(\"pop from empty list\");\n");
+        output.push_str("    const at = i < 0 ? list.length + i : i;\n");
+        output.push_str("    if (at < 0 || at >= list.length) throw new IndexError(\"pop index out of range\");\n");
+        output.push_str("    return list.splice(at, 1)[0];\n");
+        output.push_str("  },\n");
+        output.push_str("  insert: (list, i, item) => { list.splice(i < 0 ? Math.max(0, list.length + i) : i, 0, item); return null; },\n");
+        output.push_str("  remove: (list, item) => { list.splice(__listIndex(list, item), 1); return null; },\n");
+        output.push_str("  index: __listIndex,\n");
+        output.push_str("  count: (list, item) => list.filter((x) => x === item).length,\n");
+        output.push_str("  extend: (list, items) => { list.push(...items); return null; },\n");
+        output.push_str("  clear: (list) => { list.length = 0; return null; },\n");
+        output.push_str("  copy: (list) => list.slice(),\n");
+        output.push_str("  sort: async (list, ...args) => {\n");
+        output.push_str("    const { key, reverse = false } = __splitKwargs(args)[1];\n");
+        output.push_str("    const keys = key ? await Promise.all(list.map((x) => key(x))) : list;\n");
+        output.push_str("    const sign = reverse ? -1 : 1;\n");
+        output.push_str("    const order = list.map((_, i) => i).sort((i, j) => sign * __compare(keys[i], keys[j]) || i - j);\n");
+        output.push_str("    const sorted = order.map((i) => list[i]);\n");
+        output.push_str("    sorted.forEach((x, i) => { list[i] = x; });\n");
+        output.push_str("    return null;\n");
+        output.push_str("  },\n");
+        output.push_str("};\n");
+        // Dict methods apply to plain objects only, so class instances keep their own
+        output.push_str("const __isDict = (obj) => obj === env || obj !== null && typeof obj === \"object\"\n");
+        output.push_str("  && [Object.prototype, null].includes(Object.getPrototypeOf(obj));\n");
+        output.push_str("const __dictMethods = {\n");
+        output.push_str("  get: (d, key, fallback = null) => (Object.hasOwn(d, key) ? d[key] : fallback),\n");
+        output.push_str("  pop: (d, key, ...fallback) => {\n");
+        output.push_str("    if (!Object.hasOwn(d, key)) {\n");
+        output.push_str("      if (fallback.length) return fallback[0];\n");
+        output.push_str("      throw new KeyError(JSON.stringify(key));\n");
+        output.push_str("    }\n");
+        output.push_str("    const value = d[key];\n");
+        output.push_str("    delete d[key];\n");
+        output.push_str("    return value;\n");
+        output.push_str("  },\n");
+        output.push_str("  update: (d, other) => { Object.assign(d, other); return null; },\n");
+        output.push_str("  setdefault: (d, key, value = null) => {\n");
+        output.push_str("    if (!Object.hasOwn(d, key)) d[key] = value;\n");
+        output.push_str("    return d[key];\n");
+        output.push_str("  },\n");
+        output.push_str("  clear: (d) => { for (const key of Object.keys(d)) delete d[key]; return null; },\n");
+        output.push_str("  copy: (d) => ({ ...d }),\n");
+        output.push_str("};\n");
+        // Methods of bytes; the rest of Buffer's own (slice, ...) work as they are
+        output.push_str("const __bytesMethods = {\n");
+        output.push_str("  decode: (b, encoding = \"utf-8\") => b.toString(__encoding(encoding)),\n");
+        output.push_str("  hex: (b) => b.toString(\"hex\"),\n");
+        output.push_str("  join: (b, parts) => Buffer.concat(Array.from(parts).flatMap((part, i) => (i ? [b, __bytesOf(part)] : [__bytesOf(part)]))),\n");
+        output.push_str("  find: (b, sub) => b.indexOf(__bytesOf(sub)),\n");
+        output.push_str("  startswith: (b, prefix) => b.subarray(0, __bytesOf(prefix).length).equals(__bytesOf(prefix)),\n");
+        output.push_str("  endswith: (b, suffix) => __bytesOf(suffix).length <= b.length && b.subarray(b.length - __bytesOf(suffix).length).equals(__bytesOf(suffix)),\n");
+        output.push_str("};\n");
+        output.push_str("const __method = (obj, name, ...args) => {\n");
+        output.push_str("  if (typeof obj === \"string\" && name in __strMethods) return __strMethods[name](obj, ...args);\n");
+        output.push_str("  if (Array.isArray(obj) && name in __listMethods) return __listMethods[name](obj, ...args);\n");
+        output.push_str("  if (Buffer.isBuffer(obj) && name in __bytesMethods) return __bytesMethods[name](obj, ...args);\n");
+        output.push_str("  if (__isDict(obj) && name in __dictMethods && typeof obj[name] !== \"function\") return __dictMethods[name](obj, ...args);\n");
+        output.push_str("  if (obj instanceof __HttpClient) return obj[name](...args);\n");
+        output.push_str("  return obj[name](...__splitKwargs(args)[0]);\n");
+        output.push_str("};\n");
+        // dict(): a dict backed by a Map, so keys keep their type (1 and "1"
+        // differ) and insertion order. Literals with a non-string key make one
+        output.push_str("const __pairs = (source) => (__isDict(source) ? Object.entries(source) : source instanceof Map ? source.entries() : source);\n");
+        output.push_str("const __Dict = class Dict extends Map {\n");
+        output.push_str("  get(key, fallback = null) { return this.has(key) ? super.get(key) : fallback; }\n");
+        output.push_str("  pop(key, ...fallback) {\n");
+        output.push_str("    if (!this.has(key)) {\n");
+        output.push_str("      if (fallback.length) return fallback[0];\n");
+        output.push_str("      throw new KeyError(JSON.stringify(key) ?? String(key));\n");
+        output.push_str("    }\n");
+        output.push_str("    const value = super.get(key);\n");
+        output.push_str("    this.delete(key);\n");
+        output.push_str("    return value;\n");
+        output.push_str("  }\n");
+        output.push_str("  update(other) { for (const [k, v] of __pairs(other)) this.set(k, v); return null; }\n");
+        output.push_str("  setdefault(key, value = null) {\n");
+        output.push_str("    if (!this.has(key)) this.set(key, value);\n");
+        output.push_str("    return super.get(key);\n");
+        output.push_str("  }\n");
+        output.push_str("  clear() { super.clear(); return null; }\n");
+        output.push_str("  copy() { return new __Dict(this); }\n");
+        output.push_str("  keys() { return [...super.keys()]; }\n");
+        output.push_str("  values() { return [...super.values()]; }\n");
+        output.push_str("  items() { return [...super.entries()]; }\n");
+        // JSON has only string keys, as with Python's json.dumps
+        output.push_str("  toJSON() { return Object.fromEntries(super.entries()); }\n");
+        output.push_str("};\n");
+        output.push_str("const dict = (...args) => {\n");
+        output.push_str("  const [[source], kw] = __splitKwargs(args);\n");
+        output.push_str("  const d = new __Dict(source == null ? [] : __pairs(source));\n");
+        output.push_str("  for (const [k, v] of Object.entries(kw)) d.set(k, v);\n");
+        output.push_str("  return d;\n");
+        output.push_str("};\n");
+        // Indexing, which for a Map means its entries
+        output.push_str("const __getitem = (obj, key) => {\n");
+        output.push_str("  if (!(obj instanceof Map)) return obj[key];\n");
+        output.push_str("  if (!obj.has(key)) throw new KeyError(JSON.stringify(key) ?? String(key));\n");
+        output.push_str("  return Map.prototype.get.call(obj, key);\n");
+        output.push_str("};\n");
+        output.push_str("const __setitem = (obj, key, value) => {\n");
+        output.push_str("  if (obj instanceof Map) obj.set(key, value);\n");
+        output.push_str("  else obj[key] = value;\n");
+        output.push_str("};\n");
+        // copy/deepcopy, as in Python's copy module: class instances keep their
+        // class, deepcopy copies shared and cyclic references once, and dates,
+        // buffers and the like go through structuredClone
+        output.push_str("const __cloneValue = (x) => {\n");
+        output.push_str("  try { return Object.setPrototypeOf(structuredClone(x), Object.getPrototypeOf(x)); } catch { return x; }\n");
+        output.push_str("};\n");
+        output.push_str("const __isValue = (x) => ArrayBuffer.isView(x) || x instanceof Date || x instanceof RegExp;\n");
+        output.push_str("const copy = (x) => {\n");
+        output.push_str("  if (x === null || typeof x !== \"object\") return x;\n");
+        output.push_str("  if (Array.isArray(x)) return x.slice();\n");
+        // dict()'s Map keeps its class, and with it get()/keys() that act like Python's
+        output.push_str("  if (x instanceof Map) return new x.constructor(x);\n");
+        output.push_str("  if (x instanceof Set) return new Set(x);\n");
+        output.push_str("  if (__isValue(x)) return __cloneValue(x);\n");
+        output.push_str("  return Object.assign(Object.create(Object.getPrototypeOf(x)), x);\n");
+        output.push_str("};\n");
+        output.push_str("const deepcopy = (x, seen = new Map()) => {\n");
+        output.push_str("  if (x === null || typeof x !== \"object\") return x;\n");
+        output.push_str("  if (seen.has(x)) return seen.get(x);\n");
+        output.push_str("  if (__isValue(x)) return __cloneValue(x);\n");
+        output.push_str("  let out;\n");
+        output.push_str("  if (Array.isArray(x)) {\n");
+        output.push_str("    seen.set(x, out = []);\n");
+        output.push_str("    for (const item of x) out.push(deepcopy(item, seen));\n");
+        output.push_str("  } else if (x instanceof Map) {\n");
+        output.push_str("    seen.set(x, out = new x.constructor());\n");
+        output.push_str("    for (const [k, v] of x) out.set(deepcopy(k, seen), deepcopy(v, seen));\n");
+        output.push_str("  } else if (x instanceof Set) {\n");
+        output.push_str("    seen.set(x, out = new Set());\n");
+        output.push_str("    for (const v of x) out.add(deepcopy(v, seen));\n");
+        output.push_str("  } else {\n");
+        output.push_str("    seen.set(x, out = Object.create(Object.getPrototypeOf(x)));\n");
+        output.push_str("    for (const key of Object.keys(x)) out[key] = deepcopy(x[key], seen);\n");
+        output.push_str("  }\n");
+        output.push_str("  return out;\n");
+        output.push_str("};\n\n");
+        // What `for` loops over: lists, strings and sets as they are, a dict's
+        // or Map's keys, and classes with __iter__ (giving a list, or an
+        // iterator whose __next__ raises StopIteration at the end) or values()
+        output.push_str("const __typeName = (x) => {\n");
+        output.push_str("  if (x == null) return \"NoneType\";\n");
+        output.push_str("  if (Array.isArray(x)) return \"list\";\n");
+        output.push_str("  if (__isDict(x) || x instanceof Map) return \"dict\";\n");
+        output.push_str("  if (x instanceof Set) return \"set\";\n");
+        output.push_str("  return { number: Number.isInteger(x) ? \"int\" : \"float\", bigint: \"int\", boolean: \"bool\", string: \"str\" }[typeof x] ?? x.constructor?.name ?? \"object\";\n");
+        output.push_str("};\n");
+        output.push_str("const __iter = async (x) => {\n");
+        output.push_str("  if (typeof x === \"string\" || Array.isArray(x) || x instanceof Set) return x;\n");
+        output.push_str("  if (x instanceof Map) return x.keys();\n");
+        output.push_str("  if (__isDict(x)) return Object.keys(x);\n");
+        output.push_str("  if (x == null || typeof x !== \"object\") throw new TypeError(`'${__typeName(x)}' object is not iterable`);\n");
+        output.push_str("  if (typeof x.__iter__ === \"function\") {\n");
+        output.push_str("    const it = await x.__iter__();\n");
+        output.push_str("    if (it !== x) return __iter(it);\n");
+        output.push_str("  }\n");
+        output.push_str("  if (typeof x.__next__ === \"function\") {\n");
+        output.push_str("    const items = [];\n");
+        output.push_str("    for (;;) {\n");
+        output.push_str("      try {\n");
+        output.push_str("        items.push(await x.__next__());\n");
+        output.push_str("      } catch (err) {\n");
+        output.push_str("        if (err instanceof StopIteration) return items;\n");
+        output.push_str("        throw err;\n");
+        output.push_str("      }\n");
+        output.push_str("    }\n");
+        output.push_str("  }\n");
+        output.push_str("  if (typeof x[Symbol.iterator] === \"function\") return x;\n");
+        output.push_str("  if (typeof x.values === \"function\") return __iter(await x.values());\n");
+        output.push_str("  throw new TypeError(`'${__typeName(x)}' object is not iterable`);\n");
+        output.push_str("};\n");
+        // What a constructor or @sync function can loop over without waiting
+        output.push_str("const __iterSync = (x) => {\n");
+        output.push_str("  if (x instanceof Map) return x.keys();\n");
+        output.push_str("  if (__isDict(x)) return Object.keys(x);\n");
+        output.push_str("  if (x != null && typeof x[Symbol.iterator] === \"function\") return x;\n");
+        output.push_str("  if (x != null && typeof x.__iter__ === \"function\") {\n");
+        output.push_str("    const it = x.__iter__();\n");
+        output.push_str("    if (it instanceof Promise) throw new TypeError(`'${__typeName(x)}'.__iter__ is async; mark it @sync to loop over it without waiting`);\n");
+        output.push_str("    if (it !== x) return __iterSync(it);\n");
+        output.push_str("  }\n");
+        output.push_str("  throw new TypeError(`'${__typeName(x)}' object is not iterable without waiting`);\n");
+        output.push_str("};\n\n");
+        // 'in' / 'not in', agreeing with what `for` loops over: a substring
+        // of a string, an item of a list or set, a key of a dict or Map, and
+        // for other objects __contains__ or else one of the items __iter gives
         output.push_str("const __contains = (container, item) => {\n");
+        output.push_str("  if (typeof container === \"string\") {\n");
+        output.push_str("    if (typeof item !== \"string\") throw new TypeError(`'in <string>' requires string as left operand, not ${__typeName(item)}`);\n");
+        output.push_str("    return container.includes(item);\n");
+        output.push_str("  }\n");
         output.push_str("  if (Array.isArray(container)) return container.includes(item);\n");
-        output.push_str("  if (typeof container === 'string') return container.includes(item);\n");
-        output.push_str("  if (typeof container === 'object' && container !== null) return item in container;\n");
+        output.push_str("  if (container instanceof Map || container instanceof Set) return container.has(item);\n");
+        output.push_str("  if (__isDict(container)) return Object.hasOwn(container, item);\n");
+        output.push_str("  if (container == null || typeof container !== \"object\") throw new TypeError(`argument of type '${__typeName(container)}' is not iterable`);\n");
+        output.push_str("  if (typeof container.__contains__ === \"function\") return container.__contains__(item);\n");
+        output.push_str("  return __iter(container).then((items) => {\n");
+        output.push_str("    for (const x of items) if (x === item) return true;\n");
+        output.push_str("    return false;\n");
+        output.push_str("  });\n");
+        output.push_str("};\n");
+        // The same where nothing can wait, which a Promise would pass as true
+        output.push_str("const __containsSync = (container, item) => {\n");
+        output.push_str("  if (container == null || typeof container !== \"object\" || Array.isArray(container) || container instanceof Map || container instanceof Set || __isDict(container)) return __contains(container, item);\n");
+        output.push_str("  if (typeof container.__contains__ === \"function\") {\n");
+        output.push_str("    const found = container.__contains__(item);\n");
+        output.push_str("    if (found instanceof Promise) throw new TypeError(`'${__typeName(container)}'.__contains__ is async; mark it @sync to use 'in' without waiting`);\n");
+        output.push_str("    return found;\n");
+        output.push_str("  }\n");
+        output.push_str("  for (const x of __iterSync(container)) if (x === item) return true;\n");
         output.push_str("  return false;\n");
         output.push_str("};\n\n");
 
+        // Background tasks: spawn starts without awaiting and logs failures;
+        // gather/wait_all wait for tasks and return their results in order
+        output.push_str("const __spawn = (fn) => {\n");
+        output.push_str("  const task = Promise.resolve().then(fn);\n");
+        output.push_str("  task.catch((err) => console.error(\"Background task failed:\", err));\n");
+        output.push_str("  return task;\n");
+        output.push_str("};\n");
+        output.push_str("const gather = (...tasks) => Promise.all(tasks.flat());\n");
+        output.push_str("const wait_all = gather;\n");
+        // sleep(seconds); retry(fn) calls fn until it succeeds, waiting `backoff`
+        // seconds after the first failure and twice as long after each one
+        // after that; with_timeout(task or fn, seconds) raises TimeoutError if
+        // the work takes longer, though the work itself carries on
+        output.push_str("const sleep = (seconds) => new Promise((resolve) => setTimeout(() => resolve(null), seconds * 1000));\n");
+        output.push_str("const retry = async (...args) => {\n");
+        output.push_str("  const [[fn, attempts = 3, backoff = 0.5], kw] = __splitKwargs(args);\n");
+        output.push_str("  const tries = kw.attempts ?? attempts, delay = kw.backoff ?? backoff;\n");
+        output.push_str("  if (!Number.isInteger(tries) || tries < 1) throw new ValueError(`retry attempts must be a positive whole number, not ${tries}`);\n");
+        output.push_str("  for (let attempt = 1; ; attempt++) {\n");
+        output.push_str("    try {\n");
+        output.push_str("      return await fn();\n");
+        output.push_str("    } catch (err) {\n");
+        output.push_str("      if (attempt >= tries) throw err;\n");
+        output.push_str("    }\n");
+        output.push_str("    await sleep(delay * 2 ** (attempt - 1));\n");
+        output.push_str("  }\n");
+        output.push_str("};\n");
+        output.push_str("const with_timeout = (...args) => {\n");
+        output.push_str("  const [[work, seconds], kw] = __splitKwargs(args);\n");
+        output.push_str("  const limit = kw.seconds ?? seconds;\n");
+        output.push_str("  const task = Promise.resolve().then(() => (typeof work === \"function\" ? work() : work));\n");
+        output.push_str("  let timer;\n");
+        output.push_str("  const timeout = new Promise((_, reject) => {\n");
+        output.push_str("    timer = setTimeout(() => reject(new TimeoutError(`Timed out after ${limit}s`)), limit * 1000);\n");
+        output.push_str("  });\n");
+        output.push_str("  return Promise.race([task, timeout]).finally(() => clearTimeout(timer));\n");
+        output.push_str("};\n\n");
+        // Scheduled tasks; a failed run is logged and the schedule continues
+        output.push_str("const __runScheduled = (fn) => Promise.resolve().then(fn).catch((err) => console.error(\"Scheduled task failed:\", err));\n");
+        output.push_str("const __every = (ms, fn) => setInterval(() => __runScheduled(fn), ms);\n");
+        output.push_str("const __cronField = (field, min, max) => {\n");
+        output.push_str("  const allowed = new Set();\n");
+        output.push_str("  for (const part of field.split(\",\")) {\n");
+        output.push_str("    const [range, step] = part.split(\"/\");\n");
+        output.push_str("    let [lo, hi] = range === \"*\" ? [min, max] : range.split(\"-\").map(Number);\n");
+        output.push_str("    if (hi === undefined) hi = step ? max : lo;\n");
+        output.push_str("    for (let v = lo; v <= hi; v += Number(step || 1)) allowed.add(v);\n");
+        output.push_str("  }\n");
+        output.push_str("  return allowed;\n");
+        output.push_str("};\n");
+        output.push_str("const __cron = (schedule, fn) => {\n");
+        output.push_str("  const fields = schedule.trim().split(/\\s+/);\n");
+        output.push_str("  const [minute, hour, day, month, weekday] = fields.map((f, i) => __cronField(f, [0, 0, 1, 1, 0][i], [59, 23, 31, 12, 7][i]));\n");
+        output.push_str("  if (weekday.has(7)) weekday.add(0);\n");
+        output.push_str("  // Like cron, a restricted day and weekday match if either does\n");
+        output.push_str("  const dayMatches = (t) => fields[2] !== \"*\" && fields[4] !== \"*\"\n");
+        output.push_str("    ? day.has(t.getDate()) || weekday.has(t.getDay())\n");
+        output.push_str("    : day.has(t.getDate()) && weekday.has(t.getDay());\n");
+        output.push_str("  const scheduleNext = () => {\n");
+        output.push_str("    const next = new Date();\n");
+        output.push_str("    next.setSeconds(0, 0);\n");
+        output.push_str("    next.setMinutes(next.getMinutes() + 1);\n");
+        output.push_str("    setTimeout(() => {\n");
+        output.push_str("      if (minute.has(next.getMinutes()) && hour.has(next.getHours()) && month.has(next.getMonth() + 1) && dayMatches(next)) {\n");
+        output.push_str("        __runScheduled(fn);\n");
+        output.push_str("      }\n");
+        output.push_str("      scheduleNext();\n");
+        output.push_str("    }, next - Date.now());\n");
+        output.push_str("  };\n");
+        output.push_str("  scheduleNext();\n");
+        output.push_str("};\n\n");
         // HTTP helpers
-        output.push_str("const parseJsonBody = (req) => new Promise((resolve) => {\n");
-        output.push_str("  let body = \"\";\n");
-        output.push_str("  req.on(\"data\", (chunk) => body += chunk);\n");
+        // Parses by Content-Type; the unparsed text stays on req.raw_body
+        output.push_str("const parseBody = (req) => new Promise((resolve) => {\n");
+        output.push_str("  const chunks = [];\n");
+        output.push_str("  req.on(\"data\", (chunk) => chunks.push(chunk));\n");
         output.push_str("  req.on(\"end\", () => {\n");
-        output.push_str("    try { resolve(JSON.parse(body)); } catch { resolve({}); }\n");
+        output.push_str("    const buf = Buffer.concat(chunks);\n");
+        output.push_str("    const body = buf.toString();\n");
+        output.push_str("    req.raw_body = body;\n");
+        output.push_str("    req.files = {};\n");
+        output.push_str("    const contentType = req.headers[\"content-type\"] || \"\";\n");
+        output.push_str("    const type = contentType.split(\";\")[0].trim().toLowerCase();\n");
+        output.push_str("    if (type === \"multipart/form-data\") {\n");
+        output.push_str("      const boundary = (contentType.match(/boundary=(?:\"([^\"]+)\"|([^;]+))/i) || []).slice(1).find(Boolean);\n");
+        output.push_str("      const { fields, files } = boundary ? parseMultipart(buf, boundary) : { fields: {}, files: {} };\n");
+        output.push_str("      req.files = files;\n");
+        output.push_str("      resolve(fields);\n");
+        output.push_str("    } else if (type === \"application/x-www-form-urlencoded\") {\n");
+        output.push_str("      const form = {};\n");
+        output.push_str("      for (const [k, v] of new URLSearchParams(body)) {\n");
+        output.push_str("        form[k] = k in form ? [].concat(form[k], v) : v;\n");
+        output.push_str("      }\n");
+        output.push_str("      resolve(form);\n");
+        output.push_str("    } else if (type.startsWith(\"text/\")) {\n");
+        output.push_str("      resolve(body);\n");
+        output.push_str("    } else if (type === \"application/octet-stream\" || /^(image|audio|video)\\//.test(type)) {\n");
+        output.push_str("      resolve(buf);\n");
+        output.push_str("    } else {\n");
+        output.push_str("      try { resolve(JSON.parse(body)); } catch { resolve({}); }\n");
+        output.push_str("    }\n");
         output.push_str("  });\n");
         output.push_str("});\n\n");
+        // Splits a multipart/form-data body into text fields and uploaded files
+        output.push_str("const parseMultipart = (buf, boundary) => {\n");
+        output.push_str("  const fields = {}, files = {};\n");
+        output.push_str("  const delim = Buffer.from(\"--\" + boundary);\n");
+        output.push_str("  let pos = buf.indexOf(delim);\n");
+        output.push_str("  while (pos !== -1) {\n");
+        output.push_str("    pos += delim.length;\n");
+        output.push_str("    if (buf.slice(pos, pos + 2).toString() === \"--\") break;\n");
+        output.push_str("    const headEnd = buf.indexOf(\"\\r\\n\\r\\n\", pos);\n");
+        output.push_str("    if (headEnd === -1) break;\n");
+        output.push_str("    const head = buf.slice(pos, headEnd).toString();\n");
+        output.push_str("    const next = buf.indexOf(delim, headEnd);\n");
+        output.push_str("    if (next === -1) break;\n");
+        output.push_str("    const data = buf.slice(headEnd + 4, next - 2);\n");
+        output.push_str("    const name = (head.match(/;\\s*name=\"([^\"]*)\"/i) || [])[1];\n");
+        output.push_str("    const filename = (head.match(/;\\s*filename=\"([^\"]*)\"/i) || [])[1];\n");
+        output.push_str("    const type = (head.match(/content-type:\\s*([^\\r\\n]+)/i) || [])[1];\n");
+        output.push_str("    if (filename !== undefined) {\n");
+        output.push_str("      files[name] = {\n");
+        output.push_str("        filename, type: type || \"application/octet-stream\", size: data.length, data,\n");
+        output.push_str("        save: (path) => __fs.writeFile(path, data),\n");
+        output.push_str("      };\n");
+        output.push_str("    } else if (name !== undefined) {\n");
+        output.push_str("      fields[name] = data.toString();\n");
+        output.push_str("    }\n");
+        output.push_str("    pos = next;\n");
+        output.push_str("  }\n");
+        output.push_str("  return { fields, files };\n");
+        output.push_str("};\n\n");
+        // Writes one server-sent event; a no-op once the client is gone
+        output.push_str("const __sse = (res, event, data) => {\n");
+        output.push_str("  if (res.writableEnded || res.destroyed) return false;\n");
+        output.push_str("  if (event != null) res.write(`event: ${event}\\n`);\n");
+        output.push_str("  const text = typeof data === \"string\" ? data : JSON.stringify(data);\n");
+        output.push_str("  res.write(text.split(\"\\n\").map((line) => `data: ${line}`).join(\"\\n\") + \"\\n\\n\");\n");
+        output.push_str("  return true;\n");
+        output.push_str("};\n\n");
+        // Access log line once the response finishes; placeholders: {method} {path} {status} {ms} {ip}
+        output.push_str("const __logRequest = (req, res, format) => {\n");
+        output.push_str("  const start = process.hrtime.bigint();\n");
+        output.push_str("  res.on(\"finish\", () => {\n");
+        output.push_str("    const ms = Number(process.hrtime.bigint() - start) / 1e6;\n");
+        output.push_str("    const fields = { method: req.method, path: req.url.split(\"?\")[0], status: res.statusCode, ms: ms.toFixed(1), ip: req.socket.remoteAddress };\n");
+        output.push_str("    console.log((format || \"{method} {path} {status} {ms}ms\").replace(/\\{(\\w+)\\}/g, (m, k) => (k in fields ? fields[k] : m)));\n");
+        output.push_str("  });\n");
+        output.push_str("};\n\n");
+
+        // Token bucket per client IP: `count` requests refill evenly over `windowMs`
+        output.push_str("const __rateLimiter = (count, windowMs) => {\n");
+        output.push_str("  const buckets = new Map();\n");
+        output.push_str("  setInterval(() => {\n");
+        output.push_str("    const now = Date.now();\n");
+        output.push_str("    for (const [ip, b] of buckets) if (now - b.at >= windowMs) buckets.delete(ip);\n");
+        output.push_str("  }, windowMs).unref();\n");
+        output.push_str("  return (req, res) => {\n");
+        output.push_str("    const ip = req.socket.remoteAddress;\n");
+        output.push_str("    const now = Date.now();\n");
+        output.push_str("    const b = buckets.get(ip) || { tokens: count, at: now };\n");
+        output.push_str("    b.tokens = Math.min(count, b.tokens + ((now - b.at) * count) / windowMs);\n");
+        output.push_str("    b.at = now;\n");
+        output.push_str("    buckets.set(ip, b);\n");
+        output.push_str("    if (b.tokens >= 1) {\n");
+        output.push_str("      b.tokens -= 1;\n");
+        output.push_str("      return true;\n");
+        output.push_str("    }\n");
+        output.push_str("    const retry = Math.ceil(((1 - b.tokens) * windowMs) / count / 1000);\n");
+        output.push_str("    res.writeHead(429, { \"Content-Type\": \"application/json\", \"Retry-After\": String(retry) });\n");
+        output.push_str("    res.end(JSON.stringify({ error: \"Too Many Requests\" }));\n");
+        output.push_str("    return false;\n");
+        output.push_str("  };\n");
+        output.push_str("};\n\n");
+
+        // HS256 JSON Web Tokens for `require_auth bearer`
+        output.push_str("const __safeEqual = (a, b) => {\n");
+        output.push_str("  const x = Buffer.from(String(a)), y = Buffer.from(String(b));\n");
+        output.push_str("  return x.length === y.length && __crypto.timingSafeEqual(x, y);\n");
+        output.push_str("};\n");
+        output.push_str("const __jwtSignature = (data, secret) => __crypto.createHmac(\"sha256\", String(secret)).update(data).digest(\"base64url\");\n");
+        output.push_str("const jwt_sign = (payload, secret) => {\n");
+        output.push_str("  const head = Buffer.from(JSON.stringify({ alg: \"HS256\", typ: \"JWT\" })).toString(\"base64url\");\n");
+        output.push_str("  const body = Buffer.from(JSON.stringify(payload)).toString(\"base64url\");\n");
+        output.push_str("  return `${head}.${body}.${__jwtSignature(`${head}.${body}`, secret)}`;\n");
+        output.push_str("};\n");
+        output.push_str("const jwt_verify = (token, secret) => {\n");
+        output.push_str("  const parts = String(token).split(\".\");\n");
+        output.push_str("  if (parts.length !== 3 || !__safeEqual(parts[2], __jwtSignature(`${parts[0]}.${parts[1]}`, secret))) return null;\n");
+        output.push_str("  try {\n");
+        output.push_str("    if (JSON.parse(Buffer.from(parts[0], \"base64url\").toString()).alg !== \"HS256\") return null;\n");
+        output.push_str("    const payload = JSON.parse(Buffer.from(parts[1], \"base64url\").toString());\n");
+        output.push_str("    if (payload.exp != null && Date.now() / 1000 >= payload.exp) return null;\n");
+        output.push_str("    return payload;\n");
+        output.push_str("  } catch {\n");
+        output.push_str("    return null;\n");
+        output.push_str("  }\n");
+        output.push_str("};\n\n");
+        // Checks the Authorization header; sets req.user or responds 401
+        output.push_str("const __requireAuth = (req, res, scheme, secret) => {\n");
+        output.push_str("  const header = req.headers.authorization || \"\";\n");
+        output.push_str("  const space = header.indexOf(\" \");\n");
+        output.push_str("  const kind = header.slice(0, space).toLowerCase();\n");
+        output.push_str("  const cred = header.slice(space + 1).trim();\n");
+        output.push_str("  let user = null;\n");
+        output.push_str("  if (space !== -1 && kind === scheme && scheme === \"bearer\") {\n");
+        output.push_str("    user = jwt_verify(cred, secret);\n");
+        output.push_str("  } else if (space !== -1 && kind === scheme && scheme === \"basic\") {\n");
+        output.push_str("    const decoded = Buffer.from(cred, \"base64\").toString();\n");
+        output.push_str("    const colon = decoded.indexOf(\":\");\n");
+        output.push_str("    const name = decoded.slice(0, colon);\n");
+        output.push_str("    if (colon !== -1 && secret && Object.hasOwn(secret, name) && __safeEqual(secret[name], decoded.slice(colon + 1))) {\n");
+        output.push_str("      user = { username: name };\n");
+        output.push_str("    }\n");
+        output.push_str("  }\n");
+        output.push_str("  if (user) {\n");
+        output.push_str("    req.user = user;\n");
+        output.push_str("    return true;\n");
+        output.push_str("  }\n");
+        output.push_str("  const challenge = scheme === \"basic\" ? \"Basic realm=\\\"harbor\\\"\" : \"Bearer\";\n");
+        output.push_str("  res.writeHead(401, { \"Content-Type\": \"application/json\", \"WWW-Authenticate\": challenge });\n");
+        output.push_str("  res.end(JSON.stringify({ error: \"Unauthorized\" }));\n");
+        output.push_str("  return false;\n");
+        output.push_str("};\n\n");
+
+        // On SIGINT/SIGTERM: stop accepting connections, let in-flight requests
+        // finish (up to timeoutMs), run the on_shutdown hook, then exit
+        output.push_str("const __gracefulShutdown = (server, onShutdown, timeoutMs = 10000) => {\n");
+        output.push_str("  let inFlight = 0;\n");
+        output.push_str("  server.on(\"request\", (req, res) => {\n");
+        output.push_str("    inFlight++;\n");
+        output.push_str("    res.on(\"close\", () => inFlight--);\n");
+        output.push_str("  });\n");
+        output.push_str("  const stop = async (signal) => {\n");
+        output.push_str("    console.log(`Received ${signal}, shutting down...`);\n");
+        output.push_str("    server.close();\n");
+        output.push_str("    server.closeIdleConnections();\n");
+        output.push_str("    const deadline = Date.now() + timeoutMs;\n");
+        output.push_str("    while (inFlight > 0 && Date.now() < deadline) {\n");
+        output.push_str("      await new Promise((resolve) => setTimeout(resolve, 50));\n");
+        output.push_str("    }\n");
+        output.push_str("    try {\n");
+        output.push_str("      await onShutdown();\n");
+        output.push_str("    } catch (err) {\n");
+        output.push_str("      console.error(err);\n");
+        output.push_str("    }\n");
+        output.push_str("    process.exit(0);\n");
+        output.push_str("  };\n");
+        output.push_str("  process.once(\"SIGINT\", stop);\n");
+        output.push_str("  process.once(\"SIGTERM\", stop);\n");
+        output.push_str("};\n\n");
+
+        // Last-resort reply when a handler throws. The log leads with the request
+        // and the first .hb line in the stack, so the failing route is easy to find
+        output.push_str("const __serverError = (req, res, err) => {\n");
+        output.push_str("  if (err === __responded) return;\n");
+        output.push_str("  const at = String(err?.cause?.stack ?? err?.stack ?? \"\").match(/([^\\s()]+\\.hb:\\d+)/);\n");
+        output.push_str("  const where = at ? ` at ${require(\"path\").relative(process.cwd(), at[1])}` : \"\";\n");
+        output.push_str("  console.error(`Error handling ${req.method} ${req.url}${where}:`);\n");
+        output.push_str("  console.error(err);\n");
+        output.push_str("  if (!res.headersSent) {\n");
+        output.push_str("    res.writeHead(500, { \"Content-Type\": \"application/json\" });\n");
+        output.push_str("    res.end(JSON.stringify({ error: \"Internal Server Error\" }));\n");
+        output.push_str("  } else if (!res.writableEnded) {\n");
+        output.push_str("    res.end();\n");
+        output.push_str("  }\n");
+        output.push_str("};\n\n");
+
+        // Streams a file with its Content-Type and Content-Length; 404 when missing
+        output.push_str("const __mimeTypes = {\n");
+        output.push_str("  \".html\": \"text/html; charset=utf-8\", \".htm\": \"text/html; charset=utf-8\", \".css\": \"text/css\",\n");
+        output.push_str("  \".js\": \"text/javascript\", \".mjs\": \"text/javascript\", \".json\": \"application/json\",\n");
+        output.push_str("  \".txt\": \"text/plain; charset=utf-8\", \".csv\": \"text/csv\", \".xml\": \"application/xml\",\n");
+        output.push_str("  \".pdf\": \"application/pdf\", \".zip\": \"application/zip\", \".wasm\": \"application/wasm\",\n");
+        output.push_str("  \".png\": \"image/png\", \".jpg\": \"image/jpeg\", \".jpeg\": \"image/jpeg\", \".gif\": \"image/gif\",\n");
+        output.push_str("  \".svg\": \"image/svg+xml\", \".webp\": \"image/webp\", \".ico\": \"image/x-icon\",\n");
+        output.push_str("  \".mp3\": \"audio/mpeg\", \".wav\": \"audio/wav\", \".mp4\": \"video/mp4\", \".webm\": \"video/webm\",\n");
+        output.push_str("  \".woff\": \"font/woff\", \".woff2\": \"font/woff2\",\n");
+        output.push_str("};\n");
+        output.push_str("const __sendFile = async (res, file) => {\n");
+        output.push_str("  const stat = await __fs.stat(file).catch(() => null);\n");
+        output.push_str("  if (!stat || !stat.isFile()) {\n");
+        output.push_str("    res.statusCode = 404;\n");
+        output.push_str("    res.end(\"Not Found\");\n");
+        output.push_str("    return;\n");
+        output.push_str("  }\n");
+        output.push_str("  const ext = require(\"path\").extname(file).toLowerCase();\n");
+        output.push_str("  res.setHeader(\"Content-Type\", __mimeTypes[ext] || \"application/octet-stream\");\n");
+        output.push_str("  res.setHeader(\"Content-Length\", stat.size);\n");
+        output.push_str("  await new Promise((resolve) => {\n");
+        output.push_str("    const stream = require(\"fs\").createReadStream(file);\n");
+        output.push_str("    stream.on(\"error\", (err) => res.destroy(err));\n");
+        output.push_str("    res.on(\"close\", resolve);\n");
+        output.push_str("    stream.pipe(res);\n");
+        output.push_str("  });\n");
+        output.push_str("};\n\n");
+        // Response cache keyed by full URL; only 200s are stored, for ttl seconds.
+        // Behind require_auth the key includes the credentials, so users never
+        // see each other's responses, and proxies are told not to share them
+        output.push_str("const __responseCache = (ttl, authed) => {\n");
+        output.push_str("  const entries = new Map();\n");
+        output.push_str("  const scope = authed ? \"private\" : \"public\";\n");
+        output.push_str("  const key = (req) => authed ? `${req.headers.authorization || \"\"} ${req.url}` : req.url;\n");
+        output.push_str("  setInterval(() => {\n");
+        output.push_str("    const now = Date.now();\n");
+        output.push_str("    for (const [url, entry] of entries) if (entry.expires <= now) entries.delete(url);\n");
+        output.push_str("  }, ttl * 1000).unref();\n");
+        output.push_str("  return {\n");
+        output.push_str("    // Replays a fresh entry; true when it responded\n");
+        output.push_str("    hit(req, res) {\n");
+        output.push_str("      const entry = entries.get(key(req));\n");
+        output.push_str("      if (!entry || entry.expires <= Date.now()) return false;\n");
+        output.push_str("      const maxAge = Math.ceil((entry.expires - Date.now()) / 1000);\n");
+        output.push_str("      res.writeHead(200, { ...entry.headers, \"Cache-Control\": `${scope}, max-age=${maxAge}` });\n");
+        output.push_str("      res.end(entry.body);\n");
+        output.push_str("      return true;\n");
+        output.push_str("    },\n");
+        output.push_str("    // Captures what the handler writes so the next request can replay it\n");
+        output.push_str("    record(req, res) {\n");
+        output.push_str("      const chunks = [];\n");
+        output.push_str("      const write = res.write.bind(res);\n");
+        output.push_str("      const end = res.end.bind(res);\n");
+        output.push_str("      res.setHeader(\"Cache-Control\", `${scope}, max-age=${ttl}`);\n");
+        output.push_str("      res.write = (chunk, ...rest) => {\n");
+        output.push_str("        chunks.push(Buffer.from(chunk));\n");
+        output.push_str("        return write(chunk, ...rest);\n");
+        output.push_str("      };\n");
+        output.push_str("      res.end = (chunk, ...rest) => {\n");
+        output.push_str("        if (chunk != null && typeof chunk !== \"function\") chunks.push(Buffer.from(chunk));\n");
+        output.push_str("        const streaming = String(res.getHeader(\"Content-Type\")).startsWith(\"text/event-stream\");\n");
+        output.push_str("        if (res.statusCode === 200 && !streaming) {\n");
+        output.push_str("          entries.set(key(req), { headers: res.getHeaders(), body: Buffer.concat(chunks), expires: Date.now() + ttl * 1000 });\n");
+        output.push_str("        }\n");
+        output.push_str("        return end(chunk, ...rest);\n");
+        output.push_str("      };\n");
+        output.push_str("    },\n");
+        output.push_str("  };\n");
+        output.push_str("};\n\n");
+        // Checks req.body against an `expect` schema; responds 400 with
+        // per-field errors when it doesn't match
+        output.push_str("const __schemaErrors = (value, schema, path, errors) => {\n");
+        output.push_str("  if (typeof schema === \"string\") {\n");
+        output.push_str("    const ok = {\n");
+        output.push_str("      str: typeof value === \"string\",\n");
+        output.push_str("      int: Number.isInteger(value),\n");
+        output.push_str("      float: typeof value === \"number\",\n");
+        output.push_str("      bool: typeof value === \"boolean\",\n");
+        output.push_str("      list: Array.isArray(value),\n");
+        output.push_str("      dict: typeof value === \"object\" && value !== null && !Array.isArray(value),\n");
+        output.push_str("      any: true,\n");
+        output.push_str("    }[schema];\n");
+        output.push_str("    if (!ok) errors[path || \"body\"] = `expected ${schema}`;\n");
+        output.push_str("  } else if (Array.isArray(schema)) {\n");
+        output.push_str("    if (!Array.isArray(value)) errors[path || \"body\"] = \"expected list\";\n");
+        output.push_str("    else value.forEach((item, i) => __schemaErrors(item, schema[0], `${path}[${i}]`, errors));\n");
+        output.push_str("  } else if (typeof value !== \"object\" || value === null || Array.isArray(value)) {\n");
+        output.push_str("    errors[path || \"body\"] = \"expected dict\";\n");
+        output.push_str("  } else {\n");
+        output.push_str("    for (const [key, type] of Object.entries(schema)) {\n");
+        output.push_str("      const field = path ? `${path}.${key}` : key;\n");
+        output.push_str("      if (value[key] == null) errors[field] = \"required\";\n");
+        output.push_str("      else __schemaErrors(value[key], type, field, errors);\n");
+        output.push_str("    }\n");
+        output.push_str("  }\n");
+        output.push_str("  return errors;\n");
+        output.push_str("};\n");
+        output.push_str("const __expectBody = (req, res, schema) => {\n");
+        output.push_str("  const fields = __schemaErrors(req.body, schema, \"\", {});\n");
+        output.push_str("  if (Object.keys(fields).length === 0) return true;\n");
+        output.push_str("  res.writeHead(400, { \"Content-Type\": \"application/json\" });\n");
+        output.push_str("  res.end(JSON.stringify({ error: \"Invalid request body\", fields }));\n");
+        output.push_str("  return false;\n");
+        output.push_str("};\n\n");
+        // Prometheus metrics: request and error counts by method and status, plus
+        // a latency histogram
+        output.push_str("const __createMetrics = () => {\n");
+        output.push_str("  const buckets = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1, 2.5, 5, 10];\n");
+        output.push_str("  const requests = new Map();\n");
+        output.push_str("  const errors = new Map();\n");
+        output.push_str("  const histogram = buckets.map(() => 0);\n");
+        output.push_str("  let count = 0;\n");
+        output.push_str("  let sum = 0;\n");
+        output.push_str("  const bump = (map, key) => map.set(key, (map.get(key) || 0) + 1);\n");
+        output.push_str("  return {\n");
+        output.push_str("    track(req, res) {\n");
+        output.push_str("      const start = process.hrtime.bigint();\n");
+        output.push_str("      res.on(\"finish\", () => {\n");
+        output.push_str("        const seconds = Number(process.hrtime.bigint() - start) / 1e9;\n");
+        output.push_str("        const labels = `method=\"${req.method}\",status=\"${res.statusCode}\"`;\n");
+        output.push_str("        bump(requests, labels);\n");
+        output.push_str("        if (res.statusCode >= 500) bump(errors, labels);\n");
+        output.push_str("        buckets.forEach((le, i) => {\n");
+        output.push_str("          if (seconds <= le) histogram[i]++;\n");
+        output.push_str("        });\n");
+        output.push_str("        count++;\n");
+        output.push_str("        sum += seconds;\n");
+        output.push_str("      });\n");
+        output.push_str("    },\n");
+        output.push_str("    render() {\n");
+        output.push_str("      const lines = [\n");
+        output.push_str("        \"# HELP harbor_http_requests_total Requests handled.\",\n");
+        output.push_str("        \"# TYPE harbor_http_requests_total counter\",\n");
+        output.push_str("        ...[...requests].map(([labels, n]) => `harbor_http_requests_total{${labels}} ${n}`),\n");
+        output.push_str("        \"# HELP harbor_http_errors_total Requests answered with a 5xx status.\",\n");
+        output.push_str("        \"# TYPE harbor_http_errors_total counter\",\n");
+        output.push_str("        ...[...errors].map(([labels, n]) => `harbor_http_errors_total{${labels}} ${n}`),\n");
+        output.push_str("        \"# HELP harbor_http_request_duration_seconds Request latency.\",\n");
+        output.push_str("        \"# TYPE harbor_http_request_duration_seconds histogram\",\n");
+        output.push_str("        ...buckets.map((le, i) => `harbor_http_request_duration_seconds_bucket{le=\"${le}\"} ${histogram[i]}`),\n");
+        output.push_str("        `harbor_http_request_duration_seconds_bucket{le=\"+Inf\"} ${count}`,\n");
+        output.push_str("        `harbor_http_request_duration_seconds_sum ${sum}`,\n");
+        output.push_str("        `harbor_http_request_duration_seconds_count ${count}`,\n");
+        output.push_str("      ];\n");
+        output.push_str("      return lines.join(\"\\n\") + \"\\n\";\n");
+        output.push_str("    },\n");
+        output.push_str("  };\n");
+        output.push_str("};\n\n");
+        // `harbor dev` owns the public port and proxies to HARBOR_DEV_PORT
+        output.push_str("const __listenPort = (port) => {\n");
+        output.push_str("  const given = typeof port === \"number\" || (typeof port === \"string\" && port.trim() !== \"\") ? Number(port) : NaN;\n");
+        output.push_str("  const n = Number(process.env.HARBOR_DEV_PORT) || given;\n");
+        output.push_str("  if (!Number.isInteger(n) || n < 0 || n > 65535) throw new ValueError(`server port must be a whole number from 0 to 65535, not ${port == null ? \"None\" : JSON.stringify(port)}`);\n");
+        output.push_str("  return n;\n");
+        output.push_str("};\n");
+        // Under `harbor test` servers don't listen; __inject hands them requests
+        output.push_str("const __listen = (server, port, ready) => {\n");
+        output.push_str("  const n = __listenPort(port);\n");
+        output.push_str("  (globalThis.__harborServers ??= []).push(server);\n");
+        output.push_str("  if (!process.env.HARBOR_TEST) server.listen(n, ready);\n");
+        output.push_str("};\n\n");
+        // The request being handled, so functions called from a route can use
+        // `req` and `respond` too. Modules share one store, and the first one
+        // defines the globals. `respond` in a function throws `__responded` to
+        // stop the route that called it
+        output.push_str("const __requests = globalThis.__harborRequests ??= new (require(\"async_hooks\").AsyncLocalStorage)();\n");
+        output.push_str("const __responded = globalThis.__harborResponded ??= Symbol(\"responded\");\n");
+        output.push_str("const __handling = (what) => {\n");
+        output.push_str("  const current = __requests.getStore();\n");
+        output.push_str("  if (!current) throw new HarborError(`${what} can only be used while handling a request`);\n");
+        output.push_str("  return current;\n");
+        output.push_str("};\n");
+        output.push_str("if (!Object.hasOwn(globalThis, \"req\")) {\n");
+        output.push_str("  Object.defineProperty(globalThis, \"req\", { get: () => __handling(\"req\").req });\n");
+        output.push_str("  Object.defineProperty(globalThis, \"__res\", { get: () => __handling(\"respond\").res });\n");
+        output.push_str("}\n\n");
+        // Request metadata: path without the query string, parsed query, client
+        // IP, host name and protocol
+        output.push_str("const __requestInfo = (req, secure) => {\n");
+        output.push_str("  const q = req.url.indexOf(\"?\");\n");
+        output.push_str("  req.path = q === -1 ? req.url : req.url.slice(0, q);\n");
+        output.push_str("  req.query = Object.fromEntries(new URLSearchParams(q === -1 ? \"\" : req.url.slice(q + 1)));\n");
+        output.push_str("  req.ip = req.socket.remoteAddress;\n");
+        output.push_str("  req.host = (req.headers.host || \"\").replace(/:\\d+$/, \"\");\n");
+        output.push_str("  req.protocol = secure ? \"https\" : \"http\";\n");
+        output.push_str("};\n\n");
 
-        output.push_str("const fetchJson = (url) => new Promise((resolve) => {\n");
+        // What `respond` without a content type sends: html f-strings as HTML,
+        // bytes as they are, dicts and lists as JSON, anything else as text
+        output.push_str("const __send = (res, val) => {\n");
+        output.push_str("  if (val instanceof __SafeHtml) {\n");
+        output.push_str("    res.setHeader(\"Content-Type\", \"text/html; charset=utf-8\");\n");
+        output.push_str("    res.end(String(val));\n");
+        output.push_str("  } else if (Buffer.isBuffer(val)) {\n");
+        output.push_str("    if (!res.hasHeader(\"Content-Type\")) res.setHeader(\"Content-Type\", \"application/octet-stream\");\n");
+        output.push_str("    res.end(val);\n");
+        output.push_str("  } else if (typeof val === \"object\" && val !== null) {\n");
+        output.push_str("    res.setHeader(\"Content-Type\", \"application/json\");\n");
+        output.push_str("    res.end(JSON.stringify(val));\n");
+        output.push_str("  } else {\n");
+        output.push_str("    res.end(String(val));\n");
+        output.push_str("  }\n");
+        output.push_str("};\n");
+        // A value a route returns instead of responding: a dict with a numeric
+        // "status" and otherwise only "body" and "headers" is that response,
+        // anything else is sent as `respond` would send it
+        output.push_str("const __sendReturned = (res, value) => {\n");
+        output.push_str("  const keys = __isDict(value) ? Object.keys(value) : [];\n");
+        output.push_str("  if (typeof value?.status !== \"number\" || !keys.every((k) => [\"status\", \"body\", \"headers\"].includes(k))) return __send(res, value);\n");
+        output.push_str("  res.statusCode = value.status;\n");
+        output.push_str("  for (const [name, header] of Object.entries(value.headers ?? {})) res.setHeader(name, header);\n");
+        output.push_str("  if (value.body == null) res.end();\n");
+        output.push_str("  else __send(res, value.body);\n");
+        output.push_str("};\n");
+        // Runs the first route matching the method and path; false when none did.
+        // A matched route that returns a value sends it; one that never
+        // responds gets an empty 204.
+        output.push_str("const __dispatch = async (table, req, res) => {\n");
+        output.push_str("  for (const [pattern, handler] of table[req.method] || []) {\n");
+        output.push_str("    const match = typeof pattern === \"string\" ? (req.path === pattern ? [] : null) : req.path.match(pattern);\n");
+        output.push_str("    if (match) {\n");
+        output.push_str("      const value = await handler(req, res, match).catch((err) => {\n");
+        output.push_str("        if (err !== __responded) throw err;\n");
+        output.push_str("      });\n");
+        output.push_str("      if (value != null && !res.headersSent) __sendReturned(res, value);\n");
+        output.push_str("      if (!res.headersSent) {\n");
+        output.push_str("        res.statusCode = 204;\n");
+        output.push_str("        res.end();\n");
+        output.push_str("      }\n");
+        output.push_str("      return true;\n");
+        output.push_str("    }\n");
+        output.push_str("  }\n");
+        output.push_str("  return false;\n");
+        output.push_str("};\n\n");
+
+        // Runtime failures raise HarborError subclasses, so `except KeyError as e:` can
+        // pick a category. `kind` refines it ("timeout", "json", ...) and `traceback`
+        // lists the Harbor functions the error passed through
+        output.push_str("class HarborError extends Error {\n");
+        output.push_str("  constructor(message, details = {}) {\n");
+        output.push_str("    super(message);\n");
+        output.push_str("    this.name = new.target.name;\n");
+        output.push_str("    this.kind = \"error\";\n");
+        output.push_str("    Object.assign(this, details);\n");
+        output.push_str("  }\n");
+        output.push_str("  get traceback() {\n");
+        output.push_str("    return (this.stack || \"\").split(\"\\n\").slice(1)\n");
+        output.push_str("      .filter((line) => line.includes(__filename) || line.includes(\".hb:\"))\n");
+        output.push_str("      .map((line) => line.trim().replace(/^at (async )?/, \"\").split(\" \")[0])\n");
+        output.push_str("      .filter((name) => !name.startsWith(\"__\") && !name.startsWith(\"/\") && name !== \"Object.<anonymous>\");\n");
+        output.push_str("  }\n");
+        output.push_str("}\n");
+        output.push_str("const __errorClass = (name, kind, base = HarborError) => ({\n");
+        output.push_str("  [name]: class extends base {\n");
+        output.push_str("    constructor(message, details = {}) { super(message, { kind, ...details }); }\n");
+        output.push_str("  },\n");
+        output.push_str("})[name];\n");
+        output.push_str("const ValueError = __errorClass(\"ValueError\", \"value\");\n");
+        output.push_str("const KeyError = __errorClass(\"KeyError\", \"key\");\n");
+        output.push_str("const IndexError = __errorClass(\"IndexError\", \"index\");\n");
+        output.push_str("const AssertionError = __errorClass(\"AssertionError\", \"assert\");\n");
+        output.push_str("const OSError = __errorClass(\"OSError\", \"os\");\n");
+        output.push_str("const FileNotFoundError = __errorClass(\"FileNotFoundError\", \"not_found\", OSError);\n");
+        output.push_str("const PermissionError = __errorClass(\"PermissionError\", \"permission\", OSError);\n");
+        output.push_str("const FetchError = __errorClass(\"FetchError\", \"network\");\n");
+        output.push_str("const TimeoutError = __errorClass(\"TimeoutError\", \"timeout\");\n");
+        output.push_str("const ShellError = __errorClass(\"ShellError\", \"shell\");\n");
+        output.push_str("const StopIteration = __errorClass(\"StopIteration\", \"stop\");\n");
+        // Equality by value: lists and dicts compare their items
+        output.push_str("const __deepEqual = (a, b) => {\n");
+        output.push_str("  if (a === b) return true;\n");
+        output.push_str("  if (typeof a === \"bigint\" || typeof b === \"bigint\") return typeof a !== \"object\" && typeof b !== \"object\" && a == b;\n");
+        output.push_str("  if (Buffer.isBuffer(a) && Buffer.isBuffer(b)) return a.equals(b);\n");
+        output.push_str("  if (Array.isArray(a) && Array.isArray(b)) return a.length === b.length && a.every((item, i) => __deepEqual(item, b[i]));\n");
+        output.push_str("  if (__isDict(a) && __isDict(b)) {\n");
+        output.push_str("    const keys = Object.keys(a);\n");
+        output.push_str("    return keys.length === Object.keys(b).length && keys.every((k) => Object.hasOwn(b, k) && __deepEqual(a[k], b[k]));\n");
+        output.push_str("  }\n");
+        output.push_str("  return false;\n");
+        output.push_str("};\n");
+        // Where `actual` and `expected` differ, one line each, by the path to the item
+        output.push_str("const __differences = async (actual, expected, path, out) => {\n");
+        output.push_str("  if (__deepEqual(actual, expected)) return out;\n");
+        output.push_str("  const at = path || \"value\";\n");
+        output.push_str("  if (Array.isArray(actual) && Array.isArray(expected)) {\n");
+        output.push_str("    for (let i = 0; i < Math.max(actual.length, expected.length); i++) {\n");
+        output.push_str("      if (i >= actual.length) out.push(`${path}[${i}]: missing, expected ${await __repr(expected[i])}`);\n");
+        output.push_str("      else if (i >= expected.length) out.push(`${path}[${i}]: unexpected ${await __repr(actual[i])}`);\n");
+        output.push_str("      else await __differences(actual[i], expected[i], `${path}[${i}]`, out);\n");
+        output.push_str("    }\n");
+        output.push_str("  } else if (__isDict(actual) && __isDict(expected)) {\n");
+        output.push_str("    for (const key of new Set([...Object.keys(expected), ...Object.keys(actual)])) {\n");
+        output.push_str("      const item = `${path}[${await __repr(key)}]`;\n");
+        output.push_str("      if (!Object.hasOwn(actual, key)) out.push(`${item}: missing, expected ${await __repr(expected[key])}`);\n");
+        output.push_str("      else if (!Object.hasOwn(expected, key)) out.push(`${item}: unexpected ${await __repr(actual[key])}`);\n");
+        output.push_str("      else await __differences(actual[key], expected[key], item, out);\n");
+        output.push_str("    }\n");
+        output.push_str("  } else {\n");
+        output.push_str("    out.push(`${at}: expected ${await __repr(expected)}, got ${await __repr(actual)}`);\n");
+        output.push_str("  }\n");
+        output.push_str("  return out;\n");
+        output.push_str("};\n");
+        // assert_eq(actual, expected[, message]): raise AssertionError listing each
+        // difference, up to 20 of them, when the two aren't equal by value
+        output.push_str("const assert_eq = async (actual, expected, message = null) => {\n");
+        output.push_str("  if (__deepEqual(actual, expected)) return;\n");
+        output.push_str("  const lines = await __differences(actual, expected, \"\", []);\n");
+        output.push_str("  const shown = lines.slice(0, 20).map((line) => `  ${line}`);\n");
+        output.push_str("  if (lines.length > 20) shown.push(`  ... and ${lines.length - 20} more`);\n");
+        output.push_str("  throw new AssertionError([message ?? \"assert_eq failed\", ...shown].join(\"\\n\"));\n");
+        output.push_str("};\n");
+        // An error nothing caught ends the program: print it with the Harbor
+        // lines it passed through (Node's own frames left out) and exit with 1
+        output.push_str("const __uncaught = (err) => {\n");
+        output.push_str("  if (!(err instanceof Error)) {\n");
+        output.push_str("    console.error(`Error: ${err}`);\n");
+        output.push_str("    process.exit(1);\n");
+        output.push_str("  }\n");
+        output.push_str("  const frames = String(err.stack).split(\"\\n\").filter((line) => /^\\s+at /.test(line));\n");
+        output.push_str("  const harbor = frames.filter((line, i) => line.includes(\".hb:\") && line !== frames[i - 1]);\n");
+        output.push_str("  const shown = harbor.length ? harbor : frames.filter((line) => !line.includes(\"node:\"));\n");
+        output.push_str("  console.error([`${err.name}: ${err.message}`, ...shown].join(\"\\n\"));\n");
+        output.push_str("  process.exit(1);\n");
+        output.push_str("};\n");
+        // What `except` sees: Node errors become Harbor errors, keeping the original
+        // as `cause`
+        output.push_str("const __toHarborError = (err) => {\n");
+        output.push_str("  if (err === __responded) throw err;\n");
+        output.push_str("  if (err instanceof HarborError) return err;\n");
+        output.push_str("  if (!(err instanceof Error)) return new HarborError(String(err), { value: err });\n");
+        output.push_str("  const details = { cause: err, stack: err.stack };\n");
+        output.push_str("  if (err.syscall) {\n");
+        output.push_str("    const detail = { ...details, code: err.code, path: err.path ?? null };\n");
+        output.push_str("    if (err.code === \"ENOENT\") return new FileNotFoundError(`No such file or directory: ${err.path}`, detail);\n");
+        output.push_str("    if (err.code === \"EACCES\" || err.code === \"EPERM\") return new PermissionError(`Permission denied: ${err.path}`, detail);\n");
+        output.push_str("    return new OSError(err.message, detail);\n");
+        output.push_str("  }\n");
+        output.push_str("  const kind = { TypeError: \"type\", ReferenceError: \"name\", RangeError: \"value\", SyntaxError: \"syntax\" }[err.name];\n");
+        output.push_str("  return new HarborError(err.message, { kind: kind || \"error\", ...details });\n");
+        output.push_str("};\n\n");
+        // One request, following up to 10 redirects. The response carries the raw
+        // `bytes`, decoded `text`, final `url`, and `body`: parsed JSON for JSON
+        // responses, otherwise the text. `request` can give the method, headers,
+        // body and a cookie jar; it's a plain GET otherwise
+        output.push_str("const __fetchOnce = (url, timeoutMs, redirects = 0, request = {}) => new Promise((resolve, reject) => {\n");
         output.push_str("  const lib = url.startsWith(\"https\") ? https : http;\n");
-        output.push_str("  lib.get(url, { headers: { \"User-Agent\": \"Harbor/2.0\" } }, (res) => {\n");
-        output.push_str("    let data = \"\";\n");
-        output.push_str("    res.on(\"data\", (chunk) => data += chunk);\n");
+        output.push_str("  const headers = { \"User-Agent\": \"Harbor/2.0\", ...request.headers, ...request.jar?.header(url) };\n");
+        output.push_str("  const req = lib.request(url, { method: request.method || \"GET\", headers }, (res) => {\n");
+        output.push_str("    request.jar?.store(url, res.headers[\"set-cookie\"]);\n");
+        output.push_str("    if ([301, 302, 303, 307, 308].includes(res.statusCode) && res.headers.location) {\n");
+        output.push_str("      res.resume();\n");
+        output.push_str("      if (redirects >= 10) {\n");
+        output.push_str("        reject(new FetchError(`Too many redirects fetching ${url}`, { kind: \"redirect\", url }));\n");
+        output.push_str("      } else {\n");
+        output.push_str("        // 307 and 308 repeat the request; the others are followed with a GET\n");
+        output.push_str("        const next = [307, 308].includes(res.statusCode) ? request : { ...request, method: \"GET\", body: undefined };\n");
+        output.push_str("        resolve(__fetchOnce(new URL(res.headers.location, url).href, timeoutMs, redirects + 1, next));\n");
+        output.push_str("      }\n");
+        output.push_str("      return;\n");
+        output.push_str("    }\n");
+        output.push_str("    const chunks = [];\n");
+        output.push_str("    res.on(\"data\", (chunk) => chunks.push(chunk));\n");
         output.push_str("    res.on(\"end\", () => {\n");
-        output.push_str("      try { res.body = JSON.parse(data); } catch { res.body = data; }\n");
+        output.push_str("      res.url = url;\n");
+        output.push_str("      res.bytes = Buffer.concat(chunks);\n");
+        output.push_str("      res.text = res.bytes.toString();\n");
+        output.push_str("      const type = res.headers[\"content-type\"] || \"\";\n");
+        output.push_str("      if (type.includes(\"json\")) {\n");
+        output.push_str("        try {\n");
+        output.push_str("          res.body = JSON.parse(res.text);\n");
+        output.push_str("        } catch (err) {\n");
+        output.push_str("          reject(new FetchError(`Invalid JSON from ${url}: ${err.message}`, { kind: \"parse\", url }));\n");
+        output.push_str("          return;\n");
+        output.push_str("        }\n");
+        output.push_str("      } else if (type) {\n");
+        output.push_str("        res.body = res.text;\n");
+        output.push_str("      } else {\n");
+        output.push_str("        try { res.body = JSON.parse(res.text); } catch { res.body = res.text; }\n");
+        output.push_str("      }\n");
         output.push_str("      resolve(res);\n");
         output.push_str("    });\n");
-        output.push_str("  }).on(\"error\", (err) => {\n");
-        output.push_str("    resolve({ statusCode: 500, body: { error: err.message } });\n");
         output.push_str("  });\n");
-        output.push_str("});\n\n");
-
-        // Wrap in async IIFE
-        output.push_str("(async () => {\n");
-
-        for stmt in stmts {
-            output.push_str(&Self::gen_stmt(stmt, "null", "  "));
-        }
-
-        output.push_str("})();\n");
+        output.push_str("  if (timeoutMs > 0) {\n");
+        output.push_str("    req.setTimeout(timeoutMs, () => {\n");
+        output.push_str("      req.destroy(new FetchError(`Request to ${url} timed out after ${timeoutMs / 1000}s`, { kind: \"timeout\", url }));\n");
+        output.push_str("    });\n");
+        output.push_str("  }\n");
+        output.push_str("  req.on(\"error\", (err) => {\n");
+        output.push_str("    reject(err instanceof HarborError ? err : new FetchError(`Request to ${url} failed: ${err.message}`, { url, cause: err }));\n");
+        output.push_str("  });\n");
+        output.push_str("  req.end(request.body);\n");
+        output.push_str("});\n");
+        // fetch with `timeout` (seconds) and `retries`; network errors, timeouts and
+        // 502/503/504 are retried with backoff, and a final failure raises FetchError
+        output.push_str("const fetchJson = async (url, options = {}) => {\n");
+        output.push_str("  const retries = options.retries || 0;\n");
+        output.push_str("  for (let attempt = 0; ; attempt++) {\n");
+        output.push_str("    let res = null;\n");
+        output.push_str("    try {\n");
+        output.push_str("      res = await __fetchOnce(String(url), (options.timeout || 0) * 1000, 0, options);\n");
+        output.push_str("    } catch (err) {\n");
+        output.push_str("      if (attempt >= retries) throw err;\n");
+        output.push_str("    }\n");
+        output.push_str("    if (res && (attempt >= retries || ![502, 503, 504].includes(res.statusCode))) return res;\n");
+        output.push_str("    await new Promise((resolve) => setTimeout(resolve, 200 * 2 ** attempt));\n");
+        output.push_str("  }\n");
+        output.push_str("};\n");
+        // The cookies responses set, each sent back only to the host that set it.
+        // Ones set by hand go to every host
+        output.push_str("class __CookieJar {\n");
+        output.push_str("  constructor() {\n");
+        output.push_str("    this.cookies = {};\n");
+        output.push_str("    this.hosts = new Map();\n");
+        output.push_str("  }\n");
+        output.push_str("  header(url) {\n");
+        output.push_str("    const host = new URL(url).host;\n");
+        output.push_str("    const names = Object.keys(this.cookies).filter((name) => (this.hosts.get(name) ?? host) === host);\n");
+        output.push_str("    return names.length ? { Cookie: names.map((name) => `${name}=${this.cookies[name]}`).join(\"; \") } : {};\n");
+        output.push_str("  }\n");
+        output.push_str("  store(url, lines = []) {\n");
+        output.push_str("    for (const line of lines) {\n");
+        output.push_str("      const [pair, ...attributes] = line.split(\";\");\n");
+        output.push_str("      const eq = pair.indexOf(\"=\");\n");
+        output.push_str("      if (eq < 1) continue;\n");
+        output.push_str("      const name = pair.slice(0, eq).trim();\n");
+        output.push_str("      const expired = attributes.some((attribute) => {\n");
+        output.push_str("        const [key, value = \"\"] = attribute.trim().split(\"=\");\n");
+        output.push_str("        if (key.toLowerCase() === \"max-age\") return Number(value) <= 0;\n");
+        output.push_str("        return key.toLowerCase() === \"expires\" && Date.parse(value) <= Date.now();\n");
+        output.push_str("      });\n");
+        output.push_str("      if (expired) {\n");
+        output.push_str("        delete this.cookies[name];\n");
+        output.push_str("        this.hosts.delete(name);\n");
+        output.push_str("      } else {\n");
+        output.push_str("        this.cookies[name] = pair.slice(eq + 1).trim();\n");
+        output.push_str("        this.hosts.set(name, new URL(url).host);\n");
+        output.push_str("      }\n");
+        output.push_str("    }\n");
+        output.push_str("  }\n");
+        output.push_str("}\n");
+        // A request body: bytes as they are, strings as text, anything else as JSON
+        output.push_str("const __encodeBody = (body) => (Buffer.isBuffer(body) ? [\"application/octet-stream\", body]\n");
+        output.push_str("  : typeof body === \"string\" ? [\"text/plain; charset=utf-8\", body]\n");
+        output.push_str("  : [\"application/json\", JSON.stringify(body)]);\n");
+        // http_client(base_url, headers): requests against one API with default
+        // headers and a cookie jar. Paths are joined to base_url
+        output.push_str("class __HttpClient {\n");
+        output.push_str("  #jar = new __CookieJar();\n");
+        output.push_str("  constructor(baseUrl, headers) {\n");
+        output.push_str("    this.base_url = baseUrl;\n");
+        output.push_str("    this.headers = { ...headers };\n");
+        output.push_str("    this.cookies = this.#jar.cookies;\n");
+        output.push_str("  }\n");
+        output.push_str("  url(path) {\n");
+        output.push_str("    path = String(path);\n");
+        output.push_str("    if (!this.base_url || /^https?:\\/\\//.test(path)) return path;\n");
+        output.push_str("    return `${this.base_url.replace(/\\/+$/, \"\")}/${path.replace(/^\\/+/, \"\")}`;\n");
+        output.push_str("  }\n");
+        output.push_str("  async #send(method, path, args) {\n");
+        output.push_str("    const [[body = null], { headers = {}, timeout, retries }] = __splitKwargs(args);\n");
+        output.push_str("    const request = { method, headers: { ...this.headers, ...headers }, jar: this.#jar, timeout, retries };\n");
+        output.push_str("    if (body !== null) {\n");
+        output.push_str("      const [type, data] = __encodeBody(body);\n");
+        output.push_str("      if (!Object.keys(request.headers).some((name) => name.toLowerCase() === \"content-type\")) {\n");
+        output.push_str("        request.headers[\"Content-Type\"] = type;\n");
+        output.push_str("      }\n");
+        output.push_str("      request.body = data;\n");
+        output.push_str("    }\n");
+        output.push_str("    return fetchJson(this.url(path), request);\n");
+        output.push_str("  }\n");
+        output.push_str("  get(path, ...args) { return this.#send(\"GET\", path, args); }\n");
+        output.push_str("  post(path, ...args) { return this.#send(\"POST\", path, args); }\n");
+        output.push_str("  put(path, ...args) { return this.#send(\"PUT\", path, args); }\n");
+        output.push_str("  patch(path, ...args) { return this.#send(\"PATCH\", path, args); }\n");
+        output.push_str("  delete(path, ...args) { return this.#send(\"DELETE\", path, args); }\n");
+        output.push_str("}\n");
+        output.push_str("const http_client = (...args) => {\n");
+        output.push_str("  const [[baseUrl = \"\", headers = {}], kw] = __splitKwargs(args);\n");
+        output.push_str("  return new __HttpClient(kw.base_url ?? baseUrl, kw.headers ?? headers);\n");
+        output.push_str("};\n");
+        // __inject(method, path, body): a request handled by the last server
+        // defined, with no network in between. The response has fetch's fields
+        output.push_str("class __InjectedResponse extends require(\"stream\").Writable {\n");
+        output.push_str("  constructor() {\n");
+        output.push_str("    super();\n");
+        output.push_str("    this.statusCode = 200;\n");
+        output.push_str("    this.headersSent = false;\n");
+        output.push_str("    this.fields = {};\n");
+        output.push_str("    this.chunks = [];\n");
+        output.push_str("  }\n");
+        output.push_str("  setHeader(name, value) { this.fields[name.toLowerCase()] = value; return this; }\n");
+        output.push_str("  getHeader(name) { return this.fields[name.toLowerCase()]; }\n");
+        output.push_str("  getHeaders() { return { ...this.fields }; }\n");
+        output.push_str("  hasHeader(name) { return name.toLowerCase() in this.fields; }\n");
+        output.push_str("  removeHeader(name) { delete this.fields[name.toLowerCase()]; }\n");
+        output.push_str("  writeHead(status, headers = {}) {\n");
+        output.push_str("    this.statusCode = status;\n");
+        output.push_str("    for (const [name, value] of Object.entries(headers)) this.setHeader(name, value);\n");
+        output.push_str("    this.headersSent = true;\n");
+        output.push_str("    return this;\n");
+        output.push_str("  }\n");
+        output.push_str("  write(...args) {\n");
+        output.push_str("    this.headersSent = true;\n");
+        output.push_str("    return super.write(...args);\n");
+        output.push_str("  }\n");
+        output.push_str("  end(...args) {\n");
+        output.push_str("    this.headersSent = true;\n");
+        output.push_str("    return super.end(...args);\n");
+        output.push_str("  }\n");
+        output.push_str("  _write(chunk, encoding, done) {\n");
+        output.push_str("    this.chunks.push(Buffer.from(chunk, encoding));\n");
+        output.push_str("    done();\n");
+        output.push_str("  }\n");
+        output.push_str("}\n");
+        output.push_str("const __inject = async (...args) => {\n");
+        output.push_str("  const [[method, path, body = null], { headers = {} }] = __splitKwargs(args);\n");
+        output.push_str("  const server = globalThis.__harborServers?.at(-1);\n");
+        output.push_str("  if (!server) throw new HarborError(\"__inject needs a server to send the request to\");\n");
+        output.push_str("  const fields = { host: \"localhost\" };\n");
+        output.push_str("  for (const [name, value] of Object.entries(headers)) fields[name.toLowerCase()] = String(value);\n");
+        output.push_str("  const chunks = [];\n");
+        output.push_str("  if (body !== null) {\n");
+        output.push_str("    const [type, data] = __encodeBody(body);\n");
+        output.push_str("    fields[\"content-type\"] ??= type;\n");
+        output.push_str("    chunks.push(Buffer.from(data));\n");
+        output.push_str("  }\n");
+        output.push_str("  const req = require(\"stream\").Readable.from(chunks);\n");
+        output.push_str("  Object.assign(req, { method: String(method).toUpperCase(), url: String(path), headers: fields, socket: { remoteAddress: \"127.0.0.1\" } });\n");
+        output.push_str("  const res = new __InjectedResponse();\n");
+        output.push_str("  const finished = new Promise((resolve) => res.on(\"finish\", resolve));\n");
+        output.push_str("  server.emit(\"request\", req, res);\n");
+        output.push_str("  await finished;\n");
+        output.push_str("  const bytes = Buffer.concat(res.chunks);\n");
+        output.push_str("  const text = bytes.toString();\n");
+        output.push_str("  const type = String(res.getHeader(\"content-type\") || \"\");\n");
+        output.push_str("  let parsed = text;\n");
+        output.push_str("  if (type.includes(\"json\")) {\n");
+        output.push_str("    try { parsed = JSON.parse(text); } catch {}\n");
+        output.push_str("  }\n");
+        output.push_str("  return { statusCode: res.statusCode, headers: res.getHeaders(), url: String(path), bytes, text, body: parsed };\n");
+        output.push_str("};\n\n");
 
         output
     }
 
     // ─── Statement Code Generation ───
 
-    fn gen_stmt(stmt: &Stmt, req_name: &str, indent: &str) -> String {
+    fn write_stmt(&self, code: &mut String, stmt: &Stmt, req_name: &str, indent: &str) {
         let inner = format!("{}  ", indent);
-        let mut code = String::new();
 
         match stmt {
             Stmt::Set { target, value } => {
-                let val = Self::gen_val(value, req_name);
+                let val = self.emit_expr(value, req_name);
                 match target {
+                    Expr::Ident(name) if LOOP_LETS.with(|lets| lets.borrow().contains(name)) => {
+                        code.push_str(&format!("{}{} = {};\n", indent, name, val));
+                    }
                     Expr::Ident(name) => {
                         code.push_str(&format!("{}var {} = {};\n", indent, name, val));
                     }
                     Expr::Member(obj, field) => {
-                        let obj_code = Self::gen_val(obj, req_name);
+                        let obj_code = self.emit_expr(obj, req_name);
                         let final_obj = if obj_code == "self" { "this".to_string() } else { obj_code };
                         code.push_str(&format!("{}{}.{} = {};\n", indent, final_obj, field, val));
                     }
                     Expr::Index(obj, idx) => {
-                        code.push_str(&format!("{}{}[{}] = {};\n", indent,
-                            Self::gen_val(obj, req_name),
-                            Self::gen_val(idx, req_name),
+                        code.push_str(&format!("{}__setitem({}, {}, {});\n", indent,
+                            self.emit_expr(obj, req_name),
+                            self.emit_expr(idx, req_name),
                             val));
                     }
                     _ => {
-                        let target_code = Self::gen_val(target, req_name);
+                        let target_code = self.emit_expr(target, req_name);
                         code.push_str(&format!("{}{} = {};\n", indent, target_code, val));
                     }
                 }
             }
 
+            Stmt::AugAssign { target: Expr::Index(obj, idx), op, value } => {
+                // Evaluate the container and key once
+                let val = self.emit_expr(value, req_name);
+                code.push_str(&format!("{}{{\n", indent));
+                code.push_str(&format!("{}const __obj = {}, __key = {};\n", inner, self.emit_expr(obj, req_name), self.emit_expr(idx, req_name)));
+                code.push_str(&format!("{}__setitem(__obj, __key, __getitem(__obj, __key) {} {});\n", inner, op, val));
+                code.push_str(&format!("{}}}\n", indent));
+            }
+
             Stmt::AugAssign { target, op, value } => {
-                let target_code = Self::gen_val(target, req_name);
-                let val = Self::gen_val(value, req_name);
+                let target_code = self.emit_expr(target, req_name);
+                let val = self.emit_expr(value, req_name);
                 code.push_str(&format!("{}{} {}= {};\n", indent, target_code, op, val));
             }
 
             Stmt::Expression(expr) => {
-                let val = Self::gen_val(expr, req_name);
+                let val = self.emit_expr(expr, req_name);
                 code.push_str(&format!("{}{};\n", indent, val));
             }
 
             Stmt::Print(exprs) => {
-                let vals: Vec<String> = exprs.iter().map(|e| Self::gen_val(e, req_name)).collect();
+                let vals: Vec<String> = exprs.iter().map(|e| self.emit_text(e, req_name)).collect();
                 code.push_str(&format!("{}console.log({});\n", indent, vals.join(", ")));
             }
 
@@ -163,19 +1840,28 @@ impl CodeGen {
                 code.push_str(&format!("{}/* pass */\n", indent));
             }
 
+            Stmt::Comment(text) => {
+                code.push_str(&format!("{}//{}\n", indent, text));
+            }
+
+            // Picked out of the output again by `sourcemap::extract`
+            Stmt::Line(line) => {
+                code.push_str(&format!("{}{}{}\n", indent, sourcemap::MARKER, line));
+            }
+
             Stmt::If { condition, then_body, elif_branches, else_body } => {
-                let cond = Self::gen_val(condition, req_name);
+                let cond = self.emit_expr(condition, req_name);
                 code.push_str(&format!("{}if ({}) {{\n", indent, cond));
                 for s in then_body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                    self.write_stmt(code, s, req_name, &inner);
                 }
                 code.push_str(&format!("{}}}\n", indent));
 
                 for (elif_cond, elif_body) in elif_branches {
-                    let econd = Self::gen_val(elif_cond, req_name);
+                    let econd = self.emit_expr(elif_cond, req_name);
                     code.push_str(&format!("{}else if ({}) {{\n", indent, econd));
                     for s in elif_body {
-                        code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                        self.write_stmt(code, s, req_name, &inner);
                     }
                     code.push_str(&format!("{}}}\n", indent));
                 }
@@ -183,27 +1869,35 @@ impl CodeGen {
                 if let Some(else_stmts) = else_body {
                     code.push_str(&format!("{}else {{\n", indent));
                     for s in else_stmts {
-                        code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                        self.write_stmt(code, s, req_name, &inner);
                     }
                     code.push_str(&format!("{}}}\n", indent));
                 }
             }
 
             Stmt::ForIn { var, iterable, body } => {
-                let iter_val = Self::gen_val(iterable, req_name);
-                code.push_str(&format!("{}for (const {} of {}) {{\n", indent, var, iter_val));
-                for s in body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                let mut iter_val = self.emit_expr(iterable, req_name);
+                let is_list = match iterable {
+                    Expr::Array(_) => true,
+                    Expr::Call(callee, _) => matches!(&**callee, Expr::Ident(name) if ITERATING_BUILTINS.contains(&name.as_str())),
+                    _ => false,
+                };
+                if IN_SYNC.get() {
+                    if !is_list {
+                        iter_val = format!("__iterSync({})", iter_val);
+                    }
+                } else if !is_list {
+                    iter_val = format!("(await __iter({}))", iter_val);
                 }
+                code.push_str(&format!("{}for (const {} of {}) {{\n", indent, var, iter_val));
+                self.write_loop_body(code, body, req_name, &inner);
                 code.push_str(&format!("{}}}\n", indent));
             }
 
             Stmt::While { condition, body } => {
-                let cond = Self::gen_val(condition, req_name);
+                let cond = self.emit_expr(condition, req_name);
                 code.push_str(&format!("{}while ({}) {{\n", indent, cond));
-                for s in body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
-                }
+                self.write_loop_body(code, body, req_name, &inner);
                 code.push_str(&format!("{}}}\n", indent));
             }
 
@@ -215,17 +1909,20 @@ impl CodeGen {
                 code.push_str(&format!("{}continue;\n", indent));
             }
 
-            Stmt::Func { name, args, body } => {
-                code.push_str(&format!("{}async function {}({}) {{\n", indent, name, args.join(", ")));
-                for s in body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
-                }
+            Stmt::Func { name, args, body, sync } => {
+                let async_kw = if *sync { "" } else { "async " };
+                code.push_str(&format!("{}{}function {}({}) {{\n", indent, async_kw, name, args.join(", ")));
+                in_sync(*sync, || in_scope(args, body, || {
+                    for s in body {
+                        self.write_stmt(code, s, req_name, &inner);
+                    }
+                }));
                 code.push_str(&format!("{}}}\n", indent));
             }
 
             Stmt::Return(opt_expr) => {
                 if let Some(expr) = opt_expr {
-                    let val = Self::gen_val(expr, req_name);
+                    let val = self.emit_expr(expr, req_name);
                     code.push_str(&format!("{}return {};\n", indent, val));
                 } else {
                     code.push_str(&format!("{}return;\n", indent));
@@ -234,41 +1931,82 @@ impl CodeGen {
 
             Stmt::Class { name, methods } => {
                 code.push_str(&format!("{}class {} {{\n", indent, name));
+                let sync_methods: Vec<String> = methods.iter().filter_map(|method| match method {
+                    Stmt::Func { name, sync: true, .. } => Some(format!("this.{}", name)),
+                    _ => None,
+                }).collect();
+                SYNC_FUNCTIONS.with(|names| names.borrow_mut().extend(sync_methods.iter().cloned()));
                 for method in methods {
-                    if let Stmt::Func { name: m_name, args, body } = method {
+                    if let Stmt::Func { name: m_name, args, body, sync } = method {
                         let is_init = m_name == "init";
                         let js_name = if is_init { "constructor" } else { m_name.as_str() };
-                        let async_kw = if is_init { "" } else { "async " };
+                        let async_kw = if is_init || *sync { "" } else { "async " };
 
                         code.push_str(&format!("{}  {}{}({}) {{\n", indent, async_kw, js_name, args.join(", ")));
-                        for s in body {
-                            code.push_str(&Self::gen_stmt(s, "this", &format!("{}    ", indent)));
-                        }
+                        in_sync(is_init || *sync, || in_scope(args, body, || {
+                            for s in body {
+                                self.write_stmt(code, s, "this", &format!("{}    ", indent));
+                            }
+                        }));
                         code.push_str(&format!("{}  }}\n", indent));
                     }
                 }
+                SYNC_FUNCTIONS.with(|names| names.borrow_mut().retain(|name| !sync_methods.contains(name)));
                 code.push_str(&format!("{}}}\n", indent));
             }
 
-            Stmt::Try { body, except_var, except_body } => {
+            Stmt::Try { body, handlers } => {
                 code.push_str(&format!("{}try {{\n", indent));
                 for s in body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                    self.write_stmt(code, s, req_name, &inner);
                 }
-                let err_var = except_var.clone().unwrap_or_else(|| "_err".to_string());
-                code.push_str(&format!("{}}} catch ({}) {{\n", indent, err_var));
-                for s in except_body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
+                code.push_str(&format!("{}}} catch (__caught) {{\n", indent));
+                code.push_str(&format!("{}const __error = __toHarborError(__caught);\n", inner));
+                // Typed clauses become an instanceof chain; without a catch-all
+                // clause, unmatched errors are rethrown
+                let clause_indent = format!("{}  ", inner);
+                for (i, handler) in handlers.iter().enumerate() {
+                    let keyword = if i == 0 { "" } else { "} else " };
+                    if handler.types.is_empty() {
+                        code.push_str(&format!("{}{}{{\n", inner, keyword));
+                    } else {
+                        let test: Vec<String> = handler.types.iter().map(|t| format!("__error instanceof {}", t)).collect();
+                        code.push_str(&format!("{}{}if ({}) {{\n", inner, keyword, test.join(" || ")));
+                    }
+                    if let Some(var) = &handler.var {
+                        code.push_str(&format!("{}let {} = __error;\n", clause_indent, var));
+                    }
+                    for s in &handler.body {
+                        self.write_stmt(code, s, req_name, &clause_indent);
+                    }
+                }
+                if handlers.last().is_some_and(|h| !h.types.is_empty()) {
+                    code.push_str(&format!("{}}} else {{\n", inner));
+                    code.push_str(&format!("{}throw __error;\n", clause_indent));
                 }
+                code.push_str(&format!("{}}}\n", inner));
                 code.push_str(&format!("{}}}\n", indent));
             }
 
-            Stmt::Import { path, alias } => {
-                let import_path = if path.ends_with(".hb") {
-                    path.replace(".hb", ".js")
-                } else {
-                    path.clone()
+            Stmt::Raise(error) => {
+                let error = match error {
+                    // `raise StopIteration` raises an instance, as in Python
+                    Expr::Ident(name) if name.starts_with(|c: char| c.is_uppercase()) => format!("new {}()", name),
+                    _ => self.emit_expr(error, req_name),
+                };
+                code.push_str(&format!("{}throw {};\n", indent, error));
+            }
+
+            Stmt::Assert { condition, message } => {
+                let message = match message {
+                    Some(m) => self.emit_expr(m, req_name),
+                    None => "\"Assertion failed\"".to_string(),
                 };
+                code.push_str(&format!("{}if (!({})) throw new AssertionError(String({}));\n", indent, self.emit_expr(condition, req_name), message));
+            }
+
+            Stmt::Import { path, alias, resolved, .. } => {
+                let import_path = resolver::require_path_for(path, resolved.as_deref());
                 if let Some(name) = alias {
                     code.push_str(&format!("{}const {} = require(\"{}\");\n", indent, name, import_path));
                 } else {
@@ -276,18 +2014,17 @@ impl CodeGen {
                 }
             }
 
-            Stmt::FromImport { path, names } => {
-                let import_path = if path.ends_with(".hb") {
-                    path.replace(".hb", ".js")
-                } else {
-                    path.clone()
-                };
-                let names_str = names.join(", ");
+            Stmt::FromImport { path, names, resolved, .. } => {
+                let import_path = resolver::require_path_for(path, resolved.as_deref());
+                let names_str = names.iter().map(|n| match &n.alias {
+                    Some(alias) => format!("{}: {}", n.name, alias),
+                    None => n.name.clone(),
+                }).collect::<Vec<_>>().join(", ");
                 code.push_str(&format!("{}const {{ {} }} = require(\"{}\");\n", indent, names_str, import_path));
             }
 
             Stmt::Export(inner_stmt) => {
-                code.push_str(&Self::gen_stmt(inner_stmt, req_name, indent));
+                self.write_stmt(code, inner_stmt, req_name, indent);
                 match &**inner_stmt {
                     Stmt::Func { name, .. } => {
                         code.push_str(&format!("{}module.exports.{} = {};\n", indent, name, name));
@@ -295,129 +2032,149 @@ impl CodeGen {
                     Stmt::Class { name, .. } => {
                         code.push_str(&format!("{}module.exports.{} = {};\n", indent, name, name));
                     }
-                    Stmt::Set { target, .. } => {
-                        if let Expr::Ident(name) = target {
-                            code.push_str(&format!("{}module.exports.{} = {};\n", indent, name, name));
+                    Stmt::Set { target: Expr::Ident(name), .. } => {
+                        code.push_str(&format!("{}module.exports.{} = {};\n", indent, name, name));
+                    }
+                    Stmt::Import { alias: Some(name), .. } => {
+                        code.push_str(&format!("{}module.exports.{} = {};\n", indent, name, name));
+                    }
+                    Stmt::FromImport { names, .. } => {
+                        for name in names {
+                            code.push_str(&format!("{}module.exports.{} = {};\n", indent, name.renamed(), name.renamed()));
                         }
                     }
                     _ => {}
                 }
             }
 
+            Stmt::ExportNames(names) => {
+                for name in names {
+                    code.push_str(&format!("{}module.exports.{} = {};\n", indent, name.renamed(), name.name));
+                }
+            }
+
+            Stmt::ExportDefault(value) => {
+                // Marked the way TypeScript and Babel mark their output, so
+                // bundlers hand `module.exports.default` to a default import
+                code.push_str(&format!("{}Object.defineProperty(module.exports, \"__esModule\", {{ value: true }});\n", indent));
+                code.push_str(&format!("{}module.exports.default = {};\n", indent, self.emit_expr(value, req_name)));
+            }
+
             // ─── Harbor-specific ───
 
-            Stmt::Server { port, routes } => {
-                code.push_str(&Self::gen_server(port, routes, indent));
+            Stmt::Server(server) => {
+                code.push_str(&self.gen_server(server, indent));
             }
 
-            Stmt::Respond { status, value } => {
-                if let Some(status_code) = status {
-                    code.push_str(&format!("{}__res.statusCode = {};\n", indent, status_code));
+            Stmt::Respond { status, content_type, value } => {
+                if let Some(status) = status {
+                    code.push_str(&format!("{}__res.statusCode = {};\n", indent, self.emit_status(status, req_name)));
                 }
-                let val = Self::gen_val(value, req_name);
+                // `respond html f"..."` escapes like `html f"..."`
+                let val = match (content_type, value) {
+                    (Some(ContentType::Html), Expr::FString(parts)) => self.emit_expr(&Expr::Html(parts.clone()), req_name),
+                    _ => self.emit_expr(value, req_name),
+                };
                 code.push_str(&format!("{}const __val = {};\n", indent, val));
-                code.push_str(&format!("{}if (typeof __val === 'object' && __val !== null) {{\n", indent));
-                code.push_str(&format!("{}  __res.setHeader('Content-Type', 'application/json');\n", indent));
-                code.push_str(&format!("{}  __res.end(JSON.stringify(__val));\n", indent));
-                code.push_str(&format!("{}}} else {{\n", indent));
-                code.push_str(&format!("{}  __res.end(String(__val));\n", indent));
-                code.push_str(&format!("{}}}\n", indent));
-                code.push_str(&format!("{}return;\n", indent));
+                match content_type {
+                    Some(ContentType::Json) => {
+                        code.push_str(&format!("{}__res.setHeader('Content-Type', 'application/json');\n", indent));
+                        code.push_str(&format!("{}__res.end(JSON.stringify(__val));\n", indent));
+                    }
+                    Some(ContentType::Html) => {
+                        code.push_str(&format!("{}__res.setHeader('Content-Type', 'text/html; charset=utf-8');\n", indent));
+                        code.push_str(&format!("{}__res.end(String(__val));\n", indent));
+                    }
+                    Some(ContentType::Text) => {
+                        code.push_str(&format!("{}__res.setHeader('Content-Type', 'text/plain; charset=utf-8');\n", indent));
+                        code.push_str(&format!("{}__res.end(String(__val));\n", indent));
+                    }
+                    None => {
+                        code.push_str(&format!("{}__send(__res, __val);\n", indent));
+                    }
+                }
+                code.push_str(&format!("{}{}\n", indent, finish_route(req_name)));
             }
 
-            Stmt::Fetch { url, body } => {
-                let url_val = Self::gen_val(url, req_name);
-                code.push_str(&format!("{}const fetch_res = await fetchJson({});\n", indent, url_val));
-                code.push_str(&format!("{}{{\n", indent));
-                code.push_str(&format!("{}  const res = fetch_res;\n", indent));
-                for s in body {
-                    code.push_str(&Self::gen_stmt(s, req_name, &inner));
+            Stmt::RespondFile { status, path } => {
+                if let Some(status) = status {
+                    code.push_str(&format!("{}__res.statusCode = {};\n", indent, self.emit_status(status, req_name)));
                 }
-                code.push_str(&format!("{}}}\n", indent));
+                code.push_str(&format!("{}await __sendFile(__res, {});\n", indent, self.emit_expr(path, req_name)));
+                code.push_str(&format!("{}{}\n", indent, finish_route(req_name)));
             }
-        }
 
-        code
-    }
-
-    // ─── Server & Route Generation ───
-
-    fn gen_server(port: &Expr, routes: &[Route], indent: &str) -> String {
-        let mut code = String::new();
-        let port_val = Self::gen_val(port, "null");
-
-        code.push_str(&format!("{}const server = http.createServer(async (req, __res) => {{\n", indent));
-
-        for route in routes {
-            code.push_str(&Self::gen_route(route, indent));
-        }
-
-        code.push_str(&format!("{}  __res.statusCode = 404;\n", indent));
-        code.push_str(&format!("{}  __res.end(\"Not Found\");\n", indent));
-        code.push_str(&format!("{}}});\n\n", indent));
-
-        code.push_str(&format!("{}server.listen({}, () => {{\n", indent, port_val));
-        code.push_str(&format!("{}  console.log(`Harbor server running on http://127.0.0.1:${{{}}}`); \n", indent, port_val));
-        code.push_str(&format!("{}}});\n", indent));
-
-        code
-    }
+            Stmt::Stream { body } => {
+                code.push_str(&format!("{}__res.writeHead(__res.statusCode || 200, {{\"Content-Type\": \"text/event-stream\", \"Cache-Control\": \"no-cache\", \"Connection\": \"keep-alive\"}});\n", indent));
+                for s in body {
+                    self.write_stmt(code, s, req_name, indent);
+                }
+                code.push_str(&format!("{}__res.end();\n", indent));
+                code.push_str(&format!("{}{}\n", indent, finish_route(req_name)));
+            }
 
-    fn gen_route(route: &Route, base_indent: &str) -> String {
-        let mut code = String::new();
-        let indent = format!("{}  ", base_indent);
-        let inner = format!("{}  ", indent);
+            Stmt::Spawn { body } => {
+                code.push_str(&format!("{}__spawn(async () => {{\n", indent));
+                // Its own async function, which can wait inside a @sync one
+                in_sync(false, || in_scope(&[], body, || {
+                    for s in body {
+                        self.write_stmt(code, s, req_name, &inner);
+                    }
+                }));
+                code.push_str(&format!("{}}});\n", indent));
+            }
 
-        let has_params = route.path.contains(':');
+            Stmt::Every { count, unit_ms, body } => {
+                code.push_str(&format!("{}__every({} * {}, async () => {{\n", indent, self.emit_expr(count, req_name), unit_ms));
+                in_sync(false, || in_scope(&[], body, || {
+                    for s in body {
+                        self.write_stmt(code, s, req_name, &inner);
+                    }
+                }));
+                code.push_str(&format!("{}}});\n", indent));
+            }
 
-        if has_params {
-            let mut re_parts = Vec::new();
-            for part in route.path.split('/') {
-                if part.starts_with(':') {
-                    re_parts.push("([^/]+)".to_string());
-                } else if !part.is_empty() {
-                    re_parts.push(part.replace(".", "\\."));
-                }
+            Stmt::Cron { schedule, body } => {
+                code.push_str(&format!("{}__cron(\"{}\", async () => {{\n", indent, schedule));
+                in_sync(false, || in_scope(&[], body, || {
+                    for s in body {
+                        self.write_stmt(code, s, req_name, &inner);
+                    }
+                }));
+                code.push_str(&format!("{}}});\n", indent));
             }
-            let re_path = format!("^/{}$", re_parts.join("/"));
-            let var_name = format!("match_{}_{}", route.method.to_lowercase(),
-                route.path.replace("/", "_").replace(":", ""));
 
-            code.push_str(&format!("{}const {} = req.url.match(/{}/);\n", indent, var_name,
-                re_path.replace("/", "\\/")));
-            code.push_str(&format!("{}if ({} && req.method === \"{}\") {{\n", indent, var_name, route.method));
+            Stmt::Emit { event, data } => {
+                let event_val = match event {
+                    Some(e) => self.emit_expr(e, req_name),
+                    None => "null".to_string(),
+                };
+                let data_val = self.emit_expr(data, req_name);
+                code.push_str(&format!("{}__sse(__res, {}, {});\n", indent, event_val, data_val));
+            }
 
-            code.push_str(&format!("{}req.params = {{}};\n", inner));
-            let mut param_idx = 1;
-            for part in route.path.split('/') {
-                if part.starts_with(':') {
-                    let param_name = &part[1..];
-                    code.push_str(&format!("{}req.params[\"{}\"] = {}[{}];\n", inner, param_name, var_name, param_idx));
-                    param_idx += 1;
+            Stmt::Fetch { url, options, body } => {
+                // The URL may refer to an outer `res`, so the response is bound
+                // in a nested block; the outer one keeps `fetch_res` local
+                let url_val = self.emit_expr(url, req_name);
+                code.push_str(&format!("{}{{\n", indent));
+                code.push_str(&format!("{}const fetch_res = await fetchJson({}, {});\n", inner, url_val, self.gen_fetch_options(options, req_name)));
+                code.push_str(&format!("{}{{\n", inner));
+                code.push_str(&format!("{}  const res = fetch_res;\n", inner));
+                for s in body {
+                    self.write_stmt(code, s, req_name, &format!("{}  ", inner));
                 }
+                code.push_str(&format!("{}}}\n", inner));
+                code.push_str(&format!("{}}}\n", indent));
             }
-        } else {
-            code.push_str(&format!("{}if (req.url === \"{}\" && req.method === \"{}\") {{\n",
-                indent, route.path, route.method));
-        }
-
-        if route.method != "GET" {
-            code.push_str(&format!("{}req.body = await parseJsonBody(req);\n", inner));
-        }
-
-        for stmt in &route.body {
-            code.push_str(&Self::gen_stmt(stmt, "req", &inner));
         }
-
-        code.push_str(&format!("{}}}\n\n", indent));
-        code
     }
 
     // ─── Expression Code Generation ───
 
-    fn gen_val(expr: &Expr, req_name: &str) -> String {
+    fn emit_expr(&self, expr: &Expr, req_name: &str) -> String {
         match expr {
-            Expr::String(s) => format!("\"{}\"", s),
+            Expr::String(s) => js_string(s),
 
             Expr::FString(parts) => {
                 let mut s = String::from("`");
@@ -426,7 +2183,7 @@ impl CodeGen {
                         FStringExprPart::Literal(text) => s.push_str(text),
                         FStringExprPart::Expression(expr) => {
                             s.push_str("${");
-                            s.push_str(&Self::gen_val(expr, req_name));
+                            s.push_str(&self.emit_text(expr, req_name));
                             s.push('}');
                         }
                     }
@@ -435,7 +2192,25 @@ impl CodeGen {
                 s
             }
 
-            Expr::Number(n) => {
+            Expr::Html(parts) => {
+                let mut s = String::from("new __SafeHtml(`");
+                for part in parts {
+                    match part {
+                        FStringExprPart::Literal(text) => s.push_str(text),
+                        FStringExprPart::Expression(expr) => {
+                            s.push_str("${");
+                            s.push_str(&self.emit_html_part(expr, req_name));
+                            s.push('}');
+                        }
+                    }
+                }
+                s.push_str("`)");
+                s
+            }
+
+            // Kept as written where JavaScript reads it the same: 1_000_000, 0xff
+            Expr::Number(_, Some(text)) if numbers::is_js_literal(text) => text.clone(),
+            Expr::Number(n, _) => {
                 if *n == (*n as i64) as f64 && n.is_finite() {
                     format!("{}", *n as i64)
                 } else {
@@ -443,6 +2218,9 @@ impl CodeGen {
                 }
             }
 
+            Expr::BigInt(text) if numbers::is_js_literal(text) => format!("{}n", text),
+            Expr::BigInt(text) => format!("{}n", text.replace('_', "").trim_start_matches('0')),
+
             Expr::Bool(b) => b.to_string(),
 
             Expr::None => "null".to_string(),
@@ -458,15 +2236,12 @@ impl CodeGen {
             }
 
             Expr::Member(obj, field) => {
-                let obj_code = Self::gen_val(obj, req_name);
-                if obj_code == req_name && req_name != "null" {
+                let obj_code = self.emit_expr(obj, req_name);
+                // Outside a route, `req` is the request being handled
+                if obj_code == "req" || (obj_code == req_name && req_name != "null") {
                     match field.as_str() {
-                        "path" => format!("{}.url", req_name),
-                        "method" => format!("{}.method", req_name),
-                        "params" => format!("{}.params", req_name),
-                        "body" => format!("{}.body", req_name),
-                        "header" | "headers" => format!("{}.headers", req_name),
-                        _ => format!("{}.{}", req_name, field),
+                        "header" | "headers" => format!("{}.headers", obj_code),
+                        _ => format!("{}.{}", obj_code, field),
                     }
                 } else if obj_code == "res" {
                     match field.as_str() {
@@ -485,38 +2260,50 @@ impl CodeGen {
                 let mut obj_code = String::from("{");
                 for (i, (key, value)) in fields.iter().enumerate() {
                     if i > 0 { obj_code.push_str(", "); }
-                    obj_code.push_str(&format!("\"{}\": {}", key, Self::gen_val(value, req_name)));
+                    obj_code.push_str(&format!("{}: {}", js_string(key), self.emit_expr(value, req_name)));
                 }
                 obj_code.push('}');
                 obj_code
             }
 
+            Expr::Dict(entries) => {
+                let entries: Vec<String> = entries.iter()
+                    .map(|(key, value)| format!("[{}, {}]", self.emit_expr(key, req_name), self.emit_expr(value, req_name)))
+                    .collect();
+                format!("new __Dict([{}])", entries.join(", "))
+            }
+
             Expr::Array(elements) => {
                 let mut arr_code = String::from("[");
                 for (i, el) in elements.iter().enumerate() {
                     if i > 0 { arr_code.push_str(", "); }
-                    arr_code.push_str(&Self::gen_val(el, req_name));
+                    arr_code.push_str(&self.emit_expr(el, req_name));
                 }
                 arr_code.push(']');
                 arr_code
             }
 
-            Expr::Binary(left, op, right) => {
-                let l = Self::gen_val(left, req_name);
-                let r = Self::gen_val(right, req_name);
+            Expr::Binary(_, op, _) => {
+                let (l, r) = self.emit_operands(expr, req_name);
+                let big = numbers::kind(expr) == Some(Kind::Big);
                 match op.as_str() {
                     "and" => format!("({} && {})", l, r),
                     "or" => format!("({} || {})", l, r),
-                    "in" => format!("__contains({}, {})", r, l),
-                    "not in" => format!("!__contains({}, {})", r, l),
+                    // Classes can answer asynchronously, through __contains__ or __iter__
+                    "in" if IN_SYNC.get() => format!("__containsSync({}, {})", r, l),
+                    "not in" if IN_SYNC.get() => format!("!__containsSync({}, {})", r, l),
+                    "in" => format!("(await __contains({}, {}))", r, l),
+                    "not in" => format!("!(await __contains({}, {}))", r, l),
+                    "**" if big => format!("({} ** {})", l, r),
                     "**" => format!("Math.pow({}, {})", l, r),
+                    "//" if big => format!("__bigFloorDiv({}, {})", l, r),
                     "//" => format!("Math.floor({} / {})", l, r),
                     _ => format!("({} {} {})", l, op, r),
                 }
             }
 
             Expr::Unary(op, right) => {
-                let r = Self::gen_val(right, req_name);
+                let r = self.emit_expr(right, req_name);
                 match op.as_str() {
                     "not" => format!("(!{})", r),
                     _ => format!("({}{})", op, r),
@@ -524,27 +2311,477 @@ impl CodeGen {
             }
 
             Expr::Index(obj, idx) => {
-                format!("{}[{}]",
-                    Self::gen_val(obj, req_name),
-                    Self::gen_val(idx, req_name))
+                format!("__getitem({}, {})",
+                    self.emit_expr(obj, req_name),
+                    self.emit_expr(idx, req_name))
             }
 
             Expr::Call(func, args) => {
-                let func_code = Self::gen_val(func, req_name);
-                let args_strs: Vec<String> = args.iter()
-                    .map(|a| Self::gen_val(a, req_name))
+                let func_code = self.emit_expr(func, req_name);
+                let mut args_strs: Vec<String> = args.iter()
+                    .filter(|a| !matches!(a, Expr::Keyword(..)))
+                    .map(|a| self.emit_expr(a, req_name))
+                    .collect();
+                // Keyword arguments travel as one trailing __kw({...}) object
+                let keywords: Vec<String> = args.iter()
+                    .filter_map(|a| match a {
+                        Expr::Keyword(name, value) => Some(format!("{}: {}", name, self.emit_expr(value, req_name))),
+                        _ => None,
+                    })
                     .collect();
+                if !keywords.is_empty() {
+                    args_strs.push(format!("__kw({{{}}})", keywords.join(", ")));
+                }
                 let args_code = args_strs.join(", ");
 
+                // Python method names dispatch at runtime on the receiver's type
+                if let Expr::Member(obj, method) = &**func {
+                    if PYTHON_METHODS.contains(&method.as_str()) {
+                        let mut call_args = vec![self.emit_expr(obj, req_name), format!("\"{}\"", method)];
+                        call_args.extend(args_strs);
+                        if IN_SYNC.get() {
+                            return format!("__method({})", call_args.join(", "));
+                        }
+                        return format!("(await __method({}))", call_args.join(", "));
+                    }
+                }
+
                 // PascalCase detection: class instantiation (no 'new' keyword needed)
                 if let Expr::Ident(name) = &**func {
-                    if name.chars().next().map_or(false, |c| c.is_uppercase()) {
+                    if name.chars().next().is_some_and(|c| c.is_uppercase()) {
                         return format!("new {}({})", func_code, args_code);
                     }
                 }
 
+                // A @sync function, or anything called where nothing can wait
+                if IN_SYNC.get() || is_sync_function(&func_code) {
+                    return format!("{}({})", func_code, args_code);
+                }
                 format!("(await {}({}))", func_code, args_code)
             }
+
+            Expr::Fetch { url, options } => {
+                format!("(await fetchJson({}, {}))", self.emit_expr(url, req_name), self.gen_fetch_options(options, req_name))
+            }
+
+            Expr::Keyword(_, value) => self.emit_expr(value, req_name),
+
+            Expr::Spawn(task) => format!("__spawn(async () => {})", in_sync(false, || self.emit_expr(task, req_name))),
+
+            Expr::Await(task) => format!("(await {})", self.emit_expr(task, req_name)),
+        }
+    }
+}
+
+impl NodeBackend {
+    /// A value being printed or interpolated, through `__tostr` unless it's
+    /// known to be a string or number (`__tostrSync` in a constructor or
+    /// `@sync` function, which can't wait for an async `__str__`).
+    fn emit_text(&self, expr: &Expr, req_name: &str) -> String {
+        let code = self.emit_expr(expr, req_name);
+        match numbers::kind(expr) {
+            // console.log would show an `n` suffix
+            Some(Kind::Big) => format!("String({})", code),
+            Some(_) => code,
+            None if matches!(expr, Expr::String(_) | Expr::FString(_)) => code,
+            None if IN_SYNC.get() => format!("__tostrSync({})", code),
+            None => format!("(await __tostr({}))", code),
+        }
+    }
+
+    /// A value interpolated into `html f"..."`, escaped unless it's markup
+    /// from another `html` string.
+    fn emit_html_part(&self, expr: &Expr, req_name: &str) -> String {
+        let code = self.emit_expr(expr, req_name);
+        let plain = matches!(expr, Expr::String(_) | Expr::FString(_) | Expr::Html(_)) || numbers::kind(expr).is_some();
+        if plain {
+            format!("__escapeHtml({})", code)
+        } else if IN_SYNC.get() {
+            format!("__htmlPartSync({})", code)
+        } else {
+            format!("(await __htmlPart({}))", code)
+        }
+    }
+
+    /// A binary expression's operands, converted where a BigInt meets
+    /// another number: integers become BigInts for integer results and
+    /// comparisons, and BigInts become doubles where the result is a float.
+    fn emit_operands(&self, expr: &Expr, req_name: &str) -> (String, String) {
+        let Expr::Binary(left, op, right) = expr else { unreachable!("only binary expressions have operands") };
+        let kinds = (numbers::kind(left), numbers::kind(right));
+        let has_big = kinds.0 == Some(Kind::Big) || kinds.1 == Some(Kind::Big);
+        let comparison = matches!(op.as_str(), "===" | "!==" | "<" | ">" | "<=" | ">=");
+        let emit = |side: &Expr, kind: Option<Kind>, target: Kind| {
+            let code = self.emit_expr(side, req_name);
+            match (target, kind, side) {
+                (Kind::Big, Some(Kind::Int), Expr::Number(_, Some(text))) if numbers::is_js_literal(text) && !text.contains('.') => format!("{}n", text),
+                (Kind::Big, Some(Kind::Int), Expr::Number(n, _)) => format!("{}n", *n as i64),
+                (Kind::Big, Some(Kind::Int), _) => format!("BigInt({})", code),
+                (Kind::Float, Some(Kind::Big), _) => format!("Number({})", code),
+                _ => code,
+            }
+        };
+        match numbers::kind(expr) {
+            Some(Kind::Big) => (emit(left, kinds.0, Kind::Big), emit(right, kinds.1, Kind::Big)),
+            Some(Kind::Float) if has_big => (emit(left, kinds.0, Kind::Float), emit(right, kinds.1, Kind::Float)),
+            _ if comparison && has_big => (emit(left, kinds.0, Kind::Big), emit(right, kinds.1, Kind::Big)),
+            _ => (self.emit_expr(left, req_name), self.emit_expr(right, req_name)),
+        }
+    }
+
+    fn gen_fetch_options(&self, options: &[(String, Expr)], req_name: &str) -> String {
+        let fields: Vec<String> = options.iter()
+            .map(|(name, value)| format!("{}: {}", name, self.emit_expr(value, req_name)))
+            .collect();
+        format!("{{{}}}", fields.join(", "))
+    }
+
+    // ─── Server & Route Generation ───
+
+    fn gen_server(&self, server: &Server, indent: &str) -> String {
+        let mut code = String::new();
+        let port_val = self.emit_expr(&server.port, "null");
+        let routes = server.flat_routes();
+
+        // Limiters and caches live outside the handler so they persist across requests
+        if let Some(limit) = &server.limit {
+            code.push_str(&format!("{}const __limit_server = __rateLimiter({}, {});\n", indent, self.emit_expr(&limit.count, "null"), limit.window_ms));
+        }
+        for (index, route) in routes.iter().enumerate() {
+            if let Some(limit) = &route.limit {
+                code.push_str(&format!("{}const __limit_{} = __rateLimiter({}, {});\n", indent, index, self.emit_expr(&limit.count, "null"), limit.window_ms));
+            }
+            if let Some(ttl) = &route.cache {
+                let authed = server.auth.is_some() || route.auth.is_some();
+                code.push_str(&format!("{}const __cache_{} = __responseCache({}, {});\n", indent, index, self.emit_expr(ttl, "null"), authed));
+            }
+        }
+
+        if server.metrics.is_some() {
+            code.push_str(&format!("{}const __metrics = __createMetrics();\n", indent));
+        }
+        code.push_str(&self.gen_route_table(&routes, indent));
+        code.push('\n');
+
+        match &server.tls {
+            Some(tls) => {
+                let cert = self.emit_expr(&tls.cert, "null");
+                let key = self.emit_expr(&tls.key, "null");
+                code.push_str(&format!("{}const server = https.createServer({{ cert: require(\"fs\").readFileSync({}), key: require(\"fs\").readFileSync({}) }}, (req, __res) => __requests.run({{ req, res: __res }}, async () => {{\n", indent, cert, key));
+            }
+            None => {
+                code.push_str(&format!("{}const server = http.createServer((req, __res) => __requests.run({{ req, res: __res }}, async () => {{\n", indent));
+            }
+        }
+
+        let handler_indent = format!("{}  ", indent);
+        let catch_indent = if server.after.is_empty() { handler_indent.clone() } else { format!("{}  ", handler_indent) };
+        let body_indent = format!("{}  ", catch_indent);
+        let mut body = String::new();
+        body.push_str(&format!("{}__requestInfo(req, {});\n", body_indent, server.tls.is_some()));
+        body.push_str(&self.gen_headers(&server.headers, &body_indent));
+        // Probes and scrapes skip logging, limits, auth and hooks
+        if let Some(path) = &server.health {
+            body.push_str(&format!("{}if (req.method === \"GET\" && req.path === {}) {{\n", body_indent, js_string(path)));
+            body.push_str(&format!("{}  __res.writeHead(200, {{ \"Content-Type\": \"application/json\" }});\n", body_indent));
+            body.push_str(&format!("{}  __res.end(JSON.stringify({{ status: \"ok\", uptime: process.uptime() }}));\n", body_indent));
+            body.push_str(&format!("{}  return;\n", body_indent));
+            body.push_str(&format!("{}}}\n", body_indent));
+        }
+        if let Some(path) = &server.metrics {
+            body.push_str(&format!("{}if (req.method === \"GET\" && req.path === {}) {{\n", body_indent, js_string(path)));
+            body.push_str(&format!("{}  __res.writeHead(200, {{ \"Content-Type\": \"text/plain; version=0.0.4\" }});\n", body_indent));
+            body.push_str(&format!("{}  __res.end(__metrics.render());\n", body_indent));
+            body.push_str(&format!("{}  return;\n", body_indent));
+            body.push_str(&format!("{}}}\n", body_indent));
+            body.push_str(&format!("{}__metrics.track(req, __res);\n", body_indent));
+        }
+        if let Some(format) = &server.log {
+            let format = format.as_ref().map(|f| js_string(f)).unwrap_or_else(|| "null".to_string());
+            body.push_str(&format!("{}__logRequest(req, __res, {});\n", body_indent, format));
+        }
+        if server.limit.is_some() {
+            body.push_str(&format!("{}if (!__limit_server(req, __res)) return;\n", body_indent));
+        }
+        if let Some(auth) = &server.auth {
+            body.push_str(&self.gen_auth(auth, &body_indent));
+        }
+        for stmt in &server.before {
+            self.write_stmt(&mut body, stmt, "req", &body_indent);
+        }
+        body.push_str(&format!("{}if (await __dispatch(__routes, req, __res)) return;\n", body_indent));
+        for stmt in &server.not_found {
+            self.write_stmt(&mut body, stmt, "req", &body_indent);
+        }
+        body.push_str(&format!("{}__res.statusCode = 404;\n", body_indent));
+        body.push_str(&format!("{}__res.end(\"Not Found\");\n", body_indent));
+        let body = self.with_catch(body, server.on_error.as_ref(), &catch_indent);
+        code.push_str(&self.with_after(body, &server.after, &handler_indent));
+
+        code.push_str(&format!("{}}}));\n\n", indent));
+
+        // Evaluated once, out here where it can await
+        code.push_str(&format!("{}const __port = {};\n", indent, port_val));
+        code.push_str(&format!("{}__listen(server, __port, () => {{\n", indent));
+        let scheme = if server.tls.is_some() { "https" } else { "http" };
+        code.push_str(&format!("{}  console.log(`Harbor server running on {}://127.0.0.1:${{__port}}`); \n", indent, scheme));
+        code.push_str(&format!("{}}});\n", indent));
+
+        code.push_str(&format!("{}__gracefulShutdown(server, async () => {{\n", indent));
+        for stmt in &server.on_shutdown {
+            self.write_stmt(&mut code, stmt, "null", &format!("{}  ", indent));
+        }
+        code.push_str(&format!("{}}});\n", indent));
+
+        code
+    }
+
+    /// The status code a `respond` sets, as JS.
+    fn emit_status(&self, status: &Status, req_name: &str) -> String {
+        match status {
+            Status::Code(code) => code.to_string(),
+            Status::Named(name) => Status::code_for(name).expect("parser only accepts known status names").to_string(),
+            Status::Expr(expr) => self.emit_expr(expr, req_name),
+        }
+    }
+
+    /// Wrap already-emitted `body` (indented one level deeper than `indent`)
+    /// so a throwing handler runs the `on_error` block and, if that doesn't
+    /// respond, gets a 500 instead of crashing the process.
+    fn with_catch(&self, body: String, on_error: Option<&(String, Vec<Stmt>)>, indent: &str) -> String {
+        let inner = format!("{}  ", indent);
+        let mut code = format!("{}try {{\n", indent);
+        code.push_str(&body);
+        match on_error {
+            Some((name, handler)) => {
+                code.push_str(&format!("{}}} catch (__caught) {{\n", indent));
+                code.push_str(&format!("{}if (__caught === __responded) return;\n", inner));
+                code.push_str(&format!("{}const {} = __toHarborError(__caught);\n", inner, name));
+                code.push_str(&format!("{}try {{\n", inner));
+                for stmt in handler {
+                    self.write_stmt(&mut code, stmt, "req", &format!("{}  ", inner));
+                }
+                code.push_str(&format!("{}}} catch (__err) {{\n", inner));
+                code.push_str(&format!("{}  __serverError(req, __res, __err);\n", inner));
+                code.push_str(&format!("{}  return;\n", inner));
+                code.push_str(&format!("{}}}\n", inner));
+                code.push_str(&format!("{}__serverError(req, __res, {});\n", inner, name));
+            }
+            None => {
+                code.push_str(&format!("{}}} catch (__err) {{\n", indent));
+                code.push_str(&format!("{}__serverError(req, __res, __err);\n", inner));
+            }
+        }
+        code.push_str(&format!("{}}}\n", indent));
+        code
+    }
+
+    /// Append `after` hooks to already-emitted `body`, in a `finally` so they
+    /// still run when a `respond` returns early. `body` must be indented one
+    /// level deeper than `indent` when there are hooks.
+    fn with_after(&self, body: String, after: &[Stmt], indent: &str) -> String {
+        if after.is_empty() {
+            return body;
+        }
+        let mut code = format!("{}try {{\n", indent);
+        code.push_str(&body);
+        code.push_str(&format!("{}}} finally {{\n", indent));
+        // The response has usually gone out by now; a failing hook is only logged
+        code.push_str(&format!("{}  try {{\n", indent));
+        for stmt in after {
+            self.write_stmt(&mut code, stmt, "req", &format!("{}    ", indent));
+        }
+        code.push_str(&format!("{}  }} catch (__err) {{\n", indent));
+        code.push_str(&format!("{}    __serverError(req, __res, __err);\n", indent));
+        code.push_str(&format!("{}  }}\n", indent));
+        code.push_str(&format!("{}}}\n", indent));
+        code
+    }
+
+    fn gen_auth(&self, auth: &Auth, indent: &str) -> String {
+        let scheme = match auth.scheme {
+            AuthScheme::Basic => "basic",
+            AuthScheme::Bearer => "bearer",
+        };
+        format!("{}if (!__requireAuth(req, __res, \"{}\", {})) return;\n", indent, scheme, self.emit_expr(&auth.secret, "req"))
+    }
+
+    /// Emit the route table: method -> ordered `[path or regex, handler]`
+    /// entries, built once when the server starts.
+    fn gen_route_table(&self, routes: &[Route], indent: &str) -> String {
+        let mut methods: Vec<(&str, Vec<(usize, &Route)>)> = Vec::new();
+        for (index, route) in routes.iter().enumerate() {
+            match methods.iter_mut().find(|(m, _)| *m == route.method) {
+                Some((_, list)) => list.push((index, route)),
+                None => methods.push((&route.method, vec![(index, route)])),
+            }
+        }
+
+        let mut code = format!("{}const __routes = {{\n", indent);
+        for (method, list) in methods {
+            code.push_str(&format!("{}  {}: [\n", indent, method));
+            for (index, route) in list {
+                code.push_str(&self.gen_route(route, index, &format!("{}    ", indent)));
+            }
+            code.push_str(&format!("{}  ],\n", indent));
+        }
+        code.push_str(&format!("{}}};\n", indent));
+        code
+    }
+
+    /// A loop's statements, with the variables that `scoping` finds should
+    /// be bound per iteration declared by `let`.
+    fn write_loop_body(&self, code: &mut String, body: &[Stmt], req_name: &str, indent: &str) {
+        let lets = SCOPES.with(|scopes| scopes.borrow().last().map(|scope| scoping::per_iteration(body, scope)).unwrap_or_default());
+        LOOP_LETS.with(|names| names.borrow_mut().extend(lets.iter().map(|(name, _)| name.clone())));
+        for (i, stmt) in body.iter().enumerate() {
+            match (lets.iter().find(|(_, at)| *at == i), stmt) {
+                (Some((name, _)), Stmt::Set { value, .. }) => {
+                    code.push_str(&format!("{}let {} = {};\n", indent, name, self.emit_expr(value, req_name)));
+                }
+                _ => self.write_stmt(code, stmt, req_name, indent),
+            }
+        }
+        LOOP_LETS.with(|names| lets.iter().for_each(|(name, _)| {
+            names.borrow_mut().remove(name);
+        }));
+    }
+
+    /// Set each of `headers` on the response, ahead of whatever sends it.
+    fn gen_headers(&self, headers: &[(String, Expr)], indent: &str) -> String {
+        headers.iter()
+            .map(|(name, value)| format!("{}__res.setHeader(\"{}\", {});\n", indent, name, self.emit_expr(value, "req")))
+            .collect()
+    }
+
+    /// Emit one route table entry at `indent`. `index` is the route's place
+    /// in the server, which names its limiter and cache.
+    fn gen_route(&self, route: &Route, index: usize, indent: &str) -> String {
+        let mut code = String::new();
+        let inner = format!("{}  ", indent);
+
+        let segments = parse_route_path(&route.path).unwrap_or_default();
+        let has_params = segments.iter().any(|s| matches!(s, PathSegment::Param { .. }));
+
+        if has_params {
+            let mut re_parts = Vec::new();
+            for segment in &segments {
+                match segment {
+                    PathSegment::Static(text) => re_parts.push(regex_escape(text)),
+                    PathSegment::Param { name, kind } => {
+                        let pattern = match kind {
+                            ParamKind::Any => "[^/]+",
+                            ParamKind::Int => "-?\\d+",
+                            ParamKind::Float => "-?\\d+(?:\\.\\d+)?",
+                            ParamKind::Uuid => "[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+                            ParamKind::Pattern(p) => p.as_str(),
+                        };
+                        re_parts.push(format!("(?<{}>{})", name, pattern));
+                    }
+                }
+            }
+            let re_path = format!("^/{}$", re_parts.join("/"));
+            code.push_str(&format!("{}[/{}/, async (req, __res, __match) => {{\n", indent, re_path.replace("/", "\\/")));
+
+            code.push_str(&format!("{}req.params = {{}};\n", inner));
+            for segment in &segments {
+                if let PathSegment::Param { name, kind } = segment {
+                    let raw = format!("__match.groups[\"{}\"]", name);
+                    let value = match kind {
+                        ParamKind::Int | ParamKind::Float => format!("Number({})", raw),
+                        _ => raw,
+                    };
+                    code.push_str(&format!("{}req.params[\"{}\"] = {};\n", inner, name, value));
+                }
+            }
+        } else {
+            code.push_str(&format!("{}[{}, async (req, __res) => {{\n", indent, js_string(&route.path)));
+        }
+
+        code.push_str(&self.gen_headers(&route.headers, &inner));
+        if route.limit.is_some() {
+            code.push_str(&format!("{}if (!__limit_{}(req, __res)) return;\n", inner, index));
+        }
+        if let Some(auth) = &route.auth {
+            code.push_str(&self.gen_auth(auth, &inner));
+        }
+        if route.cache.is_some() {
+            let cache = format!("__cache_{}", index);
+            code.push_str(&format!("{}if ({}.hit(req, __res)) return;\n", inner, cache));
+            code.push_str(&format!("{}{}.record(req, __res);\n", inner, cache));
+        }
+        if route.method != "GET" || route.expect.is_some() {
+            code.push_str(&format!("{}req.body = await parseBody(req);\n", inner));
+        }
+        if let Some(schema) = &route.expect {
+            code.push_str(&format!("{}if (!__expectBody(req, __res, {})) return;\n", inner, gen_schema(schema)));
+        }
+
+        let body_indent = if route.after.is_empty() { inner.clone() } else { format!("{}  ", inner) };
+        let mut body = String::new();
+        for stmt in route.before.iter().chain(&route.body) {
+            self.write_stmt(&mut body, stmt, "req", &body_indent);
+        }
+        // `__dispatch` sends what the handler returns
+        if let Some(handler) = &route.handler {
+            body.push_str(&format!("{}return await {}(req);\n", body_indent, self.emit_expr(handler, "req")));
+        }
+        code.push_str(&self.with_after(body, &route.after, &inner));
+
+        code.push_str(&format!("{}}}],\n", indent));
+        code
+    }
+}
+
+/// An `expect` schema as a JS value: type names as strings, `[type]` for
+/// lists and objects for dicts.
+fn gen_schema(schema: &Schema) -> String {
+    match schema {
+        Schema::Type(name) => format!("\"{}\"", name),
+        Schema::List(item) => format!("[{}]", gen_schema(item)),
+        Schema::Dict(fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(key, value)| format!("{}: {}", js_string(key), gen_schema(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+/// How `respond` ends: it returns from a route, but in a function it throws
+/// to stop the route that called the function.
+fn finish_route(req_name: &str) -> &'static str {
+    if req_name == "req" { "return;" } else { "throw __responded;" }
+}
+
+/// Escape regex metacharacters in a literal path segment.
+fn regex_escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+/// A Harbor string literal as a double-quoted JS string. Escapes are kept
+/// verbatim; only a bare `"` (legal inside `'...'`) needs quoting.
+fn js_string(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('"');
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                out.push(c);
+                out.extend(chars.next());
+            }
+            '"' => out.push_str("\\\""),
+            c => out.push(c),
         }
     }
+    out.push('"');
+    out
 }
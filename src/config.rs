@@ -0,0 +1,118 @@
+//! Project settings from `harbor.toml`.
+//!
+//! The file sits in the project root and applies to every source below it.
+//! Only the small part of TOML the settings need is understood: `key =
+//! "string"` and `key = true` pairs, `[section]` headers, `#` comments and
+//! blank lines.
+//!
+//! ```toml
+//! # Definitions exported here are available in every module
+//! prelude = "src/prelude.hb"
+//!
+//! # Turn warnings off or on by code
+//! [warnings]
+//! W001 = "off"
+//! W002 = true
+//! ```
+
+use crate::warnings;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const FILE_NAME: &str = "harbor.toml";
+
+#[derive(Debug, Clone, Default)]
+pub struct Config {
+    /// A module whose exports every other module can use without
+    /// importing them.
+    pub prelude: Option<PathBuf>,
+    /// Warning codes from `[warnings]`, turned on (`true`) or off.
+    pub warnings: Vec<(String, bool)>,
+}
+
+impl Config {
+    /// The settings for sources in `dir`: from the nearest `harbor.toml` in
+    /// it or a parent directory, or the defaults if there is none.
+    pub fn find(dir: &Path) -> Result<Config, String> {
+        let dir = std::path::absolute(dir).unwrap_or_else(|_| dir.to_path_buf());
+        for ancestor in dir.ancestors() {
+            let path = ancestor.join(FILE_NAME);
+            if path.is_file() {
+                let text = fs::read_to_string(&path)
+                    .map_err(|e| format!("Error: Could not read file '{}': {}", path.display(), e))?;
+                return Config::parse(&text, ancestor).map_err(|e| format!("{}: {}", path.display(), e));
+            }
+        }
+        Ok(Config::default())
+    }
+
+    /// Parse the settings in `text`, resolving paths against `root`.
+    pub fn parse(text: &str, root: &Path) -> Result<Config, String> {
+        let mut config = Config::default();
+        let mut section = String::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |msg: &str| format!("Error: {} at line {}", msg, i + 1);
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.split_once(']')) {
+                section = name.0.trim().to_string();
+                if section != "warnings" {
+                    return Err(error(&format!("Unknown section '[{}]'", section)));
+                }
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error("Expected `key = \"value\"`"))?;
+            let value = value.trim();
+            match (section.as_str(), key.trim()) {
+                ("", "prelude") => {
+                    let path = parse_string(value).ok_or_else(|| error("Expected a quoted string"))?;
+                    config.prelude = Some(root.join(path));
+                }
+                ("warnings", code) if warnings::is_known(code) => {
+                    let on = parse_bool(value).or_else(|| match parse_string(value)?.as_str() {
+                        "on" => Some(true),
+                        "off" => Some(false),
+                        _ => None,
+                    });
+                    let on = on.ok_or_else(|| error(&format!("Expected true, false, \"on\" or \"off\" for {}", code)))?;
+                    config.warnings.push((code.to_string(), on));
+                }
+                ("warnings", code) => return Err(error(&format!("Unknown warning '{}'", code))),
+                (_, other) => return Err(error(&format!("Unknown setting '{}'", other))),
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// A basic or literal TOML string, optionally followed by a comment.
+fn parse_string(text: &str) -> Option<String> {
+    let quote = text.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let mut value = String::new();
+    let mut chars = text[1..].chars();
+    loop {
+        match chars.next()? {
+            c if c == quote => break,
+            '\\' if quote == '"' => match chars.next()? {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                c @ ('"' | '\\') => value.push(c),
+                _ => return None,
+            },
+            c => value.push(c),
+        }
+    }
+    let rest = chars.as_str().trim();
+    (rest.is_empty() || rest.starts_with('#')).then_some(value)
+}
+
+/// A TOML boolean, optionally followed by a comment.
+fn parse_bool(text: &str) -> Option<bool> {
+    match text.split('#').next()?.trim() {
+        "true" => Some(true),
+        "false" => Some(false),
+        _ => None,
+    }
+}
@@ -0,0 +1,153 @@
+//! `harbor daemon`: a long-running compiler for editors and watch tools.
+//!
+//! The daemon listens on a local TCP port and answers one request per line,
+//! keeping each document's parsed chunks between requests so that an edit
+//! only re-parses the statements it touched. Requests:
+//!
+//! ```text
+//! check <path> <length>\n<source>     -> {"diagnostics": [...]}
+//! compile <path> <length>\n<source>   -> {"js": ..., "diagnostics": [...]}
+//! build <path>\n                      -> {"ok": ..., "errors": [...], "warnings": [...]}
+//! shutdown\n                          -> {"ok": true}
+//! ```
+//!
+//! `check` and `compile` take the editor's unsaved text, `length` bytes of
+//! it; `path` names the document, and `compile` gives the JavaScript a
+//! build of that text would write there, without writing it. `build`
+//! compiles the file on disk next to its source, like `harbor build`. Each answer is a single line of
+//! JSON, and a connection can send any number of requests.
+
+use harbor::cache::Cache;
+use harbor::diagnostics::{Diagnostic, Severity};
+use harbor::incremental::IncrementalParser;
+use harbor::{checks, json_string, strict, warnings, CompileOutput};
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// What the daemon keeps between requests.
+struct State {
+    options: harbor::BuildOptions,
+    use_cache: bool,
+    /// Parsed chunks of each open document, by path.
+    documents: Mutex<HashMap<String, IncrementalParser>>,
+}
+
+pub fn run(port: u16, options: harbor::BuildOptions, use_cache: bool) -> Result<(), String> {
+    let listener = TcpListener::bind(("127.0.0.1", port))
+        .map_err(|e| format!("Error: Could not listen on port {}: {}", port, e))?;
+    let state = Arc::new(State { options, use_cache, documents: Mutex::new(HashMap::new()) });
+    println!("Harbor daemon: listening on 127.0.0.1:{} (Ctrl+C to stop)", port);
+    for stream in listener.incoming() {
+        let Ok(stream) = stream else { continue };
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            // A client that goes away mid-request has nothing to hear
+            let _ = serve(stream, &state);
+        });
+    }
+    Ok(())
+}
+
+/// Answer requests on `stream` until the client disconnects.
+fn serve(stream: TcpStream, state: &State) -> io::Result<()> {
+    // Answers are small; don't hold them back waiting for more to send
+    stream.set_nodelay(true)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let reply = match words.as_slice() {
+            [] => continue,
+            ["check", path, length] | ["compile", path, length] => match length.parse::<usize>() {
+                Ok(length) => {
+                    let mut source = vec![0; length];
+                    reader.read_exact(&mut source)?;
+                    let source = String::from_utf8_lossy(&source);
+                    if words[0] == "check" {
+                        format!("{{\"diagnostics\": {}}}", harbor::diagnostics_json(&check(state, path, &source)))
+                    } else {
+                        compile(state, path, &source).to_json()
+                    }
+                }
+                Err(_) => error(&format!("'{}' is not a length in bytes", length)),
+            },
+            ["build", path] => build(state, path),
+            ["shutdown"] => {
+                writeln!(writer, "{{\"ok\": true}}")?;
+                std::process::exit(0);
+            }
+            _ => error(&format!("Unknown request '{}'", line.trim_end())),
+        };
+        writeln!(writer, "{}", reply)?;
+    }
+}
+
+/// Parse `source` with the document's incremental parser, keeping the
+/// chunks for its next version.
+fn parse(state: &State, path: &str, source: &str) -> Result<Vec<harbor::ast::Stmt>, Diagnostic> {
+    let mut documents = state.documents.lock().unwrap_or_else(|e| e.into_inner());
+    documents.entry(path.to_string()).or_default().parse(source)
+}
+
+/// The parse error in `source`, or the warnings a build would give for it.
+fn check(state: &State, path: &str, source: &str) -> Vec<Diagnostic> {
+    let ast = match parse(state, path, source) {
+        Ok(ast) => ast,
+        Err(diag) => return vec![diag],
+    };
+    // The same warnings as a build: what --strict turns on, then harbor.toml
+    let mut overrides: Vec<(String, bool)> = Vec::new();
+    if state.options.strict {
+        overrides.extend(warnings::WARNINGS.iter().filter(|w| !w.default_on).map(|w| (w.code.to_string(), true)));
+    }
+    let dir = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    if let Ok(config) = harbor::config::Config::find(dir) {
+        overrides.extend(config.warnings);
+    }
+    let mut diagnostics = checks::missing_tls_files(&ast);
+    diagnostics.extend(checks::server_ports(&ast));
+    diagnostics.extend(checks::similar_route_paths(&ast));
+    diagnostics.extend(checks::unreachable_routes(&ast));
+    if state.options.strict || strict::WARNINGS.iter().any(|code| warnings::is_enabled(code, &overrides)) {
+        // Errors only fail a --strict build
+        let found = strict::check(&ast, source, &HashMap::new());
+        diagnostics.extend(found.into_iter().filter(|d| state.options.strict || d.severity == Severity::Warning));
+    }
+    warnings::apply_ignore_comments(source, &mut diagnostics);
+    diagnostics.retain(|d| d.code.is_none_or(|code| warnings::is_enabled(code, &overrides)));
+    diagnostics
+}
+
+/// What `build` would write for `source`, as the text of the file at `path`.
+fn compile(state: &State, path: &str, source: &str) -> CompileOutput {
+    match parse(state, path, source) {
+        Ok(ast) => harbor::compile_file(path, &crate::adjacent_js_path(path), source, ast, &state.options),
+        Err(diag) => CompileOutput { js: None, diagnostics: vec![diag] },
+    }
+}
+
+fn build(state: &State, path: &str) -> String {
+    let output = crate::adjacent_js_path(path);
+    let cache = Cache::for_source(path);
+    let (errors, warnings) = match harbor::build_file(path, &output, state.use_cache.then_some(&cache), &state.options) {
+        Ok(info) => (Vec::new(), info.warnings.iter().map(|w| w.to_string()).collect()),
+        Err(error) => (error.to_string().lines().map(String::from).collect(), Vec::new()),
+    };
+    let list = |lines: Vec<String>| lines.iter().map(|l| json_string(l)).collect::<Vec<_>>().join(", ");
+    format!("{{\"ok\": {}, \"errors\": [{}], \"warnings\": [{}]}}", errors.is_empty(), list(errors), list(warnings))
+}
+
+fn error(message: &str) -> String {
+    format!("{{\"error\": {}}}", json_string(message))
+}
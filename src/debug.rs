@@ -0,0 +1,60 @@
+//! `harbor debug`: run a program under the Node inspector.
+//!
+//! The program is compiled with inline source maps that carry the Harbor
+//! source, so DevTools shows `.hb` files and breakpoints set in them work.
+//! Node pauses before the first line until a debugger attaches.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// The DevTools frontend URL for an inspector listening at `ws_url`, as
+/// `chrome://inspect` would open it.
+fn devtools_url(ws_url: &str) -> String {
+    format!("devtools://devtools/bundled/js_app.html?experiments=true&v8only=true&ws={}", ws_url.trim_start_matches("ws://"))
+}
+
+/// Compile `input_path` to `output_path` and run it with `args` under
+/// `node --inspect-brk`, on `port` if given. Returns the exit code.
+pub fn run(input_path: &str, output_path: &str, port: Option<u16>, args: &[String], options: harbor::BuildOptions) -> Result<i32, String> {
+    let options = harbor::BuildOptions { inline_source_maps: true, ..options };
+    // A cached build would have a separate map that DevTools can't see
+    let info = harbor::build_file(input_path, output_path, None, &options)?;
+    for warning in &info.warnings {
+        eprintln!("{}", warning);
+    }
+
+    let inspect = match port {
+        Some(port) => format!("--inspect-brk={}", port),
+        None => "--inspect-brk".to_string(),
+    };
+    let mut child = Command::new("node")
+        .arg(inspect)
+        .arg("--enable-source-maps")
+        .arg(output_path)
+        .args(args)
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Error: Could not run node: {}", e))?;
+
+    // Node announces the inspector on stderr; pass everything through,
+    // adding the URL to open
+    let stderr = child.stderr.take().expect("stderr is piped");
+    let input = input_path.to_string();
+    let relay = thread::spawn(move || {
+        for line in BufReader::new(stderr).lines().map_while(Result::ok) {
+            let mut err = io::stderr().lock();
+            let _ = writeln!(err, "{}", line);
+            if let Some(ws_url) = line.strip_prefix("Debugger listening on ") {
+                let _ = writeln!(err, "─────────────────────────────────────────");
+                let _ = writeln!(err, "  Debugging {} (paused before the first line)", input);
+                let _ = writeln!(err, "  Open in Chrome: {}", devtools_url(ws_url.trim()));
+                let _ = writeln!(err, "  Or attach from chrome://inspect or your editor");
+                let _ = writeln!(err, "─────────────────────────────────────────");
+            }
+        }
+    });
+    let status = child.wait().map_err(|e| format!("Error: Could not run node: {}", e))?;
+    let _ = relay.join();
+    Ok(status.code().unwrap_or(1))
+}
@@ -0,0 +1,199 @@
+//! Compile-time constants from `--define NAME=VALUE`.
+//!
+//! Each define is declared at the top of every module, and `if`/`elif`
+//! branches whose conditions depend only on defines and literals are
+//! decided at build time: the untaken branches are dropped from the output.
+//!
+//! ```text
+//! harbor --define DEBUG=false app.hb -o app.js
+//! ```
+//!
+//! ```python
+//! if DEBUG:
+//!     print f"loaded {len(rows)} rows"   # not in app.js
+//! ```
+//!
+//! Code that assigns a define's name, or binds it as a parameter, `for` or
+//! `except` variable, sees its own value there, so it's left to run time.
+
+use crate::ast::{Expr, RouteItem, Stmt};
+use crate::scoping::Scope;
+
+/// A `NAME=VALUE` define. `true`/`false` (or `True`/`False`), `None` and
+/// numbers are taken as such, anything else as a string; a bare `NAME`
+/// means `NAME=true`.
+pub fn parse_define(arg: &str) -> Result<(String, Expr), String> {
+    let (name, value) = arg.split_once('=').unwrap_or((arg, "true"));
+    let mut chars = name.chars();
+    let is_ident = chars.next().is_some_and(|c| c.is_alphabetic() || c == '_')
+        && chars.all(|c| c.is_alphanumeric() || c == '_');
+    if !is_ident {
+        return Err(format!("Error: Invalid define '{}': expected NAME or NAME=VALUE", arg));
+    }
+    let value = match value {
+        "true" | "True" => Expr::Bool(true),
+        "false" | "False" => Expr::Bool(false),
+        "None" => Expr::None,
+        _ => match value.parse::<f64>() {
+            Ok(n) if n.is_finite() => Expr::Number(n, None),
+            _ => Expr::String(value.to_string()),
+        },
+    };
+    Ok((name.to_string(), value))
+}
+
+/// Declare `defines` at the top of `stmts` and drop the branches they rule
+/// out.
+pub fn apply(stmts: &mut Vec<Stmt>, defines: &[(String, Expr)]) {
+    if defines.is_empty() {
+        return;
+    }
+    fold(stmts, &unbound(defines, &[], &[stmts]));
+    for (i, (name, value)) in defines.iter().enumerate() {
+        stmts.insert(i, Stmt::Set { target: Expr::Ident(name.clone()), value: value.clone() });
+    }
+}
+
+fn fold(stmts: &mut Vec<Stmt>, defines: &[(String, Expr)]) {
+    let mut i = 0;
+    while i < stmts.len() {
+        if let Stmt::If { .. } = &stmts[i] {
+            if let Some(kept) = fold_if(&mut stmts[i], defines) {
+                // The taken branch (or nothing) replaces the whole `if`,
+                // and is folded in turn as part of this block
+                stmts.splice(i..=i, kept);
+                continue;
+            }
+        }
+        let stmt = match &mut stmts[i] {
+            Stmt::Export(inner) => &mut **inner,
+            stmt => stmt,
+        };
+        match stmt {
+            Stmt::Server(server) => {
+                for hook in [&mut server.before, &mut server.after, &mut server.on_shutdown, &mut server.not_found] {
+                    fold(hook, &unbound(defines, &[], &[hook]));
+                }
+                if let Some((name, body)) = &mut server.on_error {
+                    fold(body, &unbound(defines, std::slice::from_ref(name), &[body]));
+                }
+                fold_routes(&mut server.routes, defines);
+            }
+            Stmt::Func { args, body, .. } => {
+                let inner = unbound(defines, args, &[body]);
+                fold(body, &inner);
+            }
+            Stmt::Spawn { body } | Stmt::Every { body, .. } | Stmt::Cron { body, .. } => {
+                let inner = unbound(defines, &[], &[body]);
+                fold(body, &inner);
+            }
+            other => {
+                for body in other.bodies_mut() {
+                    fold(body, defines);
+                }
+            }
+        }
+        i += 1;
+    }
+}
+
+fn fold_routes(items: &mut [RouteItem], defines: &[(String, Expr)]) {
+    for item in items {
+        match item {
+            RouteItem::Route(route) => {
+                // A route's hooks and body run as one function
+                let inner = unbound(defines, &[], &[&route.before, &route.body, &route.after]);
+                for body in [&mut route.body, &mut route.before, &mut route.after] {
+                    fold(body, &inner);
+                }
+            }
+            RouteItem::Group { items, .. } => fold_routes(items, defines),
+            RouteItem::Comment(_) => {}
+        }
+    }
+}
+
+/// The defines that the function with `params` and the statements in
+/// `bodies` doesn't rebind.
+fn unbound(defines: &[(String, Expr)], params: &[String], bodies: &[&[Stmt]]) -> Vec<(String, Expr)> {
+    let scopes: Vec<Scope> = bodies.iter().map(|body| Scope::of(params, body)).collect();
+    defines.iter().filter(|(name, _)| !scopes.iter().any(|scope| scope.binds(name))).cloned().collect()
+}
+
+/// Drop the branches of an `if` that the defines rule out. Returns the
+/// statements to put in its place when at most one branch can run.
+fn fold_if(stmt: &mut Stmt, defines: &[(String, Expr)]) -> Option<Vec<Stmt>> {
+    let Stmt::If { condition, then_body, elif_branches, else_body } = stmt else { return None };
+    let all = std::iter::once((condition.clone(), std::mem::take(then_body)))
+        .chain(std::mem::take(elif_branches));
+    let mut branches: Vec<(Expr, Vec<Stmt>)> = Vec::new();
+    let mut otherwise = else_body.take();
+    for (cond, body) in all {
+        match evaluate(&cond, defines) {
+            Some(false) => {}
+            Some(true) => {
+                otherwise = Some(body);
+                break;
+            }
+            None => branches.push((cond, body)),
+        }
+    }
+    if branches.is_empty() {
+        return Some(otherwise.unwrap_or_default());
+    }
+    let mut rest = branches.into_iter();
+    let (first_cond, first_body) = rest.next().expect("checked non-empty");
+    *condition = first_cond;
+    *then_body = first_body;
+    *elif_branches = rest.collect();
+    *else_body = otherwise;
+    None
+}
+
+/// The truth of `expr` if it only involves defines and literals.
+fn evaluate(expr: &Expr, defines: &[(String, Expr)]) -> Option<bool> {
+    match expr {
+        Expr::Binary(left, op, right) if op == "and" => match (evaluate(left, defines), evaluate(right, defines)) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        },
+        Expr::Binary(left, op, right) if op == "or" => match (evaluate(left, defines), evaluate(right, defines)) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        },
+        Expr::Binary(left, op, right) if op == "===" || op == "!==" => {
+            let equal = literal_eq(&constant(left, defines)?, &constant(right, defines)?)?;
+            Some(equal == (op == "==="))
+        }
+        Expr::Unary(op, inner) if op == "not" => evaluate(inner, defines).map(|b| !b),
+        _ => match constant(expr, defines)? {
+            Expr::Bool(b) => Some(b),
+            Expr::Number(n, _) => Some(n != 0.0),
+            Expr::String(s) => Some(!s.is_empty()),
+            Expr::None => Some(false),
+            _ => None,
+        },
+    }
+}
+
+/// The literal value of `expr`: a define or a literal.
+fn constant(expr: &Expr, defines: &[(String, Expr)]) -> Option<Expr> {
+    match expr {
+        Expr::Ident(name) => defines.iter().find(|(n, _)| n == name).map(|(_, value)| value.clone()),
+        Expr::Bool(_) | Expr::Number(..) | Expr::String(_) | Expr::None => Some(expr.clone()),
+        _ => None,
+    }
+}
+
+fn literal_eq(a: &Expr, b: &Expr) -> Option<bool> {
+    match (a, b) {
+        (Expr::Bool(x), Expr::Bool(y)) => Some(x == y),
+        (Expr::Number(x, _), Expr::Number(y, _)) => Some(x == y),
+        (Expr::String(x), Expr::String(y)) => Some(x == y),
+        (Expr::None, Expr::None) => Some(true),
+        // Left to runtime, where `True == 1` holds
+        _ => None,
+    }
+}
@@ -0,0 +1,182 @@
+//! `harbor dev`: recompile and restart a program whenever its sources change.
+//!
+//! When the program starts a server on a literal port, the supervisor binds
+//! that port itself and proxies connections to the current child, which
+//! listens on a private port (`HARBOR_DEV_PORT`). Restarts then only delay
+//! requests instead of refusing them.
+
+use harbor::cache::Cache;
+use std::fs;
+use std::io;
+use std::net::{Shutdown, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(300);
+
+/// How long a proxied connection waits for a restarting server.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub fn run(input_path: &str, output_path: &str, options: &harbor::BuildOptions) {
+    let dir = Path::new(input_path).parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let upstream = Arc::new(AtomicU16::new(0));
+    let mut proxy_port = None;
+    let mut child: Option<Child> = None;
+    // The modules the program imports and the asset files it compiles in,
+    // which may live outside `dir`
+    let mut watched: Vec<PathBuf> = Vec::new();
+    let mut stamp = sources_stamp(dir, &watched);
+    let cache = Cache::for_source(input_path);
+
+    println!("Harbor dev: watching {} (Ctrl+C to stop)", dir.display());
+    loop {
+        match compile(input_path, output_path, &cache, options) {
+            Ok(info) => {
+                let files: Vec<PathBuf> = info.imports.iter().chain(&info.assets).cloned().collect();
+                if files != watched {
+                    watched = files;
+                    stamp = sources_stamp(dir, &watched);
+                }
+                let port = info.port;
+                if let Some(mut old) = child.take() {
+                    let _ = old.kill();
+                    let _ = old.wait();
+                }
+                if proxy_port.is_none() {
+                    if let Some(port) = port {
+                        match TcpListener::bind(("0.0.0.0", port)) {
+                            Ok(listener) => {
+                                let upstream = Arc::clone(&upstream);
+                                thread::spawn(move || proxy(listener, upstream));
+                                proxy_port = Some(port);
+                            }
+                            Err(e) => eprintln!("Harbor dev: could not bind port {} for reloads: {}", port, e),
+                        }
+                    }
+                }
+                child = spawn(output_path, proxy_port.map(|_| &*upstream));
+            }
+            Err(message) => eprintln!("{}\nHarbor dev: waiting for changes...", message),
+        }
+
+        // Wait for the next change, noting if the program exits on its own.
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            if let Some(status) = child.as_mut().and_then(|c| c.try_wait().ok().flatten()) {
+                println!("Harbor dev: program exited ({}), waiting for changes...", status);
+                child = None;
+            }
+            let current = sources_stamp(dir, &watched);
+            if current != stamp {
+                stamp = current;
+                println!("Harbor dev: change detected, restarting...");
+                break;
+            }
+        }
+    }
+}
+
+/// Compile `input_path` to `output_path`, printing any warnings.
+fn compile(input_path: &str, output_path: &str, cache: &Cache, options: &harbor::BuildOptions) -> Result<harbor::BuildInfo, String> {
+    let info = harbor::build_file(input_path, output_path, Some(cache), options)?;
+    for warning in &info.warnings {
+        eprintln!("{}", warning);
+    }
+    Ok(info)
+}
+
+/// Start node on the compiled program. With `upstream`, the server is moved
+/// to a fresh private port that the proxy forwards to.
+fn spawn(output_path: &str, upstream: Option<&AtomicU16>) -> Option<Child> {
+    let mut cmd = Command::new("node");
+    cmd.arg(output_path);
+    if let Some(upstream) = upstream {
+        let port = match TcpListener::bind("127.0.0.1:0").and_then(|l| l.local_addr()) {
+            Ok(addr) => addr.port(),
+            Err(e) => {
+                eprintln!("Harbor dev: could not pick a port: {}", e);
+                return None;
+            }
+        };
+        cmd.env("HARBOR_DEV_PORT", port.to_string());
+        upstream.store(port, Ordering::SeqCst);
+    }
+    match cmd.spawn() {
+        Ok(child) => Some(child),
+        Err(e) => {
+            eprintln!("Error: Could not run node: {}", e);
+            None
+        }
+    }
+}
+
+/// Latest modification time and count of the `.hb` files under `dir` and
+/// the `extra` files.
+fn sources_stamp(dir: &Path, extra: &[PathBuf]) -> (Option<SystemTime>, usize) {
+    let mut files = extra.to_vec();
+    collect_sources(dir, &mut files);
+    let latest = files.iter().filter_map(|f| fs::metadata(f).and_then(|m| m.modified()).ok()).max();
+    (latest, files.len())
+}
+
+pub fn collect_sources(dir: &Path, out: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = path.file_name().and_then(|n| n.to_str()).is_some_and(|n| n.starts_with('.') || n == "node_modules" || n == "target");
+        if hidden {
+            continue;
+        }
+        if path.is_dir() {
+            collect_sources(&path, out);
+        } else if path.extension().is_some_and(|e| e == "hb") {
+            out.push(path);
+        }
+    }
+}
+
+fn proxy(listener: TcpListener, upstream: Arc<AtomicU16>) {
+    for client in listener.incoming().flatten() {
+        let upstream = Arc::clone(&upstream);
+        thread::spawn(move || {
+            if let Some(server) = connect_upstream(&upstream) {
+                let _ = pipe(client, server);
+            }
+        });
+    }
+}
+
+/// Connect to the current child, retrying while it restarts.
+fn connect_upstream(upstream: &AtomicU16) -> Option<TcpStream> {
+    let start = SystemTime::now();
+    loop {
+        if let Ok(stream) = TcpStream::connect(("127.0.0.1", upstream.load(Ordering::SeqCst))) {
+            return Some(stream);
+        }
+        if start.elapsed().map_or(true, |e| e > CONNECT_TIMEOUT) {
+            return None;
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Copy bytes both ways until either side closes.
+fn pipe(client: TcpStream, server: TcpStream) -> io::Result<()> {
+    let (mut client_read, mut server_write) = (client.try_clone()?, server.try_clone()?);
+    let forward = thread::spawn(move || {
+        let _ = io::copy(&mut client_read, &mut server_write);
+        let _ = server_write.shutdown(Shutdown::Write);
+    });
+    let (mut server_read, mut client_write) = (server, client);
+    let _ = io::copy(&mut server_read, &mut client_write);
+    let _ = client_write.shutdown(Shutdown::Write);
+    let _ = forward.join();
+    Ok(())
+}
@@ -0,0 +1,42 @@
+use crate::lexer::Span;
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A compiler message tied to a source position.
+///
+/// The lexer and parser return these instead of exiting so the compiler core
+/// can be embedded (WASM, editor tooling) without taking the process down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    /// A warning's code from `warnings::WARNINGS`, e.g. `W001`.
+    pub code: Option<&'static str>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Error, message: message.into(), span, code: None }
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>, span: Span) -> Self {
+        Self { severity: Severity::Warning, message: message.into(), span, code: Some(code) }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let label = match (self.severity, self.code) {
+            (Severity::Error, _) => "Error".to_string(),
+            (Severity::Warning, Some(code)) => format!("Warning[{}]", code),
+            (Severity::Warning, None) => "Warning".to_string(),
+        };
+        write!(f, "{}: {} at line {}, col {}", label, self.message, self.span.line, self.span.col)
+    }
+}
@@ -0,0 +1,220 @@
+use crate::ast::{RouteItem, Stmt};
+use crate::diagnostics::Diagnostic;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Re-parses a document by top-level chunk, reusing the statements of any
+/// chunk whose text hasn't changed since the previous call. Statements come
+/// with `Stmt::Line` nodes, as from `parse_source_with_lines`.
+///
+/// A chunk is one top-level statement (including its indented body and any
+/// `elif`/`else`/`except` continuation, and strings and brackets that run
+/// onto later lines). Top-level statements don't share parser state, so
+/// parsing chunks independently yields the same program as parsing the
+/// whole file. Chunks are parsed and cached with lines counted from their
+/// own start, and moved to where the chunk now starts as they're used, so
+/// an edit above a chunk doesn't stop it being reused. Editors and watch
+/// mode call `parse` with the new text on every change; only edited chunks
+/// are lexed and parsed again.
+#[derive(Default)]
+pub struct IncrementalParser {
+    cache: HashMap<u64, Vec<Stmt>>,
+    reused: usize,
+}
+
+/// A slice of the document that parses on its own.
+struct Chunk<'a> {
+    text: &'a str,
+    /// 1-based line the chunk starts on.
+    line: usize,
+}
+
+impl IncrementalParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse `source`, reusing cached chunks where possible.
+    pub fn parse(&mut self, source: &str) -> Result<Vec<Stmt>, Diagnostic> {
+        let mut stmts = Vec::new();
+        let mut live = HashMap::new();
+        self.reused = 0;
+
+        for chunk in split_chunks(source) {
+            let key = hash_text(chunk.text);
+            let parsed = match self.cache.remove(&key).or_else(|| live.remove(&key)) {
+                Some(parsed) => {
+                    self.reused += 1;
+                    parsed
+                }
+                None => crate::parse_source_with_lines(chunk.text).map_err(|mut diag| {
+                    diag.span.line += chunk.line - 1;
+                    diag
+                })?,
+            };
+            let start = stmts.len();
+            stmts.extend(parsed.iter().cloned());
+            shift_lines(&mut stmts[start..], chunk.line - 1);
+            live.insert(key, parsed);
+        }
+
+        // Only keep chunks that exist in the current document.
+        self.cache = live;
+        Ok(stmts)
+    }
+
+    /// How many chunks the last `parse` call served from the cache.
+    pub fn reused_chunks(&self) -> usize {
+        self.reused
+    }
+}
+
+/// Move every line number in `stmts` down by `lines`.
+fn shift_lines(stmts: &mut [Stmt], lines: usize) {
+    if lines == 0 {
+        return;
+    }
+    for stmt in stmts {
+        match stmt {
+            Stmt::Line(line) => *line += lines,
+            Stmt::Import { span, .. } | Stmt::FromImport { span, .. } => span.line += lines,
+            Stmt::Export(inner) => shift_lines(std::slice::from_mut(&mut **inner), lines),
+            Stmt::If { then_body, elif_branches, else_body, .. } => {
+                shift_lines(then_body, lines);
+                for (_, body) in elif_branches {
+                    shift_lines(body, lines);
+                }
+                if let Some(body) = else_body {
+                    shift_lines(body, lines);
+                }
+            }
+            Stmt::Try { body, handlers } => {
+                shift_lines(body, lines);
+                for handler in handlers {
+                    shift_lines(&mut handler.body, lines);
+                }
+            }
+            Stmt::ForIn { body, .. } | Stmt::While { body, .. } | Stmt::Func { body, .. } | Stmt::Fetch { body, .. }
+            | Stmt::Stream { body } | Stmt::Spawn { body } | Stmt::Every { body, .. } | Stmt::Cron { body, .. } => shift_lines(body, lines),
+            Stmt::Class { methods, .. } => shift_lines(methods, lines),
+            Stmt::Server(server) => {
+                server.port_span.line += lines;
+                if let Some(tls) = &mut server.tls {
+                    tls.span.line += lines;
+                }
+                for body in [&mut server.before, &mut server.after, &mut server.not_found, &mut server.on_shutdown] {
+                    shift_lines(body, lines);
+                }
+                if let Some((_, body)) = &mut server.on_error {
+                    shift_lines(body, lines);
+                }
+                shift_route_lines(&mut server.routes, lines);
+            }
+            _ => {}
+        }
+    }
+}
+
+fn shift_route_lines(items: &mut [RouteItem], lines: usize) {
+    for item in items {
+        match item {
+            RouteItem::Route(route) => {
+                route.span.line += lines;
+                for body in [&mut route.before, &mut route.body, &mut route.after] {
+                    shift_lines(body, lines);
+                }
+            }
+            RouteItem::Group { items, .. } => shift_route_lines(items, lines),
+            RouteItem::Comment(_) => {}
+        }
+    }
+}
+
+fn hash_text(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Split `source` at lines that begin a new top-level statement.
+fn split_chunks(source: &str) -> Vec<Chunk<'_>> {
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut start_line = 1;
+    let mut offset = 0;
+    let mut depth: usize = 0;
+    let mut open_string = None;
+    // A `@sync` line belongs with the def after it
+    let mut decorated = false;
+
+    for (idx, line) in source.split_inclusive('\n').enumerate() {
+        let at_top_level = depth == 0 && open_string.is_none();
+        if at_top_level && starts_statement(line) {
+            if offset > start && !decorated {
+                chunks.push(Chunk { text: &source[start..offset], line: start_line });
+                start = offset;
+                start_line = idx + 1;
+            }
+            decorated = line.starts_with('@');
+        }
+        scan_line(line, &mut depth, &mut open_string);
+        offset += line.len();
+    }
+    if start < source.len() {
+        chunks.push(Chunk { text: &source[start..], line: start_line });
+    }
+    chunks
+}
+
+/// Whether `line` opens a new top-level statement rather than continuing
+/// the previous one.
+fn starts_statement(line: &str) -> bool {
+    let first = match line.chars().next() {
+        Some(c) => c,
+        None => return false,
+    };
+    if first.is_whitespace() || first == '#' {
+        return false;
+    }
+    let word: String = line.chars().take_while(|c| c.is_ascii_alphanumeric() || *c == '_').collect();
+    !matches!(word.as_str(), "elif" | "else" | "except")
+}
+
+/// Track bracket depth and strings left open across a line, ignoring
+/// string contents and comments.
+/// `open_string` is the quote of a string still open, and whether it's an
+/// f-string.
+fn scan_line(line: &str, depth: &mut usize, open_string: &mut Option<(char, bool)>) {
+    let chars: Vec<char> = line.chars().collect();
+    let mut quote = open_string.map(|(q, _)| q);
+    let mut is_fstring = open_string.is_some_and(|(_, f)| f);
+    let mut continued = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if let Some(q) = quote {
+            if c == '\\' {
+                continued = chars.get(i + 1) == Some(&'\n');
+                i += 1;
+            } else if c == q {
+                quote = None;
+            }
+        } else {
+            match c {
+                '#' => break,
+                '"' | '\'' => {
+                    quote = Some(c);
+                    is_fstring = i > 0 && chars[i - 1] == 'f';
+                }
+                '(' | '[' | '{' => *depth += 1,
+                ')' | ']' | '}' => *depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+        i += 1;
+    }
+    // Plain strings end at the newline unless it's escaped; f-strings
+    // span lines
+    *open_string = quote.filter(|_| is_fstring || continued).map(|q| (q, is_fstring));
+}
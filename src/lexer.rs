@@ -1,3 +1,6 @@
+use crate::diagnostics::Diagnostic;
+use crate::numbers;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Span {
     pub line: usize,
@@ -7,10 +10,12 @@ pub struct Span {
 #[derive(Debug, Clone, PartialEq)]
 pub enum FStringPart {
     Literal(String),
-    Expression(String),
+    /// The text between the braces, and where it starts in the file.
+    Expression(String, Span),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenData {
     // Python-like keywords
     Def,
@@ -60,7 +65,10 @@ pub enum TokenData {
     // Identifiers and literals
     Ident(String),
     String(String),
-    Number(f64),
+    /// A number literal's value and its text as written.
+    Number(f64, String),
+    /// An integer literal too large for a double, as written.
+    BigInt(String),
     FStringToken(Vec<FStringPart>),
 
     // Punctuation
@@ -76,6 +84,8 @@ pub enum TokenData {
     RBracket,
     LParen,
     RParen,
+    Arrow,      // ->
+    At,         // @
 
     // Operators
     Plus,
@@ -99,34 +109,49 @@ pub enum TokenData {
     EOF,
 }
 
+/// A `#` comment, kept as trivia on the token that follows it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// Text after the `#`, verbatim.
+    pub text: String,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub struct Token {
     pub data: TokenData,
     pub span: Span,
+    /// Comments that appeared between the previous token and this one.
+    pub comments: Vec<Comment>,
 }
 
-pub struct Lexer {
-    src: Vec<char>,
+/// Scans source text in place: `pos` is a byte offset into `src`, and
+/// identifiers, numbers, strings and comments are sliced out rather than
+/// collected a character at a time.
+pub struct Lexer<'a> {
+    src: &'a str,
     pos: usize,
     line: usize,
     col: usize,
     indent_stack: Vec<usize>,
     pending_tokens: std::collections::VecDeque<Token>,
+    pending_comments: Vec<Comment>,
     at_line_start: bool,
     brace_level: usize,
     bracket_level: usize,
     paren_level: usize,
 }
 
-impl Lexer {
-    pub fn new(input: &str) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a str) -> Self {
         Self {
-            src: input.chars().collect(),
+            src: input,
             pos: 0,
             line: 1,
             col: 1,
             indent_stack: vec![0],
             pending_tokens: std::collections::VecDeque::new(),
+            pending_comments: Vec::new(),
             at_line_start: true,
             brace_level: 0,
             bracket_level: 0,
@@ -134,29 +159,35 @@ impl Lexer {
         }
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    /// A lexer for text that starts at `start` in a larger file, such as an
+    /// f-string expression, so its spans point into that file.
+    pub fn starting_at(input: &'a str, start: Span) -> Self {
+        Self { line: start.line, col: start.col, ..Self::new(input) }
+    }
+
+    pub fn tokenize(&mut self) -> Result<Vec<Token>, Diagnostic> {
         let mut tokens = Vec::new();
         loop {
-            let tok = self.next_token();
+            let mut tok = self.next_token()?;
+            // Dedents carry only the comments inside the block they close
+            if tok.data != TokenData::Dedent {
+                tok.comments = std::mem::take(&mut self.pending_comments);
+            }
             let is_eof = tok.data == TokenData::EOF;
             tokens.push(tok);
             if is_eof {
                 break;
             }
         }
-        tokens
+        Ok(tokens)
     }
 
     fn advance(&mut self) -> Option<char> {
-        if self.pos >= self.src.len() {
-            return None;
-        }
-        let ch = self.src[self.pos];
-        self.pos += 1;
+        let ch = self.peek()?;
+        self.pos += ch.len_utf8();
         if ch == '\n' {
             self.line += 1;
             self.col = 1;
-            self.at_line_start = true;
         } else {
             self.col += 1;
         }
@@ -164,25 +195,46 @@ impl Lexer {
     }
 
     fn peek(&self) -> Option<char> {
-        if self.pos >= self.src.len() {
-            return None;
+        match *self.src.as_bytes().get(self.pos)? {
+            b if b.is_ascii() => Some(b as char),
+            _ => self.src[self.pos..].chars().next(),
         }
-        Some(self.src[self.pos])
     }
 
     #[allow(dead_code)]
     fn peek_ahead(&self, offset: usize) -> Option<char> {
-        let idx = self.pos + offset;
-        if idx >= self.src.len() {
-            return None;
+        self.src[self.pos..].chars().nth(offset)
+    }
+
+    /// Advance while `pred` holds, returning the text consumed.
+    fn take_while(&mut self, pred: impl Fn(char) -> bool) -> &'a str {
+        let start = self.pos;
+        while self.peek().is_some_and(&pred) {
+            self.advance();
         }
-        Some(self.src[idx])
+        &self.src[start..self.pos]
     }
 
     fn inside_brackets(&self) -> bool {
         self.brace_level > 0 || self.bracket_level > 0 || self.paren_level > 0
     }
 
+    /// Consume the rest of a comment (the `#` is already consumed).
+    fn scan_comment(&mut self, span: Span) {
+        let text = self.take_while(|c| c != '\n').trim_end_matches('\r').to_string();
+        self.pending_comments.push(Comment { text, span });
+    }
+
+    /// Take the pending comments indented at least `indent` deep: they end
+    /// the block being closed rather than introduce the code after it.
+    fn comments_within(&mut self, indent: usize) -> Vec<Comment> {
+        let (inside, after) = std::mem::take(&mut self.pending_comments)
+            .into_iter()
+            .partition(|c| c.span.col > indent);
+        self.pending_comments = after;
+        inside
+    }
+
     fn scan_fstring(&mut self) -> TokenData {
         let quote = self.advance().unwrap(); // consume opening ' or "
         let mut parts = Vec::new();
@@ -208,6 +260,7 @@ impl Lexer {
                     current_lit.clear();
                 }
                 // Collect expression text until '}'
+                let start = Span { line: self.line, col: self.col };
                 let mut expr_text = String::new();
                 let mut brace_depth = 1;
                 while let Some(ec) = self.peek() {
@@ -222,7 +275,7 @@ impl Lexer {
                     }
                     expr_text.push(self.advance().unwrap());
                 }
-                parts.push(FStringPart::Expression(expr_text));
+                parts.push(FStringPart::Expression(expr_text, start));
             } else if c == '}' {
                 self.advance();
                 // Check for }} (escaped brace)
@@ -247,9 +300,9 @@ impl Lexer {
         TokenData::FStringToken(parts)
     }
 
-    fn next_token(&mut self) -> Token {
+    fn next_token(&mut self) -> Result<Token, Diagnostic> {
         if let Some(tok) = self.pending_tokens.pop_front() {
-            return tok;
+            return Ok(tok);
         }
 
         // Handle indentation at line starts (only outside brackets)
@@ -263,6 +316,8 @@ impl Lexer {
                     match ch {
                         ' ' => { current_line_spaces += 1; self.advance(); }
                         '\t' => { current_line_spaces += 4; self.advance(); }
+                        // So a blank CRLF line is blank
+                        '\r' => { self.advance(); }
                         _ => break,
                     }
                 }
@@ -274,11 +329,10 @@ impl Lexer {
                         continue;
                     }
                     Some('#') => {
-                        // Skip comment lines entirely
-                        while let Some(c) = self.peek() {
-                            if c == '\n' { break; }
-                            self.advance();
-                        }
+                        // Comment lines produce no tokens, only trivia
+                        let span = Span { line: self.line, col: self.col };
+                        self.advance();
+                        self.scan_comment(span);
                         if self.peek() == Some('\n') {
                             self.advance();
                             self.at_line_start = true;
@@ -303,14 +357,15 @@ impl Lexer {
             let current_indent = *self.indent_stack.last().unwrap();
             if indent > current_indent {
                 self.indent_stack.push(indent);
-                return Token { data: TokenData::Indent, span: Span { line: self.line, col: self.col } };
+                return Ok(Token { data: TokenData::Indent, span: Span { line: self.line, col: self.col }, comments: Vec::new() });
             } else if indent < current_indent {
                 while indent < *self.indent_stack.last().unwrap() {
-                    self.indent_stack.pop();
-                    self.pending_tokens.push_back(Token { data: TokenData::Dedent, span: Span { line: self.line, col: self.col } });
+                    let block_indent = self.indent_stack.pop().unwrap();
+                    let comments = self.comments_within(block_indent);
+                    self.pending_tokens.push_back(Token { data: TokenData::Dedent, span: Span { line: self.line, col: self.col }, comments });
                 }
                 if let Some(tok) = self.pending_tokens.pop_front() {
-                    return tok;
+                    return Ok(tok);
                 }
             }
         }
@@ -320,8 +375,9 @@ impl Lexer {
             if ch == ' ' || ch == '\t' || ch == '\r' {
                 self.advance();
             } else if ch == '\n' && self.inside_brackets() {
+                // Lines inside brackets continue the statement; only the
+                // newline after the closing bracket starts a new line
                 self.advance();
-                self.at_line_start = true;
             } else {
                 break;
             }
@@ -336,20 +392,21 @@ impl Lexer {
             None => {
                 // EOF: emit remaining dedents
                 while self.indent_stack.len() > 1 {
-                    self.indent_stack.pop();
-                    self.pending_tokens.push_back(Token { data: TokenData::Dedent, span });
+                    let block_indent = self.indent_stack.pop().unwrap();
+                    let comments = self.comments_within(block_indent);
+                    self.pending_tokens.push_back(Token { data: TokenData::Dedent, span, comments });
                 }
                 if let Some(tok) = self.pending_tokens.pop_front() {
-                    return tok;
+                    return Ok(tok);
                 }
-                return Token { data: TokenData::EOF, span };
+                return Ok(Token { data: TokenData::EOF, span, comments: Vec::new() });
             }
         };
 
         if ch == '\n' {
             self.advance();
             self.at_line_start = true;
-            return Token { data: TokenData::Newline, span };
+            return Ok(Token { data: TokenData::Newline, span, comments: Vec::new() });
         }
 
         self.advance(); // advance past the peeked character
@@ -367,13 +424,11 @@ impl Lexer {
             ':' => TokenData::Colon,
             ',' => TokenData::Comma,
             '%' => TokenData::Percent,
+            '@' => TokenData::At,
 
             // Comments (Python-style)
             '#' => {
-                while let Some(c) = self.peek() {
-                    if c == '\n' { break; }
-                    self.advance();
-                }
+                self.scan_comment(span);
                 return self.next_token();
             }
 
@@ -383,14 +438,14 @@ impl Lexer {
 
             '!' if self.peek() == Some('=') => { self.advance(); TokenData::NotEq }
             '!' => {
-                eprintln!("Error: Use 'not' instead of '!' at line {}, col {}", start_line, start_col);
-                std::process::exit(1);
+                return Err(Diagnostic::error("Use 'not' instead of '!'", span));
             }
 
             '+' if self.peek() == Some('=') => { self.advance(); TokenData::PlusAssign }
             '+' => TokenData::Plus,
 
             '-' if self.peek() == Some('=') => { self.advance(); TokenData::DashAssign }
+            '-' if self.peek() == Some('>') => { self.advance(); TokenData::Arrow }
             '-' => TokenData::Dash,
 
             '*' if self.peek() == Some('*') => { self.advance(); TokenData::DoubleStar }
@@ -409,44 +464,42 @@ impl Lexer {
 
             // Strings (single and double quotes)
             '"' | '\'' => {
+                // Escapes are kept verbatim, so the value is the source text
+                // between the quotes
                 let quote = ch;
-                let mut s = String::new();
+                let start = self.pos;
+                let mut end = self.src.len();
                 while let Some(c) = self.peek() {
                     if c == '\\' {
-                        s.push(self.advance().unwrap()); // push '\'
-                        if let Some(escaped) = self.advance() {
-                            s.push(escaped);
-                        }
+                        self.advance();
+                        self.advance();
                     } else if c == quote {
+                        end = self.pos;
                         self.advance(); // consume closing quote
                         break;
-                    } else if c == '\n' {
-                        // Unterminated string
+                    } else if c == '\n' || c == '\r' {
+                        // Unterminated string; the \r of a CRLF isn't part of it
+                        end = self.pos;
                         break;
                     } else {
-                        s.push(self.advance().unwrap());
+                        self.advance();
                     }
                 }
-                TokenData::String(s)
+                TokenData::String(self.src[start..end].to_string())
             }
 
             // Identifiers and keywords
             c if c.is_ascii_alphabetic() || c == '_' => {
-                let mut ident = String::from(c);
-                while let Some(n) = self.peek() {
-                    if n.is_ascii_alphanumeric() || n == '_' {
-                        ident.push(self.advance().unwrap());
-                    } else {
-                        break;
-                    }
-                }
+                let start = self.pos - 1;
+                self.take_while(|n| n.is_ascii_alphanumeric() || n == '_');
+                let ident = &self.src[start..self.pos];
 
                 // Check for f-string: identifier "f" followed by quote
                 if ident == "f" && matches!(self.peek(), Some('"') | Some('\'')) {
-                    return Token { data: self.scan_fstring(), span };
+                    return Ok(Token { data: self.scan_fstring(), span, comments: Vec::new() });
                 }
 
-                match ident.as_str() {
+                match ident {
                     // Python-like keywords
                     "def" => TokenData::Def,
                     "return" => TokenData::Return,
@@ -489,30 +542,35 @@ impl Lexer {
                     "respond" => TokenData::Respond,
                     "fetch" => TokenData::Fetch,
 
-                    _ => TokenData::Ident(ident),
+                    _ => TokenData::Ident(ident.to_string()),
                 }
             }
 
             // Numbers
             c if c.is_ascii_digit() => {
-                let mut n = String::from(c);
-                while let Some(next) = self.peek() {
-                    if next.is_ascii_digit() || next == '.' {
-                        n.push(self.advance().unwrap());
-                    } else {
-                        break;
-                    }
+                let start = self.pos - 1;
+                if c == '0' && matches!(self.peek(), Some('x' | 'X' | 'o' | 'O' | 'b' | 'B')) {
+                    self.advance();
+                    self.take_while(|next| next.is_ascii_alphanumeric() || next == '_');
+                } else {
+                    self.take_while(|next| next.is_ascii_digit() || next == '.' || next == '_');
+                }
+                let text = &self.src[start..self.pos];
+                if let Some(problem) = numbers::literal_problem(text) {
+                    return Err(Diagnostic::error(problem, span));
+                }
+                if !text.contains('.') && numbers::needs_bigint(text) {
+                    TokenData::BigInt(text.to_string())
+                } else {
+                    TokenData::Number(numbers::parse(text), text.to_string())
                 }
-                TokenData::Number(n.parse().unwrap_or(0.0))
             }
 
             _ => {
-                eprintln!("Error: Unexpected character '{}' at line {}, col {}",
-                    ch, start_line, start_col);
-                std::process::exit(1);
+                return Err(Diagnostic::error(format!("Unexpected character '{}'", ch), span));
             }
         };
 
-        Token { data, span }
+        Ok(Token { data, span, comments: Vec::new() })
     }
 }
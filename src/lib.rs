@@ -0,0 +1,509 @@
+pub mod assets;
+pub mod ast;
+pub mod cache;
+pub mod checks;
+pub mod codegen;
+pub mod config;
+pub mod defines;
+pub mod diagnostics;
+pub mod incremental;
+pub mod lexer;
+pub mod numbers;
+pub mod parser;
+pub mod plugins;
+pub mod printer;
+pub mod resolver;
+pub mod scoping;
+pub mod sourcemap;
+pub mod stdlib;
+pub mod strict;
+pub mod symbols;
+pub mod warnings;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+use codegen::Backend;
+use diagnostics::Diagnostic;
+use std::collections::HashSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Result of compiling a single source string.
+///
+/// `js` is `None` when compilation failed; `diagnostics` then holds the error.
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    pub js: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Tokenize and parse `source` into a program.
+pub fn parse_source(source: &str) -> Result<Vec<ast::Stmt>, Diagnostic> {
+    let tokens = lexer::Lexer::new(source).tokenize()?;
+    parser::Parser::new(tokens).parse()
+}
+
+/// Like `parse_source`, but keeps comments as `Stmt::Comment` nodes.
+pub fn parse_source_lossless(source: &str) -> Result<Vec<ast::Stmt>, Diagnostic> {
+    let tokens = lexer::Lexer::new(source).tokenize()?;
+    parser::Parser::with_comments(tokens).parse()
+}
+
+/// Like `parse_source`, but records where each statement starts as
+/// `Stmt::Line` nodes, for source maps.
+pub fn parse_source_with_lines(source: &str) -> Result<Vec<ast::Stmt>, Diagnostic> {
+    let tokens = lexer::Lexer::new(source).tokenize()?;
+    parser::Parser::with_lines(tokens).parse()
+}
+
+/// Reformat `source` as canonical Harbor, keeping its comments.
+pub fn format_source(source: &str) -> Result<String, Diagnostic> {
+    Ok(printer::print_program(&parse_source_lossless(source)?))
+}
+
+/// Compile Harbor source to JavaScript without touching the filesystem or
+/// exiting the process.
+pub fn compile(source: &str) -> CompileOutput {
+    match parse_source(source) {
+        Ok(ast) => CompileOutput {
+            js: Some(codegen::NodeBackend.generate(&ast)),
+            diagnostics: Vec::new(),
+        },
+        Err(diag) => CompileOutput { js: None, diagnostics: vec![diag] },
+    }
+}
+
+/// Generate JavaScript for `ast` straight into the file at `path`, without
+/// building the whole output in memory first.
+pub fn write_js(ast: &[ast::Stmt], path: impl AsRef<Path>) -> std::io::Result<()> {
+    use std::io::Write;
+    let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+    codegen::NodeBackend.generate_to(ast, &mut out)?;
+    out.flush()
+}
+
+/// Write the JavaScript for `ast`, parsed from `input` with
+/// `parse_source_with_lines`, to `output`, with a source map so stack traces
+/// and debuggers point into `input`. The map goes to `map_path(output)`, or
+/// with `inline` into the JavaScript itself along with `source`, the Harbor
+/// text.
+pub fn write_mapped_js(ast: &[ast::Stmt], input: &Path, source: &str, output: &Path, inline: bool) -> std::io::Result<()> {
+    let (code, map) = mapped_js(ast, input, source, output, inline);
+    match map {
+        Some(map) => {
+            std::fs::write(output, code)?;
+            std::fs::write(map_path(output), map)
+        }
+        None => {
+            // A map left by an earlier build would no longer match
+            let _ = std::fs::remove_file(map_path(output));
+            std::fs::write(output, code)
+        }
+    }
+}
+
+/// The JavaScript `write_mapped_js` writes to `output`, and the source map
+/// to write beside it unless it's `inline`.
+fn mapped_js(ast: &[ast::Stmt], input: &Path, source: &str, output: &Path, inline: bool) -> (String, Option<String>) {
+    let js = sourcemap::runtime_hook() + &codegen::NodeBackend.generate(ast);
+    let (mut code, lines) = sourcemap::extract(&js);
+    let file_name = |path: &Path| path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    let out_dir = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let source_path = resolver::require_path(out_dir, input);
+    let source_path = source_path.strip_prefix("./").unwrap_or(&source_path);
+    if inline {
+        let json = lines.to_json(&file_name(output), source_path, Some(source));
+        code.push_str(&format!("//# sourceMappingURL=data:application/json;base64,{}\n", sourcemap::base64(json.as_bytes())));
+        return (code, None);
+    }
+    code.push_str(&format!("//# sourceMappingURL={}\n", file_name(&map_path(output))));
+    (code, Some(lines.to_json(&file_name(output), source_path, None)))
+}
+
+/// Where the source map for the JavaScript at `output` goes.
+pub fn map_path(output: &Path) -> PathBuf {
+    let mut path = output.as_os_str().to_owned();
+    path.push(".map");
+    PathBuf::from(path)
+}
+
+/// What building a file reports besides the JavaScript it writes.
+#[derive(Debug, Clone)]
+pub struct BuildInfo {
+    /// Warnings from the file and the modules it imports.
+    pub warnings: Vec<BuildWarning>,
+    /// The literal port of the first server, if any.
+    pub port: Option<u16>,
+    /// The Harbor modules the file imports, which are built along with it.
+    /// From `build_file`, these include what they import in turn, and the
+    /// prelude.
+    pub imports: Vec<PathBuf>,
+    /// The asset files compiled into it.
+    pub assets: Vec<PathBuf>,
+}
+
+/// A warning from a build. `file` is the imported module it's in, or
+/// `None` for the file being built.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildWarning {
+    pub file: Option<PathBuf>,
+    pub diagnostic: Diagnostic,
+}
+
+impl fmt::Display for BuildWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.file {
+            Some(file) => write!(f, "{}: {}", file.display(), self.diagnostic),
+            None => write!(f, "{}", self.diagnostic),
+        }
+    }
+}
+
+/// Why a build failed, as messages for the CLI, one per line. `file` is
+/// the imported module they're in, or `None` for the file being built and
+/// failures that aren't in any one module.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildError {
+    pub file: Option<PathBuf>,
+    pub message: String,
+}
+
+impl From<String> for BuildError {
+    fn from(message: String) -> Self {
+        BuildError { file: None, message }
+    }
+}
+
+impl From<BuildError> for String {
+    fn from(error: BuildError) -> Self {
+        error.to_string()
+    }
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(file) = &self.file else { return write!(f, "{}", self.message) };
+        let lines: Vec<String> = self.message.lines().map(|line| format!("{}: {}", file.display(), line)).collect();
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// How `build_file` writes its output.
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Embed source maps, with the Harbor source, in the JavaScript instead
+    /// of writing `.map` files, for debuggers.
+    pub inline_source_maps: bool,
+    /// Compile-time constants from `--define`; see `defines`.
+    pub defines: Vec<(String, ast::Expr)>,
+    /// `--strict`: the checks in `strict` on, undefined names and wrong
+    /// argument counts failing the build.
+    pub strict: bool,
+    /// AST transforms run on each module; see `plugins`.
+    pub plugins: Vec<std::sync::Arc<dyn plugins::Plugin>>,
+}
+
+impl BuildOptions {
+    /// The options as part of a cache key, so builds with different options
+    /// don't share entries.
+    fn cache_key(&self) -> String {
+        let mut key = String::new();
+        if self.inline_source_maps {
+            key.push_str(" inline-maps");
+        }
+        for (name, value) in &self.defines {
+            key.push_str(&format!(" {}={:?}", name, value));
+        }
+        if self.strict {
+            key.push_str(" strict");
+        }
+        for plugin in &self.plugins {
+            key.push_str(&format!(" plugin={}", plugin.name()));
+        }
+        key
+    }
+}
+
+/// Compile the file at `input_path` into `output_path`, along with the
+/// Harbor modules it imports and the project prelude, reusing `cache` for
+/// sources that haven't changed.
+pub fn build_file(input_path: &str, output_path: &str, cache: Option<&cache::Cache>, options: &BuildOptions) -> Result<BuildInfo, BuildError> {
+    let input = Path::new(input_path);
+    let config = config::Config::find(input.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))?;
+    let mut build = Build {
+        resolver: resolver::Resolver::for_source(input_path),
+        cache,
+        options,
+        built: HashSet::new(),
+        prelude: None,
+        symbols: symbols::Index::default(),
+        warning_overrides: Vec::new(),
+    };
+    // --strict turns on the warnings that are off by default; harbor.toml
+    // still has the last word
+    if options.strict {
+        build.warning_overrides.extend(warnings::WARNINGS.iter().filter(|w| !w.default_on).map(|w| (w.code.to_string(), true)));
+    }
+    build.warning_overrides.extend(config.warnings.iter().cloned());
+    let mut warnings = Vec::new();
+    if let Some(path) = config.prelude {
+        std::fs::metadata(&path)
+            .map_err(|e| format!("Error: Could not read prelude '{}': {}", path.display(), e))?;
+        // The prelude and what it imports are built first, without it, so
+        // that nothing it depends on can import it back
+        if let Some(info) = build.import(&path)? {
+            warnings.extend(info.warnings);
+        }
+        let exports = build.symbols.module(&path, &build.resolver)?.exports();
+        let names = exports.into_iter().filter(|name| name != "default").collect();
+        build.prelude = Some((absolute(&path), names));
+    }
+    let mut info = build.module(input, Path::new(output_path), true)?;
+    let entry = absolute(input);
+    info.imports = build.built.iter().filter(|path| **path != entry).cloned().collect();
+    info.imports.sort();
+    warnings.append(&mut info.warnings);
+    warnings.retain(|w| w.diagnostic.code.is_none_or(|code| warnings::is_enabled(code, &build.warning_overrides)));
+    info.warnings = warnings;
+    Ok(info)
+}
+
+/// State shared by the modules of one `build_file`.
+struct Build<'a> {
+    resolver: resolver::Resolver,
+    cache: Option<&'a cache::Cache>,
+    options: &'a BuildOptions,
+    /// Modules this build has already started, so shared and circular
+    /// imports are compiled once.
+    built: HashSet<PathBuf>,
+    /// The prelude and its exports, once it's been built.
+    prelude: Option<(PathBuf, Vec<String>)>,
+    /// What the modules seen so far define and export.
+    symbols: symbols::Index,
+    /// Warning codes turned on or off, by `--strict` and then `harbor.toml`.
+    warning_overrides: Vec<(String, bool)>,
+}
+
+impl Build<'_> {
+    /// Build one module and everything it imports. Diagnostics in imported
+    /// modules name the file they're in.
+    fn module(&mut self, input: &Path, output: &Path, entry: bool) -> Result<BuildInfo, BuildError> {
+        let input_abs = absolute(input);
+        self.built.insert(input_abs.clone());
+        let file = (!entry).then(|| resolver::tidy(input));
+        let render = |message: String| BuildError { file: file.clone(), message };
+        let src = std::fs::read_to_string(input)
+            .map_err(|e| format!("Error: Could not read file '{}': {}", input.display(), e))?;
+        let prelude = self.prelude.clone()
+            .filter(|(path, _)| *path != input_abs && !self.resolver.is_stdlib(input));
+        // Require paths in the output are relative to where it's written
+        let mut options = output.display().to_string() + &self.options.cache_key();
+        if let Some((path, _)) = &prelude {
+            options.push_str(&format!(" prelude={}", path.display()));
+        }
+        for (code, on) in &self.warning_overrides {
+            options.push_str(&format!(" {}={}", code, on));
+        }
+        let key = cache::Cache::key(&src, &options);
+        if let Some(mut info) = self.cache.and_then(|c| c.restore(&key, output)) {
+            for import in info.imports.clone() {
+                let dep = self.import(&import)?;
+                info.warnings.extend(dep.map(|d| d.warnings).unwrap_or_default());
+            }
+            return Ok(info);
+        }
+
+        let mut ast = parse_source_with_lines(&src).map_err(|d| render(d.to_string()))?;
+        let resolved = prepare(&mut ast, input, output, self.options, prelude, &self.resolver).map_err(|d| render(d.to_string()))?;
+        let mut warnings = Vec::new();
+        let mut imports: Vec<PathBuf> = Vec::new();
+        for dep in &resolved.modules {
+            if !imports.contains(&dep.source) {
+                imports.push(dep.source.clone());
+            }
+        }
+        // Built before their exports are checked, so an error in one is
+        // reported as its own
+        for import in &imports {
+            if let Some(dep) = self.import(import)? {
+                warnings.extend(dep.warnings);
+            }
+        }
+        for dep in &resolved.modules {
+            let exports = self.symbols.module(&dep.source, &self.resolver)?.exports();
+            resolver::check_exports(dep, &exports).map_err(|d| render(d.to_string()))?;
+        }
+
+        if let Some(dir) = output.parent().filter(|d| !d.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)
+                .map_err(|e| format!("Error: Could not create '{}': {}", dir.display(), e))?;
+        }
+        write_mapped_js(&ast, input, &src, output, self.options.inline_source_maps)
+            .map_err(|e| format!("Error: Could not write to '{}': {}", output.display(), e))?;
+        let mut own_warnings = checks::missing_tls_files(&ast);
+        own_warnings.extend(checks::server_ports(&ast));
+        own_warnings.extend(checks::similar_route_paths(&ast));
+        own_warnings.extend(checks::unreachable_routes(&ast));
+        if self.options.strict || strict::WARNINGS.iter().any(|code| warnings::is_enabled(code, &self.warning_overrides)) {
+            let arities = imported_arities(&mut self.symbols, &resolved, &self.resolver)?;
+            let (errors, found): (Vec<Diagnostic>, Vec<Diagnostic>) = strict::check(&ast, &src, &arities)
+                .into_iter()
+                .partition(|d| d.severity == diagnostics::Severity::Error);
+            if self.options.strict && !errors.is_empty() {
+                return Err(render(errors.iter().map(Diagnostic::to_string).collect::<Vec<_>>().join("\n")));
+            }
+            own_warnings.extend(found);
+        }
+        warnings::apply_ignore_comments(&src, &mut own_warnings);
+        warnings.extend(own_warnings.into_iter().map(|diagnostic| BuildWarning { file: file.clone(), diagnostic }));
+        let info = BuildInfo {
+            warnings,
+            port: ast.iter().find_map(|stmt| match stmt {
+                ast::Stmt::Server(server) => match server.port {
+                    ast::Expr::Number(n, _) if n >= 1.0 && n <= u16::MAX as f64 => Some(n as u16),
+                    _ => None,
+                },
+                _ => None,
+            }),
+            imports,
+            assets: resolved.assets,
+        };
+        if let Some(cache) = self.cache {
+            // A cache that can't be written only costs the next build time
+            let _ = cache.save(&key, output, &info);
+        }
+        Ok(info)
+    }
+
+    /// Build an imported module unless this build already has.
+    fn import(&mut self, source: &Path) -> Result<Option<BuildInfo>, BuildError> {
+        if self.built.contains(&absolute(source)) {
+            return Ok(None);
+        }
+        let output = self.resolver.output_path(source);
+        self.module(source, &output, false).map(Some)
+    }
+}
+
+/// What a build does to a module's AST before generating it: run the
+/// plugins, fold the defines, import the prelude's names and resolve
+/// imports and assets for `output`.
+fn prepare(ast: &mut Vec<ast::Stmt>, input: &Path, output: &Path, options: &BuildOptions, prelude: Option<(PathBuf, Vec<String>)>, resolver: &resolver::Resolver) -> Result<resolver::Imports, Diagnostic> {
+    plugins::apply(ast, &options.plugins)?;
+    defines::apply(ast, &options.defines);
+    if let Some((path, exports)) = prelude {
+        resolver::import_prelude(ast, &path, exports);
+    }
+    resolver::resolve_imports(ast, input, output, resolver)
+}
+
+/// The parameter counts of the functions and classes `imports` brings in
+/// by name, for `strict::check`.
+fn imported_arities(symbols: &mut symbols::Index, imports: &resolver::Imports, resolver: &resolver::Resolver) -> Result<std::collections::HashMap<String, usize>, String> {
+    let mut arities = std::collections::HashMap::new();
+    for dep in &imports.modules {
+        for name in &dep.names {
+            if let Some(def) = symbols.definition(&dep.source, &name.name, resolver)? {
+                if def.symbol.kind != symbols::SymbolKind::Variable {
+                    arities.insert(name.renamed().to_string(), def.symbol.params.len());
+                }
+            }
+        }
+    }
+    Ok(arities)
+}
+
+/// Compile `source`, the unsaved text of the file at `input_path`, parsed
+/// as `ast` by `parse_source_with_lines`, to the JavaScript `build_file`
+/// would write to `output_path`: with its plugins, defines, prelude,
+/// imports and assets, failing where the build would. Nothing is written
+/// and imported modules aren't built, so editors can call it on every
+/// change.
+pub fn compile_file(input_path: &str, output_path: &str, source: &str, mut ast: Vec<ast::Stmt>, options: &BuildOptions) -> CompileOutput {
+    match compile_module(Path::new(input_path), Path::new(output_path), source, &mut ast, options) {
+        Ok(js) => CompileOutput { js: Some(js), diagnostics: Vec::new() },
+        Err(diag) => CompileOutput { js: None, diagnostics: vec![diag] },
+    }
+}
+
+fn compile_module(input: &Path, output: &Path, source: &str, ast: &mut Vec<ast::Stmt>, options: &BuildOptions) -> Result<String, Diagnostic> {
+    let resolver = resolver::Resolver::for_source(&input.display().to_string());
+    let mut symbols = symbols::Index::default();
+    let dir = input.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let config = config::Config::find(dir).map_err(whole_file)?;
+    let prelude = match config.prelude {
+        Some(path) if absolute(&path) != absolute(input) && !resolver.is_stdlib(input) => {
+            let exports = symbols.module(&path, &resolver).map_err(whole_file)?.exports();
+            Some((absolute(&path), exports.into_iter().filter(|name| name != "default").collect()))
+        }
+        _ => None,
+    };
+    let resolved = prepare(ast, input, output, options, prelude, &resolver)?;
+    for dep in &resolved.modules {
+        let exports = symbols.module(&dep.source, &resolver).map_err(whole_file)?.exports();
+        resolver::check_exports(dep, &exports)?;
+    }
+    if options.strict {
+        let arities = imported_arities(&mut symbols, &resolved, &resolver).map_err(whole_file)?;
+        if let Some(error) = strict::check(ast, source, &arities).into_iter().find(|d| d.severity == diagnostics::Severity::Error) {
+            return Err(error);
+        }
+    }
+    Ok(mapped_js(ast, input, source, output, options.inline_source_maps).0)
+}
+
+/// A build failure outside the source, like an unreadable prelude, which
+/// has no place in it to point at.
+fn whole_file(message: String) -> Diagnostic {
+    Diagnostic::error(message.trim_start_matches("Error: "), lexer::Span { line: 1, col: 1 })
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    std::path::absolute(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl CompileOutput {
+    /// Serialize as `{"js": ..., "diagnostics": [...]}`.
+    pub fn to_json(&self) -> String {
+        let js = match &self.js {
+            Some(js) => json_string(js),
+            None => "null".to_string(),
+        };
+        format!("{{\"js\": {}, \"diagnostics\": {}}}", js, diagnostics_json(&self.diagnostics))
+    }
+}
+
+/// Serialize `diagnostics` as a JSON array of
+/// `{"severity", "code", "message", "line", "col"}` objects.
+pub fn diagnostics_json(diagnostics: &[Diagnostic]) -> String {
+    let diags: Vec<String> = diagnostics.iter().map(|d| {
+        let severity = match d.severity {
+            diagnostics::Severity::Error => "error",
+            diagnostics::Severity::Warning => "warning",
+        };
+        let code = d.code.map(json_string).unwrap_or_else(|| "null".to_string());
+        format!("{{\"severity\": \"{}\", \"code\": {}, \"message\": {}, \"line\": {}, \"col\": {}}}",
+            severity, code, json_string(&d.message), d.span.line, d.span.col)
+    }).collect();
+    format!("[{}]", diags.join(", "))
+}
+
+/// Quote and escape a string as a JSON string literal.
+pub fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
@@ -1,12 +1,139 @@
-mod lexer;
-mod parser;
-mod ast;
-mod codegen;
+mod build;
+mod daemon;
+mod debug;
+mod dev;
 
+use harbor::cache::Cache;
+use harbor::resolver::Resolver;
+use harbor::symbols::{Index, Symbol, SymbolKind};
 use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Where `harbor <file.hb>` writes its JavaScript: next to the source, so
+/// imports of sibling modules resolve.
+fn adjacent_js_path(input_path: &str) -> String {
+    let path = std::path::Path::new(input_path);
+    let file_stem = path.file_stem().unwrap().to_str().unwrap();
+    let mut out = path.to_path_buf();
+    out.set_file_name(format!("{}.js", file_stem));
+    out.to_str().unwrap().to_string()
+}
+
+/// Print the signature of each `def`/`class`, followed by the comment block
+/// directly above it.
+fn print_docs(symbols: &[Symbol], indent: &str) {
+    for symbol in symbols {
+        print_symbol(symbol, symbol.exported, "", indent);
+    }
+}
+
+fn print_symbol(symbol: &Symbol, exported: bool, origin: &str, indent: &str) {
+    let prefix = if exported { "export " } else { "" };
+    match symbol.kind {
+        SymbolKind::Function => println!("{}{}def {}({}){}", indent, prefix, symbol.name, symbol.params.join(", "), origin),
+        SymbolKind::Class => println!("{}{}class {}:{}", indent, prefix, symbol.name, origin),
+        SymbolKind::Variable => return,
+    }
+    for line in &symbol.doc {
+        println!("{}    {}", indent, line);
+    }
+    print_docs(&symbol.members, &format!("{}    ", indent));
+}
+
+/// List the exported functions and classes in `inputs` that have no doc
+/// comment, then how many do. Returns whether that's at least `min`
+/// percent of them.
+fn doc_coverage(inputs: &[String], min: usize) -> Result<bool, String> {
+    let mut index = Index::default();
+    let (mut documented, mut total) = (0, 0);
+    for input in inputs {
+        let input = resolve_module(input).map_or(input.clone(), |path| path.display().to_string());
+        let module = index.module(Path::new(&input), &Resolver::for_source(&input))?;
+        for symbol in module.symbols.iter().filter(|s| s.exported && s.kind != SymbolKind::Variable) {
+            total += 1;
+            if !symbol.doc.is_empty() {
+                documented += 1;
+                continue;
+            }
+            let (keyword, params) = match symbol.kind {
+                SymbolKind::Class => ("class", String::new()),
+                _ => ("def", format!("({})", symbol.params.join(", "))),
+            };
+            println!("{}:{}: {} {}{} has no doc comment", display_path(Path::new(&input)), symbol.line, keyword, symbol.name, params);
+        }
+    }
+    if total == 0 {
+        println!("No exported functions or classes");
+        return Ok(true);
+    }
+    let percent = documented * 100 / total;
+    println!("Documented {} of {} exported functions and classes ({}%)", documented, total, percent);
+    Ok(documented * 100 >= min * total)
+}
+
+/// `path` relative to the current directory when it's inside it.
+fn display_path(path: &Path) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    path.strip_prefix(&cwd).unwrap_or(path).display().to_string()
+}
+
+/// Reformat the file at `path`, returning whether it changed. With `check`
+/// the file is left alone. The result must compile to the same JavaScript,
+/// so a printer bug can never rewrite a working program into another one.
+fn format_file(path: &str, check: bool) -> Result<bool, String> {
+    let src = fs::read_to_string(path)
+        .map_err(|e| format!("Error: Could not read file '{}': {}", path, e))?;
+    let formatted = harbor::format_source(&src).map_err(|diag| format!("{}: {}", path, diag))?;
+    if formatted == src {
+        return Ok(false);
+    }
+    if harbor::compile(&formatted).js != harbor::compile(&src).js {
+        return Err(format!("Error: Formatting '{}' would change what it compiles to; left unchanged", path));
+    }
+    if !check {
+        fs::write(path, formatted).map_err(|e| format!("Error: Could not write to '{}': {}", path, e))?;
+    }
+    Ok(true)
+}
+
+/// The Harbor source `spec` names as an import from the current
+/// directory, if it isn't a file itself.
+fn resolve_module(spec: &str) -> Option<PathBuf> {
+    if Path::new(spec).is_file() {
+        return None;
+    }
+    // A directory stands for its index.hb, as in an import
+    let index = Path::new(spec).join("index.hb");
+    if index.is_file() {
+        return Some(index);
+    }
+    let resolver = harbor::resolver::Resolver::new(&std::path::absolute(".").ok()?);
+    match resolver.resolve(spec, Path::new("./main.hb")) {
+        Ok(harbor::resolver::Module::Source(path)) => Some(path),
+        _ => None,
+    }
+}
 
 fn main() {
-    let args: Vec<String> = std::env::args().collect();
+    let mut args: Vec<String> = std::env::args().collect();
+    let use_cache = !args.iter().any(|a| a == "--no-cache");
+    args.retain(|a| a != "--no-cache");
+    let mut options = harbor::BuildOptions { strict: args.iter().any(|a| a == "--strict"), ..Default::default() };
+    args.retain(|a| a != "--strict");
+    while let Some(pos) = args.iter().position(|a| a == "--define") {
+        let Some(define) = args.get(pos + 1) else {
+            eprintln!("Error: --define needs NAME=VALUE");
+            std::process::exit(1);
+        };
+        match harbor::defines::parse_define(define) {
+            Ok(define) => options.defines.push(define),
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+        args.drain(pos..pos + 2);
+    }
     
     if args.contains(&"--version".to_string()) {
         println!("Harbor v2.0.0");
@@ -16,19 +143,148 @@ fn main() {
     if args.len() < 2 || args.contains(&"--help".to_string()) {
         println!("Harbor v2.0.0");
         println!("Usage: harbor <input.hb> [-o output.js]");
+        println!("       harbor <input.hb> [args...]   Compile and run, passing args to os.args");
+        println!("       harbor build <dir | file.hb>...   Compile every file, reporting all their errors");
+        println!("       harbor dev <input.hb>   Recompile and restart on changes");
+        println!("       harbor daemon [--port <n>]   Serve check, compile and build requests to editors, keeping parses warm");
+        println!("       harbor debug [--port <n>] <input.hb> [args...]   Run under the Node inspector");
+        println!("       harbor test <input.hb> [args...]   Run without servers listening; __inject sends them requests");
+        println!("       harbor fmt [--check] <file.hb>...   Rewrite files in canonical style");
+        println!("       harbor doc <file.hb | module>   List a module's functions and classes");
+        println!("       harbor doc --coverage [--min <percent>] <file.hb | module>...   List exports without doc comments; exit 1 below the minimum (default 100)");
+        println!("       harbor def <file.hb> <name>   Print the file:line where a name used in the file is defined");
         println!("\nFlags:");
         println!("  --help      Show this help");
         println!("  --version   Show version information");
         println!("  -o <path>   Specify output file (default: output.js)");
+        println!("  --no-cache  Rebuild even if .harbor/cache has this source");
+        println!("  --strict    Fail on undefined names and wrong argument counts; warn about truthiness and shadowing");
+        println!("  --define NAME=VALUE  Compile-time constant; `if NAME:` branches it rules out are left out");
+        println!("  --emit ast  Print the syntax tree instead of compiling (--pretty: as Harbor source)");
+        return;
+    }
+
+    if args[1] == "doc" && args.iter().any(|a| a == "--coverage") {
+        let mut inputs: Vec<String> = args[2..].iter().filter(|a| *a != "--coverage").cloned().collect();
+        let mut min = 100;
+        if let Some(pos) = inputs.iter().position(|a| a == "--min") {
+            match inputs.get(pos + 1).and_then(|n| n.trim_end_matches('%').parse::<usize>().ok()).filter(|n| *n <= 100) {
+                Some(n) => min = n,
+                None => {
+                    eprintln!("Error: --min needs a percentage from 0 to 100");
+                    std::process::exit(1);
+                }
+            }
+            inputs.drain(pos..pos + 2);
+        }
+        if inputs.is_empty() {
+            println!("Usage: harbor doc --coverage [--min <percent>] <file.hb | module>...");
+            return;
+        }
+        match doc_coverage(&inputs, min) {
+            Ok(true) => {}
+            Ok(false) => {
+                eprintln!("Error: Fewer than {}% of exported functions and classes are documented", min);
+                std::process::exit(1);
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
         return;
     }
 
     if args[1] == "doc" {
         if args.len() < 3 {
-             println!("Usage: harbor doc <file.hb>");
+             println!("Usage: harbor doc <file.hb | module>");
              return;
         }
-        let input_path = &args[2];
+        // Besides a path, accept anything an import could name
+        let input_path = &match resolve_module(&args[2]) {
+            Some(path) => path.display().to_string(),
+            None => args[2].clone(),
+        };
+        let resolver = Resolver::for_source(input_path);
+        let mut index = Index::default();
+        let module = match index.module(Path::new(input_path), &resolver) {
+            Ok(module) => module.clone(),
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        };
+
+        println!("Documentation for {}:", input_path);
+        println!("--------------------------------");
+        print_docs(&module.symbols, "");
+        // Re-exported names are documented where they're defined
+        for import in module.imports.iter().filter(|i| i.exported && !i.whole_module) {
+            if let Ok(Some(def)) = index.definition(Path::new(input_path), &import.name, &resolver) {
+                // Under the name it's exported as, which `as` may have changed
+                let symbol = Symbol { name: import.name.clone(), ..def.symbol };
+                print_symbol(&symbol, true, &format!("  (from {})", display_path(&def.source)), "");
+            }
+        }
+        println!("--------------------------------");
+        return;
+    }
+
+    if args[1] == "def" {
+        if args.len() < 4 {
+            println!("Usage: harbor def <file.hb> <name>");
+            return;
+        }
+        let (input_path, name) = (Path::new(&args[2]), &args[3]);
+        let resolver = Resolver::for_source(&args[2]);
+        match Index::default().definition(input_path, name, &resolver) {
+            Ok(Some(def)) => println!("{}:{}", display_path(&def.source), def.symbol.line),
+            Ok(None) => {
+                eprintln!("Error: '{}' is not defined in '{}' or the modules it imports", name, input_path.display());
+                std::process::exit(1);
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args[1] == "fmt" {
+        let check = args.iter().any(|a| a == "--check");
+        let files: Vec<&String> = args[2..].iter().filter(|a| *a != "--check").collect();
+        if files.is_empty() {
+            println!("Usage: harbor fmt [--check] <file.hb>...");
+            return;
+        }
+        let mut unformatted = false;
+        for path in files {
+            match format_file(path, check) {
+                Ok(true) if check => {
+                    println!("{} is not formatted", path);
+                    unformatted = true;
+                }
+                Ok(true) => println!("Formatted {}", path),
+                Ok(false) => {}
+                Err(message) => {
+                    eprintln!("{}", message);
+                    std::process::exit(1);
+                }
+            }
+        }
+        if unformatted {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(pos) = args.iter().position(|a| a == "--emit") {
+        if args.get(pos + 1).map(String::as_str) != Some("ast") {
+            eprintln!("Error: --emit supports only 'ast'");
+            std::process::exit(1);
+        }
+        let input_path = &args[1];
         let src = match fs::read_to_string(input_path) {
             Ok(s) => s,
             Err(e) => {
@@ -36,47 +292,117 @@ fn main() {
                 std::process::exit(1);
             }
         };
-        
-        let mut lexer = lexer::Lexer::new(&src);
-        let tokens = lexer.tokenize();
-        let mut parser = parser::Parser::new(tokens);
-        let ast = parser.parse();
-        
-        println!("Documentation for {}:", input_path);
-        println!("--------------------------------");
-        for stmt in ast {
-            match stmt {
-                ast::Stmt::Func { name, args, .. } => {
-                    println!("def {}({})", name, args.join(", "));
+        let pretty = args.iter().any(|a| a == "--pretty");
+        let printed = if pretty {
+            harbor::format_source(&src)
+        } else {
+            harbor::parse_source(&src).map(|ast| format!("{:#?}\n", ast))
+        };
+        match printed {
+            Ok(text) => print!("{}", text),
+            Err(diag) => {
+                eprintln!("{}", diag);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args[1] == "debug" {
+        let mut rest = &args[2..];
+        let mut port = None;
+        if rest.first().is_some_and(|a| a == "--port") {
+            match rest.get(1).and_then(|p| p.parse::<u16>().ok()) {
+                Some(p) => port = Some(p),
+                None => {
+                    eprintln!("Error: --port needs a port number");
+                    std::process::exit(1);
                 }
-                ast::Stmt::Class { name, methods } => {
-                    println!("class {}:", name);
-                    for method in methods {
-                        if let ast::Stmt::Func { name: m_name, args: m_args, .. } = method {
-                             println!("    def {}({})", m_name, m_args.join(", "));
-                        }
-                    }
+            }
+            rest = &rest[2..];
+        }
+        let Some(input_path) = rest.first() else {
+            println!("Usage: harbor debug [--port <n>] <file.hb> [args...]");
+            return;
+        };
+        match debug::run(input_path, &adjacent_js_path(input_path), port, &rest[1..], options) {
+            Ok(code) => std::process::exit(code),
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args[1] == "build" {
+        if args.len() < 3 {
+            println!("Usage: harbor build <dir | file.hb>...");
+            return;
+        }
+        std::process::exit(build::run(&args[2..], &options, use_cache));
+    }
+
+    if args[1] == "daemon" {
+        let port = match args.get(2).map(String::as_str) {
+            None => daemon::DEFAULT_PORT,
+            Some("--port") => match args.get(3).and_then(|p| p.parse::<u16>().ok()) {
+                Some(p) => p,
+                None => {
+                    eprintln!("Error: --port needs a port number");
+                    std::process::exit(1);
                 }
-                ast::Stmt::Export(inner) => {
-                     match *inner {
-                        ast::Stmt::Func { name, args, .. } => {
-                            println!("export def {}({})", name, args.join(", "));
-                        }
-                        ast::Stmt::Class { name, methods } => {
-                            println!("export class {}:", name);
-                            for method in methods {
-                                if let ast::Stmt::Func { name: m_name, args: m_args, .. } = method {
-                                     println!("    def {}({})", m_name, m_args.join(", "));
-                                }
-                            }
-                        }
-                        _ => {}
-                     }
+            },
+            Some(_) => {
+                println!("Usage: harbor daemon [--port <n>]");
+                return;
+            }
+        };
+        if let Err(message) = daemon::run(port, options, use_cache) {
+            eprintln!("{}", message);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if args[1] == "test" {
+        let Some(input_path) = args.get(2) else {
+            println!("Usage: harbor test <file.hb> [args...]");
+            return;
+        };
+        let output_path = adjacent_js_path(input_path);
+        let cache = Cache::for_source(input_path);
+        match harbor::build_file(input_path, &output_path, use_cache.then_some(&cache), &options) {
+            Ok(info) => {
+                for warning in &info.warnings {
+                    eprintln!("{}", warning);
                 }
-                _ => {}
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                std::process::exit(1);
             }
         }
-        println!("--------------------------------");
+        // Servers skip listening, so the tests' requests go through __inject
+        let status = std::process::Command::new("node")
+            .arg(&output_path)
+            .args(&args[3..])
+            .env("HARBOR_TEST", "1")
+            .status();
+        match status {
+            Ok(s) => std::process::exit(s.code().unwrap_or(1)),
+            Err(e) => {
+                eprintln!("Error: Could not run node: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if args[1] == "dev" {
+        if args.len() < 3 {
+            println!("Usage: harbor dev <file.hb>");
+            return;
+        }
+        dev::run(&args[2], &adjacent_js_path(&args[2]), &options);
         return;
     }
 
@@ -86,14 +412,7 @@ fn main() {
     let input_path = &args[1];
     let mut output_path = if is_run_mode {
         // If running, create adjacent .js file
-        let path = std::path::Path::new(input_path);
-        let file_stem = path.file_stem().unwrap().to_str().unwrap();
-        // Use a hidden file to avoid clutter? or just .js?
-        // Node requires .js extension usually.
-        // Let's use same dir.
-        let mut out = path.to_path_buf();
-        out.set_file_name(format!("{}.js", file_stem));
-        out.to_str().unwrap().to_string()
+        adjacent_js_path(input_path)
     } else {
         "output.js".to_string()
     };
@@ -104,28 +423,14 @@ fn main() {
         }
     }
 
-    let src = match fs::read_to_string(input_path) {
-        Ok(s) => s,
-        Err(e) => {
-            eprintln!("Error: Could not read file '{}': {}", input_path, e);
-            std::process::exit(1);
-        }
-    };
-
-    // Tokenize
-    let mut lexer = lexer::Lexer::new(&src);
-    let tokens = lexer.tokenize();
-
-    // Parse
-    let mut parser = parser::Parser::new(tokens);
-    let ast = parser.parse();
-
-    // Generate Code
-    let js_code = codegen::CodeGen::generate(&ast);
-
-    // Save Output
-    match fs::write(&output_path, js_code) {
-        Ok(_) => {
+    // Tokenize, parse and generate code into the output file, or reuse a
+    // cached build of the same source
+    let cache = Cache::for_source(input_path);
+    match harbor::build_file(input_path, &output_path, use_cache.then_some(&cache), &options) {
+        Ok(info) => {
+            for warning in &info.warnings {
+                eprintln!("{}", warning);
+            }
             if !is_run_mode {
                 println!("─────────────────────────────────────────");
                 println!("  � Harbor Compilation Successful!");
@@ -136,6 +441,7 @@ fn main() {
                  // Run it!
                  let status = std::process::Command::new("node")
                     .arg(&output_path)
+                    .args(&args[2..])
                     .status();
                  
                  // Cleanup
@@ -159,8 +465,8 @@ fn main() {
                  }
             }
         }
-        Err(e) => {
-            eprintln!("Error: Could not write to '{}': {}", output_path, e);
+        Err(message) => {
+            eprintln!("{}", message);
             std::process::exit(1);
         }
     }
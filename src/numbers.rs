@@ -0,0 +1,156 @@
+//! Integer tracking for arithmetic. Harbor numbers are JS doubles, which
+//! hold integers exactly up to 2^53; integers past that are BigInts, which
+//! JS refuses to mix with doubles. What's known at compile time about an
+//! expression's kind decides where codegen converts between the two.
+
+use crate::ast::Expr;
+
+/// The largest integer a double holds exactly (`Number.MAX_SAFE_INTEGER`).
+pub const MAX_SAFE: i128 = (1 << 53) - 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Int,
+    Float,
+    Big,
+}
+
+/// Whether `value` fits in a double without rounding.
+pub fn is_safe(value: i128) -> bool {
+    (-MAX_SAFE..=MAX_SAFE).contains(&value)
+}
+
+/// Whether `text`, an integer literal as written, is too large for a
+/// double and must be a BigInt.
+pub fn needs_bigint(text: &str) -> bool {
+    parse_int(text).is_none_or(|value| !is_safe(value))
+}
+
+/// The digits of a number literal and their base: `0x`, `0o` and `0b`
+/// prefixes give hex, octal and binary.
+fn split_radix(text: &str) -> (&str, u32) {
+    match text.get(..2) {
+        Some("0x" | "0X") => (&text[2..], 16),
+        Some("0o" | "0O") => (&text[2..], 8),
+        Some("0b" | "0B") => (&text[2..], 2),
+        _ => (text, 10),
+    }
+}
+
+/// The value of an integer literal as written, `_` separators and all,
+/// unless it overflows an i128.
+pub fn parse_int(text: &str) -> Option<i128> {
+    let (digits, radix) = split_radix(text);
+    i128::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+/// The value of a number literal as written, as a double.
+pub fn parse(text: &str) -> f64 {
+    match split_radix(text) {
+        (digits, 10) => digits.replace('_', "").parse().unwrap_or(0.0),
+        _ => parse_int(text).map_or(f64::INFINITY, |value| value as f64),
+    }
+}
+
+/// What's wrong with the number literal `text`, if anything: digits its
+/// base doesn't have, or a `_` that isn't between two digits.
+pub fn literal_problem(text: &str) -> Option<String> {
+    let (digits, radix) = split_radix(text);
+    let base = match radix {
+        16 => "hex",
+        8 => "octal",
+        2 => "binary",
+        _ => "decimal",
+    };
+    if digits.is_empty() {
+        return Some(format!("Expected {} digits after '{}'", base, text));
+    }
+    if let Some(bad) = digits.chars().find(|&c| c != '_' && c != '.' && !c.is_digit(radix)) {
+        return Some(format!("'{}' isn't a {} digit in '{}'", bad, base, text));
+    }
+    let chars: Vec<char> = digits.chars().collect();
+    let between_digits = |i: usize| i > 0 && i + 1 < chars.len() && chars[i - 1].is_digit(radix) && chars[i + 1].is_digit(radix);
+    if (0..chars.len()).any(|i| chars[i] == '_' && !between_digits(i)) {
+        return Some(format!("A '_' in a number goes between two digits, as in 1_000_000, not as in '{}'", text));
+    }
+    None
+}
+
+/// Whether the number literal `text` reads the same in JavaScript, so
+/// codegen can keep it as written. Decimals with a leading zero (`007`)
+/// are legacy octal there.
+pub fn is_js_literal(text: &str) -> bool {
+    let bytes = text.trim_start_matches('-').as_bytes();
+    !(bytes.len() > 1 && bytes[0] == b'0' && (bytes[1].is_ascii_digit() || bytes[1] == b'_'))
+}
+
+/// The kind of number `expr` evaluates to, if it can be told from literals,
+/// arithmetic on them, and the builtins that return numbers.
+pub fn kind(expr: &Expr) -> Option<Kind> {
+    match expr {
+        Expr::Number(n, _) if n.fract() == 0.0 && n.abs() <= MAX_SAFE as f64 => Some(Kind::Int),
+        Expr::Number(..) => Some(Kind::Float),
+        Expr::BigInt(_) => Some(Kind::Big),
+        Expr::Unary(op, operand) if op == "-" => kind(operand),
+        Expr::Call(callee, _) => match &**callee {
+            Expr::Ident(name) => match name.as_str() {
+                "int" | "len" | "ord" | "round" => Some(Kind::Int),
+                "float" => Some(Kind::Float),
+                "bigint" => Some(Kind::Big),
+                _ => None,
+            },
+            _ => None,
+        },
+        Expr::Binary(left, op, right) if is_arithmetic(op) => {
+            let (left, right) = (kind(left)?, kind(right)?);
+            if op == "/" || left == Kind::Float || right == Kind::Float {
+                Some(Kind::Float)
+            } else if left == Kind::Big || right == Kind::Big || overflows(expr) {
+                Some(Kind::Big)
+            } else {
+                Some(Kind::Int)
+            }
+        }
+        _ => None,
+    }
+}
+
+pub fn is_arithmetic(op: &str) -> bool {
+    matches!(op, "+" | "-" | "*" | "/" | "//" | "%" | "**")
+}
+
+/// Whether `expr` is integer arithmetic on literals whose result is too
+/// large for a double (`2 ** 64`), so it has to be worked out in BigInts.
+fn overflows(expr: &Expr) -> bool {
+    match expr {
+        Expr::Binary(left, op, right) if matches!(op.as_str(), "+" | "-" | "*" | "**") => {
+            match (constant(left), constant(right)) {
+                (Some(_), Some(exponent)) if op == "**" && exponent < 0 => false,
+                (Some(_), Some(_)) => constant(expr).is_none_or(|value| !is_safe(value)),
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// The value of an integer expression made only of literals, unless it
+/// overflows an i128.
+fn constant(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Number(n, _) if n.fract() == 0.0 && n.abs() <= MAX_SAFE as f64 => Some(*n as i128),
+        Expr::BigInt(text) => parse_int(text),
+        Expr::Unary(op, operand) if op == "-" => constant(operand)?.checked_neg(),
+        Expr::Binary(left, op, right) => {
+            let (left, right) = (constant(left)?, constant(right)?);
+            match op.as_str() {
+                "+" => left.checked_add(right),
+                "-" => left.checked_sub(right),
+                "*" => left.checked_mul(right),
+                "**" => left.checked_pow(u32::try_from(right).ok()?),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
@@ -1,14 +1,59 @@
 use crate::ast::*;
-use crate::lexer::{Token, TokenData, FStringPart};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::{Comment, Token, TokenData, FStringPart};
+
+/// Deepest nesting of blocks and expressions the parser accepts. Codegen and
+/// the checks walk the tree recursively, so anything deeper would overflow
+/// the stack instead of reporting an error.
+const MAX_DEPTH: usize = 100;
 
 pub struct Parser {
     tokens: Vec<Token>,
     pos: usize,
+    keep_comments: bool,
+    keep_lines: bool,
+    pending_comments: Vec<Comment>,
+    /// Nesting depth of `stream:` blocks, where `emit` is a statement.
+    stream_depth: usize,
+    /// Current nesting of blocks and expressions, capped at `MAX_DEPTH`.
+    depth: usize,
+    /// Set by `@sync` for the `def` that follows it.
+    sync_def: bool,
+    /// Inside a `@sync` function's body, which can't wait.
+    in_sync: bool,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+        Self { tokens, pos: 0, keep_comments: false, keep_lines: false, pending_comments: Vec::new(), stream_depth: 0, depth: 0, sync_def: false, in_sync: false }
+    }
+
+    /// A parser that keeps comment trivia as `Stmt::Comment` nodes at
+    /// statement boundaries, for formatting and doc tooling.
+    pub fn with_comments(tokens: Vec<Token>) -> Self {
+        Self { keep_comments: true, ..Self::new(tokens) }
+    }
+
+    /// A parser that records the source line of each statement as a
+    /// `Stmt::Line` node before it, for source maps.
+    pub fn with_lines(tokens: Vec<Token>) -> Self {
+        Self { keep_lines: true, ..Self::new(tokens) }
+    }
+
+    /// A parser that keeps both comments and statement lines, for tooling
+    /// that reports where things are defined along with their docs.
+    pub fn with_trivia(tokens: Vec<Token>) -> Self {
+        Self { keep_comments: true, keep_lines: true, ..Self::new(tokens) }
+    }
+
+    /// Go one level deeper, failing once the nesting exceeds `MAX_DEPTH`.
+    /// Callers subtract from `depth` again when the nested part is done.
+    fn enter(&mut self) -> Result<(), Diagnostic> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err(Diagnostic::error(format!("Code is nested too deeply (more than {} levels)", MAX_DEPTH), self.peek().span));
+        }
+        Ok(())
     }
 
     fn peek(&self) -> &Token {
@@ -24,14 +69,64 @@ impl Parser {
     }
 
     fn advance(&mut self) -> &Token {
-        let tok = &self.tokens[self.pos];
-        if tok.data != TokenData::EOF {
+        let idx = self.pos;
+        if self.keep_comments {
+            let comments = std::mem::take(&mut self.tokens[idx].comments);
+            self.pending_comments.extend(comments);
+        }
+        if self.tokens[idx].data != TokenData::EOF {
             self.pos += 1;
         }
-        tok
+        &self.tokens[idx]
+    }
+
+    /// Consume the current token and move it out of the buffer, so its text
+    /// can go into the AST without a copy. Tokens are never revisited once
+    /// consumed.
+    fn take(&mut self) -> Token {
+        let idx = self.pos;
+        let span = self.advance().span;
+        if idx == self.pos {
+            return Token { data: TokenData::EOF, span, comments: Vec::new() };
+        }
+        std::mem::replace(&mut self.tokens[idx], Token { data: TokenData::Newline, span, comments: Vec::new() })
     }
 
-    pub fn parse(&mut self) -> Vec<Stmt> {
+    /// Take the current token's name if it is an identifier.
+    fn take_ident(&mut self) -> Option<String> {
+        match self.peek().data {
+            TokenData::Ident(_) => match self.take().data {
+                TokenData::Ident(name) => Some(name),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Take the current token's text if it is a string literal.
+    fn take_string(&mut self) -> Option<String> {
+        match self.peek().data {
+            TokenData::String(_) => match self.take().data {
+                TokenData::String(text) => Some(text),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Emit comments seen since the last statement, plus those attached to
+    /// the current token, as `Stmt::Comment` nodes.
+    fn flush_comments(&mut self, out: &mut Vec<Stmt>) {
+        if !self.keep_comments {
+            return;
+        }
+        let current = std::mem::take(&mut self.tokens[self.pos].comments);
+        for comment in self.pending_comments.drain(..).chain(current) {
+            out.push(Stmt::Comment(comment.text));
+        }
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
         let mut stmts = Vec::new();
         while self.peek().data != TokenData::EOF {
             match self.peek().data {
@@ -41,58 +136,113 @@ impl Parser {
                 }
                 _ => {}
             }
-            stmts.push(self.parse_stmt());
+            self.flush_comments(&mut stmts);
+            self.push_stmt(&mut stmts)?;
         }
-        stmts
+        self.flush_comments(&mut stmts);
+        Ok(stmts)
     }
 
-    fn parse_block(&mut self) -> Vec<Stmt> {
+    fn parse_block(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
         let mut body = Vec::new();
+        self.enter()?;
+        self.parse_block_with(&mut body, |p, body| p.push_stmt(body))?;
+        self.depth -= 1;
+        Ok(body)
+    }
 
+    /// Walk a braced, indented, or single-line block, calling `item` at the
+    /// start of each entry. Blocks that allow directives besides statements
+    /// (server and route bodies) supply their own `item`.
+    fn parse_block_with<F>(&mut self, body: &mut Vec<Stmt>, mut item: F) -> Result<(), Diagnostic>
+    where
+        F: FnMut(&mut Self, &mut Vec<Stmt>) -> Result<(), Diagnostic>,
+    {
         match self.peek().data {
             TokenData::LBrace => {
                 self.advance(); // consume '{'
-                while !matches!(self.peek().data, TokenData::RBrace | TokenData::EOF) {
-                    body.push(self.parse_stmt());
-                }
-                self.expect(TokenData::RBrace);
-            }
-            TokenData::Newline => {
-                self.advance(); // consume newline after colon
-                self.expect(TokenData::Indent);
                 loop {
-                    while matches!(self.peek().data, TokenData::Newline) {
-                        self.advance();
-                    }
-                    if matches!(self.peek().data, TokenData::Dedent | TokenData::EOF) {
+                    self.flush_comments(body);
+                    if matches!(self.peek().data, TokenData::RBrace | TokenData::EOF) {
                         break;
                     }
-                    body.push(self.parse_stmt());
+                    item(self, body)?;
                 }
-                self.expect(TokenData::Dedent);
+                self.expect(TokenData::RBrace)?;
             }
-            TokenData::Indent => {
-                self.advance();
+            TokenData::Newline | TokenData::Indent => {
+                if matches!(self.peek().data, TokenData::Newline) {
+                    self.advance(); // consume newline after colon
+                }
+                self.expect(TokenData::Indent)?;
                 loop {
                     while matches!(self.peek().data, TokenData::Newline) {
                         self.advance();
                     }
+                    self.flush_comments(body);
                     if matches!(self.peek().data, TokenData::Dedent | TokenData::EOF) {
                         break;
                     }
-                    body.push(self.parse_stmt());
+                    item(self, body)?;
                 }
-                self.expect(TokenData::Dedent);
+                self.expect(TokenData::Dedent)?;
             }
             _ => {
                 // Single-line block
-                body.push(self.parse_stmt());
+                item(self, body)?;
             }
         }
-        body
+        Ok(())
+    }
+
+    /// Whether the next tokens are the contextual directive `name` (an
+    /// identifier that isn't being assigned to or called).
+    fn at_directive(&self, name: &str) -> bool {
+        match &self.peek().data {
+            TokenData::Ident(n) if n == name => !matches!(
+                self.peek_next().map(|t| &t.data),
+                Some(TokenData::Assign | TokenData::Dot | TokenData::LParen | TokenData::LBracket
+                    | TokenData::PlusAssign | TokenData::DashAssign | TokenData::StarAssign | TokenData::SlashAssign)
+            ),
+            _ => false,
+        }
     }
 
-    fn parse_stmt(&mut self) -> Stmt {
+    /// Whether the token after the current one starts a value, so a word
+    /// like `html` in `respond html page` modifies it instead of being it.
+    fn value_follows(&self) -> bool {
+        matches!(
+            self.peek_next().map(|t| &t.data),
+            Some(TokenData::Ident(_) | TokenData::String(_) | TokenData::FStringToken(_) | TokenData::Number(..)
+                | TokenData::BigInt(_) | TokenData::LBrace | TokenData::LBracket | TokenData::LParen
+                | TokenData::Dash | TokenData::Not | TokenData::Fetch | TokenData::True | TokenData::False
+                | TokenData::None_ | TokenData::Self_)
+        )
+    }
+
+    /// Parse a `name:` directive followed by a block.
+    fn parse_hook(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
+        self.advance(); // consume directive name
+        if matches!(self.peek().data, TokenData::Colon) {
+            self.advance();
+        }
+        self.parse_block()
+    }
+
+    /// Parse a statement onto `out`, after its line if the parser keeps
+    /// lines.
+    fn push_stmt(&mut self, out: &mut Vec<Stmt>) -> Result<(), Diagnostic> {
+        if self.keep_lines {
+            while matches!(self.peek().data, TokenData::Newline) {
+                self.advance();
+            }
+            out.push(Stmt::Line(self.peek().span.line));
+        }
+        out.push(self.parse_stmt()?);
+        Ok(())
+    }
+
+    fn parse_stmt(&mut self) -> Result<Stmt, Diagnostic> {
         // Skip leading newlines
         while matches!(self.peek().data, TokenData::Newline) {
             self.advance();
@@ -100,38 +250,66 @@ impl Parser {
 
         let stmt = match self.peek().data {
             // Control flow
-            TokenData::If => self.parse_if(),
-            TokenData::For => self.parse_for(),
-            TokenData::While => self.parse_while(),
+            TokenData::If => self.parse_if()?,
+            TokenData::For => self.parse_for()?,
+            TokenData::While => self.parse_while()?,
             TokenData::Break => { self.advance(); Stmt::Break }
             TokenData::Continue => { self.advance(); Stmt::Continue }
             TokenData::Pass => { self.advance(); Stmt::Pass }
 
             // Functions & classes
-            TokenData::Def => self.parse_func(),
-            TokenData::Return => self.parse_return(),
-            TokenData::Class => self.parse_class(),
+            TokenData::Def => self.parse_func()?,
+            TokenData::At => self.parse_decorated()?,
+            TokenData::Return => self.parse_return()?,
+            TokenData::Class => self.parse_class()?,
 
             // Error handling
-            TokenData::Try => self.parse_try(),
+            TokenData::Try => self.parse_try()?,
 
             // Modules
-            TokenData::Import => self.parse_import(),
-            TokenData::From => self.parse_from_import(),
-            TokenData::Export => self.parse_export(),
+            TokenData::Import => self.parse_import()?,
+            TokenData::From => self.parse_from_import()?,
+            TokenData::Export => self.parse_export()?,
 
             // Print
-            TokenData::Print => self.parse_print(),
+            TokenData::Print => self.parse_print()?,
 
             // Harbor-specific
-            TokenData::Server => self.parse_server(),
-            TokenData::Respond => self.parse_respond(),
-            TokenData::Fetch => self.parse_fetch(),
+            TokenData::Server => self.parse_server()?,
+            TokenData::Respond => self.parse_respond()?,
+            TokenData::Fetch => self.parse_fetch()?,
 
             TokenData::EOF => Stmt::Pass,
 
+            // Contextual statements
+            TokenData::Ident(ref n) if n == "stream"
+                && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Colon)) => self.parse_stream()?,
+            TokenData::Ident(_) if self.stream_depth > 0 && self.at_directive("emit") => self.parse_emit()?,
+            TokenData::Ident(ref n) if n == "assert"
+                && !matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Assign | TokenData::Dot)) => self.parse_assert()?,
+            TokenData::Ident(ref n) if n == "raise"
+                && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Ident(_))) => {
+                self.advance(); // consume 'raise'
+                Stmt::Raise(self.parse_expr()?)
+            }
+            TokenData::Ident(ref n) if n == "every" && self.at_directive("every") => self.parse_every()?,
+            TokenData::Ident(ref n) if n == "cron"
+                && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::String(_))) => self.parse_cron()?,
+            // Every function is async already; `async def` is accepted as is
+            TokenData::Ident(ref n) if n == "async"
+                && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Def)) => {
+                self.advance(); // consume 'async'
+                self.parse_func()?
+            }
+            TokenData::Ident(ref n) if n == "spawn"
+                && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Colon)) => {
+                self.advance(); // consume 'spawn'
+                self.advance(); // consume ':'
+                Stmt::Spawn { body: self.parse_async_block()? }
+            }
+
             // Expression or assignment
-            _ => self.parse_expr_or_assign(),
+            _ => self.parse_expr_or_assign()?,
         };
 
         // Skip trailing newlines
@@ -139,24 +317,22 @@ impl Parser {
             self.advance();
         }
 
-        stmt
+        Ok(stmt)
     }
 
-    fn parse_expr_or_assign(&mut self) -> Stmt {
-        let expr = self.parse_expr();
+    fn parse_expr_or_assign(&mut self) -> Result<Stmt, Diagnostic> {
+        let expr = self.parse_expr()?;
 
         if matches!(self.peek().data, TokenData::Assign) {
             self.advance(); // consume '='
-            let value = self.parse_expr();
+            let value = self.parse_expr()?;
             match &expr {
                 Expr::Ident(_) | Expr::Member(_, _) | Expr::Index(_, _) => {
-                    Stmt::Set { target: expr, value }
+                    Ok(Stmt::Set { target: expr, value })
                 }
                 _ => {
                     let tok = self.peek();
-                    eprintln!("Error: Invalid assignment target at line {}, col {}",
-                        tok.span.line, tok.span.col);
-                    std::process::exit(1);
+                    Err(Diagnostic::error("Invalid assignment target", tok.span))
                 }
             }
         } else if matches!(self.peek().data,
@@ -170,24 +346,24 @@ impl Parser {
                 TokenData::SlashAssign => "/".to_string(),
                 _ => unreachable!(),
             };
-            let value = self.parse_expr();
-            Stmt::AugAssign { target: expr, op, value }
+            let value = self.parse_expr()?;
+            Ok(Stmt::AugAssign { target: expr, op, value })
         } else {
-            Stmt::Expression(expr)
+            Ok(Stmt::Expression(expr))
         }
     }
 
     // ─── Control Flow ───
 
-    fn parse_if(&mut self) -> Stmt {
+    fn parse_if(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'if'
-        let condition = self.parse_expr();
+        let condition = self.parse_expr()?;
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let then_body = self.parse_block();
+        let then_body = self.parse_block()?;
 
         let mut elif_branches = Vec::new();
         let mut else_body = None;
@@ -195,120 +371,160 @@ impl Parser {
         loop {
             if matches!(self.peek().data, TokenData::Elif) {
                 self.advance(); // consume 'elif'
-                let elif_cond = self.parse_expr();
+                let elif_cond = self.parse_expr()?;
                 if matches!(self.peek().data, TokenData::Colon) {
                     self.advance();
                 }
-                let elif_body = self.parse_block();
+                let elif_body = self.parse_block()?;
                 elif_branches.push((elif_cond, elif_body));
             } else if matches!(self.peek().data, TokenData::Else) {
                 self.advance(); // consume 'else'
                 if matches!(self.peek().data, TokenData::Colon) {
                     self.advance();
                 }
-                else_body = Some(self.parse_block());
+                else_body = Some(self.parse_block()?);
                 break;
             } else {
                 break;
             }
         }
 
-        Stmt::If { condition, then_body, elif_branches, else_body }
+        Ok(Stmt::If { condition, then_body, elif_branches, else_body })
     }
 
-    fn parse_for(&mut self) -> Stmt {
+    fn parse_for(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'for'
 
-        let var_tok = self.advance();
-        let var = match &var_tok.data {
-            TokenData::Ident(n) => n.clone(),
+        let var_tok = self.take();
+        let var = match var_tok.data {
+            TokenData::Ident(n) => n,
             _ => {
-                eprintln!("Error: Expected variable name after 'for' at line {}, col {}",
-                    var_tok.span.line, var_tok.span.col);
-                std::process::exit(1);
+                return Err(Diagnostic::error("Expected variable name after 'for'", var_tok.span));
             }
         };
 
-        self.expect(TokenData::In);
+        self.expect(TokenData::In)?;
 
-        let iterable = self.parse_expr();
+        let iterable = self.parse_expr()?;
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let body = self.parse_block();
+        let body = self.parse_block()?;
 
-        Stmt::ForIn { var, iterable, body }
+        Ok(Stmt::ForIn { var, iterable, body })
     }
 
-    fn parse_while(&mut self) -> Stmt {
+    fn parse_while(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'while'
-        let condition = self.parse_expr();
+        let condition = self.parse_expr()?;
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
-        let body = self.parse_block();
-        Stmt::While { condition, body }
+        let body = self.parse_block()?;
+        Ok(Stmt::While { condition, body })
     }
 
     // ─── Functions & Classes ───
 
-    fn parse_func(&mut self) -> Stmt {
+    fn parse_func(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'def'
 
-        let name = match &self.advance().data {
-            TokenData::Ident(n) => n.clone(),
+        let tok = self.take();
+
+        let name = match tok.data {
+            TokenData::Ident(n) => n,
             _ => {
-                eprintln!("Error: Expected function name after 'def'");
-                std::process::exit(1);
+                return Err(Diagnostic::error("Expected function name after 'def'", tok.span));
             }
         };
 
-        self.expect(TokenData::LParen);
+        self.expect(TokenData::LParen)?;
         let mut args = Vec::new();
         if !matches!(self.peek().data, TokenData::RParen) {
             loop {
-                let arg = match &self.advance().data {
-                    TokenData::Ident(n) => n.clone(),
+                let tok = self.take();
+                let arg = match tok.data {
+                    TokenData::Ident(n) => n,
                     _ => {
-                        eprintln!("Error: Expected argument name");
-                        std::process::exit(1);
+                        return Err(Diagnostic::error("Expected argument name", tok.span));
                     }
                 };
                 args.push(arg);
                 if matches!(self.peek().data, TokenData::RParen) {
                     break;
                 }
-                self.expect(TokenData::Comma);
+                self.expect(TokenData::Comma)?;
             }
         }
-        self.expect(TokenData::RParen);
+        self.expect(TokenData::RParen)?;
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let body = self.parse_block();
-        Stmt::Func { name, args, body }
+        let sync = std::mem::take(&mut self.sync_def);
+        let outer = std::mem::replace(&mut self.in_sync, sync);
+        let body = self.parse_block()?;
+        self.in_sync = outer;
+        Ok(Stmt::Func { name, args, body, sync })
+    }
+
+    /// `@sync` on the line before a `def` (or `export def`).
+    fn parse_decorated(&mut self) -> Result<Stmt, Diagnostic> {
+        let at = self.advance().span; // consume '@'
+        match self.take_ident() {
+            Some(name) if name == "sync" => {}
+            Some(name) => return Err(Diagnostic::error(format!("Unknown decorator '@{}'; the only one is @sync", name), at)),
+            None => return Err(Diagnostic::error("Expected a decorator name after '@'", at)),
+        }
+        if !matches!(self.peek().data, TokenData::Newline) {
+            return Err(Diagnostic::error("Expected a new line after @sync, then the def it marks", self.peek().span));
+        }
+        while matches!(self.peek().data, TokenData::Newline) {
+            self.advance();
+        }
+        if matches!(&self.peek().data, TokenData::Ident(n) if n == "async") {
+            return Err(Diagnostic::error("A @sync function can't also be an async def", self.peek().span));
+        }
+        let def = match self.peek().data {
+            TokenData::Export => self.peek_next().map(|t| &t.data),
+            ref data => Some(data),
+        };
+        if !matches!(def, Some(TokenData::Def)) {
+            return Err(Diagnostic::error("@sync goes on the line before a def", at));
+        }
+        // parse_func takes the flag
+        self.sync_def = true;
+        self.parse_stmt()
+    }
+
+    /// The block of `spawn:`, `every` or `cron`, which runs as a function of
+    /// its own and so can wait even inside a `@sync` function.
+    fn parse_async_block(&mut self) -> Result<Vec<Stmt>, Diagnostic> {
+        let outer = std::mem::take(&mut self.in_sync);
+        let body = self.parse_block()?;
+        self.in_sync = outer;
+        Ok(body)
     }
 
-    fn parse_return(&mut self) -> Stmt {
+    fn parse_return(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'return'
         if matches!(self.peek().data, TokenData::Newline | TokenData::EOF | TokenData::Dedent) {
-            Stmt::Return(None)
+            Ok(Stmt::Return(None))
         } else {
-            Stmt::Return(Some(self.parse_expr()))
+            Ok(Stmt::Return(Some(self.parse_expr()?)))
         }
     }
 
-    fn parse_class(&mut self) -> Stmt {
+    fn parse_class(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'class'
-        let name = match &self.advance().data {
-            TokenData::Ident(n) => n.clone(),
+        let tok = self.take();
+        let name = match tok.data {
+            TokenData::Ident(n) => n,
             _ => {
-                eprintln!("Error: Expected class name after 'class'");
-                std::process::exit(1);
+                return Err(Diagnostic::error("Expected class name after 'class'", tok.span));
             }
         };
 
@@ -316,108 +532,186 @@ impl Parser {
             self.advance();
         }
 
-        let methods = self.parse_block();
-        Stmt::Class { name, methods }
+        let methods = self.parse_block()?;
+        Ok(Stmt::Class { name, methods })
     }
 
     // ─── Error Handling ───
 
-    fn parse_try(&mut self) -> Stmt {
+    fn parse_try(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'try'
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let body = self.parse_block();
+        let body = self.parse_block()?;
 
-        self.expect(TokenData::Except);
+        let mut handlers: Vec<ExceptClause> = Vec::new();
+        loop {
+            if handlers.last().is_some_and(|h| h.types.is_empty()) {
+                return Err(Diagnostic::error("An 'except' without error types must be the last clause", self.peek().span));
+            }
+            self.expect(TokenData::Except)?;
+            handlers.push(self.parse_except_clause()?);
+            if !matches!(self.peek().data, TokenData::Except) {
+                break;
+            }
+        }
 
-        let mut except_var = None;
-        if let TokenData::Ident(name) = &self.peek().data {
-            except_var = Some(name.clone());
-            self.advance();
+        Ok(Stmt::Try { body, handlers })
+    }
+
+    /// After `except`: error types (capitalized names, comma-separated) with
+    /// an optional `as <name>`, or just the variable name, then the block.
+    fn parse_except_clause(&mut self) -> Result<ExceptClause, Diagnostic> {
+        let mut types = Vec::new();
+        let mut var = None;
+        if matches!(&self.peek().data, TokenData::Ident(name) if name.starts_with(|c: char| c.is_ascii_uppercase())) {
+            loop {
+                let tok = self.take();
+                match tok.data {
+                    TokenData::Ident(name) => types.push(name),
+                    _ => return Err(Diagnostic::error(format!("Expected an error type after ',', found {:?}", tok.data), tok.span)),
+                }
+                if !matches!(self.peek().data, TokenData::Comma) {
+                    break;
+                }
+                self.advance();
+            }
+            if matches!(self.peek().data, TokenData::As) {
+                self.advance();
+                let tok = self.take();
+                match tok.data {
+                    TokenData::Ident(name) => var = Some(name),
+                    _ => return Err(Diagnostic::error(format!("Expected a name after 'as', found {:?}", tok.data), tok.span)),
+                }
+            }
+        } else {
+            var = self.take_ident();
         }
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let except_body = self.parse_block();
+        let body = self.parse_block()?;
+        Ok(ExceptClause { types, var, body })
+    }
 
-        Stmt::Try { body, except_var, except_body }
+    fn parse_assert(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance(); // consume 'assert'
+        let condition = self.parse_expr()?;
+        let message = if matches!(self.peek().data, TokenData::Comma) {
+            self.advance();
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+        Ok(Stmt::Assert { condition, message })
     }
 
     // ─── Modules ───
 
-    fn parse_import(&mut self) -> Stmt {
+    fn parse_import(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'import'
 
-        let path = match &self.advance().data {
-            TokenData::String(s) => s.clone(),
+        let tok = self.take();
+        let span = tok.span;
+
+        let path = match tok.data {
+            TokenData::String(s) => s,
             _ => {
-                eprintln!("Error: Expected string path after 'import'");
-                std::process::exit(1);
+                return Err(Diagnostic::error("Expected string path after 'import'", tok.span));
             }
         };
 
         let mut alias = None;
         if matches!(self.peek().data, TokenData::As) {
             self.advance(); // consume 'as'
-            match &self.advance().data {
+            let tok = self.take();
+            match tok.data {
                 TokenData::Ident(name) => {
-                    alias = Some(name.clone());
+                    alias = Some(name);
                 }
                 _ => {
-                    eprintln!("Error: Expected identifier after 'as'");
-                    std::process::exit(1);
+                    return Err(Diagnostic::error("Expected identifier after 'as'", tok.span));
                 }
             }
         }
 
-        Stmt::Import { path, alias }
+        Ok(Stmt::Import { path, alias, resolved: None, span })
     }
 
-    fn parse_from_import(&mut self) -> Stmt {
+    fn parse_from_import(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'from'
 
-        let path = match &self.advance().data {
-            TokenData::String(s) => s.clone(),
+        let tok = self.take();
+        let span = tok.span;
+
+        let path = match tok.data {
+            TokenData::String(s) => s,
             _ => {
-                eprintln!("Error: Expected string path after 'from'");
-                std::process::exit(1);
+                return Err(Diagnostic::error("Expected string path after 'from'", tok.span));
             }
         };
 
-        self.expect(TokenData::Import);
+        self.expect(TokenData::Import)?;
+
+        if matches!(self.peek().data, TokenData::Ident(ref n) if n == "default") {
+            return Err(Diagnostic::error(format!("Import a default export as part of the module: import \"{}\" as name, then name.default", path), self.peek().span));
+        }
+        let names = self.parse_names("import")?;
+        Ok(Stmt::FromImport { path, names, resolved: None, span })
+    }
 
+    /// A comma-separated list of names after `import` or `export`, each
+    /// optionally followed by `as <alias>`.
+    fn parse_names(&mut self, keyword: &str) -> Result<Vec<NameAs>, Diagnostic> {
         let mut names = Vec::new();
         loop {
-            match &self.advance().data {
-                TokenData::Ident(n) => names.push(n.clone()),
-                _ => {
-                    eprintln!("Error: Expected identifier in import list");
-                    std::process::exit(1);
-                }
+            let Some(name) = self.take_ident() else {
+                return Err(Diagnostic::error(format!("Expected identifier in {} list", keyword), self.peek().span));
+            };
+            let mut alias = None;
+            if matches!(self.peek().data, TokenData::As) {
+                self.advance(); // consume 'as'
+                let span = self.peek().span;
+                alias = match self.take_ident() {
+                    Some(alias) if alias == "default" && keyword == "export" => {
+                        return Err(Diagnostic::error(format!("Export a default with export default {}", name), span));
+                    }
+                    Some(alias) => Some(alias),
+                    None => return Err(Diagnostic::error("Expected identifier after 'as'", span)),
+                };
             }
+            names.push(NameAs { name, alias });
             if !matches!(self.peek().data, TokenData::Comma) {
                 break;
             }
             self.advance(); // consume comma
         }
-
-        Stmt::FromImport { path, names }
+        Ok(names)
     }
 
-    fn parse_export(&mut self) -> Stmt {
+    fn parse_export(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'export'
-        let stmt = self.parse_stmt();
-        Stmt::Export(Box::new(stmt))
+        if matches!(self.peek().data, TokenData::Ident(ref n) if n == "default") {
+            self.advance(); // consume 'default'
+            return Ok(Stmt::ExportDefault(self.parse_expr()?));
+        }
+        // `export a, b as c` exports names defined elsewhere in the module
+        let names_follow = matches!(self.peek_next().map(|t| &t.data), Some(TokenData::As | TokenData::Comma | TokenData::Newline | TokenData::EOF | TokenData::Dedent));
+        if matches!(self.peek().data, TokenData::Ident(_)) && names_follow {
+            return Ok(Stmt::ExportNames(self.parse_names("export")?));
+        }
+        let stmt = self.parse_stmt()?;
+        Ok(Stmt::Export(Box::new(stmt)))
     }
 
     // ─── Print ───
 
-    fn parse_print(&mut self) -> Stmt {
+    fn parse_print(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'print'
 
         let mut exprs = Vec::new();
@@ -426,11 +720,11 @@ impl Parser {
         if matches!(self.peek().data, TokenData::Newline | TokenData::EOF | TokenData::Dedent) {
             // print with no arguments → print empty line
             exprs.push(Expr::String("".to_string()));
-            return Stmt::Print(exprs);
+            return Ok(Stmt::Print(exprs));
         }
 
         loop {
-            exprs.push(self.parse_expr());
+            exprs.push(self.parse_expr()?);
             if !matches!(self.peek().data, TokenData::Comma) {
                 break;
             }
@@ -443,156 +737,478 @@ impl Parser {
             }
         }
 
-        Stmt::Print(exprs)
+        Ok(Stmt::Print(exprs))
     }
 
     // ─── Harbor-specific ───
 
-    fn parse_server(&mut self) -> Stmt {
-        self.advance(); // consume 'server'
+    fn parse_server(&mut self) -> Result<Stmt, Diagnostic> {
+        let keyword = self.advance().span; // consume 'server'
 
         while matches!(self.peek().data, TokenData::Indent) {
             self.advance();
         }
 
-        let port = match self.peek().data {
-            TokenData::LBrace | TokenData::Colon | TokenData::Newline | TokenData::Indent => Expr::Number(8080.0),
-            _ => self.parse_expr(),
+        let default_port = if self.at_directive("tls") { 443.0 } else { 8080.0 };
+        let start = self.peek().span;
+        let (port, port_span) = match self.peek().data {
+            TokenData::LBrace | TokenData::Colon | TokenData::Newline | TokenData::Indent => (Expr::Number(default_port, None), keyword),
+            _ if self.at_directive("tls") => (Expr::Number(default_port, None), keyword),
+            _ => (self.parse_expr()?, start),
         };
 
+        let tls = if self.at_directive("tls") { Some(self.parse_tls()?) } else { None };
+
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let mut routes = Vec::new();
-        match self.peek().data {
-            TokenData::LBrace => {
+        if !matches!(self.peek().data, TokenData::LBrace | TokenData::Indent | TokenData::Newline) {
+            let tok = self.peek();
+            return Err(Diagnostic::error(format!("Expected block after server, found {:?}", tok.data), tok.span));
+        }
+
+        let mut server = Server { port, port_span, tls, log: None, limit: None, auth: None, health: None, metrics: None, headers: Vec::new(), routes: Vec::new(), before: Vec::new(), after: Vec::new(), on_shutdown: Vec::new(), on_error: None, not_found: Vec::new() };
+        let mut comments = Vec::new();
+        self.parse_block_with(&mut comments, |p, comments| {
+            server.routes.extend(route_comments(comments));
+            p.parse_server_item(&mut server)
+        })?;
+        server.routes.extend(route_comments(&mut comments));
+
+        Ok(Stmt::Server(Box::new(server)))
+    }
+
+    fn parse_tls(&mut self) -> Result<Tls, Diagnostic> {
+        let span = self.advance().span; // consume 'tls'
+        let mut cert = None;
+        let mut key = None;
+        while self.at_directive("cert") || self.at_directive("key") {
+            let is_cert = matches!(&self.advance().data, TokenData::Ident(n) if n == "cert");
+            let value = self.parse_expr()?;
+            if is_cert { cert = Some(value) } else { key = Some(value) }
+        }
+        match (cert, key) {
+            (Some(cert), Some(key)) => Ok(Tls { cert, key, span }),
+            _ => Err(Diagnostic::error("Expected 'cert <path> key <path>' after 'tls'", span)),
+        }
+    }
+
+    fn parse_server_item(&mut self, server: &mut Server) -> Result<(), Diagnostic> {
+        while matches!(self.peek().data, TokenData::Newline) {
+            self.advance();
+        }
+        if self.at_directive("before") {
+            server.before.extend(self.parse_hook()?);
+        } else if self.at_directive("after") {
+            server.after.extend(self.parse_hook()?);
+        } else if self.at_directive("on_error") {
+            self.advance(); // consume 'on_error'
+            let name = self.take_ident().unwrap_or_else(|| "err".to_string());
+            if matches!(self.peek().data, TokenData::Colon) {
                 self.advance();
-                while !matches!(self.peek().data, TokenData::RBrace | TokenData::EOF) {
-                    routes.push(self.parse_route());
-                }
-                self.expect(TokenData::RBrace);
-            }
-            TokenData::Indent | TokenData::Newline => {
-                routes = self.parse_routes_block();
             }
+            server.on_error = Some((name, self.parse_block()?));
+        } else if self.at_directive("not_found") {
+            server.not_found.extend(self.parse_hook()?);
+        } else if self.at_directive("on_shutdown") {
+            server.on_shutdown.extend(self.parse_hook()?);
+        } else if self.at_directive("log") {
+            server.log = Some(self.parse_log()?);
+        } else if self.at_directive("limit") {
+            server.limit = Some(self.parse_limit()?);
+        } else if self.at_directive("require_auth") {
+            server.auth = Some(self.parse_auth()?);
+        } else if self.at_directive("health") {
+            server.health = Some(self.parse_endpoint_path("/healthz")?);
+        } else if self.at_directive("metrics") {
+            server.metrics = Some(self.parse_endpoint_path("/metrics")?);
+        } else if self.at_directive("headers") {
+            server.headers.extend(self.parse_headers()?);
+        } else {
+            server.routes.push(self.parse_route_item()?);
+        }
+        Ok(())
+    }
+
+    /// `log requests` with an optional format string.
+    fn parse_log(&mut self) -> Result<Option<String>, Diagnostic> {
+        self.advance(); // consume 'log'
+        let tok = self.advance();
+        if !matches!(&tok.data, TokenData::Ident(n) if n == "requests") {
+            return Err(Diagnostic::error(format!("Expected 'requests' after 'log', found {:?}", tok.data), tok.span));
+        }
+        Ok(self.take_string())
+    }
+
+    /// `limit <count> per "second" | "minute" | "hour" | "day"`.
+    fn parse_limit(&mut self) -> Result<RateLimit, Diagnostic> {
+        self.advance(); // consume 'limit'
+        let count = self.parse_expr()?;
+        let per_tok = self.advance();
+        if !matches!(&per_tok.data, TokenData::Ident(n) if n == "per") {
+            return Err(Diagnostic::error(format!("Expected 'per' in limit, found {:?}", per_tok.data), per_tok.span));
+        }
+        let window_ms = self.parse_time_unit()?;
+        Ok(RateLimit { count, window_ms })
+    }
+
+    /// A `"second"`/`"minute"`/`"hour"`/`"day"` string (plural allowed), as
+    /// milliseconds.
+    fn parse_time_unit(&mut self) -> Result<u64, Diagnostic> {
+        let unit_tok = self.take();
+        match unit_tok.data {
+            TokenData::String(unit) => match unit.trim_end_matches('s') {
+                "second" => Ok(1_000),
+                "minute" => Ok(60_000),
+                "hour" => Ok(3_600_000),
+                "day" => Ok(86_400_000),
+                _ => Err(Diagnostic::error(format!("Unknown time unit '{}' (use second, minute, hour or day)", unit), unit_tok.span)),
+            },
+            _ => Err(Diagnostic::error(format!("Expected a time unit string, found {:?}", unit_tok.data), unit_tok.span)),
+        }
+    }
+
+    /// `every <n> "<unit>":` block.
+    fn parse_every(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance(); // consume 'every'
+        let count = self.parse_expr()?;
+        let unit_ms = self.parse_time_unit()?;
+        if matches!(self.peek().data, TokenData::Colon) {
+            self.advance();
+        }
+        Ok(Stmt::Every { count, unit_ms, body: self.parse_async_block()? })
+    }
+
+    /// `cron "<schedule>":` block.
+    fn parse_cron(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance(); // consume 'cron'
+        let tok = self.take();
+        let schedule = match tok.data {
+            TokenData::String(s) => s,
             _ => {
-                let tok = self.peek();
-                eprintln!("Error: Expected block after server at line {}, col {}, found {:?}",
-                    tok.span.line, tok.span.col, tok.data);
-                std::process::exit(1);
+                return Err(Diagnostic::error(format!("Expected schedule string after 'cron', found {:?}", tok.data), tok.span));
             }
+        };
+        if let Err(msg) = validate_cron(&schedule) {
+            return Err(Diagnostic::error(msg, tok.span));
         }
+        if matches!(self.peek().data, TokenData::Colon) {
+            self.advance();
+        }
+        Ok(Stmt::Cron { schedule, body: self.parse_async_block()? })
+    }
 
-        Stmt::Server { port, routes }
+    /// A directive name followed by an optional static path.
+    fn parse_endpoint_path(&mut self, default: &str) -> Result<String, Diagnostic> {
+        self.advance(); // consume directive name
+        let span = self.peek().span;
+        let path = match self.take_string() {
+            Some(path) => path,
+            None => return Ok(default.to_string()),
+        };
+        match parse_route_path(&path) {
+            Ok(segments) if segments.iter().all(|s| matches!(s, PathSegment::Static(_))) => Ok(path),
+            _ => Err(Diagnostic::error(format!("Expected a plain path like \"{}\", found \"{}\"", default, path), span)),
+        }
+    }
+
+    /// `headers:` block of `"Name": value` lines.
+    fn parse_headers(&mut self) -> Result<Vec<(String, Expr)>, Diagnostic> {
+        self.advance(); // consume 'headers'
+        if matches!(self.peek().data, TokenData::Colon) {
+            self.advance();
+        }
+        let mut headers = Vec::new();
+        let mut comments = Vec::new();
+        self.parse_block_with(&mut comments, |p, _| {
+            let tok = p.take();
+            let name = match tok.data {
+                TokenData::String(name) => name,
+                _ => {
+                    return Err(Diagnostic::error(format!("Expected a header name string in headers, found {:?}", tok.data), tok.span));
+                }
+            };
+            // The characters HTTP allows in a field name
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)) {
+                return Err(Diagnostic::error(format!("Invalid header name '{}'", name), tok.span));
+            }
+            p.expect(TokenData::Colon)?;
+            headers.push((name, p.parse_expr()?));
+            Ok(())
+        })?;
+        Ok(headers)
+    }
+
+    /// `require_auth basic <users>` or `require_auth bearer <secret>`.
+    fn parse_auth(&mut self) -> Result<Auth, Diagnostic> {
+        self.advance(); // consume 'require_auth'
+        let scheme_tok = self.take();
+        let scheme = match scheme_tok.data {
+            TokenData::Ident(n) if n == "basic" => AuthScheme::Basic,
+            TokenData::Ident(n) if n == "bearer" => AuthScheme::Bearer,
+            _ => {
+                return Err(Diagnostic::error(format!("Expected 'basic' or 'bearer' after 'require_auth', found {:?}", scheme_tok.data), scheme_tok.span));
+            }
+        };
+        let secret = self.parse_expr()?;
+        Ok(Auth { scheme, secret })
     }
 
-    fn parse_routes_block(&mut self) -> Vec<Route> {
+    fn parse_route_item(&mut self) -> Result<RouteItem, Diagnostic> {
         while matches!(self.peek().data, TokenData::Newline) {
             self.advance();
         }
-        self.expect(TokenData::Indent);
-        let mut routes = Vec::new();
-        while !matches!(self.peek().data, TokenData::Dedent | TokenData::EOF) {
-            routes.push(self.parse_route());
-            while matches!(self.peek().data, TokenData::Newline) {
-                self.advance();
+        if !self.at_directive("group") {
+            return Ok(RouteItem::Route(Box::new(self.parse_route()?)));
+        }
+
+        self.advance(); // consume 'group'
+        let prefix_tok = self.take();
+        let prefix = match prefix_tok.data {
+            TokenData::String(s) => s,
+            _ => {
+                return Err(Diagnostic::error(format!("Expected string prefix after 'group', found {:?}", prefix_tok.data), prefix_tok.span));
             }
+        };
+        if let Err(msg) = parse_route_path(&prefix) {
+            return Err(Diagnostic::error(msg, prefix_tok.span));
+        }
+        if matches!(self.peek().data, TokenData::Colon) {
+            self.advance();
         }
-        self.expect(TokenData::Dedent);
-        routes
+
+        let mut items = Vec::new();
+        let mut comments = Vec::new();
+        self.parse_block_with(&mut comments, |p, comments| {
+            items.extend(route_comments(comments));
+            items.push(p.parse_route_item()?);
+            Ok(())
+        })?;
+        items.extend(route_comments(&mut comments));
+        Ok(RouteItem::Group { prefix, items })
     }
 
-    fn parse_route(&mut self) -> Route {
+    fn parse_route(&mut self) -> Result<Route, Diagnostic> {
         while matches!(self.peek().data, TokenData::Newline) {
             self.advance();
         }
-        let method_tok = self.advance();
-        let method = match &method_tok.data {
+        let method_tok = self.take();
+        let method = match method_tok.data {
             TokenData::Get => "GET".to_string(),
             TokenData::Post => "POST".to_string(),
             TokenData::Put => "PUT".to_string(),
             TokenData::Delete => "DELETE".to_string(),
             TokenData::Patch => "PATCH".to_string(),
             _ => {
-                eprintln!("Error: Expected HTTP method (get, post, put, delete, patch) at line {}, col {}, found {:?}",
-                    method_tok.span.line, method_tok.span.col, method_tok.data);
-                std::process::exit(1);
+                return Err(Diagnostic::error(format!("Expected HTTP method (get, post, put, delete, patch), found {:?}", method_tok.data), method_tok.span));
             }
         };
 
-        let path_tok = self.advance();
-        let path = match &path_tok.data {
-            TokenData::String(s) => s.clone(),
+        let path_tok = self.take();
+        let path = match path_tok.data {
+            TokenData::String(s) => s,
             _ => {
-                eprintln!("Error: Expected string path in route at line {}, col {}, found {:?}",
-                    path_tok.span.line, path_tok.span.col, path_tok.data);
-                std::process::exit(1);
+                return Err(Diagnostic::error(format!("Expected string path in route, found {:?}", path_tok.data), path_tok.span));
             }
         };
+        if let Err(msg) = parse_route_path(&path) {
+            return Err(Diagnostic::error(msg, path_tok.span));
+        }
+
+        let mut route = Route { method, path, span: path_tok.span, body: Vec::new(), before: Vec::new(), after: Vec::new(), limit: None, auth: None, cache: None, expect: None, headers: Vec::new(), handler: None };
+        let mut handler_span = None;
+        if matches!(self.peek().data, TokenData::Arrow) {
+            self.advance(); // consume '->'
+            let span = self.peek().span;
+            let handler = self.parse_expr()?;
+            if !is_dotted_name(&handler) {
+                return Err(Diagnostic::error("Expected a function name after '->' in route", span));
+            }
+            route.handler = Some(handler);
+            handler_span = Some(span);
+            // Without a block of directives, that's the whole route
+            if !matches!(self.peek().data, TokenData::Colon) {
+                return Ok(route);
+            }
+        }
 
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let body = self.parse_block();
+        let mut body = Vec::new();
+        self.parse_block_with(&mut body, |p, body| {
+            if p.at_directive("before") {
+                route.before.extend(p.parse_hook()?);
+            } else if p.at_directive("after") {
+                route.after.extend(p.parse_hook()?);
+            } else if p.at_directive("limit") {
+                route.limit = Some(p.parse_limit()?);
+            } else if p.at_directive("require_auth") {
+                route.auth = Some(p.parse_auth()?);
+            } else if p.at_directive("headers") {
+                route.headers.extend(p.parse_headers()?);
+            } else if p.at_directive("expect") {
+                p.advance(); // consume 'expect'
+                let span = p.peek().span;
+                let schema = p.parse_expr()?;
+                route.expect = Some(Schema::from_expr(&schema).map_err(|msg| Diagnostic::error(msg, span))?);
+            } else if p.at_directive("cache") {
+                let span = p.advance().span; // consume 'cache'
+                if route.method != "GET" {
+                    return Err(Diagnostic::error(format!("'cache' only applies to GET routes, not {}", route.method), span));
+                }
+                route.cache = Some(p.parse_expr()?);
+            } else {
+                p.push_stmt(body)?;
+            }
+            Ok(())
+        })?;
+        if let Some(span) = handler_span {
+            if body.iter().any(|stmt| !matches!(stmt, Stmt::Comment(_) | Stmt::Line(_))) {
+                return Err(Diagnostic::error("A route with a '->' handler can only have directives (before, after, limit, require_auth, expect, cache), not statements", span));
+            }
+        }
+        route.body = body;
 
-        Route { method, path, body }
+        Ok(route)
     }
 
-    fn parse_respond(&mut self) -> Stmt {
+    fn parse_respond(&mut self) -> Result<Stmt, Diagnostic> {
         self.advance(); // consume 'respond'
 
-        let status = if let TokenData::Number(n) = self.peek().data {
-            self.advance();
-            Some(n as u16)
-        } else {
-            None
+        // A name or `status <expr>` is only a status when a value follows it;
+        // otherwise it's the value (`respond ok` sends the variable `ok`)
+        let value_follows = self.value_follows();
+        let status = match &self.peek().data {
+            TokenData::Number(n, _) => {
+                let code = *n as u16;
+                self.advance();
+                Some(Status::Code(code))
+            }
+            TokenData::Ident(name) if value_follows && name == "status" => {
+                self.advance(); // consume 'status'
+                Some(Status::Expr(self.parse_status_expr()?))
+            }
+            TokenData::Ident(name) if value_follows && Status::code_for(name).is_some() => {
+                let name = name.clone();
+                self.advance();
+                Some(Status::Named(name))
+            }
+            _ => None,
         };
 
-        let value = self.parse_expr();
-        Stmt::Respond { status, value }
+        let file_arg = !matches!(
+            self.peek_next().map(|t| &t.data),
+            None | Some(TokenData::Newline | TokenData::Dedent | TokenData::EOF)
+        );
+        if file_arg && self.at_directive("file") {
+            self.advance(); // consume 'file'
+            let path = self.parse_expr()?;
+            return Ok(Stmt::RespondFile { status, path });
+        }
+
+        let content_type = match &self.peek().data {
+            TokenData::Ident(name) if self.value_follows() => ContentType::from_name(name),
+            _ => None,
+        };
+        if content_type.is_some() {
+            self.advance(); // consume the content type
+        }
+        let value = self.parse_expr()?;
+        Ok(Stmt::Respond { status, content_type, value })
     }
 
-    fn parse_fetch(&mut self) -> Stmt {
-        self.advance(); // consume 'fetch'
-        let url = self.parse_expr();
+    /// The code after `respond status`: a name, literal or parenthesized
+    /// expression and its `.field`s. An index or call would take in the
+    /// value, as in `respond status code [item]`.
+    fn parse_status_expr(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_primary()?;
+        while matches!(self.peek().data, TokenData::Dot) {
+            self.advance();
+            let tok = self.take();
+            match tok.data {
+                TokenData::Ident(field) => expr = Expr::Member(Box::new(expr), field),
+                _ => return Err(Diagnostic::error(format!("Expected field name after '.', found {:?}", tok.data), tok.span)),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn parse_stream(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance(); // consume 'stream'
+        self.expect(TokenData::Colon)?;
+        self.stream_depth += 1;
+        let body = self.parse_block();
+        self.stream_depth -= 1;
+        Ok(Stmt::Stream { body: body? })
+    }
 
+    fn parse_emit(&mut self) -> Result<Stmt, Diagnostic> {
+        self.advance(); // consume 'emit'
+        let first = self.parse_expr()?;
+        if matches!(self.peek().data, TokenData::Comma) {
+            self.advance();
+            let data = self.parse_expr()?;
+            Ok(Stmt::Emit { event: Some(first), data })
+        } else {
+            Ok(Stmt::Emit { event: None, data: first })
+        }
+    }
+
+    /// `fetch <url>:` with a block, or otherwise a `fetch` expression used as
+    /// a statement.
+    fn parse_fetch(&mut self) -> Result<Stmt, Diagnostic> {
+        let expr = self.parse_expr()?;
+        let (url, options) = match expr {
+            Expr::Fetch { url, options } if matches!(self.peek().data, TokenData::Colon | TokenData::LBrace | TokenData::Indent) => (*url, options),
+            expr => return Ok(Stmt::Expression(expr)),
+        };
         if matches!(self.peek().data, TokenData::Colon) {
             self.advance();
         }
 
-        let body = self.parse_block();
-        Stmt::Fetch { url, body }
+        let body = self.parse_block()?;
+        Ok(Stmt::Fetch { url, options, body })
     }
 
     // ─── Expression Parsing (Precedence Climbing) ───
 
-    pub fn parse_expr(&mut self) -> Expr {
-        self.parse_or()
+    pub fn parse_expr(&mut self) -> Result<Expr, Diagnostic> {
+        self.enter()?;
+        let expr = self.parse_or()?;
+        self.depth -= 1;
+        Ok(expr)
     }
 
-    fn parse_or(&mut self) -> Expr {
-        let mut expr = self.parse_and();
+    fn parse_or(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_and()?;
+        let depth = self.depth;
         while matches!(self.peek().data, TokenData::Or) {
+            self.enter()?;
             self.advance();
-            let right = self.parse_and();
+            let right = self.parse_and()?;
             expr = Expr::Binary(Box::new(expr), "or".to_string(), Box::new(right));
         }
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_and(&mut self) -> Expr {
-        let mut expr = self.parse_not();
+    fn parse_and(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_not()?;
+        let depth = self.depth;
         while matches!(self.peek().data, TokenData::And) {
+            self.enter()?;
             self.advance();
-            let right = self.parse_not();
+            let right = self.parse_not()?;
             expr = Expr::Binary(Box::new(expr), "and".to_string(), Box::new(right));
         }
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_not(&mut self) -> Expr {
+    fn parse_not(&mut self) -> Result<Expr, Diagnostic> {
         if matches!(self.peek().data, TokenData::Not) {
             // Check for "not in" (two-token operator)
             if self.peek_next().map(|t| &t.data) == Some(&TokenData::In) {
@@ -600,14 +1216,17 @@ impl Parser {
                 return self.parse_comparison();
             }
             self.advance(); // consume 'not'
-            let right = self.parse_not();
-            return Expr::Unary("not".to_string(), Box::new(right));
+            self.enter()?;
+            let right = self.parse_not()?;
+            self.depth -= 1;
+            return Ok(Expr::Unary("not".to_string(), Box::new(right)));
         }
         self.parse_comparison()
     }
 
-    fn parse_comparison(&mut self) -> Expr {
-        let mut expr = self.parse_term();
+    fn parse_comparison(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_term()?;
+        let depth = self.depth;
 
         while matches!(self.peek().data,
             TokenData::Eq | TokenData::NotEq |
@@ -615,12 +1234,13 @@ impl Parser {
             TokenData::LessEq | TokenData::GreaterEq |
             TokenData::In | TokenData::Not)
         {
+            self.enter()?;
             // Handle "not in"
             if matches!(self.peek().data, TokenData::Not) {
                 if self.peek_next().map(|t| &t.data) == Some(&TokenData::In) {
                     self.advance(); // consume 'not'
                     self.advance(); // consume 'in'
-                    let right = self.parse_term();
+                    let right = self.parse_term()?;
                     expr = Expr::Binary(Box::new(expr), "not in".to_string(), Box::new(right));
                     continue;
                 } else {
@@ -631,7 +1251,7 @@ impl Parser {
             // Handle "in"
             if matches!(self.peek().data, TokenData::In) {
                 self.advance();
-                let right = self.parse_term();
+                let right = self.parse_term()?;
                 expr = Expr::Binary(Box::new(expr), "in".to_string(), Box::new(right));
                 continue;
             }
@@ -645,33 +1265,39 @@ impl Parser {
                 TokenData::GreaterEq => ">=".to_string(),
                 _ => unreachable!(),
             };
-            let right = self.parse_term();
+            let right = self.parse_term()?;
             expr = Expr::Binary(Box::new(expr), op, Box::new(right));
         }
 
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_term(&mut self) -> Expr {
-        let mut expr = self.parse_factor();
+    fn parse_term(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_factor()?;
+        let depth = self.depth;
         while matches!(self.peek().data, TokenData::Plus | TokenData::Dash) {
+            self.enter()?;
             let op = match self.advance().data {
                 TokenData::Plus => "+".to_string(),
                 TokenData::Dash => "-".to_string(),
                 _ => unreachable!(),
             };
-            let right = self.parse_factor();
+            let right = self.parse_factor()?;
             expr = Expr::Binary(Box::new(expr), op, Box::new(right));
         }
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_factor(&mut self) -> Expr {
-        let mut expr = self.parse_power();
+    fn parse_factor(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_power()?;
+        let depth = self.depth;
         while matches!(self.peek().data,
             TokenData::Star | TokenData::Slash |
             TokenData::Percent | TokenData::DoubleSlash)
         {
+            self.enter()?;
             let op = match self.advance().data {
                 TokenData::Star => "*".to_string(),
                 TokenData::Slash => "/".to_string(),
@@ -679,182 +1305,331 @@ impl Parser {
                 TokenData::DoubleSlash => "//".to_string(),
                 _ => unreachable!(),
             };
-            let right = self.parse_power();
+            let right = self.parse_power()?;
             expr = Expr::Binary(Box::new(expr), op, Box::new(right));
         }
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_power(&mut self) -> Expr {
-        let base = self.parse_unary();
+    fn parse_power(&mut self) -> Result<Expr, Diagnostic> {
+        let base = self.parse_unary()?;
         if matches!(self.peek().data, TokenData::DoubleStar) {
             self.advance();
-            let exp = self.parse_power(); // right-associative
-            Expr::Binary(Box::new(base), "**".to_string(), Box::new(exp))
+            self.enter()?;
+            let exp = self.parse_power()?; // right-associative
+            self.depth -= 1;
+            Ok(Expr::Binary(Box::new(base), "**".to_string(), Box::new(exp)))
         } else {
-            base
+            Ok(base)
         }
     }
 
-    fn parse_unary(&mut self) -> Expr {
+    fn parse_unary(&mut self) -> Result<Expr, Diagnostic> {
         if matches!(self.peek().data, TokenData::Dash) {
             self.advance();
-            let right = self.parse_unary();
-            return Expr::Unary("-".to_string(), Box::new(right));
+            self.enter()?;
+            let right = self.parse_unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Unary("-".to_string(), Box::new(right)));
+        }
+        let spawns_call = matches!(&self.peek().data, TokenData::Ident(n) if n == "spawn")
+            && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Ident(_) | TokenData::Self_));
+        if spawns_call {
+            self.advance(); // consume 'spawn'
+            self.enter()?;
+            let outer = std::mem::take(&mut self.in_sync);
+            let task = self.parse_unary()?;
+            self.in_sync = outer;
+            self.depth -= 1;
+            return Ok(Expr::Spawn(Box::new(task)));
+        }
+        let awaits = matches!(&self.peek().data, TokenData::Ident(n) if n == "await")
+            && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::Ident(_) | TokenData::Self_ | TokenData::LParen | TokenData::LBracket));
+        if awaits {
+            if self.in_sync {
+                return Err(Diagnostic::error("A @sync function can't await; drop the @sync or the await", self.peek().span));
+            }
+            self.advance(); // consume 'await'
+            self.enter()?;
+            let task = self.parse_unary()?;
+            self.depth -= 1;
+            return Ok(Expr::Await(Box::new(task)));
         }
         self.parse_member()
     }
 
-    fn parse_member(&mut self) -> Expr {
-        let mut expr = self.parse_primary();
+    fn parse_member(&mut self) -> Result<Expr, Diagnostic> {
+        let mut expr = self.parse_primary()?;
+        let depth = self.depth;
 
         while matches!(self.peek().data, TokenData::Dot | TokenData::LBracket | TokenData::LParen) {
+            self.enter()?;
             if matches!(self.peek().data, TokenData::Dot) {
                 self.advance();
-                let field_tok = self.advance();
-                let field = match &field_tok.data {
-                    TokenData::Ident(s) => s.clone(),
-                    TokenData::String(s) => s.clone(),
+                let field_tok = self.take();
+                let field = match field_tok.data {
+                    TokenData::Ident(s) => s,
+                    TokenData::String(s) => s,
+                    // Harbor keywords are ordinary names after a dot (`d.get`, `api.post`)
+                    TokenData::Get => "get".to_string(),
+                    TokenData::Post => "post".to_string(),
+                    TokenData::Put => "put".to_string(),
+                    TokenData::Delete => "delete".to_string(),
+                    TokenData::Patch => "patch".to_string(),
+                    TokenData::Print => "print".to_string(),
+                    TokenData::Server => "server".to_string(),
+                    TokenData::Respond => "respond".to_string(),
+                    TokenData::Fetch => "fetch".to_string(),
                     _ => {
-                        eprintln!("Error: Expected field name after '.' at line {}, col {}, found {:?}",
-                            field_tok.span.line, field_tok.span.col, field_tok.data);
-                        std::process::exit(1);
+                        return Err(Diagnostic::error(format!("Expected field name after '.', found {:?}", field_tok.data), field_tok.span));
                     }
                 };
                 expr = Expr::Member(Box::new(expr), field);
             } else if matches!(self.peek().data, TokenData::LBracket) {
                 self.advance();
-                let index = self.parse_expr();
-                self.expect(TokenData::RBracket);
+                let index = self.parse_expr()?;
+                self.expect(TokenData::RBracket)?;
                 expr = Expr::Index(Box::new(expr), Box::new(index));
             } else if matches!(self.peek().data, TokenData::LParen) {
                 self.advance();
-                let args = self.parse_arguments();
-                self.expect(TokenData::RParen);
+                let args = self.parse_arguments()?;
+                self.expect(TokenData::RParen)?;
                 expr = Expr::Call(Box::new(expr), args);
             }
         }
 
-        expr
+        self.depth = depth;
+        Ok(expr)
     }
 
-    fn parse_arguments(&mut self) -> Vec<Expr> {
+    fn parse_arguments(&mut self) -> Result<Vec<Expr>, Diagnostic> {
         let mut args = Vec::new();
         if !matches!(self.peek().data, TokenData::RParen) {
             loop {
-                args.push(self.parse_expr());
-                if matches!(self.peek().data, TokenData::RParen) {
+                let keyword = match self.peek_next().map(|t| &t.data) {
+                    Some(TokenData::Assign) => self.take_ident(),
+                    _ => None,
+                };
+                match keyword {
+                    Some(name) => {
+                        self.advance(); // consume '='
+                        args.push(Expr::Keyword(name, Box::new(self.parse_expr()?)));
+                    }
+                    None => args.push(self.parse_expr()?),
+                }
+                if !self.list_continues(TokenData::RParen)? {
                     break;
                 }
-                self.expect(TokenData::Comma);
             }
         }
-        args
+        Ok(args)
     }
 
-    fn parse_primary(&mut self) -> Expr {
+    fn parse_primary(&mut self) -> Result<Expr, Diagnostic> {
         // Skip stray Indents/Dedents
         while matches!(self.peek().data, TokenData::Indent | TokenData::Dedent) {
             self.advance();
         }
 
-        let tok = self.advance();
-        match &tok.data {
-            TokenData::String(s) => Expr::String(s.clone()),
-            TokenData::Number(n) => Expr::Number(*n),
-            TokenData::True => Expr::Bool(true),
-            TokenData::False => Expr::Bool(false),
-            TokenData::None_ => Expr::None,
-            TokenData::Ident(name) => Expr::Ident(name.clone()),
-            TokenData::Self_ => Expr::Ident("this".to_string()),
+        if matches!(self.peek().data, TokenData::Fetch) {
+            return self.parse_fetch_expr();
+        }
+
+        if matches!(&self.peek().data, TokenData::Ident(name) if name == "html")
+            && matches!(self.peek_next().map(|t| &t.data), Some(TokenData::FStringToken(_)))
+        {
+            self.advance(); // consume 'html'
+            if let TokenData::FStringToken(parts) = self.take().data {
+                return Ok(Expr::Html(self.fstring_parts(parts)?));
+            }
+        }
+
+        let tok = self.take();
+
+        match tok.data {
+            TokenData::String(s) => Ok(Expr::String(s)),
+            TokenData::Number(n, text) => Ok(Expr::Number(n, Some(text))),
+            TokenData::BigInt(digits) => Ok(Expr::BigInt(digits)),
+            TokenData::True => Ok(Expr::Bool(true)),
+            TokenData::False => Ok(Expr::Bool(false)),
+            TokenData::None_ => Ok(Expr::None),
+            TokenData::Ident(name) => Ok(Expr::Ident(name)),
+            TokenData::Self_ => Ok(Expr::Ident("this".to_string())),
 
             TokenData::LBrace => self.parse_object(),
             TokenData::LBracket => self.parse_array(),
 
             TokenData::LParen => {
-                let expr = self.parse_expr();
-                self.expect(TokenData::RParen);
-                expr
-            }
-
-            TokenData::FStringToken(parts) => {
-                let parts = parts.clone();
-                let mut expr_parts = Vec::new();
-                for part in &parts {
-                    match part {
-                        FStringPart::Literal(s) => {
-                            expr_parts.push(FStringExprPart::Literal(s.clone()));
-                        }
-                        FStringPart::Expression(text) => {
-                            let mut sub_lexer = crate::lexer::Lexer::new(text);
-                            let sub_tokens = sub_lexer.tokenize();
-                            let mut sub_parser = Parser::new(sub_tokens);
-                            let expr = sub_parser.parse_expr();
-                            expr_parts.push(FStringExprPart::Expression(expr));
-                        }
-                    }
-                }
-                Expr::FString(expr_parts)
+                let expr = self.parse_expr()?;
+                self.expect(TokenData::RParen)?;
+                Ok(expr)
             }
 
+            TokenData::FStringToken(parts) => Ok(Expr::FString(self.fstring_parts(parts)?)),
+
             _ => {
-                eprintln!("Error: Unexpected token {:?} in expression at line {}, col {}",
-                    tok.data, tok.span.line, tok.span.col);
-                std::process::exit(1);
+                Err(Diagnostic::error(format!("Unexpected token {:?} in expression", tok.data), tok.span))
+            }
+        }
+    }
+
+    /// Parse the expressions inside an f-string's braces.
+    fn fstring_parts(&self, parts: Vec<FStringPart>) -> Result<Vec<FStringExprPart>, Diagnostic> {
+        let mut expr_parts = Vec::new();
+        for part in parts {
+            match part {
+                FStringPart::Literal(s) => {
+                    expr_parts.push(FStringExprPart::Literal(s));
+                }
+                FStringPart::Expression(text, start) => {
+                    let mut sub_lexer = crate::lexer::Lexer::starting_at(&text, start);
+                    let sub_tokens = sub_lexer.tokenize()?;
+                    let mut sub_parser = Parser::new(sub_tokens);
+                    sub_parser.depth = self.depth;
+                    let expr = sub_parser.parse_expr()?;
+                    // Only one expression fits between the braces
+                    while matches!(sub_parser.peek().data, TokenData::Newline) {
+                        sub_parser.advance();
+                    }
+                    let tok = sub_parser.peek();
+                    if tok.data != TokenData::EOF {
+                        return Err(Diagnostic::error(format!("Expected '}}' after the f-string expression, found {:?}", tok.data), tok.span));
+                    }
+                    expr_parts.push(FStringExprPart::Expression(expr));
+                }
             }
         }
+        Ok(expr_parts)
+    }
+
+    /// `fetch(url).body` applies `.body` to the response, while a bare
+    /// `fetch url` takes the rest of the expression as the URL.
+    fn parse_fetch_expr(&mut self) -> Result<Expr, Diagnostic> {
+        if self.in_sync {
+            return Err(Diagnostic::error("A @sync function can't fetch, which waits for the response", self.peek().span));
+        }
+        self.advance(); // consume 'fetch'
+        let parenthesized = matches!(self.peek().data, TokenData::LParen);
+        if parenthesized {
+            self.advance();
+        }
+        let url = Box::new(self.parse_expr()?);
+        let options = self.parse_fetch_options()?;
+        if parenthesized {
+            self.expect(TokenData::RParen)?;
+        }
+        Ok(Expr::Fetch { url, options })
     }
 
-    fn parse_object(&mut self) -> Expr {
+    /// `, timeout=<seconds>, retries=<n>` after a fetch URL.
+    fn parse_fetch_options(&mut self) -> Result<Vec<(String, Expr)>, Diagnostic> {
+        let mut options = Vec::new();
+        while matches!(self.peek().data, TokenData::Comma) {
+            self.advance();
+            let tok = self.take();
+            let name = match tok.data {
+                TokenData::Ident(n) if n == "timeout" || n == "retries" => n,
+                _ => {
+                    return Err(Diagnostic::error(format!("Expected fetch option (timeout, retries), found {:?}", tok.data), tok.span));
+                }
+            };
+            self.expect(TokenData::Assign)?;
+            options.push((name, self.parse_expr()?));
+        }
+        Ok(options)
+    }
+
+    fn parse_object(&mut self) -> Result<Expr, Diagnostic> {
         let mut fields = Vec::new();
         if !matches!(self.peek().data, TokenData::RBrace) {
             loop {
-                let key_tok = self.advance();
-                let key = match &key_tok.data {
-                    TokenData::String(s) => s.clone(),
-                    TokenData::Ident(s) => s.clone(),
+                let key_tok = self.take();
+                let key = match key_tok.data {
+                    TokenData::String(s) => Expr::String(s),
+                    TokenData::Ident(s) => Expr::String(s),
+                    TokenData::Number(n, text) => Expr::Number(n, Some(text)),
+                    TokenData::BigInt(digits) => Expr::BigInt(digits),
+                    TokenData::Dash => match self.take().data {
+                        TokenData::Number(n, text) => Expr::Number(-n, Some(format!("-{}", text))),
+                        TokenData::BigInt(digits) => Expr::Unary("-".to_string(), Box::new(Expr::BigInt(digits))),
+                        _ => return Err(Diagnostic::error("Expected a number after '-' in dict key", key_tok.span)),
+                    },
+                    TokenData::True => Expr::Bool(true),
+                    TokenData::False => Expr::Bool(false),
+                    TokenData::None_ => Expr::None,
                     _ => {
-                        eprintln!("Error: Expected key in object at line {}, col {}, found {:?}",
-                            key_tok.span.line, key_tok.span.col, key_tok.data);
-                        std::process::exit(1);
+                        return Err(Diagnostic::error(format!("Expected key in object, found {:?}", key_tok.data), key_tok.span));
                     }
                 };
 
-                self.expect(TokenData::Colon);
-                let value = self.parse_expr();
+                self.expect(TokenData::Colon)?;
+                let value = self.parse_expr()?;
                 fields.push((key, value));
 
-                if matches!(self.peek().data, TokenData::RBrace) {
+                if !self.list_continues(TokenData::RBrace)? {
                     break;
                 }
-                self.expect(TokenData::Comma);
             }
         }
-        self.expect(TokenData::RBrace);
-        Expr::Object(fields)
+        self.expect(TokenData::RBrace)?;
+        if fields.iter().all(|(key, _)| matches!(key, Expr::String(_))) {
+            let fields = fields.into_iter().map(|(key, value)| match key {
+                Expr::String(key) => (key, value),
+                _ => unreachable!("all keys are strings"),
+            }).collect();
+            return Ok(Expr::Object(fields));
+        }
+        Ok(Expr::Dict(fields))
     }
 
-    fn parse_array(&mut self) -> Expr {
+    fn parse_array(&mut self) -> Result<Expr, Diagnostic> {
         let mut elements = Vec::new();
         if !matches!(self.peek().data, TokenData::RBracket) {
             loop {
-                elements.push(self.parse_expr());
-                if matches!(self.peek().data, TokenData::RBracket) {
+                elements.push(self.parse_expr()?);
+                if !self.list_continues(TokenData::RBracket)? {
                     break;
                 }
-                self.expect(TokenData::Comma);
             }
         }
-        self.expect(TokenData::RBracket);
-        Expr::Array(elements)
+        self.expect(TokenData::RBracket)?;
+        Ok(Expr::Array(elements))
     }
 
-    fn expect(&mut self, expected: TokenData) {
+    /// After an item of a bracketed list, consume the comma that follows it
+    /// and say whether another item comes before `close`. A trailing comma
+    /// is allowed, so one item per line can end in one too.
+    fn list_continues(&mut self, close: TokenData) -> Result<bool, Diagnostic> {
+        if self.peek().data == close {
+            return Ok(false);
+        }
+        self.expect(TokenData::Comma)?;
+        Ok(self.peek().data != close)
+    }
+
+    fn expect(&mut self, expected: TokenData) -> Result<(), Diagnostic> {
         let tok = self.advance();
         if tok.data != expected {
-            eprintln!("Error: Expected {:?} at line {}, col {}, found {:?}",
-                expected, tok.span.line, tok.span.col, tok.data);
-            std::process::exit(1);
+            return Err(Diagnostic::error(format!("Expected {:?}, found {:?}", expected, tok.data), tok.span));
         }
+        Ok(())
+    }
+}
+
+/// Move the comments kept in a server or group block in among its routes.
+/// `name` or `module.name`, the forms a route handler can take.
+fn is_dotted_name(expr: &Expr) -> bool {
+    match expr {
+        Expr::Ident(_) => true,
+        Expr::Member(object, _) => is_dotted_name(object),
+        _ => false,
     }
 }
+
+fn route_comments(comments: &mut Vec<Stmt>) -> impl Iterator<Item = RouteItem> + '_ {
+    comments.drain(..).filter_map(|stmt| match stmt {
+        Stmt::Comment(text) => Some(RouteItem::Comment(text)),
+        _ => None,
+    })
+}
@@ -0,0 +1,65 @@
+//! Compiler plugins: project-specific transforms of the syntax tree, run on
+//! every module after parsing and before `--define` folding, import
+//! resolution and code generation.
+//!
+//! A plugin can't add syntax, but it can give existing syntax a meaning,
+//! such as turning calls of a `metric(name)` function into the code that
+//! records it, or checking project rules and failing the build. Plugins are
+//! passed to `build_file` in `BuildOptions::plugins` by a program that embeds
+//! the compiler:
+//!
+//! ```text
+//! struct Metrics;
+//!
+//! impl Plugin for Metrics {
+//!     fn name(&self) -> &str {
+//!         "metrics-v1"
+//!     }
+//!
+//!     fn transform(&self, stmts: &mut Vec<Stmt>) -> Result<(), Diagnostic> {
+//!         for stmt in stmts.iter_mut() {
+//!             if let Stmt::Expression(Expr::Call(func, _)) = stmt {
+//!                 if matches!(&**func, Expr::Ident(name) if name == "metric") {
+//!                     **func = Expr::Member(Box::new(Expr::Ident("stats".into())), "increment".into());
+//!                 }
+//!             }
+//!         }
+//!         Ok(())
+//!     }
+//! }
+//!
+//! let options = BuildOptions { plugins: vec![Arc::new(Metrics)], ..Default::default() };
+//! harbor::build_file("app.hb", "app.js", None, &options)?;
+//! ```
+//!
+//! Only the module being built is transformed: `harbor doc`, `harbor def`
+//! and the checks that an imported name is exported see the source as
+//! written.
+
+use crate::ast::Stmt;
+use crate::diagnostics::Diagnostic;
+
+/// A transform of each module's statements.
+pub trait Plugin: Send + Sync {
+    /// Identifies the plugin in the build cache, so output from another set
+    /// of plugins isn't reused; change it when the plugin's output changes.
+    fn name(&self) -> &str;
+
+    /// Rewrite `stmts`, a whole module, in place. An error fails the build
+    /// and is reported against the module.
+    fn transform(&self, stmts: &mut Vec<Stmt>) -> Result<(), Diagnostic>;
+}
+
+impl std::fmt::Debug for dyn Plugin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Plugin({})", self.name())
+    }
+}
+
+/// Run `plugins` over `stmts` in order, stopping at the first error.
+pub fn apply(stmts: &mut Vec<Stmt>, plugins: &[std::sync::Arc<dyn Plugin>]) -> Result<(), Diagnostic> {
+    for plugin in plugins {
+        plugin.transform(stmts)?;
+    }
+    Ok(())
+}
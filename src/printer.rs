@@ -0,0 +1,517 @@
+//! Render an AST back into canonical Harbor source.
+//!
+//! This powers `harbor fmt` and `--emit ast --pretty`. The output uses
+//! four-space indentation, one statement per line, and a blank line around
+//! definitions (`def`, `class`, `server`, scheduled blocks) and between
+//! routes. Comments survive when the tree comes from
+//! `Parser::with_comments`, but a comment at the end of a line moves onto
+//! its own line before the next statement. Printing and parsing again gives
+//! a program that compiles to the same JavaScript.
+
+use crate::ast::*;
+use crate::numbers;
+
+const INDENT: &str = "    ";
+
+/// Canonical source for a whole program.
+pub fn print_program(stmts: &[Stmt]) -> String {
+    let mut out = String::new();
+    write_stmts(&mut out, stmts, "");
+    out
+}
+
+/// Canonical source for a single expression.
+pub fn print_expr(expr: &Expr) -> String {
+    expr_at(expr, 0)
+}
+
+/// One statement or directive as printed, and how it sits among its
+/// neighbours.
+struct Unit {
+    text: String,
+    /// Set apart from its neighbours by blank lines.
+    spaced: bool,
+    /// A comment, which stays directly above whatever follows it.
+    comment: bool,
+}
+
+/// Append `units`, putting a blank line between two of them when either is
+/// spaced. Comments count as part of the unit they precede.
+fn write_units(out: &mut String, units: &[Unit]) {
+    for (i, unit) in units.iter().enumerate() {
+        if i > 0 && !units[i - 1].comment {
+            let prev = &units[i - 1];
+            let next = units[i..].iter().find(|u| !u.comment).unwrap_or(unit);
+            if prev.spaced || next.spaced {
+                out.push('\n');
+            }
+        }
+        out.push_str(&unit.text);
+    }
+}
+
+fn write_stmts(out: &mut String, stmts: &[Stmt], indent: &str) {
+    // Line markers carry no source of their own
+    let units: Vec<Unit> = stmts.iter().filter(|stmt| !matches!(stmt, Stmt::Line(_))).map(|stmt| {
+        let mut text = String::new();
+        write_stmt(&mut text, stmt, indent);
+        Unit { text, spaced: is_definition(stmt), comment: matches!(stmt, Stmt::Comment(_)) }
+    }).collect();
+    write_units(out, &units);
+}
+
+fn is_definition(stmt: &Stmt) -> bool {
+    match stmt {
+        Stmt::Export(inner) => is_definition(inner),
+        Stmt::Func { .. } | Stmt::Class { .. } | Stmt::Server(_) | Stmt::Every { .. } | Stmt::Cron { .. } => true,
+        _ => false,
+    }
+}
+
+/// Write `header:` and the indented block, or `pass` for an empty one.
+fn write_block(out: &mut String, header: &str, body: &[Stmt], indent: &str) {
+    out.push_str(&format!("{}{}:\n", indent, header));
+    let inner = format!("{}{}", indent, INDENT);
+    write_stmts(out, body, &inner);
+    if is_empty(body) {
+        out.push_str(&format!("{}pass\n", inner));
+    }
+}
+
+/// Whether a block has nothing but comments, so it needs a `pass`.
+fn is_empty(body: &[Stmt]) -> bool {
+    body.iter().all(|s| matches!(s, Stmt::Comment(_) | Stmt::Line(_)))
+}
+
+fn write_stmt(out: &mut String, stmt: &Stmt, indent: &str) {
+    let line = |out: &mut String, text: String| out.push_str(&format!("{}{}\n", indent, text));
+    match stmt {
+        Stmt::Set { target, value } => line(out, format!("{} = {}", print_expr(target), print_expr(value))),
+        Stmt::AugAssign { target, op, value } => {
+            line(out, format!("{} {}= {}", print_expr(target), op, print_expr(value)))
+        }
+        Stmt::Expression(expr) => line(out, print_expr(expr)),
+        Stmt::Print(exprs) => match exprs.as_slice() {
+            [Expr::String(s)] if s.is_empty() => line(out, "print".to_string()),
+            _ => line(out, format!("print {}", join_exprs(exprs))),
+        },
+        Stmt::Pass => line(out, "pass".to_string()),
+        Stmt::Comment(text) => line(out, format!("#{}", text.trim_end())),
+        Stmt::Line(_) => {}
+
+        Stmt::If { condition, then_body, elif_branches, else_body } => {
+            write_block(out, &format!("if {}", print_expr(condition)), then_body, indent);
+            for (cond, body) in elif_branches {
+                write_block(out, &format!("elif {}", print_expr(cond)), body, indent);
+            }
+            if let Some(body) = else_body {
+                write_block(out, "else", body, indent);
+            }
+        }
+        Stmt::ForIn { var, iterable, body } => {
+            write_block(out, &format!("for {} in {}", var, print_expr(iterable)), body, indent);
+        }
+        Stmt::While { condition, body } => write_block(out, &format!("while {}", print_expr(condition)), body, indent),
+        Stmt::Break => line(out, "break".to_string()),
+        Stmt::Continue => line(out, "continue".to_string()),
+
+        Stmt::Func { name, args, body, sync } => {
+            if *sync {
+                line(out, "@sync".to_string());
+            }
+            write_block(out, &format!("def {}({})", name, args.join(", ")), body, indent)
+        }
+        Stmt::Return(None) => line(out, "return".to_string()),
+        Stmt::Return(Some(value)) => line(out, format!("return {}", print_expr(value))),
+        Stmt::Class { name, methods } => write_block(out, &format!("class {}", name), methods, indent),
+
+        Stmt::Try { body, handlers } => {
+            write_block(out, "try", body, indent);
+            for handler in handlers {
+                let header = match (handler.types.is_empty(), &handler.var) {
+                    (true, None) => "except".to_string(),
+                    (true, Some(var)) => format!("except {}", var),
+                    (false, None) => format!("except {}", handler.types.join(", ")),
+                    (false, Some(var)) => format!("except {} as {}", handler.types.join(", "), var),
+                };
+                write_block(out, &header, &handler.body, indent);
+            }
+        }
+        Stmt::Raise(error) => line(out, format!("raise {}", print_expr(error))),
+        Stmt::Assert { condition, message: None } => line(out, format!("assert {}", print_expr(condition))),
+        Stmt::Assert { condition, message: Some(message) } => {
+            line(out, format!("assert {}, {}", print_expr(condition), print_expr(message)))
+        }
+
+        Stmt::Import { path, alias: None, .. } => line(out, format!("import {}", quote(path))),
+        Stmt::Import { path, alias: Some(alias), .. } => line(out, format!("import {} as {}", quote(path), alias)),
+        Stmt::FromImport { path, names, .. } => line(out, format!("from {} import {}", quote(path), name_list(names))),
+        Stmt::Export(inner) => {
+            let mut text = String::new();
+            write_stmt(&mut text, inner, indent);
+            // `@sync` stays on its own line above `export def`
+            let start = match &**inner {
+                Stmt::Func { sync: true, .. } => text.find('\n').map_or(0, |i| i + 1),
+                _ => 0,
+            };
+            out.push_str(&text[..start]);
+            out.push_str(&format!("{}export {}", indent, &text[start + indent.len()..]));
+        }
+        Stmt::ExportDefault(value) => line(out, format!("export default {}", print_expr(value))),
+        Stmt::ExportNames(names) => line(out, format!("export {}", name_list(names))),
+
+        Stmt::Server(server) => write_server(out, server, indent),
+        Stmt::Respond { status, content_type, value } => {
+            // A bare number after `respond` would be read as the status
+            let value = match (status, content_type, value) {
+                (None, None, Expr::Number(..)) => format!("({})", print_expr(value)),
+                _ => print_expr(value),
+            };
+            let content_type = content_type.map(|c| format!("{} ", c.name())).unwrap_or_default();
+            line(out, format!("respond {}{}{}", status_prefix(status), content_type, value))
+        }
+        Stmt::RespondFile { status, path } => {
+            line(out, format!("respond {}file {}", status_prefix(status), print_expr(path)))
+        }
+        Stmt::Fetch { url, options, body } => {
+            write_block(out, &format!("fetch {}{}", print_expr(url), fetch_options(options)), body, indent);
+        }
+        Stmt::Stream { body } => write_block(out, "stream", body, indent),
+        Stmt::Emit { event: None, data } => line(out, format!("emit {}", print_expr(data))),
+        Stmt::Emit { event: Some(event), data } => line(out, format!("emit {}, {}", print_expr(event), print_expr(data))),
+        Stmt::Spawn { body } => write_block(out, "spawn", body, indent),
+        Stmt::Every { count, unit_ms, body } => {
+            let plural = !matches!(count, Expr::Number(n, _) if *n == 1.0);
+            let header = format!("every {} {}", print_expr(count), quote(&time_unit(*unit_ms, plural)));
+            write_block(out, &header, body, indent);
+        }
+        Stmt::Cron { schedule, body } => write_block(out, &format!("cron {}", quote(schedule)), body, indent),
+    }
+}
+
+fn status_prefix(status: &Option<Status>) -> String {
+    match status {
+        Some(Status::Code(code)) => format!("{} ", code),
+        Some(Status::Named(name)) => format!("{} ", name),
+        Some(Status::Expr(expr)) if is_plain_status(expr) => format!("status {} ", print_expr(expr)),
+        Some(Status::Expr(expr)) => format!("status ({}) ", print_expr(expr)),
+        None => String::new(),
+    }
+}
+
+/// Whether a status code reads back without parentheses: the parser takes
+/// a name or literal and its `.field`s, and nothing after them.
+fn is_plain_status(expr: &Expr) -> bool {
+    match expr {
+        Expr::Member(object, _) => is_plain_status(object),
+        Expr::Ident(_) | Expr::Number(..) | Expr::BigInt(_) | Expr::String(_) | Expr::Bool(_) | Expr::None => true,
+        _ => false,
+    }
+}
+
+fn fetch_options(options: &[(String, Expr)]) -> String {
+    options.iter().map(|(name, value)| format!(", {}={}", name, print_expr(value))).collect()
+}
+
+/// The unit string for a window parsed by `Parser::parse_time_unit`.
+fn time_unit(ms: u64, plural: bool) -> String {
+    let unit = match ms {
+        1_000 => "second",
+        60_000 => "minute",
+        3_600_000 => "hour",
+        _ => "day",
+    };
+    if plural { format!("{}s", unit) } else { unit.to_string() }
+}
+
+fn write_server(out: &mut String, server: &Server, indent: &str) {
+    let mut header = format!("server {}", print_expr(&server.port));
+    if let Some(tls) = &server.tls {
+        header.push_str(&format!(" tls cert {} key {}", print_expr(&tls.cert), print_expr(&tls.key)));
+    }
+    let inner = format!("{}{}", indent, INDENT);
+    let directive = |text: String| Unit { text: format!("{}{}\n", inner, text), spaced: false, comment: false };
+    let hook = |header: &str, body: &[Stmt]| {
+        let mut text = String::new();
+        write_block(&mut text, header, body, &inner);
+        Unit { text, spaced: true, comment: false }
+    };
+
+    let mut units = Vec::new();
+    match &server.log {
+        Some(None) => units.push(directive("log requests".to_string())),
+        Some(Some(format)) => units.push(directive(format!("log requests {}", quote(format)))),
+        None => {}
+    }
+    if let Some(limit) = &server.limit {
+        units.push(directive(rate_limit(limit)));
+    }
+    if let Some(auth) = &server.auth {
+        units.push(directive(require_auth(auth)));
+    }
+    if let Some(path) = &server.health {
+        units.push(directive(format!("health {}", quote(path))));
+    }
+    if let Some(path) = &server.metrics {
+        units.push(directive(format!("metrics {}", quote(path))));
+    }
+    if !server.headers.is_empty() {
+        let mut text = String::new();
+        write_headers(&mut text, &server.headers, &inner);
+        units.push(Unit { text, spaced: true, comment: false });
+    }
+    if !server.before.is_empty() {
+        units.push(hook("before", &server.before));
+    }
+    if !server.after.is_empty() {
+        units.push(hook("after", &server.after));
+    }
+    if let Some((name, body)) = &server.on_error {
+        units.push(hook(&format!("on_error {}", name), body));
+    }
+    if !server.not_found.is_empty() {
+        units.push(hook("not_found", &server.not_found));
+    }
+    if !server.on_shutdown.is_empty() {
+        units.push(hook("on_shutdown", &server.on_shutdown));
+    }
+    route_units(&mut units, &server.routes, &inner);
+
+    if units.is_empty() {
+        out.push_str(&format!("{}{} {{}}\n", indent, header));
+        return;
+    }
+    out.push_str(&format!("{}{}:\n", indent, header));
+    write_units(out, &units);
+}
+
+fn route_units(units: &mut Vec<Unit>, items: &[RouteItem], indent: &str) {
+    for item in items {
+        let mut text = String::new();
+        match item {
+            RouteItem::Comment(comment) => {
+                units.push(Unit { text: format!("{}#{}\n", indent, comment.trim_end()), spaced: false, comment: true });
+                continue;
+            }
+            RouteItem::Route(route) => write_route(&mut text, route, indent),
+            RouteItem::Group { prefix, items } => {
+                let inner = format!("{}{}", indent, INDENT);
+                let mut group = Vec::new();
+                route_units(&mut group, items, &inner);
+                if group.is_empty() {
+                    text.push_str(&format!("{}group {} {{}}\n", indent, quote(prefix)));
+                } else {
+                    text.push_str(&format!("{}group {}:\n", indent, quote(prefix)));
+                    write_units(&mut text, &group);
+                }
+            }
+        }
+        units.push(Unit { text, spaced: true, comment: false });
+    }
+}
+
+fn write_route(out: &mut String, route: &Route, indent: &str) {
+    let handler = route.handler.as_ref().map(|h| format!(" -> {}", print_expr(h))).unwrap_or_default();
+    out.push_str(&format!("{}{} {}{}", indent, route.method.to_lowercase(), quote(&route.path), handler));
+    let inner = format!("{}{}", indent, INDENT);
+    let mut directives = Vec::new();
+    if let Some(limit) = &route.limit {
+        directives.push(rate_limit(limit));
+    }
+    if let Some(auth) = &route.auth {
+        directives.push(require_auth(auth));
+    }
+    if let Some(schema) = &route.expect {
+        directives.push(format!("expect {}", print_schema(schema)));
+    }
+    if let Some(seconds) = &route.cache {
+        directives.push(format!("cache {}", print_expr(seconds)));
+    }
+    let has_directives = !directives.is_empty() || !route.headers.is_empty() || !route.before.is_empty() || !route.after.is_empty();
+    if route.handler.is_some() && !has_directives && is_empty(&route.body) {
+        out.push('\n');
+        return;
+    }
+    out.push_str(":\n");
+    for directive in &directives {
+        out.push_str(&format!("{}{}\n", inner, directive));
+    }
+    if !route.headers.is_empty() {
+        write_headers(out, &route.headers, &inner);
+    }
+    if !route.before.is_empty() {
+        write_block(out, "before", &route.before, &inner);
+    }
+    if !route.after.is_empty() {
+        write_block(out, "after", &route.after, &inner);
+    }
+    write_stmts(out, &route.body, &inner);
+    if is_empty(&route.body) && !has_directives {
+        out.push_str(&format!("{}pass\n", inner));
+    }
+}
+
+fn write_headers(out: &mut String, headers: &[(String, Expr)], indent: &str) {
+    out.push_str(&format!("{}headers:\n", indent));
+    for (name, value) in headers {
+        out.push_str(&format!("{}{}{}: {}\n", indent, INDENT, quote(name), print_expr(value)));
+    }
+}
+
+fn rate_limit(limit: &RateLimit) -> String {
+    format!("limit {} per {}", print_expr(&limit.count), quote(&time_unit(limit.window_ms, false)))
+}
+
+fn require_auth(auth: &Auth) -> String {
+    let scheme = match auth.scheme {
+        AuthScheme::Basic => "basic",
+        AuthScheme::Bearer => "bearer",
+    };
+    format!("require_auth {} {}", scheme, print_expr(&auth.secret))
+}
+
+fn print_schema(schema: &Schema) -> String {
+    match schema {
+        Schema::Type(name) => name.clone(),
+        Schema::List(item) => format!("[{}]", print_schema(item)),
+        Schema::Dict(fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(key, value)| format!("{}: {}", quote(key), print_schema(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+    }
+}
+
+// ─── Expressions ───
+
+/// How tightly an expression binds, matching the parser's precedence
+/// climbing: `or` < `and` < `not` < comparisons < `+` < `*` < `**` < unary
+/// minus < member access, calls and atoms.
+fn precedence(expr: &Expr) -> u8 {
+    match expr {
+        Expr::Binary(_, op, _) => match op.as_str() {
+            "or" => 1,
+            "and" => 2,
+            "+" | "-" => 5,
+            "*" | "/" | "%" | "//" => 6,
+            "**" => 7,
+            _ => 4,
+        },
+        Expr::Unary(op, _) if op == "not" => 3,
+        Expr::Unary(_, _) | Expr::Spawn(_) | Expr::Await(_) => 8,
+        _ => 9,
+    }
+}
+
+/// `expr`, parenthesized if it binds looser than `min`.
+fn expr_at(expr: &Expr, min: u8) -> String {
+    let text = match expr {
+        Expr::String(text) => quote(text),
+        Expr::FString(parts) => fstring(parts),
+        Expr::Html(parts) => format!("html {}", fstring(parts)),
+        Expr::Number(_, Some(text)) => text.clone(),
+        // Written without a fraction, a double past 2^53 would read back as a BigInt
+        Expr::Number(n, None) if n.fract() == 0.0 && n.abs() > numbers::MAX_SAFE as f64 => format!("{}.0", n),
+        Expr::Number(n, None) => format!("{}", n),
+        Expr::BigInt(digits) => digits.clone(),
+        Expr::Bool(true) => "True".to_string(),
+        Expr::Bool(false) => "False".to_string(),
+        Expr::None => "None".to_string(),
+        // The parser reads `self` as `this`
+        Expr::Ident(name) if name == "this" => "self".to_string(),
+        Expr::Ident(name) => name.clone(),
+        Expr::Member(object, field) => format!("{}.{}", expr_at(object, 9), field),
+        Expr::Index(object, index) => format!("{}[{}]", expr_at(object, 9), print_expr(index)),
+        Expr::Call(callee, args) => format!("{}({})", expr_at(callee, 9), join_exprs(args)),
+        Expr::Keyword(name, value) => format!("{}={}", name, print_expr(value)),
+        Expr::Object(fields) => {
+            let fields: Vec<String> = fields.iter()
+                .map(|(key, value)| format!("{}: {}", quote(key), print_expr(value)))
+                .collect();
+            format!("{{{}}}", fields.join(", "))
+        }
+        Expr::Dict(entries) => {
+            let entries: Vec<String> = entries.iter()
+                .map(|(key, value)| format!("{}: {}", print_expr(key), print_expr(value)))
+                .collect();
+            format!("{{{}}}", entries.join(", "))
+        }
+        Expr::Array(items) => format!("[{}]", join_exprs(items)),
+        Expr::Binary(left, op, right) => {
+            let prec = precedence(expr);
+            // `**` is right-associative; everything else associates left
+            let (left_min, right_min) = if op == "**" { (8, 7) } else { (prec, prec + 1) };
+            let op = match op.as_str() {
+                "===" => "==",
+                "!==" => "!=",
+                op => op,
+            };
+            format!("{} {} {}", expr_at(left, left_min), op, expr_at(right, right_min))
+        }
+        Expr::Unary(op, operand) if op == "not" => format!("not {}", expr_at(operand, 3)),
+        Expr::Unary(op, operand) => {
+            let operand = expr_at(operand, 8);
+            // Keep `- -x` from reading as one token
+            if operand.starts_with('-') { format!("{} {}", op, operand) } else { format!("{}{}", op, operand) }
+        }
+        Expr::Spawn(task) => format!("spawn {}", expr_at(task, 8)),
+        Expr::Await(task) => format!("await {}", expr_at(task, 8)),
+        Expr::Fetch { url, options } => format!("fetch({}{})", print_expr(url), fetch_options(options)),
+    };
+    if precedence(expr) < min { format!("({})", text) } else { text }
+}
+
+fn join_exprs(exprs: &[Expr]) -> String {
+    exprs.iter().map(print_expr).collect::<Vec<_>>().join(", ")
+}
+
+/// `a, b as c`, for an import or export list.
+fn name_list(names: &[NameAs]) -> String {
+    names.iter().map(|n| match &n.alias {
+        Some(alias) => format!("{} as {}", n.name, alias),
+        None => n.name.clone(),
+    }).collect::<Vec<_>>().join(", ")
+}
+
+/// Quote string text, which holds escapes verbatim. Text with a bare `"`
+/// came from a single-quoted string and goes back into one.
+fn quote(text: &str) -> String {
+    let q = if has_bare(text, '"') { '\'' } else { '"' };
+    format!("{}{}{}", q, text, q)
+}
+
+/// Whether `text` contains `c` outside a backslash escape.
+fn has_bare(text: &str, c: char) -> bool {
+    let mut chars = text.chars();
+    while let Some(next) = chars.next() {
+        if next == '\\' {
+            chars.next();
+        } else if next == c {
+            return true;
+        }
+    }
+    false
+}
+
+fn fstring(parts: &[FStringExprPart]) -> String {
+    let bare_double = parts.iter().any(|p| matches!(p, FStringExprPart::Literal(s) if has_bare(s, '"')));
+    let q = if bare_double { '\'' } else { '"' };
+    let mut out = format!("f{}", q);
+    for part in parts {
+        match part {
+            FStringExprPart::Literal(text) => out.push_str(&text.replace('{', "{{").replace('}', "}}")),
+            FStringExprPart::Expression(expr) => {
+                let text = print_expr(expr);
+                // `{{` would read as an escaped brace
+                if text.starts_with('{') {
+                    out.push_str(&format!("{{ {}}}", text));
+                } else {
+                    out.push_str(&format!("{{{}}}", text));
+                }
+            }
+        }
+    }
+    out.push(q);
+    out
+}
@@ -0,0 +1,344 @@
+//! Import resolution: what the string in `import "..."` refers to.
+//!
+//! A specifier is looked up in order:
+//!
+//! 1. `./` and `../` paths, relative to the importing file (or absolute
+//!    paths)
+//! 2. `std/...`, in the standard library (see `stdlib`)
+//! 3. any other name, in the project's `src/` directory and then in each
+//!    directory listed in `HARBOR_PATH`
+//!
+//! The `.hb` extension may be left off, and a directory stands for its
+//! `index.hb`, so `import "./models"` finds `./models/index.hb` when there's
+//! no `./models.hb`. A file with another extension
+//! (other than JavaScript's) is an asset, compiled in as a value; see
+//! `assets`. A specifier that names neither is left to Node's `require`, so
+//! `import "fs"` and npm packages keep working.
+
+use crate::assets;
+use crate::ast::{Expr, NameAs, Stmt};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::stdlib;
+use std::path::{Component, Path, PathBuf};
+
+/// What an import specifier names.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Module {
+    /// A Harbor source file, compiled alongside the importer.
+    Source(PathBuf),
+    /// A data or text file, imported as its value.
+    Asset(PathBuf),
+    /// Anything else, passed to `require` unchanged.
+    Node(String),
+}
+
+/// A Harbor module imported by a file, as found by `resolve_imports`.
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub source: PathBuf,
+    /// The names a `from ... import` takes from it.
+    pub names: Vec<NameAs>,
+    pub specifier: String,
+    pub span: Span,
+}
+
+/// What `resolve_imports` found a file importing.
+#[derive(Debug, Clone, Default)]
+pub struct Imports {
+    pub modules: Vec<Dependency>,
+    /// Asset files, whose contents are now part of the program.
+    pub assets: Vec<PathBuf>,
+}
+
+pub struct Resolver {
+    project_dir: PathBuf,
+    /// The project's `src/` directory, then `HARBOR_PATH`.
+    search_path: Vec<PathBuf>,
+    stdlib: Option<PathBuf>,
+}
+
+impl Resolver {
+    /// A resolver for the project in `project_dir`, configured from the
+    /// environment: `HARBOR_PATH` adds search directories and
+    /// `HARBOR_STDLIB` replaces the embedded standard library with a
+    /// directory of modules.
+    pub fn new(project_dir: &Path) -> Self {
+        let mut search_path = Vec::new();
+        let src = if project_dir.file_name().is_some_and(|n| n == "src") {
+            project_dir.to_path_buf()
+        } else {
+            project_dir.join("src")
+        };
+        if src.is_dir() {
+            search_path.push(src);
+        }
+        if let Some(paths) = std::env::var_os("HARBOR_PATH") {
+            search_path.extend(std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+        }
+        let stdlib = std::env::var_os("HARBOR_STDLIB").map(PathBuf::from);
+        Self { project_dir: project_dir.to_path_buf(), search_path, stdlib }
+    }
+
+    /// The resolver for the project holding `source_path`.
+    pub fn for_source(source_path: &str) -> Self {
+        let dir = Path::new(source_path).parent().filter(|p| !p.as_os_str().is_empty());
+        Self::new(dir.unwrap_or(Path::new(".")))
+    }
+
+    /// Find the module `specifier` names when imported from `importer`.
+    pub fn resolve(&self, specifier: &str, importer: &Path) -> Result<Module, String> {
+        let relative = specifier.starts_with("./") || specifier.starts_with("../");
+        let dirs: Vec<PathBuf> = if Path::new(specifier).is_absolute() {
+            vec![PathBuf::new()]
+        } else if relative {
+            let dir = importer.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+            vec![dir.to_path_buf()]
+        } else if let Some(name) = specifier.strip_prefix("std/") {
+            return self.resolve_std(name, specifier);
+        } else {
+            self.search_path.clone()
+        };
+
+        let searched = || dirs.iter().map(|d| d.display().to_string()).collect::<Vec<_>>().join(", ");
+        if is_asset(specifier) {
+            if let Some(path) = dirs.iter().map(|dir| dir.join(specifier)).find(|path| path.is_file()) {
+                return Ok(Module::Asset(path));
+            }
+            // A bare name with a dot may still be a package, like "chart.js"
+            if relative || Path::new(specifier).is_absolute() {
+                return Err(format!("Cannot find file '{}' (searched {})", specifier, searched()));
+            }
+            return Ok(Module::Node(specifier.to_string()));
+        }
+        for dir in &dirs {
+            if let Some(source) = find_source(dir, specifier) {
+                return Ok(Module::Source(source));
+            }
+        }
+        if specifier.ends_with(".hb") {
+            return Err(format!("Cannot find module '{}' (searched {})", specifier, searched()));
+        }
+        if let Some(dir) = dirs.iter().map(|dir| dir.join(specifier)).find(|dir| is_module_dir(dir)) {
+            return Err(format!("Cannot import '{}': the directory {} has no index.hb", specifier, tidy(&dir).display()));
+        }
+        Ok(Module::Node(specifier.to_string()))
+    }
+
+    fn resolve_std(&self, name: &str, specifier: &str) -> Result<Module, String> {
+        if let Some(dir) = &self.stdlib {
+            return find_source(dir, name).map(Module::Source)
+                .ok_or_else(|| format!("No standard library module '{}' in {}", specifier, dir.display()));
+        }
+        let dir = self.project_dir.join(".harbor").join("std");
+        match stdlib::install(&dir, name) {
+            Ok(Some(source)) => Ok(Module::Source(source)),
+            Ok(None) => {
+                let available: Vec<&str> = stdlib::names().collect();
+                Err(format!("No standard library module '{}' (available: {})", specifier, available.join(", ")))
+            }
+            Err(e) => Err(format!("Could not write '{}' to {}: {}", specifier, dir.display(), e)),
+        }
+    }
+
+    /// Where the JavaScript for the Harbor module at `source` is written:
+    /// next to it, except that modules from a `HARBOR_STDLIB` directory go
+    /// under the project's `.harbor/std` so that directory can stay
+    /// read-only.
+    pub fn output_path(&self, source: &Path) -> PathBuf {
+        if let Some(name) = self.stdlib.as_deref().and_then(|dir| source.strip_prefix(dir).ok()) {
+            return self.project_dir.join(".harbor").join("std").join(name).with_extension("js");
+        }
+        source.with_extension("js")
+    }
+
+    /// Whether `source` is a standard library module.
+    pub fn is_stdlib(&self, source: &Path) -> bool {
+        let embedded = self.project_dir.join(".harbor").join("std");
+        source.starts_with(&embedded) || self.stdlib.as_deref().is_some_and(|dir| source.starts_with(dir))
+    }
+}
+
+/// Whether `specifier` names a file to import as a value rather than code.
+fn is_asset(specifier: &str) -> bool {
+    let name = specifier.rsplit('/').next().unwrap_or(specifier);
+    match name.rsplit_once('.') {
+        Some((stem, ext)) => !stem.is_empty() && !matches!(ext, "hb" | "js" | "cjs" | "mjs" | "node"),
+        None => false,
+    }
+}
+
+/// `dir/specifier`, with `.hb` added if left off, when that file exists;
+/// otherwise the `index.hb` of a directory `dir/specifier`.
+fn find_source(dir: &Path, specifier: &str) -> Option<PathBuf> {
+    if specifier.ends_with(".hb") {
+        let path = dir.join(specifier);
+        return path.is_file().then_some(path);
+    }
+    let path = dir.join(format!("{}.hb", specifier));
+    if path.is_file() {
+        return Some(path);
+    }
+    let index = dir.join(specifier).join("index.hb");
+    index.is_file().then_some(index)
+}
+
+/// Whether `path` is a directory of Harbor modules, which needs an
+/// `index.hb` to be imported.
+fn is_module_dir(path: &Path) -> bool {
+    let Ok(entries) = std::fs::read_dir(path) else { return false };
+    entries.flatten().any(|entry| entry.path().extension().is_some_and(|ext| ext == "hb"))
+}
+
+/// Resolve every import in `stmts`, recording in the AST the `require` path
+/// each compiles to from a program written at `output`. Asset imports are
+/// replaced by their values.
+pub fn resolve_imports(stmts: &mut [Stmt], importer: &Path, output: &Path, resolver: &Resolver) -> Result<Imports, Diagnostic> {
+    let mut imports = Imports::default();
+    let out_dir = output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    resolve_in(stmts, importer, out_dir, resolver, &mut imports)?;
+    Ok(imports)
+}
+
+fn resolve_in(stmts: &mut [Stmt], importer: &Path, out_dir: &Path, resolver: &Resolver, imports: &mut Imports) -> Result<(), Diagnostic> {
+    for stmt in stmts {
+        let (specifier, names, resolved, span) = match stmt {
+            Stmt::Import { path, resolved, span, .. } => (path, Vec::new(), resolved, *span),
+            Stmt::FromImport { path, names, resolved, span } => (path, names.clone(), resolved, *span),
+            Stmt::Export(inner) => {
+                resolve_in(std::slice::from_mut(&mut **inner), importer, out_dir, resolver, imports)?;
+                continue;
+            }
+            other => {
+                for body in other.bodies_mut() {
+                    resolve_in(body, importer, out_dir, resolver, imports)?;
+                }
+                continue;
+            }
+        };
+        match resolver.resolve(specifier, importer).map_err(|msg| Diagnostic::error(msg, span))? {
+            Module::Source(source) => {
+                *resolved = Some(require_path(out_dir, &resolver.output_path(&source)));
+                imports.modules.push(Dependency { source, names, specifier: specifier.clone(), span });
+            }
+            Module::Node(name) => *resolved = Some(name),
+            Module::Asset(path) => {
+                let alias = match &*stmt {
+                    Stmt::Import { alias: Some(alias), .. } => alias.clone(),
+                    Stmt::Import { path, .. } | Stmt::FromImport { path, .. } => {
+                        return Err(Diagnostic::error(format!("Import '{}' whole, with a name: import \"{}\" as name", path, path), span));
+                    }
+                    _ => unreachable!("only imports resolve"),
+                };
+                let value = assets::load(&path).map_err(|msg| Diagnostic::error(msg, span))?;
+                *stmt = Stmt::Set { target: Expr::Ident(alias), value };
+                if !imports.assets.contains(&path) {
+                    imports.assets.push(path);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Import the prelude's `exports` at the top of `stmts`, except those the
+/// module declares or imports itself, which take precedence.
+pub fn import_prelude(stmts: &mut Vec<Stmt>, prelude: &Path, exports: Vec<String>) {
+    let mut declared = Vec::new();
+    declared_names(stmts, &mut declared);
+    let names: Vec<NameAs> = exports.into_iter().filter(|name| !declared.contains(name)).map(|name| NameAs::new(&name)).collect();
+    if names.is_empty() {
+        return;
+    }
+    let path = prelude.to_string_lossy().into_owned();
+    stmts.insert(0, Stmt::FromImport { path, names, resolved: None, span: Span { line: 1, col: 1 } });
+}
+
+/// Names declared in the module's top-level scope: definitions, imports,
+/// and variables assigned outside functions (which are function-scoped in
+/// the output, so assignments in nested blocks count).
+fn declared_names(stmts: &[Stmt], names: &mut Vec<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Func { name, .. } | Stmt::Class { name, .. } => names.push(name.clone()),
+            Stmt::Set { target: Expr::Ident(name), .. } => names.push(name.clone()),
+            Stmt::FromImport { names: imported, .. } => names.extend(imported.iter().map(|n| n.renamed().to_string())),
+            Stmt::Import { alias: Some(alias), .. } => names.push(alias.clone()),
+            Stmt::Export(inner) => declared_names(std::slice::from_ref(&**inner), names),
+            Stmt::If { then_body, elif_branches, else_body, .. } => {
+                declared_names(then_body, names);
+                for (_, body) in elif_branches {
+                    declared_names(body, names);
+                }
+                declared_names(else_body.as_deref().unwrap_or_default(), names);
+            }
+            Stmt::Try { body, handlers } => {
+                declared_names(body, names);
+                for handler in handlers {
+                    declared_names(&handler.body, names);
+                }
+            }
+            Stmt::ForIn { body, .. } | Stmt::While { body, .. } => declared_names(body, names),
+            _ => {}
+        }
+    }
+}
+
+/// Check that each `from ... import` takes only names `dep` exports.
+pub fn check_exports(dep: &Dependency, exported: &[String]) -> Result<(), Diagnostic> {
+    match dep.names.iter().find(|n| !exported.contains(&n.name)) {
+        Some(n) => Err(Diagnostic::error(format!("'{}' is not exported by '{}'", n.name, dep.specifier), dep.span)),
+        None => Ok(()),
+    }
+}
+
+/// The `require` path of an import: where resolution put it, or for source
+/// that was never resolved (e.g. `compile` without a filesystem), the `.js`
+/// compiled next to a `.hb` file.
+pub fn require_path_for(specifier: &str, resolved: Option<&str>) -> String {
+    match resolved {
+        Some(path) => path.to_string(),
+        None => match specifier.strip_suffix(".hb") {
+            Some(stem) => format!("{}.js", stem),
+            None => specifier.to_string(),
+        },
+    }
+}
+
+/// A `require` path from a file in `from_dir` to `target`: relative when
+/// both are on the same root, absolute otherwise.
+pub fn require_path(from_dir: &Path, target: &Path) -> String {
+    let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+    let (from, to) = (normalize(&absolute(from_dir)), normalize(&absolute(target)));
+    let from_parts: Vec<Component> = from.components().collect();
+    let to_parts: Vec<Component> = to.components().collect();
+    if from_parts.first() != to_parts.first() {
+        return to.to_string_lossy().replace('\\', "/");
+    }
+    let common = from_parts.iter().zip(&to_parts).take_while(|(a, b)| a == b).count();
+    let mut parts: Vec<String> = vec!["..".to_string(); from_parts.len() - common];
+    parts.extend(to_parts[common..].iter().map(|c| c.as_os_str().to_string_lossy().into_owned()));
+    let path = parts.join("/");
+    if path.starts_with("..") { path } else { format!("./{}", path) }
+}
+
+/// `path` without its `.` components, for messages: joining `./lib/a.hb`
+/// to the directory `.` gives `././lib/a.hb`.
+pub fn tidy(path: &Path) -> PathBuf {
+    path.components().filter(|c| *c != Component::CurDir).collect()
+}
+
+/// Drop `.` and fold `..` components without touching the filesystem.
+pub fn normalize(path: &Path) -> PathBuf {
+    let mut out = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                out.pop();
+            }
+            other => out.push(other),
+        }
+    }
+    out
+}
@@ -0,0 +1,282 @@
+//! Which variables a loop binds once per iteration.
+//!
+//! Assignments compile to `var`, which JavaScript scopes to the whole
+//! function, so a function or `spawn:` block made in a loop would see the
+//! variable's value from the last iteration rather than its own. A variable
+//! that a loop assigns before anything else in each iteration, that
+//! something the loop creates uses, and that nothing outside the loop uses
+//! is declared with `let` instead, giving each iteration its own.
+
+use crate::ast::{ExceptClause, Expr, FStringExprPart, Stmt};
+use std::collections::{HashMap, HashSet};
+
+/// How often each name is used or bound in a function body, nested
+/// functions and routes included, and which names the function itself binds.
+#[derive(Debug, Default)]
+pub struct Scope {
+    counts: HashMap<String, usize>,
+    bound: HashSet<String>,
+}
+
+impl Scope {
+    pub fn of(params: &[String], body: &[Stmt]) -> Scope {
+        let mut counts = HashMap::new();
+        count_stmts(body, &mut counts);
+        let mut bound: HashSet<String> = params.iter().cloned().collect();
+        bind_stmts(body, &mut bound);
+        Scope { counts, bound }
+    }
+
+    /// Whether `name` is a parameter or local of this function, shadowing
+    /// anything outside it.
+    pub fn binds(&self, name: &str) -> bool {
+        self.bound.contains(name)
+    }
+}
+
+/// The names that `stmts` bind in their own function: assignments, `for`
+/// and `except` variables, imports and nested `def`s and classes.
+fn bind_stmts(stmts: &[Stmt], bound: &mut HashSet<String>) {
+    for stmt in stmts {
+        match stmt {
+            Stmt::Set { target, .. } => bind_target(target, bound),
+            Stmt::ForIn { var, .. } => {
+                bound.insert(var.clone());
+            }
+            Stmt::Func { name, .. } | Stmt::Class { name, .. } => {
+                bound.insert(name.clone());
+            }
+            Stmt::Try { handlers, .. } => bound.extend(handlers.iter().filter_map(|h| h.var.clone())),
+            Stmt::Import { alias, .. } => bound.extend(alias.iter().cloned()),
+            Stmt::FromImport { names, .. } => bound.extend(names.iter().map(|name| name.renamed().to_string())),
+            _ => {}
+        }
+        for body in blocks(stmt) {
+            bind_stmts(body, bound);
+        }
+    }
+}
+
+fn bind_target(target: &Expr, bound: &mut HashSet<String>) {
+    match target {
+        Expr::Ident(name) => {
+            bound.insert(name.clone());
+        }
+        Expr::Array(items) => items.iter().for_each(|item| bind_target(item, bound)),
+        _ => {}
+    }
+}
+
+/// The variables the loop with `body` should bind per iteration, each with
+/// the index of the statement in `body` that declares it.
+pub fn per_iteration(body: &[Stmt], scope: &Scope) -> Vec<(String, usize)> {
+    let mut local = HashMap::new();
+    count_stmts(body, &mut local);
+    let mut captured = HashMap::new();
+    closure_uses(body, &mut captured);
+    let mut nested = Vec::new();
+    nested_loops(body, &mut nested);
+
+    let mut names = Vec::new();
+    for name in captured.keys() {
+        let uses = local.get(name).copied().unwrap_or(0);
+        if scope.counts.get(name).copied().unwrap_or(0) != uses {
+            continue;
+        }
+        // An inner loop that has every use binds it itself
+        if nested.iter().any(|inner| count_of(inner, name) == uses) {
+            continue;
+        }
+        // The first statement to mention it must assign it, without
+        // reading it, or an iteration could see the previous one's value
+        let first = body.iter().position(|stmt| count_of(std::slice::from_ref(stmt), name) > 0);
+        if let Some(i) = first {
+            if let Stmt::Set { target: Expr::Ident(target), value } = &body[i] {
+                let mut reads = HashMap::new();
+                count_expr(value, &mut reads);
+                if target == name && !reads.contains_key(name) {
+                    names.push((name.clone(), i));
+                }
+            }
+        }
+    }
+    names.sort();
+    names
+}
+
+fn count_of(stmts: &[Stmt], name: &str) -> usize {
+    let mut counts = HashMap::new();
+    count_stmts(stmts, &mut counts);
+    counts.get(name).copied().unwrap_or(0)
+}
+
+/// The bodies of functions and blocks that run after the statement that
+/// creates them: `def`, class methods, `spawn:`, `every` and `cron`.
+fn closure_bodies(stmt: &Stmt) -> Vec<&[Stmt]> {
+    match stmt {
+        Stmt::Func { body, .. } | Stmt::Spawn { body } | Stmt::Every { body, .. } | Stmt::Cron { body, .. } => vec![body],
+        Stmt::Class { methods, .. } => vec![methods],
+        Stmt::Export(inner) => closure_bodies(inner),
+        _ => Vec::new(),
+    }
+}
+
+/// The blocks that run as part of the statement itself.
+fn blocks(stmt: &Stmt) -> Vec<&[Stmt]> {
+    match stmt {
+        Stmt::If { then_body, elif_branches, else_body, .. } => {
+            let mut bodies: Vec<&[Stmt]> = vec![then_body];
+            bodies.extend(elif_branches.iter().map(|(_, body)| body.as_slice()));
+            bodies.extend(else_body.as_deref());
+            bodies
+        }
+        Stmt::Try { body, handlers } => {
+            let mut bodies: Vec<&[Stmt]> = vec![body];
+            bodies.extend(handlers.iter().map(|h| h.body.as_slice()));
+            bodies
+        }
+        Stmt::ForIn { body, .. } | Stmt::While { body, .. } | Stmt::Fetch { body, .. } | Stmt::Stream { body } => vec![body],
+        _ => Vec::new(),
+    }
+}
+
+/// Names used inside the functions and blocks that `stmts` create.
+fn closure_uses(stmts: &[Stmt], uses: &mut HashMap<String, usize>) {
+    for stmt in stmts {
+        for body in closure_bodies(stmt) {
+            count_stmts(body, uses);
+        }
+        for body in blocks(stmt) {
+            closure_uses(body, uses);
+        }
+    }
+}
+
+/// The bodies of the loops inside `stmts`, outside any function.
+fn nested_loops<'a>(stmts: &'a [Stmt], loops: &mut Vec<&'a [Stmt]>) {
+    for stmt in stmts {
+        if let Stmt::ForIn { body, .. } | Stmt::While { body, .. } = stmt {
+            loops.push(body);
+        }
+        for body in blocks(stmt) {
+            nested_loops(body, loops);
+        }
+    }
+}
+
+fn bump(name: &str, counts: &mut HashMap<String, usize>) {
+    *counts.entry(name.to_string()).or_default() += 1;
+}
+
+fn count_stmts(stmts: &[Stmt], counts: &mut HashMap<String, usize>) {
+    for stmt in stmts {
+        count_stmt(stmt, counts);
+    }
+}
+
+fn count_stmt(stmt: &Stmt, counts: &mut HashMap<String, usize>) {
+    match stmt {
+        Stmt::Set { target, value } | Stmt::AugAssign { target, value, .. } => {
+            count_expr(target, counts);
+            count_expr(value, counts);
+        }
+        Stmt::Expression(expr) | Stmt::Raise(expr) | Stmt::ExportDefault(expr) => count_expr(expr, counts),
+        Stmt::Print(exprs) => exprs.iter().for_each(|e| count_expr(e, counts)),
+        Stmt::Return(expr) => expr.iter().for_each(|e| count_expr(e, counts)),
+        Stmt::If { condition, elif_branches, .. } => {
+            count_expr(condition, counts);
+            elif_branches.iter().for_each(|(condition, _)| count_expr(condition, counts));
+        }
+        Stmt::ForIn { var, iterable, .. } => {
+            bump(var, counts);
+            count_expr(iterable, counts);
+        }
+        Stmt::While { condition, .. } => count_expr(condition, counts),
+        Stmt::Func { name, args, .. } => {
+            bump(name, counts);
+            args.iter().for_each(|arg| bump(arg, counts));
+        }
+        Stmt::Class { name, .. } => bump(name, counts),
+        Stmt::Try { handlers, .. } => {
+            for ExceptClause { types, var, .. } in handlers {
+                types.iter().chain(var).for_each(|name| bump(name, counts));
+            }
+        }
+        Stmt::Assert { condition, message } => {
+            count_expr(condition, counts);
+            message.iter().for_each(|m| count_expr(m, counts));
+        }
+        Stmt::Import { alias, .. } => alias.iter().for_each(|name| bump(name, counts)),
+        Stmt::FromImport { names, .. } => names.iter().for_each(|name| bump(name.renamed(), counts)),
+        Stmt::ExportNames(names) => names.iter().for_each(|name| bump(&name.name, counts)),
+        Stmt::Export(inner) => count_stmt(inner, counts),
+        Stmt::Server(server) => {
+            count_expr(&server.port, counts);
+            for (_, value) in &server.headers {
+                count_expr(value, counts);
+            }
+            for body in [&server.before, &server.after, &server.not_found, &server.on_shutdown] {
+                count_stmts(body, counts);
+            }
+            if let Some((name, body)) = &server.on_error {
+                bump(name, counts);
+                count_stmts(body, counts);
+            }
+            for route in server.flat_routes() {
+                route.handler.iter().chain(route.headers.iter().map(|(_, value)| value)).for_each(|e| count_expr(e, counts));
+                for body in [&route.before, &route.body, &route.after] {
+                    count_stmts(body, counts);
+                }
+            }
+        }
+        Stmt::Respond { value, .. } => count_expr(value, counts),
+        Stmt::RespondFile { path, .. } => count_expr(path, counts),
+        Stmt::Fetch { url, options, .. } => {
+            count_expr(url, counts);
+            options.iter().for_each(|(_, value)| count_expr(value, counts));
+        }
+        Stmt::Emit { event, data } => {
+            event.iter().for_each(|e| count_expr(e, counts));
+            count_expr(data, counts);
+        }
+        Stmt::Every { count, .. } => count_expr(count, counts),
+        Stmt::Pass | Stmt::Comment(_) | Stmt::Line(_) | Stmt::Break | Stmt::Continue
+        | Stmt::Stream { .. } | Stmt::Spawn { .. } | Stmt::Cron { .. } => {}
+    }
+    for body in blocks(stmt).into_iter().chain(closure_bodies(stmt)) {
+        count_stmts(body, counts);
+    }
+}
+
+fn count_expr(expr: &Expr, counts: &mut HashMap<String, usize>) {
+    match expr {
+        Expr::Ident(name) => bump(name, counts),
+        Expr::FString(parts) | Expr::Html(parts) => {
+            for part in parts {
+                if let FStringExprPart::Expression(e) = part {
+                    count_expr(e, counts);
+                }
+            }
+        }
+        Expr::Member(object, _) | Expr::Unary(_, object) | Expr::Keyword(_, object) | Expr::Spawn(object) | Expr::Await(object) => count_expr(object, counts),
+        Expr::Object(pairs) => pairs.iter().for_each(|(_, value)| count_expr(value, counts)),
+        Expr::Dict(pairs) => pairs.iter().for_each(|(key, value)| {
+            count_expr(key, counts);
+            count_expr(value, counts);
+        }),
+        Expr::Array(items) => items.iter().for_each(|item| count_expr(item, counts)),
+        Expr::Binary(left, _, right) | Expr::Index(left, right) => {
+            count_expr(left, counts);
+            count_expr(right, counts);
+        }
+        Expr::Call(callee, args) => {
+            count_expr(callee, counts);
+            args.iter().for_each(|arg| count_expr(arg, counts));
+        }
+        Expr::Fetch { url, options } => {
+            count_expr(url, counts);
+            options.iter().for_each(|(_, value)| count_expr(value, counts));
+        }
+        Expr::String(_) | Expr::Number(..) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None => {}
+    }
+}
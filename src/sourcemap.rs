@@ -0,0 +1,155 @@
+//! Source maps from generated JavaScript back to Harbor source.
+//!
+//! Codegen writes a marker line before each statement parsed with
+//! `Parser::with_lines`; `extract` strips them out again and remembers which
+//! source line each remaining line of JavaScript came from. Mappings are
+//! line-granular: every column of a generated line points at the start of
+//! its source line.
+
+/// Starts a marker line; the source line number follows.
+pub const MARKER: &str = "//@line ";
+
+/// For each line of generated JavaScript, the 1-based source line it came
+/// from. Lines before the first statement (the runtime prelude) map nowhere.
+#[derive(Debug, Clone, Default)]
+pub struct LineMap {
+    lines: Vec<Option<usize>>,
+}
+
+/// Remove the line markers from `js`, returning the code that's left and
+/// where each of its lines came from.
+pub fn extract(js: &str) -> (String, LineMap) {
+    let mut code = String::with_capacity(js.len());
+    let mut map = LineMap::default();
+    let mut current = None;
+    for line in js.split_inclusive('\n') {
+        if let Some(n) = line.trim_start().strip_prefix(MARKER).and_then(|rest| rest.trim_end().parse().ok()) {
+            current = Some(n);
+            continue;
+        }
+        code.push_str(line);
+        map.lines.push(current);
+    }
+    (code, map)
+}
+
+impl LineMap {
+    /// The source line generated line `line` (1-based) came from.
+    pub fn source_line(&self, line: usize) -> Option<usize> {
+        self.lines.get(line.checked_sub(1)?).copied().flatten()
+    }
+
+    /// A version 3 source map for `file` compiled from `source`, both given
+    /// relative to where the map is written. `content` embeds the source
+    /// text, for debuggers that can't read the file.
+    pub fn to_json(&self, file: &str, source: &str, content: Option<&str>) -> String {
+        let mut mappings = String::new();
+        let mut previous = 0i64;
+        let mut first = true;
+        for (i, line) in self.lines.iter().enumerate() {
+            if i > 0 {
+                mappings.push(';');
+            }
+            if let Some(n) = line {
+                let n = *n as i64 - 1;
+                // Generated column, source index, source line, source column;
+                // all but the first are relative to the previous segment
+                vlq(0, &mut mappings);
+                vlq(0, &mut mappings);
+                vlq(if first { n } else { n - previous }, &mut mappings);
+                vlq(0, &mut mappings);
+                previous = n;
+                first = false;
+            }
+        }
+        let content = match content {
+            Some(text) => format!(",\"sourcesContent\":[{}]", crate::json_string(text)),
+            None => String::new(),
+        };
+        format!(
+            "{{\"version\":3,\"file\":{},\"sources\":[{}]{},\"names\":[],\"mappings\":\"{}\"}}",
+            crate::json_string(file),
+            crate::json_string(source),
+            content,
+            mappings
+        )
+    }
+}
+
+const BASE64: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// `bytes` as padded base64, for `data:` URLs.
+pub fn base64(bytes: &[u8]) -> String {
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(BASE64[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+/// Append `value` as a base64 VLQ.
+fn vlq(value: i64, out: &mut String) {
+    let mut rest = if value < 0 { ((-value) << 1) | 1 } else { value << 1 } as u64;
+    loop {
+        let mut digit = (rest & 31) as usize;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 32;
+        }
+        out.push(BASE64[digit] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+/// JavaScript that makes stack traces point into Harbor source: frames in
+/// a file with a `.map` beside it are rewritten through the map. It goes
+/// first in the output, so errors thrown while starting up are covered too.
+/// Several modules can each include it; the first one installs the hook.
+pub fn runtime_hook() -> String {
+    let mut output = String::new();
+    output.push_str("// Report errors at their Harbor source locations\n");
+    output.push_str("(() => {\n");
+    output.push_str("  const maps = globalThis.__harborSourceMaps;\n");
+    output.push_str("  if (maps) return maps.set(__filename, undefined);\n");
+    output.push_str("  if (process.sourceMapsEnabled) return;\n");
+    output.push_str("  const registry = new Map([[__filename, undefined]]);\n");
+    output.push_str("  globalThis.__harborSourceMaps = registry;\n");
+    output.push_str("  const { SourceMap } = require(\"module\");\n");
+    output.push_str("  const load = (file) => {\n");
+    output.push_str("    if (!registry.has(file)) return null;\n");
+    output.push_str("    if (registry.get(file) === undefined) {\n");
+    output.push_str("      let map = null;\n");
+    output.push_str("      try {\n");
+    output.push_str("        map = new SourceMap(JSON.parse(require(\"fs\").readFileSync(file + \".map\", \"utf8\")));\n");
+    output.push_str("      } catch {}\n");
+    output.push_str("      registry.set(file, map);\n");
+    output.push_str("    }\n");
+    output.push_str("    return registry.get(file);\n");
+    output.push_str("  };\n");
+    output.push_str("  Error.prepareStackTrace = (err, frames) => {\n");
+    output.push_str("    const mapped = frames.map((frame) => {\n");
+    output.push_str("      const file = frame.getFileName();\n");
+    output.push_str("      const map = file && load(file);\n");
+    output.push_str("      const entry = map && map.findEntry(frame.getLineNumber() - 1, frame.getColumnNumber() - 1);\n");
+    output.push_str("      if (!entry || entry.originalLine === undefined) return `    at ${frame}`;\n");
+    output.push_str("      const source = require(\"path\").resolve(require(\"path\").dirname(file), entry.originalSource);\n");
+    output.push_str("      const where = `${source}:${entry.originalLine + 1}`;\n");
+    output.push_str("      const name = frame.getFunctionName();\n");
+    output.push_str("      return name ? `    at ${frame.isAsync() ? \"async \" : \"\"}${name} (${where})` : `    at ${where}`;\n");
+    output.push_str("    });\n");
+    output.push_str("    let header;\n");
+    output.push_str("    try { header = String(err); } catch { header = \"Error\"; }\n");
+    output.push_str("    return [header, ...mapped].join(\"\\n\");\n");
+    output.push_str("  };\n");
+    output.push_str("})();\n\n");
+    output
+}
@@ -0,0 +1,40 @@
+//! The standard library: Harbor modules embedded in the compiler.
+//!
+//! `import "std/strings"` resolves to the embedded source, which is written
+//! out under the project's `.harbor/std` and compiled there like any other
+//! module. Set `HARBOR_STDLIB` to use a directory of modules instead, e.g.
+//! while working on the library itself.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MODULES: &[(&str, &str)] = &[
+    ("collections", include_str!("../std/collections.hb")),
+    ("http", include_str!("../std/http.hb")),
+    ("strings", include_str!("../std/strings.hb")),
+];
+
+/// The source of the module `std/<name>`.
+pub fn source(name: &str) -> Option<&'static str> {
+    let name = name.strip_suffix(".hb").unwrap_or(name);
+    MODULES.iter().find(|(n, _)| *n == name).map(|(_, src)| *src)
+}
+
+/// The names of the embedded modules.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    MODULES.iter().map(|(name, _)| *name)
+}
+
+/// Write `std/<name>` into `dir` unless it's already there unchanged, and
+/// return its path. `None` if there's no such module.
+pub fn install(dir: &Path, name: &str) -> io::Result<Option<PathBuf>> {
+    let Some(src) = source(name) else { return Ok(None) };
+    let path = dir.join(format!("{}.hb", name.strip_suffix(".hb").unwrap_or(name)));
+    // Rewriting an unchanged file would bump its mtime and wake `harbor dev`
+    if fs::read_to_string(&path).ok().as_deref() != Some(src) {
+        fs::create_dir_all(dir)?;
+        fs::write(&path, src)?;
+    }
+    Ok(Some(path))
+}
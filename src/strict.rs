@@ -0,0 +1,711 @@
+//! The checks `--strict` adds. Using a name that's never defined, or calling
+//! a function or class with the wrong number of arguments, is an error.
+//! Conditions that rely on truthiness (W002) and names that shadow a
+//! module-level or builtin one (W003) are warnings, which `harbor.toml` can
+//! also turn on without `--strict`. So is `+` on a string and a number
+//! (W007), which is on by default: JavaScript joins them as text where
+//! Python would raise a TypeError. So is a `@sync` function or constructor
+//! calling an async function (W008), which hands it a Promise.
+//!
+//! Scopes follow the output: each function, route, server hook block and
+//! background block (`spawn`, `every`, `cron`) has its own, and a name
+//! assigned anywhere in one is local to all of it. Whether a name is
+//! assigned before it's used isn't checked.
+
+use crate::ast::*;
+use crate::codegen::{Backend, NodeBackend};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::numbers;
+use std::collections::{HashMap, HashSet};
+use std::sync::OnceLock;
+
+/// The warnings these checks give.
+pub const WARNINGS: [&str; 4] = ["W002", "W003", "W007", "W008"];
+
+/// JavaScript globals Harbor code can use as they are.
+const JS_GLOBALS: &[&str] = &[
+    "console", "process", "require", "module", "exports", "__dirname", "__filename", "globalThis",
+    "Math", "JSON", "Date", "Promise", "Object", "Array", "String", "Number", "Boolean", "Symbol",
+    "BigInt", "RegExp", "Map", "Set", "WeakMap", "WeakSet", "Error", "TypeError", "RangeError",
+    "Buffer", "URL", "URLSearchParams", "TextEncoder", "TextDecoder", "AbortController",
+    "setTimeout", "setInterval", "setImmediate", "clearTimeout", "clearInterval", "queueMicrotask",
+    "structuredClone", "parseInt", "parseFloat", "isNaN", "isFinite", "encodeURIComponent",
+    "decodeURIComponent", "NaN", "Infinity", "undefined",
+];
+
+/// Builtins that return a string, number or bool.
+const SCALAR_BUILTINS: &[&str] = &[
+    "str", "int", "float", "bigint", "bool", "len", "abs", "round", "chr", "ord", "type",
+    "isinstance", "any", "all", "sum", "input",
+];
+
+/// Builtins that return a list or dict.
+const COLLECTION_BUILTINS: &[&str] = &[
+    "range", "sorted", "reversed", "enumerate", "zip", "keys", "values", "items", "dict", "copy", "deepcopy",
+    "unique", "count_by", "group_by", "flatten", "chunk", "bytes",
+];
+
+/// Builtins that return a Promise, which code that can't wait gets instead
+/// of the result.
+const ASYNC_BUILTINS: &[&str] = &[
+    "str", "sorted", "min", "max", "unique", "count_by", "group_by", "flatten", "chunk", "input",
+    "gather", "wait_all", "sleep", "retry", "with_timeout", "assert_eq",
+];
+
+/// The members of runtime modules that return a Promise.
+const ASYNC_MEMBERS: &[(&str, &[&str])] = &[
+    ("fs", &["read", "write", "append", "read_bytes", "write_bytes", "exists", "listdir", "mkdir", "remove", "copy", "stat"]),
+    ("csv", &["read", "write"]),
+    ("shell", &["run", "stream"]),
+    ("input", &["int", "float", "lines", "all"]),
+];
+
+/// The methods of an `http_client()`, which all send a request.
+const CLIENT_METHODS: &[&str] = &["get", "post", "put", "patch", "delete"];
+
+/// Check `stmts`, parsed with lines from `source`. `imported` holds the
+/// parameter counts of the functions and classes the module imports by
+/// name. Errors and warnings come back together.
+pub fn check(stmts: &[Stmt], source: &str, imported: &HashMap<String, usize>) -> Vec<Diagnostic> {
+    let module = Scope::of(&[stmts], &[], &[], 1);
+    let mut arities = imported.clone();
+    let mut async_functions = HashSet::new();
+    for stmt in stmts {
+        let stmt = match stmt {
+            Stmt::Export(inner) => &**inner,
+            other => other,
+        };
+        match stmt {
+            Stmt::Func { name, args, sync, .. } => {
+                arities.insert(name.clone(), args.len());
+                if !sync {
+                    async_functions.insert(name.clone());
+                }
+            }
+            Stmt::Class { name, methods } => {
+                arities.insert(name.clone(), method_arities(methods).get("init").copied().unwrap_or(0));
+            }
+            _ => {}
+        }
+    }
+    // A name that's also assigned could hold anything
+    arities.retain(|name, _| !module.assigned.contains(name));
+    async_functions.retain(|name| !module.assigned.contains(name));
+
+    let mut checker = Checker {
+        lines: source.lines().collect(),
+        scopes: Vec::new(),
+        arities,
+        methods: HashMap::new(),
+        async_functions,
+        async_methods: HashSet::new(),
+        sync_body: None,
+        line: 1,
+        diags: Vec::new(),
+    };
+    checker.warn_shadowing(&module);
+    checker.scopes.push(module);
+    checker.stmts(stmts);
+    checker.diags
+}
+
+/// Names the runtime prelude declares, which every module can use.
+fn runtime_names() -> &'static HashSet<String> {
+    static NAMES: OnceLock<HashSet<String>> = OnceLock::new();
+    NAMES.get_or_init(|| {
+        NodeBackend.runtime_prelude().lines().filter_map(|line| {
+            let rest = ["const ", "let ", "var ", "function ", "async function ", "class "]
+                .iter()
+                .find_map(|keyword| line.strip_prefix(keyword))?;
+            let name: String = rest.chars().take_while(|c| c.is_alphanumeric() || *c == '_' || *c == '$').collect();
+            (!name.is_empty()).then_some(name)
+        }).collect()
+    })
+}
+
+/// Whether `name` is one of the builtins Harbor code is meant to call,
+/// rather than a runtime helper.
+fn is_public_builtin(name: &str) -> bool {
+    runtime_names().contains(name)
+        && !name.starts_with('_')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+fn method_arities(methods: &[Stmt]) -> HashMap<String, usize> {
+    methods.iter().filter_map(|m| match m {
+        Stmt::Func { name, args, .. } => Some((name.clone(), args.len())),
+        _ => None,
+    }).collect()
+}
+
+/// The names one scope binds.
+#[derive(Default)]
+struct Scope {
+    /// Each name, with the line it's first bound on.
+    names: HashMap<String, usize>,
+    /// Names bound by assignment, as opposed to `def`, `class` or imports.
+    assigned: HashSet<String>,
+    /// Names only ever assigned strings, numbers, bools or None, whose
+    /// truthiness is the same as in Python.
+    scalars: HashSet<String>,
+    /// Names the output binds, like `req`, which can't shadow anything.
+    implicit: HashSet<String>,
+    /// Names only ever assigned strings, or only ever numbers. While the
+    /// scope is collected, `None` marks a name assigned anything else.
+    types: HashMap<String, Option<Type>>,
+    /// Whether each assigned name is only ever assigned an `http_client()`.
+    clients: HashMap<String, bool>,
+}
+
+/// What's known about a value for `+`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Type {
+    Str,
+    Num,
+}
+
+impl Scope {
+    /// The scope of the blocks in `bodies`, which share it, with `params`
+    /// bound on `line`.
+    fn of(bodies: &[&[Stmt]], params: &[String], implicit: &[&str], line: usize) -> Scope {
+        let mut scope = Scope::default();
+        for name in params {
+            scope.names.entry(name.clone()).or_insert(line);
+        }
+        for name in implicit {
+            scope.names.insert(name.to_string(), line);
+            scope.implicit.insert(name.to_string());
+        }
+        let mut mixed = HashSet::new();
+        let mut line = line;
+        for body in bodies {
+            scope.collect(body, &mut line, &mut mixed);
+        }
+        scope.scalars.retain(|name| !mixed.contains(name) && !params.contains(name));
+        scope.types.retain(|name, found| found.is_some() && !mixed.contains(name) && !params.contains(name));
+        scope.clients.retain(|name, only| *only && !params.contains(name));
+        scope
+    }
+
+    fn bind(&mut self, name: &str, line: usize) {
+        self.names.entry(name.to_string()).or_insert(line);
+    }
+
+    fn collect(&mut self, body: &[Stmt], line: &mut usize, mixed: &mut HashSet<String>) {
+        for stmt in body {
+            match stmt {
+                Stmt::Line(n) => *line = *n,
+                Stmt::Set { target: Expr::Ident(name), value } => {
+                    self.bind(name, *line);
+                    self.assigned.insert(name.clone());
+                    let client = matches!(value, Expr::Call(callee, _) if matches!(&**callee, Expr::Ident(f) if f == "http_client"));
+                    *self.clients.entry(name.clone()).or_insert(client) &= client;
+                    if is_scalar(value) {
+                        self.scalars.insert(name.clone());
+                    } else {
+                        mixed.insert(name.clone());
+                    }
+                    // Kept apart from `mixed`, which would also drop a name
+                    // assigned comparisons from `scalars`
+                    let found = literal_type(value);
+                    if self.types.get(name).is_some_and(|&known| known != found) {
+                        self.types.insert(name.clone(), None);
+                    } else {
+                        self.types.insert(name.clone(), found);
+                    }
+                }
+                Stmt::ForIn { var, body, .. } => {
+                    self.bind(var, *line);
+                    self.assigned.insert(var.clone());
+                    mixed.insert(var.clone());
+                    self.collect(body, line, mixed);
+                }
+                Stmt::Func { name, .. } | Stmt::Class { name, .. } => {
+                    self.bind(name, *line);
+                    mixed.insert(name.clone());
+                }
+                Stmt::Import { alias: Some(alias), .. } => {
+                    self.bind(alias, *line);
+                    mixed.insert(alias.clone());
+                }
+                Stmt::FromImport { names, .. } => {
+                    for name in names {
+                        self.bind(name.renamed(), *line);
+                        mixed.insert(name.renamed().to_string());
+                    }
+                }
+                Stmt::Export(inner) => self.collect(std::slice::from_ref(&**inner), line, mixed),
+                Stmt::If { then_body, elif_branches, else_body, .. } => {
+                    self.collect(then_body, line, mixed);
+                    for (_, body) in elif_branches {
+                        self.collect(body, line, mixed);
+                    }
+                    self.collect(else_body.as_deref().unwrap_or_default(), line, mixed);
+                }
+                Stmt::Try { body, handlers } => {
+                    self.collect(body, line, mixed);
+                    for handler in handlers {
+                        if let Some(var) = &handler.var {
+                            self.bind(var, *line);
+                            mixed.insert(var.clone());
+                        }
+                        self.collect(&handler.body, line, mixed);
+                    }
+                }
+                Stmt::While { body, .. } | Stmt::Fetch { body, .. } | Stmt::Stream { body } => self.collect(body, line, mixed),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Whether `expr` is a string, number, bool or None.
+fn is_scalar(expr: &Expr) -> bool {
+    match expr {
+        Expr::Bool(_) | Expr::None | Expr::String(_) | Expr::FString(_) => true,
+        Expr::Binary(left, op, right) => match op.as_str() {
+            "===" | "!==" | "<" | ">" | "<=" | ">=" | "in" | "not in" => true,
+            "and" | "or" => is_scalar(left) && is_scalar(right),
+            "+" => matches!(**left, Expr::String(_) | Expr::FString(_)) || matches!(**right, Expr::String(_) | Expr::FString(_)) || numbers::kind(expr).is_some(),
+            _ => numbers::kind(expr).is_some(),
+        },
+        Expr::Unary(op, operand) => op == "not" || is_scalar(operand),
+        Expr::Call(callee, _) => matches!(&**callee, Expr::Ident(name) if SCALAR_BUILTINS.contains(&name.as_str())),
+        _ => numbers::kind(expr).is_some(),
+    }
+}
+
+/// Whether `expr` is a string or a number, from its literals and the
+/// builtins it calls, without looking at names.
+fn literal_type(expr: &Expr) -> Option<Type> {
+    match expr {
+        Expr::String(_) | Expr::FString(_) | Expr::Html(_) => Some(Type::Str),
+        Expr::Call(callee, _) if matches!(&**callee, Expr::Ident(name) if matches!(name.as_str(), "str" | "chr" | "input")) => Some(Type::Str),
+        Expr::Binary(left, op, right) if op == "+" && (literal_type(left) == Some(Type::Str) || literal_type(right) == Some(Type::Str)) => Some(Type::Str),
+        _ => numbers::kind(expr).map(|_| Type::Num),
+    }
+}
+
+struct Checker<'a> {
+    lines: Vec<&'a str>,
+    /// The module's scope, then each enclosing function's, innermost last.
+    scopes: Vec<Scope>,
+    /// Parameter counts of module-level functions and classes.
+    arities: HashMap<String, usize>,
+    /// Parameter counts of the methods of the class being checked.
+    methods: HashMap<String, usize>,
+    /// Module-level functions that aren't `@sync`.
+    async_functions: HashSet<String>,
+    /// Methods of the class being checked that aren't `@sync`.
+    async_methods: HashSet<String>,
+    /// What the code being checked is, if it can't wait: "a @sync
+    /// function" or "a constructor".
+    sync_body: Option<&'static str>,
+    line: usize,
+    diags: Vec<Diagnostic>,
+}
+
+impl Checker<'_> {
+    /// Where `name` appears on `line`, for pointing at it.
+    fn span(&self, name: &str, line: usize) -> Span {
+        let text = self.lines.get(line.wrapping_sub(1)).copied().unwrap_or("");
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+        let col = text.match_indices(name).find(|(i, _)| {
+            !text[..*i].ends_with(is_word) && !text[i + name.len()..].starts_with(is_word)
+        });
+        Span { line, col: col.map_or(1, |(i, _)| text[..i].chars().count() + 1) }
+    }
+
+    fn error(&mut self, message: String, name: &str) {
+        let diag = Diagnostic::error(message, self.span(name, self.line));
+        if !self.diags.contains(&diag) {
+            self.diags.push(diag);
+        }
+    }
+
+    /// The scope a name refers to: an index into `scopes`.
+    fn lookup(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rposition(|scope| scope.names.contains_key(name))
+    }
+
+    /// W003 for each name `scope` binds that hides one from an enclosing
+    /// scope or a builtin.
+    fn warn_shadowing(&mut self, scope: &Scope) {
+        let mut names: Vec<(&String, &usize)> = scope.names.iter().filter(|(name, _)| !scope.implicit.contains(*name)).collect();
+        names.sort_by_key(|(name, line)| (**line, (*name).clone()));
+        for (name, line) in names {
+            let message = match self.lookup(name) {
+                Some(0) => format!("'{}' hides the module-level '{}'", name, name),
+                Some(_) => format!("'{}' hides '{}' from the enclosing function", name, name),
+                None if is_public_builtin(name) => format!("'{}' hides the builtin '{}'", name, name),
+                None => continue,
+            };
+            self.diags.push(Diagnostic::warning("W003", message, self.span(name, *line)));
+        }
+    }
+
+    /// Check a function-like block in a scope of its own.
+    fn block(&mut self, params: &[String], implicit: &[&str], bodies: &[&[Stmt]]) {
+        let scope = Scope::of(bodies, params, implicit, self.line);
+        self.warn_shadowing(&scope);
+        self.scopes.push(scope);
+        for body in bodies {
+            self.stmts(body);
+        }
+        self.scopes.pop();
+    }
+
+    fn stmts(&mut self, body: &[Stmt]) {
+        for stmt in body {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Line(n) => self.line = *n,
+            Stmt::Set { target, value } => {
+                self.expr(value);
+                if !matches!(target, Expr::Ident(_)) {
+                    self.expr(target);
+                }
+            }
+            Stmt::AugAssign { target, op, value } => {
+                self.expr(target);
+                self.expr(value);
+                if op == "+" {
+                    self.mixed_addition(target, value);
+                }
+            }
+            Stmt::Expression(expr) | Stmt::Return(Some(expr)) | Stmt::Raise(expr) | Stmt::ExportDefault(expr) => self.expr(expr),
+            Stmt::Print(args) => args.iter().for_each(|arg| self.expr(arg)),
+            Stmt::If { condition, then_body, elif_branches, else_body } => {
+                self.condition(condition);
+                self.stmts(then_body);
+                for (condition, body) in elif_branches {
+                    self.condition(condition);
+                    self.stmts(body);
+                }
+                self.stmts(else_body.as_deref().unwrap_or_default());
+            }
+            Stmt::ForIn { iterable, body, .. } => {
+                self.expr(iterable);
+                self.stmts(body);
+            }
+            Stmt::While { condition, body } => {
+                self.condition(condition);
+                self.stmts(body);
+            }
+            // A function called from a route can use the request it's handling
+            Stmt::Func { args, body, sync, .. } => {
+                let outer = std::mem::replace(&mut self.sync_body, sync.then_some("a @sync function"));
+                self.block(args, &["req"], &[body]);
+                self.sync_body = outer;
+            }
+            Stmt::Class { methods, .. } => {
+                let outer = std::mem::replace(&mut self.methods, method_arities(methods));
+                let async_methods = methods.iter().filter_map(|method| match method {
+                    Stmt::Func { name, sync: false, .. } if name != "init" => Some(name.clone()),
+                    _ => None,
+                }).collect();
+                let outer_async = std::mem::replace(&mut self.async_methods, async_methods);
+                for method in methods {
+                    match method {
+                        Stmt::Func { name, args, body, sync } => {
+                            let sync_body = match (name == "init", sync) {
+                                (true, _) => Some("a constructor"),
+                                (false, true) => Some("a @sync function"),
+                                (false, false) => None,
+                            };
+                            let outer_sync = std::mem::replace(&mut self.sync_body, sync_body);
+                            self.block(args, &["this"], &[body]);
+                            self.sync_body = outer_sync;
+                        }
+                        other => self.stmt(other),
+                    }
+                }
+                self.methods = outer;
+                self.async_methods = outer_async;
+            }
+            Stmt::Try { body, handlers } => {
+                self.stmts(body);
+                for handler in handlers {
+                    for name in &handler.types {
+                        self.name(name);
+                    }
+                    self.stmts(&handler.body);
+                }
+            }
+            Stmt::Assert { condition, message } => {
+                self.condition(condition);
+                if let Some(message) = message {
+                    self.expr(message);
+                }
+            }
+            Stmt::Export(inner) => self.stmt(inner),
+            Stmt::Server(server) => self.server(server),
+            Stmt::Respond { status, value, .. } => {
+                self.status(status);
+                self.expr(value);
+            }
+            Stmt::RespondFile { status, path } => {
+                self.status(status);
+                self.expr(path);
+            }
+            Stmt::Fetch { url, options, body } => {
+                self.expr(url);
+                options.iter().for_each(|(_, value)| self.expr(value));
+                // The response is `res` inside the block
+                let scope = Scope::of(&[], &[], &["res"], self.line);
+                self.scopes.push(scope);
+                self.stmts(body);
+                self.scopes.pop();
+            }
+            Stmt::Stream { body } => self.stmts(body),
+            Stmt::Emit { event, data } => {
+                if let Some(event) = event {
+                    self.expr(event);
+                }
+                self.expr(data);
+            }
+            // Background blocks run as async functions of their own
+            Stmt::Spawn { body } | Stmt::Cron { body, .. } => {
+                let outer = self.sync_body.take();
+                self.block(&[], &[], &[body]);
+                self.sync_body = outer;
+            }
+            Stmt::Every { count, body, .. } => {
+                self.expr(count);
+                let outer = self.sync_body.take();
+                self.block(&[], &[], &[body]);
+                self.sync_body = outer;
+            }
+            Stmt::ExportNames(names) => names.iter().for_each(|n| self.name(&n.name)),
+            Stmt::Import { .. } | Stmt::FromImport { .. } | Stmt::Return(None) | Stmt::Pass | Stmt::Break
+            | Stmt::Continue | Stmt::Comment(_) => {}
+        }
+    }
+
+    fn status(&mut self, status: &Option<Status>) {
+        if let Some(Status::Expr(expr)) = status {
+            self.expr(expr);
+        }
+    }
+
+    fn server(&mut self, server: &Server) {
+        self.expr(&server.port);
+        if let Some(tls) = &server.tls {
+            self.expr(&tls.cert);
+            self.expr(&tls.key);
+        }
+        if let Some(limit) = &server.limit {
+            self.expr(&limit.count);
+        }
+        if let Some(auth) = &server.auth {
+            self.expr(&auth.secret);
+        }
+        // Hooks run in the request handler, where `req` is the request
+        let (error_name, on_error) = match &server.on_error {
+            Some((name, body)) => (vec![name.clone()], body.as_slice()),
+            None => (Vec::new(), &[][..]),
+        };
+        let headers: Vec<Stmt> = server.headers.iter().map(|(_, value)| Stmt::Expression(value.clone())).collect();
+        self.block(&error_name, &["req"], &[&headers, &server.before, &server.after, &server.not_found, on_error]);
+        self.block(&[], &[], &[&server.on_shutdown]);
+        for route in server.flat_routes() {
+            for expr in [route.limit.as_ref().map(|l| &l.count), route.auth.as_ref().map(|a| &a.secret), route.cache.as_ref(), route.handler.as_ref()].into_iter().flatten() {
+                self.expr(expr);
+            }
+            let headers: Vec<Stmt> = route.headers.iter().map(|(_, value)| Stmt::Expression(value.clone())).collect();
+            self.block(&[], &["req"], &[&headers, &route.before, &route.body, &route.after]);
+        }
+    }
+
+    fn name(&mut self, name: &str) {
+        if name == "this" || self.lookup(name).is_some() || runtime_names().contains(name) || JS_GLOBALS.contains(&name) {
+            return;
+        }
+        self.error(format!("Undefined name '{}'", name), name);
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Ident(name) => self.name(name),
+            Expr::Member(object, _) => self.expr(object),
+            Expr::Object(pairs) => pairs.iter().for_each(|(_, value)| self.expr(value)),
+            Expr::Dict(pairs) => pairs.iter().for_each(|(key, value)| {
+                self.expr(key);
+                self.expr(value);
+            }),
+            Expr::Array(items) => items.iter().for_each(|item| self.expr(item)),
+            Expr::Binary(left, op, right) => {
+                self.expr(left);
+                self.expr(right);
+                if op == "+" {
+                    self.mixed_addition(left, right);
+                }
+            }
+            Expr::Index(left, right) => {
+                self.expr(left);
+                self.expr(right);
+            }
+            Expr::Unary(_, operand) | Expr::Keyword(_, operand) | Expr::Await(operand) => self.expr(operand),
+            Expr::Spawn(task) => {
+                let outer = self.sync_body.take();
+                self.expr(task);
+                self.sync_body = outer;
+            }
+            Expr::Call(callee, args) => self.call(callee, args),
+            Expr::Fetch { url, options } => {
+                self.expr(url);
+                options.iter().for_each(|(_, value)| self.expr(value));
+            }
+            Expr::FString(parts) | Expr::Html(parts) => {
+                for part in parts {
+                    if let FStringExprPart::Expression(expr) = part {
+                        self.expr(expr);
+                    }
+                }
+            }
+            Expr::String(_) | Expr::Number(..) | Expr::BigInt(_) | Expr::Bool(_) | Expr::None => {}
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, args: &[Expr]) {
+        self.expr(callee);
+        args.iter().for_each(|arg| self.expr(arg));
+        self.async_call(callee);
+        // Keyword arguments travel as one extra object
+        if args.iter().any(|arg| matches!(arg, Expr::Keyword(..))) {
+            return;
+        }
+        let (name, expected) = match callee {
+            Expr::Ident(name) if self.lookup(name) == Some(0) => match self.arities.get(name) {
+                Some(expected) => (name, *expected),
+                None => return,
+            },
+            Expr::Member(object, method) if matches!(&**object, Expr::Ident(this) if this == "this") => match self.methods.get(method) {
+                Some(expected) => (method, *expected),
+                None => return,
+            },
+            _ => return,
+        };
+        if args.len() != expected {
+            let plural = |n: usize| if n == 1 { "argument" } else { "arguments" };
+            let given = if args.len() == 1 { "was" } else { "were" };
+            self.error(format!("{}() takes {} {} but {} {} given", name, expected, plural(expected), args.len(), given), name);
+        }
+    }
+
+    /// W008 where code that can't wait calls a function that's async.
+    fn async_call(&mut self, callee: &Expr) {
+        let Some(place) = self.sync_body else { return };
+        let is_this = |object: &Expr| matches!(object, Expr::Ident(this) if this == "this");
+        let (name, own) = match callee {
+            Expr::Ident(name) if self.lookup(name) == Some(0) && self.async_functions.contains(name) => (name.clone(), true),
+            Expr::Ident(name) if self.lookup(name).is_none() && ASYNC_BUILTINS.contains(&name.as_str()) => (name.clone(), false),
+            Expr::Member(object, method) if is_this(object) && self.async_methods.contains(method) => (method.clone(), true),
+            Expr::Member(object, method) if self.async_member(object, method) => match &**object {
+                Expr::Ident(object) => (format!("{}.{}", object, method), false),
+                _ => return,
+            },
+            _ => return,
+        };
+        let fix = if own { format!("; mark {} @sync too", name) } else { String::new() };
+        let message = format!("{}() is async, so {} gets a Promise from it instead of its result{}", name, place, fix);
+        self.diags.push(Diagnostic::warning("W008", message, self.span(&name, self.line)));
+    }
+
+    /// Whether `object.method` is an async member of a runtime module, like
+    /// `fs.read`, or a request from an `http_client()`.
+    fn async_member(&self, object: &Expr, method: &str) -> bool {
+        let Expr::Ident(name) = object else { return false };
+        match self.lookup(name) {
+            None => ASYNC_MEMBERS.iter().any(|(module, members)| module == name && members.contains(&method)),
+            Some(scope) => self.scopes[scope].clients.contains_key(name) && CLIENT_METHODS.contains(&method),
+        }
+    }
+
+    /// Whether `expr` is a string or a number, as far as its literals and
+    /// the names it uses tell.
+    fn type_of(&self, expr: &Expr) -> Option<Type> {
+        match expr {
+            Expr::Ident(name) => self.scopes[self.lookup(name)?].types.get(name).copied().flatten(),
+            Expr::Binary(left, op, right) if numbers::is_arithmetic(op) => match (self.type_of(left), self.type_of(right)) {
+                (Some(Type::Num), Some(Type::Num)) => Some(Type::Num),
+                (Some(Type::Str), _) | (_, Some(Type::Str)) if op == "+" => Some(Type::Str),
+                _ => None,
+            },
+            _ => literal_type(expr),
+        }
+    }
+
+    /// W007 where `+` adds a string and a number, which JavaScript joins as
+    /// text instead of raising a TypeError.
+    fn mixed_addition(&mut self, left: &Expr, right: &Expr) {
+        let (Some(left_type), Some(right_type)) = (self.type_of(left), self.type_of(right)) else { return };
+        if left_type == right_type {
+            return;
+        }
+        let (string, number) = if left_type == Type::Str { (left, right) } else { (right, left) };
+        let describe = |expr: &Expr, kind: &str| match expr {
+            Expr::Ident(name) => format!("'{}' (a {})", name, kind),
+            _ => format!("a {}", kind),
+        };
+        let name = [left, right].into_iter().find_map(|e| match e {
+            Expr::Ident(name) => Some(name.as_str()),
+            _ => None,
+        });
+        let span = match name {
+            Some(name) => self.span(name, self.line),
+            None => {
+                let text = self.lines.get(self.line.wrapping_sub(1)).copied().unwrap_or("");
+                Span { line: self.line, col: text.find('+').map_or(1, |i| text[..i].chars().count() + 1) }
+            }
+        };
+        self.diags.push(Diagnostic::warning(
+            "W007",
+            format!(
+                "'+' adds {} to {}, which joins them as text (\"1\" + 2 is \"12\"); convert one with str(), int() or float() first",
+                describe(number, "number"),
+                describe(string, "string"),
+            ),
+            span,
+        ));
+    }
+
+    /// Check a condition, warning (W002) where it relies on truthiness and
+    /// could be a list or dict, which is true even when empty.
+    fn condition(&mut self, condition: &Expr) {
+        self.expr(condition);
+        self.truthiness(condition);
+    }
+
+    fn truthiness(&mut self, condition: &Expr) {
+        let (subject, name) = match condition {
+            Expr::Binary(left, op, right) if op == "and" || op == "or" => {
+                self.truthiness(left);
+                self.truthiness(right);
+                return;
+            }
+            Expr::Unary(op, operand) if op == "not" => return self.truthiness(operand),
+            Expr::Ident(name) => match self.lookup(name) {
+                Some(scope) if !self.scopes[scope].scalars.contains(name) => (format!("'{}'", name), name.as_str()),
+                _ => return,
+            },
+            Expr::Array(_) | Expr::Object(_) | Expr::Dict(_) => ("A list or dict literal".to_string(), ""),
+            Expr::Call(callee, _) => match &**callee {
+                Expr::Ident(name) if COLLECTION_BUILTINS.contains(&name.as_str()) => (format!("{}()", name), name.as_str()),
+                _ => return,
+            },
+            _ => return,
+        };
+        let span = self.span(name, self.line);
+        self.diags.push(Diagnostic::warning(
+            "W002",
+            format!("{} is used as a condition; an empty list or dict counts as true, so compare explicitly (e.g. len(x) > 0)", subject),
+            span,
+        ));
+    }
+}
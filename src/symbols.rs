@@ -0,0 +1,257 @@
+//! The project's symbol index: the functions, classes and variables each
+//! module defines, what it imports, and which names it exports.
+//!
+//! Modules are found by following imports with the `resolver` and parsed
+//! once, however many files import them. The build checks `from ... import`
+//! names and reads the prelude's exports from here, `harbor doc` renders a
+//! module's entries, and `harbor def` answers go-to-definition across
+//! imports.
+
+use crate::ast::{Expr, NameAs, Stmt};
+use crate::lexer::Lexer;
+use crate::parser::Parser;
+use crate::resolver::{normalize, Module, Resolver};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    Variable,
+}
+
+/// A name defined at the top level of a module, or a method of a class.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where it's defined; the first assignment, for a variable.
+    pub line: usize,
+    /// A function's parameters, or for a class those of its `init`.
+    pub params: Vec<String>,
+    /// The comments directly above the definition, as written.
+    pub doc: Vec<String>,
+    pub exported: bool,
+    /// A class's methods.
+    pub members: Vec<Symbol>,
+}
+
+/// A name brought in from another module: `from "x" import name`, or with
+/// `import "x" as name` the whole module. `export a as b` makes `b` one of
+/// these too, taken from the module itself.
+#[derive(Debug, Clone)]
+pub struct Imported {
+    pub name: String,
+    /// What `source` calls it, which `as` may have changed.
+    pub original: String,
+    /// The Harbor file it comes from; `None` for Node modules and assets.
+    pub source: Option<PathBuf>,
+    /// Whether `name` is the whole module rather than one of its exports.
+    pub whole_module: bool,
+    pub line: usize,
+    /// Re-exported with `export from ... import` or `export import`.
+    pub exported: bool,
+}
+
+/// Everything the index knows about one module.
+#[derive(Debug, Clone, Default)]
+pub struct ModuleSymbols {
+    pub symbols: Vec<Symbol>,
+    pub imports: Vec<Imported>,
+    pub has_default: bool,
+}
+
+impl ModuleSymbols {
+    /// Index a module parsed with its comments and lines kept, resolving
+    /// the imports of the file at `source` with `resolver`.
+    pub fn from_ast(stmts: &[Stmt], source: &Path, resolver: &Resolver) -> ModuleSymbols {
+        let mut module = ModuleSymbols::default();
+        let mut line = 0;
+        let mut doc = Vec::new();
+        let mut renames = Vec::new();
+        for stmt in stmts {
+            let (exported, stmt) = match stmt {
+                Stmt::Export(inner) => (true, &**inner),
+                other => (false, other),
+            };
+            match stmt {
+                Stmt::Line(n) => {
+                    line = *n;
+                    continue;
+                }
+                Stmt::Comment(text) => {
+                    doc.push(text.trim().to_string());
+                    continue;
+                }
+                Stmt::Import { path, alias: Some(alias), .. } => {
+                    let target = harbor_source(resolver, path, source);
+                    module.imports.push(Imported { name: alias.clone(), original: alias.clone(), source: target, whole_module: true, line, exported });
+                }
+                Stmt::FromImport { path, names, .. } => {
+                    let target = harbor_source(resolver, path, source);
+                    for name in names {
+                        let (local, original) = (name.renamed().to_string(), name.name.clone());
+                        module.imports.push(Imported { name: local, original, source: target.clone(), whole_module: false, line, exported });
+                    }
+                }
+                Stmt::ExportNames(names) => {
+                    for name in names {
+                        renames.push((name.clone(), line));
+                    }
+                }
+                Stmt::ExportDefault(_) => module.has_default = true,
+                Stmt::Set { target: Expr::Ident(name), .. } => match module.symbols.iter_mut().find(|s| s.name == *name) {
+                    Some(symbol) => symbol.exported |= exported,
+                    None => module.symbols.push(Symbol::new(name, SymbolKind::Variable, line, Vec::new(), doc.clone(), exported)),
+                },
+                Stmt::Func { .. } | Stmt::Class { .. } => module.symbols.push(definition(stmt, line, doc.clone(), exported)),
+                _ => {}
+            }
+            doc.clear();
+        }
+        // Names exported on their own export what they name, wherever in
+        // the module that's defined
+        let itself = normalize(&std::path::absolute(source).unwrap_or_else(|_| source.to_path_buf()));
+        for (NameAs { name, alias }, line) in renames {
+            if let Some(symbol) = module.symbols.iter_mut().find(|s| s.name == name && alias.is_none()) {
+                symbol.exported = true;
+            } else if let Some(import) = module.imports.iter().find(|i| i.name == name).cloned() {
+                module.imports.push(Imported { name: alias.unwrap_or(name), line, exported: true, ..import });
+            } else {
+                let local = alias.unwrap_or_else(|| name.clone());
+                module.imports.push(Imported { name: local, original: name, source: Some(itself.clone()), whole_module: false, line, exported: true });
+            }
+        }
+        module
+    }
+
+    /// The symbol this module defines as `name`.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.symbols.iter().find(|s| s.name == name)
+    }
+
+    /// The names importers can take from this module.
+    pub fn exports(&self) -> Vec<String> {
+        let defined = self.symbols.iter().filter(|s| s.exported).map(|s| s.name.clone());
+        let reexported = self.imports.iter().filter(|i| i.exported).map(|i| i.name.clone());
+        let default = self.has_default.then(|| "default".to_string());
+        defined.chain(reexported).chain(default).collect()
+    }
+}
+
+impl Symbol {
+    fn new(name: &str, kind: SymbolKind, line: usize, params: Vec<String>, doc: Vec<String>, exported: bool) -> Symbol {
+        Symbol { name: name.to_string(), kind, line, params, doc, exported, members: Vec::new() }
+    }
+}
+
+/// The Harbor file `specifier` imports from `importer`, as an absolute
+/// path, if it names one.
+fn harbor_source(resolver: &Resolver, specifier: &str, importer: &Path) -> Option<PathBuf> {
+    match resolver.resolve(specifier, importer) {
+        Ok(Module::Source(path)) => Some(normalize(&std::path::absolute(&path).unwrap_or(path))),
+        _ => None,
+    }
+}
+
+/// The symbol for a `def` or `class`, with a class's methods as members.
+fn definition(stmt: &Stmt, line: usize, doc: Vec<String>, exported: bool) -> Symbol {
+    match stmt {
+        Stmt::Class { name, methods } => {
+            let mut members = Vec::new();
+            let mut method_line = line;
+            let mut method_doc = Vec::new();
+            for method in methods {
+                match method {
+                    Stmt::Line(n) => method_line = *n,
+                    Stmt::Comment(text) => method_doc.push(text.trim().to_string()),
+                    Stmt::Func { .. } => {
+                        members.push(definition(method, method_line, std::mem::take(&mut method_doc), false));
+                    }
+                    _ => method_doc.clear(),
+                }
+            }
+            let params = members.iter().find(|m| m.name == "init").map(|m| m.params.clone()).unwrap_or_default();
+            Symbol { members, ..Symbol::new(name, SymbolKind::Class, line, params, doc, exported) }
+        }
+        Stmt::Func { name, args, .. } => Symbol::new(name, SymbolKind::Function, line, args.clone(), doc, exported),
+        _ => unreachable!("only definitions have symbols"),
+    }
+}
+
+/// Where a name is defined.
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub source: PathBuf,
+    pub symbol: Symbol,
+}
+
+/// Modules indexed so far, keyed by absolute path.
+#[derive(Debug, Default)]
+pub struct Index {
+    modules: HashMap<PathBuf, ModuleSymbols>,
+}
+
+impl Index {
+    /// The symbols of the module at `source`, parsing it the first time
+    /// it's asked for. Errors are rendered messages naming the file.
+    pub fn module(&mut self, source: &Path, resolver: &Resolver) -> Result<&ModuleSymbols, String> {
+        let key = std::path::absolute(source).unwrap_or_else(|_| source.to_path_buf());
+        if !self.modules.contains_key(&key) {
+            let src = std::fs::read_to_string(source)
+                .map_err(|e| format!("Error: Could not read file '{}': {}", source.display(), e))?;
+            let render = |diag| format!("{}: {}", crate::resolver::tidy(source).display(), diag);
+            let tokens = Lexer::new(&src).tokenize().map_err(render)?;
+            let ast = Parser::with_trivia(tokens).parse().map_err(render)?;
+            self.modules.insert(key.clone(), ModuleSymbols::from_ast(&ast, source, resolver));
+        }
+        Ok(&self.modules[&key])
+    }
+
+    /// Where `name`, as used in the module at `source`, is defined. Imported
+    /// names are followed to the module that defines them, through any
+    /// re-exports, and `alias.name` looks in the module imported as `alias`.
+    pub fn definition(&mut self, source: &Path, name: &str, resolver: &Resolver) -> Result<Option<Definition>, String> {
+        let mut source = source.to_path_buf();
+        let mut name = name.to_string();
+        let mut seen = Vec::new();
+        loop {
+            // A cycle of re-exports never reaches a definition
+            if seen.contains(&(source.clone(), name.clone())) {
+                return Ok(None);
+            }
+            seen.push((source.clone(), name.clone()));
+            let module = self.module(&source, resolver)?;
+            let (head, member) = match name.split_once('.') {
+                Some((head, member)) => (head.to_string(), Some(member.to_string())),
+                None => (name.clone(), None),
+            };
+            if let Some(symbol) = module.get(&head) {
+                let symbol = match &member {
+                    Some(member) => match symbol.members.iter().find(|m| m.name == *member) {
+                        Some(method) => method.clone(),
+                        None => return Ok(None),
+                    },
+                    None => symbol.clone(),
+                };
+                return Ok(Some(Definition { source, symbol }));
+            }
+            let import = match module.imports.iter().find(|i| i.name == head) {
+                Some(import) => import,
+                None => return Ok(None),
+            };
+            let target = match &import.source {
+                Some(target) => target.clone(),
+                None => return Ok(None),
+            };
+            name = match (import.whole_module, member) {
+                (true, Some(member)) => member,
+                (true, None) => return Ok(None),
+                (false, Some(member)) => format!("{}.{}", import.original, member),
+                (false, None) => import.original.clone(),
+            };
+            source = target;
+        }
+    }
+}
@@ -0,0 +1,78 @@
+//! Warning codes, and the two ways to silence a warning: a
+//! `# harbor: ignore[W001]` comment on the line it's about (or alone on
+//! the line above), and a `[warnings]` section in `harbor.toml`, which can
+//! also turn on warnings that are off by default.
+
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Lexer;
+
+/// A kind of warning the compiler can give.
+pub struct Warning {
+    pub code: &'static str,
+    pub summary: &'static str,
+    /// Whether it's given unless `harbor.toml` turns it off.
+    pub default_on: bool,
+}
+
+pub const WARNINGS: &[Warning] = &[
+    Warning { code: "W001", summary: "a `tls` certificate or key file doesn't exist", default_on: true },
+    Warning { code: "W002", summary: "a condition relies on truthiness and could be a list or dict", default_on: false },
+    Warning { code: "W003", summary: "a name hides a module-level name, an enclosing function's or a builtin", default_on: false },
+    Warning { code: "W004", summary: "a server's port can't be a number, or is None when an environment variable isn't set", default_on: true },
+    Warning { code: "W005", summary: "a route's path is a letter or two away from another method's route, so one is likely misspelled", default_on: true },
+    Warning { code: "W006", summary: "a route can never run, because an earlier route for its method matches every path it does", default_on: true },
+    Warning { code: "W007", summary: "`+` adds a string and a number, which joins them as text instead of raising an error", default_on: true },
+    Warning { code: "W008", summary: "a `@sync` function or constructor calls an async one, getting a Promise instead of its result", default_on: true },
+];
+
+pub fn is_known(code: &str) -> bool {
+    WARNINGS.iter().any(|w| w.code == code)
+}
+
+/// Whether warnings with `code` are given, after the `overrides` from
+/// `harbor.toml`.
+pub fn is_enabled(code: &str, overrides: &[(String, bool)]) -> bool {
+    match overrides.iter().rev().find(|(c, _)| c == code) {
+        Some((_, on)) => *on,
+        None => WARNINGS.iter().find(|w| w.code == code).is_none_or(|w| w.default_on),
+    }
+}
+
+/// Drop the warnings in `source` that an ignore comment covers.
+pub fn apply_ignore_comments(source: &str, warnings: &mut Vec<Diagnostic>) {
+    if warnings.is_empty() {
+        return;
+    }
+    let ignores = ignore_comments(source);
+    warnings.retain(|w| !ignores.iter().any(|(line, codes)| {
+        *line == w.span.line && codes.as_ref().is_none_or(|codes| w.code.is_some_and(|code| codes.iter().any(|c| c == code)))
+    }));
+}
+
+/// The lines `# harbor: ignore[...]` comments cover, with the codes they
+/// name (`None` for a bare `# harbor: ignore`, which covers every code).
+fn ignore_comments(source: &str) -> Vec<(usize, Option<Vec<String>>)> {
+    // Source that doesn't lex has no warnings to ignore
+    let tokens = Lexer::new(source).tokenize().unwrap_or_default();
+    let lines: Vec<&str> = source.lines().collect();
+    let mut ignores = Vec::new();
+    for comment in tokens.iter().flat_map(|t| &t.comments) {
+        let Some(rest) = comment.text.trim().strip_prefix("harbor:") else { continue };
+        let Some(rest) = rest.trim().strip_prefix("ignore") else { continue };
+        let codes = match rest.trim() {
+            "" => None,
+            list => match list.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                Some(list) => Some(list.split(',').map(|c| c.trim().to_string()).collect()),
+                None => continue,
+            },
+        };
+        let line = comment.span.line;
+        // A comment on a line of its own covers the line below
+        let alone = lines.get(line - 1).is_some_and(|text| text.trim_start().starts_with('#'));
+        ignores.push((line, codes.clone()));
+        if alone {
+            ignores.push((line + 1, codes));
+        }
+    }
+    ignores
+}
@@ -0,0 +1,59 @@
+//! WebAssembly entry points for `wasm32-unknown-unknown`.
+//!
+//! The exports use a plain C ABI so the crate stays dependency-free. A host
+//! compiles a program like this:
+//!
+//! 1. `harbor_alloc(len)` and copy the UTF-8 source into the returned buffer.
+//! 2. `harbor_compile(ptr, len)` returns a pointer to the JSON result
+//!    (`{"js": ..., "diagnostics": [...]}`); `harbor_result_len()` gives its
+//!    length in bytes.
+//! 3. Release both buffers with `harbor_free(ptr, len)`.
+
+use std::cell::Cell;
+
+thread_local! {
+    static RESULT_LEN: Cell<usize> = const { Cell::new(0) };
+}
+
+#[no_mangle]
+pub extern "C" fn harbor_alloc(len: usize) -> *mut u8 {
+    let mut buf = Vec::<u8>::with_capacity(len);
+    let ptr = buf.as_mut_ptr();
+    std::mem::forget(buf);
+    ptr
+}
+
+/// # Safety
+///
+/// `ptr` and `len` must come from `harbor_alloc` or `harbor_compile`.
+#[no_mangle]
+pub unsafe extern "C" fn harbor_free(ptr: *mut u8, len: usize) {
+    drop(Vec::from_raw_parts(ptr, 0, len));
+}
+
+/// # Safety
+///
+/// `ptr` must point to `len` initialized bytes.
+#[no_mangle]
+pub unsafe extern "C" fn harbor_compile(ptr: *const u8, len: usize) -> *mut u8 {
+    let bytes = std::slice::from_raw_parts(ptr, len);
+    let json = match std::str::from_utf8(bytes) {
+        Ok(source) => crate::compile(source).to_json(),
+        Err(_) => crate::CompileOutput {
+            js: None,
+            diagnostics: vec![crate::diagnostics::Diagnostic::error("Source is not valid UTF-8", crate::lexer::Span { line: 1, col: 1 })],
+        }
+        .to_json(),
+    };
+    let mut out = json.into_bytes().into_boxed_slice();
+    RESULT_LEN.with(|l| l.set(out.len()));
+    let ptr = out.as_mut_ptr();
+    std::mem::forget(out);
+    ptr
+}
+
+/// Length of the buffer returned by the most recent `harbor_compile`.
+#[no_mangle]
+pub extern "C" fn harbor_result_len() -> usize {
+    RESULT_LEN.with(|l| l.get())
+}
@@ -0,0 +1,30 @@
+(async () => {
+  var name = "Harbor";
+  var count = 3;
+  console.log(`Hello, ${(await __tostr(name))}! x${(await __tostr(count))}`);
+  var total = 0;
+  for (const i of (await range(1, 6))) {
+    if (((i % 2) === 0)) {
+      continue;
+    }
+    var total = (total + i);
+  }
+  console.log("odd sum:", (await __tostr(total)));
+  var n = 0;
+  while ((n < 3)) {
+    var n = (n + 1);
+  }
+  console.log("n:", (await __tostr(n)));
+  if ((total > 10)) {
+    console.log("big");
+  }
+  else if ((total > 5)) {
+    console.log("medium");
+  }
+  else {
+    console.log("small");
+  }
+  var title = "<b>Tom & Jerry</b>";
+  var item = new __SafeHtml(`<li>${(await __htmlPart(title))}</li>`);
+  console.log((await __tostr(new __SafeHtml(`<ul>${(await __htmlPart(item))}</ul>`))));
+})().catch(__uncaught);
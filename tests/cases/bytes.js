@@ -0,0 +1,10 @@
+(async () => {
+  var data = (await bytes("héllo"));
+  console.log((await __tostr(data)), (await len(data)), (await len("héllo")));
+  console.log((await __tostr((await __method(data, "decode")))), (await __tostr((await __method(data, "hex")))), (await __tostr(__getitem(data, 0))));
+  console.log((await __tostr((await bytes([0, 255, 39, 10, 65])))), (await __tostr((await bytes(2)))), (await __tostr((await bytes.fromhex("de ad")))));
+  console.log((await __tostr((await __method((await base64.decode_bytes((await base64.encode(data)))), "decode")))));
+  console.log((await __tostr((await __method((await bytes(", ")), "join", ["a", (await bytes("b"))])))));
+  console.log((await __tostr((await __method(data, "startswith", "hé")))), (await __tostr((await __method(data, "find", "l")))));
+  console.log((await __tostr((await __method((await bytes("café", "latin-1")), "hex")))));
+})().catch(__uncaught);
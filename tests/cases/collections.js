@@ -0,0 +1,42 @@
+(async () => {
+  var items = [3, 1, 2];
+  (await __method(items, "append", 5));
+  (await __method(items, "insert", 0, 4));
+  console.log((await __tostr(items)));
+  console.log((await __tostr((await sorted(items)))));
+  console.log((await __tostr((await max(["apple", "fig", "banana"], __kw({key: len}))))));
+  var scores = {"ada": 3, "bob": 5};
+  (await __method(scores, "update", {"cy": 1}));
+  console.log((await __tostr((await __method(scores, "get", "zed", 0)))));
+  console.log((await __tostr((await sorted(scores)))));
+  console.log((await len(scores)));
+  var word = "hello world";
+  console.log((await __tostr((await __method(word, "upper")))));
+  console.log((await __tostr((await __method(word, "split", " ")))));
+  console.log((await __tostr((await __method(word, "index", "world")))));
+  var original = {"tags": ["a"], "count": 1};
+  var shallow = (await copy(original));
+  var deep = (await deepcopy(original));
+  (await __method(__getitem(shallow, "tags"), "append", "b"));
+  (await __method(__getitem(deep, "tags"), "append", "c"));
+  __setitem(shallow, "count", 2);
+  console.log("copies:", (await __tostr(__getitem(original, "tags"))), (await __tostr(__getitem(original, "count"))), (await __tostr(__getitem(deep, "tags"))));
+  var by_id = new __Dict([[1, "ann"], [2, "bo"]]);
+  __setitem(by_id, 3, "cy");
+  console.log("by id:", (await __tostr(__getitem(by_id, 1))), (await len(by_id)), (await __tostr((await __contains(by_id, 2)))), (await __tostr((await __contains(by_id, "2")))));
+  console.log("keys:", (await __tostr((await by_id.keys()))));
+  console.log("json:", (await __tostr((await json.dumps(by_id)))));
+  var names = (await dict(new __Dict([[1, "ann"]])));
+  console.log("dict copies:", (await __tostr((await __method((await copy(names)), "get", 2, 0)))), (await __tostr((await (await copy(names)).keys()))), (await __tostr((await __method((await deepcopy(names)), "get", 1)))));
+  var quoted = {"say \"hi\"": 1};
+  console.log("quoted key:", (await __tostr(__getitem(quoted, "say \"hi\""))));
+  var orders = [{"user": "ann", "total": 10}, {"user": "bo", "total": 5}, {"user": "ann", "total": 7}];
+  async function customer(order) {
+    return __getitem(order, "user");
+  }
+  console.log("unique:", (await __tostr((await unique([3, 1, 3, 2, 1])))));
+  console.log("count_by:", (await __tostr((await count_by(orders, customer)))), (await __tostr((await count_by(["a", "b", "a"])))));
+  console.log("group_by:", (await len(__getitem((await group_by(orders, __kw({key: customer}))), "ann"))));
+  console.log("flatten:", (await __tostr((await flatten([[1, 2], [3], 4])))));
+  console.log("chunk:", (await __tostr((await chunk((await range(5)), 2)))));
+})().catch(__uncaught);
@@ -0,0 +1,75 @@
+(async () => {
+  async function check(x) {
+    if (!((x > 0))) throw new AssertionError(String("x must be positive"));
+    return x;
+  }
+  try {
+    (await check((-1)));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    if (__error instanceof AssertionError) {
+      let e = __error;
+      console.log("assert:", (await __tostr(e.message)));
+    } else {
+      throw __error;
+    }
+  }
+  try {
+    (await json.loads("{bad"));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    if (__error instanceof KeyError) {
+      console.log("wrong clause");
+    } else if (__error instanceof ValueError || __error instanceof TypeError) {
+      let e = __error;
+      console.log("value error:", (await __tostr(e.kind)));
+    } else {
+      throw __error;
+    }
+  }
+  try {
+    var items = [1];
+    console.log((await __tostr((await __method(items, "index", 9)))));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    {
+      let e = __error;
+      console.log("caught:", (await __tostr(e.name)));
+    }
+  }
+  var attempts = 0;
+  async function flaky() {
+    attempts += 1;
+    if ((attempts < 3)) {
+      throw new ValueError("not yet");
+    }
+    return attempts;
+  }
+  console.log("retried:", (await __tostr((await retry(flaky, __kw({backoff: 0.01}))))));
+  async function stuck() {
+    (await sleep(1));
+  }
+  try {
+    (await with_timeout(stuck, 0.05));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    if (__error instanceof TimeoutError) {
+      let e = __error;
+      console.log("timeout:", (await __tostr(e.message)));
+    } else {
+      throw __error;
+    }
+  }
+  (await assert_eq({"tags": ["a"], "n": 1}, {"n": 1, "tags": ["a"]}));
+  try {
+    (await assert_eq({"user": {"name": "bob", "tags": ["a"]}, "extra": null}, {"user": {"name": "ada", "tags": ["a", "b"]}}));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    if (__error instanceof AssertionError) {
+      let e = __error;
+      console.log((await __tostr(e.message)));
+    } else {
+      throw __error;
+    }
+  }
+})().catch(__uncaught);
@@ -0,0 +1,23 @@
+(async () => {
+  module.exports.greet = greet;
+  module.exports.yell = shout;
+  const { helper, other: another } = require("./utils.js");
+  module.exports.helper = helper;
+  module.exports.another = another;
+  const { parse: parse_date } = require("./dates.js");
+  const events = require("./events");
+  module.exports.events = events;
+  var VERSION = "1.0";
+  module.exports.VERSION = VERSION;
+  async function greet(name) {
+    return `Hello, ${(await __tostr(name))}`;
+  }
+  async function shout(text) {
+    return (await __method(text, "upper"));
+  }
+  var total = 3;
+  module.exports.sum_total = total;
+  module.exports.parse_date = parse_date;
+  Object.defineProperty(module.exports, "__esModule", { value: true });
+  module.exports.default = greet;
+})().catch(__uncaught);
@@ -0,0 +1,60 @@
+(async () => {
+  const utils = require("utils");
+  var x = (1 + (2 * 3));
+  var y = ((1 + 2) * 3);
+  var z = Math.pow((-2), 2);
+  var s = "say \"hi\"";
+  var ok = ((!((x === 1) || (y !== 2))) && true);
+  console.log(`${(await __tostr(x))} {literal} ${(await __tostr(y))}`);
+  async function area(w, h) {
+    return (w * h);
+  }
+  class Box {
+    constructor(w) {
+      this.w = w;
+    }
+  }
+  const __routes = {
+    GET: [
+      [/^\/area\/(?<w>-?\d+)$/, async (req, __res, __match) => {
+        req.params = {};
+        req.params["w"] = Number(__match.groups["w"]);
+        const __val = {"area": (await area(req.params.w, 2))};
+        __send(__res, __val);
+        return;
+      }],
+    ],
+    POST: [
+      ["/api/echo", async (req, __res) => {
+        req.body = await parseBody(req);
+        if (!__expectBody(req, __res, {"tags": ["str"]})) return;
+        __res.statusCode = 201;
+        const __val = req.body;
+        __send(__res, __val);
+        return;
+      }],
+    ],
+  };
+
+  const server = http.createServer((req, __res) => __requests.run({ req, res: __res }, async () => {
+    try {
+      __requestInfo(req, false);
+      __logRequest(req, __res, null);
+      if (await __dispatch(__routes, req, __res)) return;
+      __res.statusCode = 404;
+      __res.end("Not Found");
+    } catch (__err) {
+      __serverError(req, __res, __err);
+    }
+  }));
+
+  const __port = 8080;
+  __listen(server, __port, () => {
+    console.log(`Harbor server running on http://127.0.0.1:${__port}`); 
+  });
+  __gracefulShutdown(server, async () => {
+  });
+  __every(5 * 1000, async () => {
+    console.log("tick");
+  });
+})().catch(__uncaught);
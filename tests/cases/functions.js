@@ -0,0 +1,62 @@
+(async () => {
+  async function greet(greeting, name) {
+    return `${(await __tostr(greeting))}, ${(await __tostr(name))}`;
+  }
+  console.log((await __tostr((await greet("Hi", "Ada")))));
+  class Counter {
+    constructor(start) {
+      this.value = start;
+    }
+    async bump() {
+      this.value = (this.value + 1);
+      return this.value;
+    }
+  }
+  var c = new Counter(10);
+  (await c.bump());
+  console.log("counter:", (await __tostr((await c.bump()))));
+  async function fib(n) {
+    if ((n < 2)) {
+      return n;
+    }
+    return ((await fib((n - 1))) + (await fib((n - 2))));
+  }
+  console.log("fib:", (await __tostr((await fib(15)))));
+  async function double(n) {
+    return (n * 2);
+  }
+  var task = __spawn(async () => (await double(21)));
+  console.log("awaited:", (await __tostr((await task))));
+  class Money {
+    constructor(cents) {
+      this.cents = cents;
+    }
+    async __str__() {
+      return `$${(await __tostr((this.cents / 100)))}`;
+    }
+  }
+  var price = new Money(250);
+  console.log((await __tostr(price)));
+  console.log(`costs ${(await __tostr(price))}`);
+  class Countdown {
+    constructor(start) {
+      this.current = start;
+    }
+    async __iter__() {
+      return this;
+    }
+    async __next__() {
+      if ((this.current <= 0)) {
+        throw new StopIteration();
+      }
+      this.current -= 1;
+      return (this.current + 1);
+    }
+  }
+  for (const n of (await __iter(new Countdown(3)))) {
+    console.log("countdown:", (await __tostr(n)));
+  }
+  for (const key of (await __iter({"a": 1, "b": 2}))) {
+    console.log("key:", (await __tostr(key)));
+  }
+})().catch(__uncaught);
@@ -0,0 +1,7 @@
+(async () => {
+  var data = {"name": "harbor", "tags": ["fast", "small"], "version": 2};
+  console.log((await __tostr((await json.dumps(data)))));
+  console.log((await __tostr((await json.dumps(data, __kw({sort_keys: true}))))));
+  var back = (await json.loads((await json.dumps(data))));
+  console.log((await __tostr(__getitem(__getitem(back, "tags"), 1))));
+})().catch(__uncaught);
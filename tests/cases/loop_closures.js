@@ -0,0 +1,43 @@
+(async () => {
+  var getters = [];
+  var n = 0;
+  while ((n < 3)) {
+    let x = (n * 10);
+    async function get_x() {
+      return x;
+    }
+    (await __method(getters, "append", get_x));
+    n += 1;
+  }
+  for (const i of (await range(2))) {
+    for (const j of (await range(2))) {
+      let cell = `${(await __tostr(i))}${(await __tostr(j))}`;
+      async function get_cell() {
+        return cell;
+      }
+      (await __method(getters, "append", get_cell));
+    }
+  }
+  for (const getter of (await __iter(getters))) {
+    console.log((await __tostr((await getter()))));
+  }
+  var greetings = [];
+  for (const name of ["a", "b"]) {
+    let greeting = ("hi " + name);
+    __spawn(async () => {
+      (await sleep(0.01));
+      (await __method(greetings, "append", greeting));
+    });
+  }
+  (await sleep(0.05));
+  console.log((await __tostr(greetings)));
+  var lasts = [];
+  for (const i of (await range(3))) {
+    var last = i;
+    async function get_last() {
+      return last;
+    }
+    (await __method(lasts, "append", get_last));
+  }
+  console.log("after the loop:", (await __tostr(last)), (await __tostr((await __getitem(lasts, 0)()))));
+})().catch(__uncaught);
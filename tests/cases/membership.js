@@ -0,0 +1,58 @@
+(async () => {
+  var d = {"a": 1};
+  console.log((await __tostr((await __contains(d, "a")))), (await __tostr((await __contains(d, "toString")))), (await __tostr(!(await __contains(d, "b")))));
+  console.log((await __tostr((await __contains("hello", "ell")))), (await __tostr((await __contains([1, 2], 2)))), (await __tostr((await __contains([1, 2], "x")))));
+  var m = new __Dict([[1, "one"]]);
+  console.log((await __tostr((await __contains(m, 1)))), (await __tostr((await __contains(m, "1")))));
+  var s = new Set([1, 2]);
+  console.log((await __tostr((await __contains(s, 2)))));
+  for (const ch of (await __iter("hi"))) {
+    console.log((await __tostr(ch)));
+  }
+  class Bag {
+    constructor(items) {
+      this.items = items;
+    }
+    async __iter__() {
+      return this.items;
+    }
+  }
+  class Evens {
+    async __contains__(n) {
+      return ((n % 2) === 0);
+    }
+  }
+  var b = new Bag([3, 4]);
+  console.log((await __tostr((await __contains(b, 3)))), (await __tostr((await __contains(b, 5)))), (await __tostr(!(await __contains(b, 4)))));
+  console.log((await __tostr((await __contains(new Evens(), 6)))), (await __tostr((await __contains(new Evens(), 7)))));
+  for (const x of (await __iter(b))) {
+    console.log((await __tostr(x)));
+  }
+  try {
+    console.log((await __tostr((await __contains("abc", 1)))));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    {
+      let e = __error;
+      console.log((await __tostr(e.message)));
+    }
+  }
+  try {
+    console.log((await __tostr((await __contains(null, 1)))));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    {
+      let e = __error;
+      console.log((await __tostr(e.message)));
+    }
+  }
+  try {
+    console.log((await __tostr((await __contains(5, 1)))));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    {
+      let e = __error;
+      console.log((await __tostr(e.message)));
+    }
+  }
+})().catch(__uncaught);
@@ -0,0 +1,27 @@
+(async () => {
+  var config = {"servers": [{"name": "api", "ports": [80, 443]}, {"name": "admin", "ports": [8080]}], "debug": false};
+  async function port_count(cfg) {
+    var n = 0;
+    for (const entry of (await __iter(__getitem(cfg, "servers")))) {
+      if (true) {
+        var n = (n + (await len(__getitem(entry, "ports"))));
+      }
+    }
+    return n;
+  }
+  console.log((await __tostr((await port_count(config)))));
+  async function names(cfg) {
+    var found = [];
+    for (const entry of (await __iter(__getitem(cfg, "servers")))) {
+      (await __method(found, "append", __getitem(entry, "name")));
+    }
+    return found;
+  }
+  console.log((await __tostr((await names(config)))));
+  var scaled = ((await len([1, 2])) * 10);
+  console.log((await __tostr(scaled)));
+  for (const port of [80, 443]) {
+    console.log((await __tostr(port)));
+  }
+  console.log((await __tostr((await max(1, 2)))), (await __tostr(__getitem(__getitem(__getitem(config, "servers"), 1), "ports"))));
+})().catch(__uncaught);
@@ -0,0 +1,29 @@
+(async () => {
+  console.log(String((2n ** 64n)));
+  console.log(String(((2n ** 64n) + 1n)));
+  console.log(String((18446744073709551617n - 1n)));
+  console.log(String(((2n ** 64n) + BigInt((await len([1, 2, 3]))))));
+  console.log((Number((2n ** 64n)) / 4));
+  console.log(String(__bigFloorDiv((-(2n ** 64n)), 3n)));
+  console.log(`${String((2n ** 70n))}`);
+  console.log((await int("ff", 16)));
+  console.log((await int(" 1_000 ")));
+  console.log((await int((-3.9))));
+  console.log(`${(await int("123456789012345678901234567890"))}`);
+  try {
+    (await int("1.5"));
+  } catch (__caught) {
+    const __error = __toHarborError(__caught);
+    if (__error instanceof ValueError) {
+      let e = __error;
+      console.log((await __tostr(e.message)));
+    } else {
+      throw __error;
+    }
+  }
+  var population = 7_900_000_000;
+  var mask = 0xFF_FF;
+  console.log((await __tostr(population)), (await __tostr(mask)), 0o755, 0b1010_1010);
+  console.log(String(0xFFFF_FFFF_FFFF_FFFF_FFn), String((1_000_000n * (2n ** 64n))));
+  console.log(1_000.5, 7);
+})().catch(__uncaught);
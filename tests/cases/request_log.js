@@ -0,0 +1,41 @@
+(async () => {
+  const __routes = {
+    GET: [
+      [/^\/users\/(?<id>[^\/]+)$/, async (req, __res, __match) => {
+        req.params = {};
+        req.params["id"] = __match.groups["id"];
+        const __val = {"id": req.params.id};
+        __send(__res, __val);
+        return;
+      }],
+      ["/say\"hi\"", async (req, __res) => {
+        const __val = "quoted";
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+    ],
+  };
+
+  const server = http.createServer((req, __res) => __requests.run({ req, res: __res }, async () => {
+    try {
+      __requestInfo(req, false);
+      __logRequest(req, __res, "{method} \"{path}\" {status}");
+      if (await __dispatch(__routes, req, __res)) return;
+      __res.statusCode = 404;
+      __res.end("Not Found");
+    } catch (__err) {
+      __serverError(req, __res, __err);
+    }
+  }));
+
+  const __port = 8080;
+  __listen(server, __port, () => {
+    console.log(`Harbor server running on http://127.0.0.1:${__port}`); 
+  });
+  __gracefulShutdown(server, async () => {
+  });
+  console.log((await __tostr((await __inject("GET", "/users/7")).statusCode)));
+  console.log((await __tostr((await __inject("GET", "/say\"hi\"")).text)));
+  console.log((await __tostr((await __inject("GET", "/nowhere")).statusCode)));
+})().catch(__uncaught);
@@ -0,0 +1,294 @@
+(async () => {
+  async function require_login() {
+    if ((!req.headers['authorization'])) {
+      __res.statusCode = 401;
+      const __val = {"error": "login required"};
+      __send(__res, __val);
+      throw __responded;
+    }
+    return req.query.name;
+  }
+  async function list_orders(req) {
+    return [{"id": 1, "user": req.query.user}];
+  }
+  const __limit_17 = __rateLimiter(10, 60000);
+  const __cache_20 = __responseCache(60, false);
+  const __cache_21 = __responseCache(60, true);
+  const __limit_22 = __rateLimiter(5, 60000);
+  const __cache_22 = __responseCache(60, false);
+  const __limit_23 = __rateLimiter(5, 60000);
+  const __cache_23 = __responseCache(60, false);
+  const __metrics = __createMetrics();
+  const __routes = {
+    GET: [
+      ["/health", async (req, __res) => {
+        const __val = {"ok": true};
+        __send(__res, __val);
+        return;
+      }],
+      [/^\/users\/(?<id>[^\/]+)$/, async (req, __res, __match) => {
+        req.params = {};
+        req.params["id"] = __match.groups["id"];
+        const __val = {"id": req.params.id};
+        __send(__res, __val);
+        return;
+      }],
+      ["/teapot", async (req, __res) => {
+        var code = 418;
+        if (req.query.polite) {
+          __res.statusCode = 200;
+          const __val = "fine";
+          __send(__res, __val);
+          return;
+        }
+        __res.statusCode = code;
+        const __val = {"short": true};
+        __send(__res, __val);
+        return;
+      }],
+      ["/page", async (req, __res) => {
+        __res.setHeader("Cache-Control", "no-store");
+        __res.statusCode = 200;
+        const __val = "<h1>Users</h1>";
+        __res.setHeader('Content-Type', 'text/html; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      ["/version", async (req, __res) => {
+        const __val = 2;
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      ["/created", async (req, __res) => {
+        __res.statusCode = 201;
+        const __val = [req.query.item];
+        __send(__res, __val);
+        return;
+      }],
+      ["/pair", async (req, __res) => {
+        __res.statusCode = 201;
+        const __val = [1, 2];
+        __send(__res, __val);
+        return;
+      }],
+      ["/grouped", async (req, __res) => {
+        __res.statusCode = 201;
+        const __val = 1;
+        __send(__res, __val);
+        return;
+      }],
+      ["/listed", async (req, __res) => {
+        var code = 202;
+        __res.statusCode = code;
+        const __val = [code];
+        __send(__res, __val);
+        return;
+      }],
+      ["/negative", async (req, __res) => {
+        __res.statusCode = 200;
+        const __val = (-1);
+        __send(__res, __val);
+        return;
+      }],
+      ["/items", async (req, __res) => {
+        const __val = [req.query.item];
+        __res.setHeader('Content-Type', 'application/json');
+        __res.end(JSON.stringify(__val));
+        return;
+      }],
+      ["/say\"hi\"", async (req, __res) => {
+        const __val = "quoted";
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      ["/stock", async (req, __res) => {
+        if (req.query.empty) {
+          return {"status": 503, "body": {"error": "try later"}};
+        }
+        return ["apples"];
+      }],
+      ["/admin", async (req, __res) => {
+        var name = (await require_login());
+        const __val = ("hello " + name);
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      ["/orders", async (req, __res) => {
+        return await list_orders(req);
+      }],
+      ["/boom", async (req, __res) => {
+        throw new ValueError("broken");
+      }],
+      ["/private", async (req, __res) => {
+        if (!__requireAuth(req, __res, "basic", {"admin": "pw"})) return;
+        console.log("private route hit");
+        const __val = req.user;
+        __send(__res, __val);
+        return;
+      }],
+      ["/cached", async (req, __res) => {
+        if (__cache_20.hit(req, __res)) return;
+        __cache_20.record(req, __res);
+        const __val = {"cached": true};
+        __send(__res, __val);
+        return;
+      }],
+      ["/me", async (req, __res) => {
+        if (!__requireAuth(req, __res, "basic", {"admin": "pw", "bob": "pw2"})) return;
+        if (__cache_21.hit(req, __res)) return;
+        __cache_21.record(req, __res);
+        const __val = req.user;
+        __send(__res, __val);
+        return;
+      }],
+      ["/a-b", async (req, __res) => {
+        if (!__limit_22(req, __res)) return;
+        if (__cache_22.hit(req, __res)) return;
+        __cache_22.record(req, __res);
+        const __val = "dash";
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      ["/a_b", async (req, __res) => {
+        if (!__limit_23(req, __res)) return;
+        if (__cache_23.hit(req, __res)) return;
+        __cache_23.record(req, __res);
+        const __val = "underscore";
+        __res.setHeader('Content-Type', 'text/plain; charset=utf-8');
+        __res.end(String(__val));
+        return;
+      }],
+      [/^\/api\/users\/(?<id>-?\d+)$/, async (req, __res, __match) => {
+        req.params = {};
+        req.params["id"] = Number(__match.groups["id"]);
+        const __val = {"next": (req.params.id + 1)};
+        __send(__res, __val);
+        return;
+      }],
+    ],
+    POST: [
+      ["/users", async (req, __res) => {
+        req.body = await parseBody(req);
+        if (!__expectBody(req, __res, {"name": "str", "nick\"name": "str", "tags": ["str"]})) return;
+        __res.statusCode = 201;
+        const __val = req.body;
+        __send(__res, __val);
+        return;
+      }],
+      ["/orders", async (req, __res) => {
+        if (!__limit_17(req, __res)) return;
+        req.body = await parseBody(req);
+        return await list_orders(req);
+      }],
+    ],
+    DELETE: [
+      [/^\/users\/(?<id>[^\/]+)$/, async (req, __res, __match) => {
+        req.params = {};
+        req.params["id"] = __match.groups["id"];
+        req.body = await parseBody(req);
+        __res.statusCode = 204;
+        const __val = "";
+        __send(__res, __val);
+        return;
+      }],
+    ],
+  };
+
+  const server = http.createServer((req, __res) => __requests.run({ req, res: __res }, async () => {
+    try {
+      try {
+        __requestInfo(req, false);
+        __res.setHeader("X-Content-Type-Options", "nosniff");
+        __res.setHeader("X-Request-Path", req.path);
+        if (req.method === "GET" && req.path === "/ready") {
+          __res.writeHead(200, { "Content-Type": "application/json" });
+          __res.end(JSON.stringify({ status: "ok", uptime: process.uptime() }));
+          return;
+        }
+        if (req.method === "GET" && req.path === "/metrics\"") {
+          __res.writeHead(200, { "Content-Type": "text/plain; version=0.0.4" });
+          __res.end(__metrics.render());
+          return;
+        }
+        __metrics.track(req, __res);
+        __logRequest(req, __res, null);
+        if (await __dispatch(__routes, req, __res)) return;
+        __res.statusCode = 404;
+        const __val = {"error": "no such route"};
+        __send(__res, __val);
+        return;
+        __res.statusCode = 404;
+        __res.end("Not Found");
+      } catch (__caught) {
+        if (__caught === __responded) return;
+        const err = __toHarborError(__caught);
+        try {
+          __res.statusCode = 500;
+          const __val = {"error": err.message};
+          __send(__res, __val);
+          return;
+        } catch (__err) {
+          __serverError(req, __res, __err);
+          return;
+        }
+        __serverError(req, __res, err);
+      }
+    } finally {
+      try {
+        console.log((await __tostr(req.path)));
+      } catch (__err) {
+        __serverError(req, __res, __err);
+      }
+    }
+  }));
+
+  const __port = 8080;
+  __listen(server, __port, () => {
+    console.log(`Harbor server running on http://127.0.0.1:${__port}`); 
+  });
+  __gracefulShutdown(server, async () => {
+  });
+  async function show(res) {
+    console.log((await __tostr(res.statusCode)), (await __tostr(res.text)));
+  }
+  (await show((await __inject("GET", "/users/7"))));
+  (await show((await __inject("POST", "/users", {"name": "ada", "nick\"name": "a", "tags": ["x"]}))));
+  (await show((await __inject("POST", "/users", {"name": 1}))));
+  (await show((await __inject("GET", "/teapot"))));
+  (await show((await __inject("GET", "/teapot?polite=1"))));
+  var page = (await __inject("GET", "/page"));
+  console.log((await __tostr(page.statusCode)), (await __tostr(__getitem(page.headers, "content-type"))), (await __tostr(__getitem(page.headers, "cache-control"))), (await __tostr(__getitem(page.headers, "x-content-type-options"))));
+  (await show((await __inject("GET", "/version"))));
+  (await show((await __inject("GET", "/created?item=pen"))));
+  (await show((await __inject("GET", "/pair"))));
+  (await show((await __inject("GET", "/grouped"))));
+  (await show((await __inject("GET", "/listed"))));
+  (await show((await __inject("GET", "/negative"))));
+  (await show((await __inject("GET", "/items?item=pen"))));
+  (await show((await __inject("GET", "/say\"hi\""))));
+  (await show((await __inject("DELETE", "/users/3"))));
+  (await show((await __inject("GET", "/stock"))));
+  (await show((await __inject("GET", "/stock?empty=1"))));
+  (await show((await __inject("GET", "/admin"))));
+  (await show((await __inject("GET", "/admin?name=ada", __kw({headers: {"Authorization": "token"}})))));
+  (await show((await __inject("GET", "/orders?user=ann"))));
+  (await show((await __inject("GET", "/private"))));
+  (await show((await __inject("GET", "/private", __kw({headers: {"Authorization": "Basic YWRtaW46cHc="}})))));
+  var cached = (await __inject("GET", "/cached"));
+  console.log((await __tostr(cached.statusCode)), (await __tostr(cached.text)), (await __tostr(__getitem(cached.headers, "cache-control"))));
+  for (const auth of ["Basic YWRtaW46cHc=", "Basic Ym9iOnB3Mg==", "Basic YWRtaW46cHc="]) {
+    var me = (await __inject("GET", "/me", __kw({headers: {"Authorization": auth}})));
+    console.log((await __tostr(me.statusCode)), (await __tostr(me.text)), (await __tostr(__getitem(me.headers, "cache-control"))));
+  }
+  (await show((await __inject("GET", "/a-b"))));
+  (await show((await __inject("GET", "/a_b"))));
+  (await show((await __inject("GET", "/api/users/41"))));
+  (await show((await __inject("GET", "/api/users/abc"))));
+  (await show((await __inject("GET", "/boom"))));
+  (await show((await __inject("GET", "/nowhere"))));
+  console.log((await __tostr((await __inject("GET", "/ready")).statusCode)), (await __tostr((await __inject("GET", "/metrics\"")).statusCode)));
+})().catch(__uncaught);
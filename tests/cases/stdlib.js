@@ -0,0 +1,26 @@
+(async () => {
+  const { capitalize, pad_left, center, truncate, is_blank, words, snake_case, kebab_case, camel_case, slugify } = require("std/strings");
+  const { query_string, parse_query, url_join, is_success, is_error, status_text, bearer } = require("std/http");
+  const { partition, zip_lists, find, pick, omit } = require("std/collections");
+  async function is_even(n) {
+    return ((n % 2) === 0);
+  }
+  async function is_big(n) {
+    return (n > 10);
+  }
+  console.log((await __tostr((await capitalize("hARBOR")))), (await __tostr((await pad_left("7", 3, "0")))), (await __tostr((("[" + (await center("hi", 6, "*"))) + "]"))));
+  console.log((await __tostr((await truncate("a long sentence", 8)))), (await __tostr((await is_blank("  ")))), (await __tostr((await is_blank(" x ")))));
+  console.log((await __tostr((await words("userId_count-total")))));
+  console.log((await __tostr((await snake_case("userIdCount")))), (await __tostr((await kebab_case("User Id")))), (await __tostr((await camel_case("user_id_count")))));
+  console.log((await __tostr((await slugify("Hello, World!")))));
+  console.log((await __tostr((await query_string({"q": "a b", "page": 2, "tag": ["x", "y"]})))));
+  console.log((await __tostr((await parse_query("?q=a%20b&tag=x&tag=y")))));
+  console.log((await __tostr((await url_join("https://example.com/", "/api")))), (await __tostr((await url_join("https://example.com", "api")))));
+  console.log((await __tostr((await is_success(204)))), (await __tostr((await is_error(204)))), (await __tostr((await is_error(503)))), (await __tostr((await status_text(404)))));
+  console.log((await __tostr((await bearer("abc")))));
+  console.log((await __tostr((await partition([1, 2, 3, 4, 5], is_even)))));
+  console.log((await __tostr((await zip_lists([1, 2, 3], ["a", "b"])))));
+  console.log((await __tostr((await find([3, 12, 40], is_big)))), (await __tostr((await find([1, 2], is_big)))));
+  var user = {"id": 1, "name": "Ada", "password": "x"};
+  console.log((await __tostr((await pick(user, ["id", "name", "email"])))), (await __tostr((await omit(user, ["password"])))));
+})().catch(__uncaught);
@@ -0,0 +1,105 @@
+(async () => {
+  function clamp(n, low, high) {
+    if ((n < low)) {
+      return low;
+    }
+    if ((n > high)) {
+      return high;
+    }
+    return n;
+  }
+  class Point {
+    constructor(x, y) {
+      this.x = clamp(x, 0, 10);
+      this.y = clamp(y, 0, 10);
+      this.axes = [];
+      for (const axis of __iterSync({"x": 1, "y": 2})) {
+        __method(this.axes, "append", axis);
+      }
+    }
+    area() {
+      return (this.x * this.y);
+    }
+    async describe() {
+      return `${(await __tostr(this.x))} by ${(await __tostr(this.y))}, area ${(await __tostr(this.area()))}`;
+    }
+  }
+  var p = new Point(12, 3);
+  console.log((await __tostr((await p.describe()))), (await __tostr(p.axes)));
+  var total = 0;
+  for (const i of (await range(6))) {
+    total += clamp(i, 1, 4);
+  }
+  console.log("total:", (await __tostr(total)));
+  function pending() {
+    __spawn(async () => {
+      (await (await sleep(0)));
+      console.log("spawned");
+    });
+    return "queued";
+  }
+  console.log((await __tostr(pending())));
+  class Bag {
+    constructor(items) {
+      this.items = items;
+    }
+    async __iter__() {
+      return this.items;
+    }
+  }
+  class Shelf {
+    constructor(items) {
+      this.items = items;
+    }
+    __iter__() {
+      return this.items;
+    }
+  }
+  class Evens {
+    async __contains__(n) {
+      return ((n % 2) === 0);
+    }
+  }
+  function has(box, item) {
+    try {
+      if (__containsSync(box, item)) {
+        return "yes";
+      }
+      return "no";
+    } catch (__caught) {
+      const __error = __toHarborError(__caught);
+      {
+        let e = __error;
+        return e.message;
+      }
+    }
+  }
+  console.log("bag:", (await __tostr(has(new Bag([1, 2]), 5))));
+  console.log("shelf:", (await __tostr(has(new Shelf([1, 2]), 2))), (await __tostr(has(new Shelf([1, 2]), 5))));
+  console.log("evens:", (await __tostr(has(new Evens(), 3))));
+  console.log("list:", (await __tostr(has([1, 2], 5))));
+  async function slow() {
+    return "slow";
+  }
+  async function run(clamp) {
+    return ((await clamp()) + "!");
+  }
+  async function local() {
+    var clamp = slow;
+    return ((await clamp()) + "?");
+  }
+  console.log((await __tostr((await run(slow)))), (await __tostr((await local()))), (await __tostr(clamp((-1), 0, 1))));
+  class Tag {
+    constructor(name) {
+      this.name = name;
+    }
+    __str__() {
+      return (("<" + this.name) + ">");
+    }
+  }
+  function show(xs) {
+    console.log(__tostrSync(xs), __tostrSync({"k": null}), __tostrSync(new Tag("b")));
+    return `${__tostrSync(xs)} ${__tostrSync(new Tag("i"))}`;
+  }
+  console.log((await __tostr(show([1, true, {"k": "v"}]))));
+})().catch(__uncaught);
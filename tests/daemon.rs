@@ -0,0 +1,206 @@
+//! `harbor daemon` must give an editor the warnings `harbor build` would.
+//!
+//! The daemon parses documents in cached chunks, so each check here runs
+//! twice: once fresh, and once after lines are added above the code, which
+//! reuses every chunk at a new place in the file.
+
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::Path;
+use std::process::{Child, Command};
+use std::thread;
+use std::time::Duration;
+
+const SOURCE: &str = r#"# Warnings from a daemon check should point where a build's do
+total = "count: " + 1
+
+server "abc":
+    get "/users":
+        respond "all"
+
+    post "/user":
+        respond "one"
+
+    get "/:page":
+        respond "page"
+
+    get "/about":
+        respond "about"
+
+@sync
+def load(path):
+    return fs.read(path)
+"#;
+
+/// A daemon on a free port, stopped when dropped.
+struct Daemon {
+    child: Child,
+    stream: TcpStream,
+    reader: BufReader<TcpStream>,
+}
+
+impl Daemon {
+    fn start() -> Daemon {
+        Daemon::start_with(&[])
+    }
+
+    /// A daemon started with `options`, like `--define`, before its own.
+    fn start_with(options: &[&str]) -> Daemon {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_harbor"))
+            .args(options)
+            .args(["daemon", "--port", &port.to_string()])
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .expect("could not start the daemon");
+        let stream = (0..100).find_map(|_| {
+            let stream = TcpStream::connect(("127.0.0.1", port)).ok();
+            if stream.is_none() {
+                thread::sleep(Duration::from_millis(50));
+            }
+            stream
+        });
+        let Some(stream) = stream else {
+            let _ = child.kill();
+            let _ = child.wait();
+            panic!("the daemon didn't start listening on port {}", port);
+        };
+        let reader = BufReader::new(stream.try_clone().unwrap());
+        Daemon { child, stream, reader }
+    }
+
+    fn request(&mut self, request: &str) -> String {
+        self.stream.write_all(request.as_bytes()).unwrap();
+        let mut reply = String::new();
+        self.reader.read_line(&mut reply).unwrap();
+        reply
+    }
+
+    /// A `check` of `source`'s text, as lines like `harbor build` prints.
+    fn check(&mut self, path: &Path, source: &str) -> Vec<String> {
+        let reply = self.request(&format!("check {} {}\n{}", path.display(), source.len(), source));
+        let mut warnings = Vec::new();
+        for diag in reply.split("{\"severity\": ").skip(1) {
+            let field = |name: &str| {
+                let start = diag.find(&format!("\"{}\": ", name)).unwrap() + name.len() + 4;
+                diag[start..].to_string()
+            };
+            let code = json_strings(&field("code")).remove(0);
+            let message = json_strings(&field("message")).remove(0);
+            let number = |text: String| text.chars().take_while(char::is_ascii_digit).collect::<String>();
+            warnings.push(format!("Warning[{}]: {} at line {}, col {}", code, message, number(field("line")), number(field("col"))));
+        }
+        warnings
+    }
+
+    /// The warnings of a `build` of the file at `path`.
+    fn build(&mut self, path: &Path) -> Vec<String> {
+        let reply = self.request(&format!("build {}\n", path.display()));
+        assert!(reply.contains("\"ok\": true"), "build failed: {}", reply);
+        // The warnings are the reply's last field
+        json_strings(&reply[reply.find("\"warnings\": [").unwrap() + 13..])
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// The JSON string literals in `text`, unescaped.
+fn json_strings(text: &str) -> Vec<String> {
+    let mut strings = Vec::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '"' {
+            continue;
+        }
+        let mut s = String::new();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => break,
+                '\\' => s.extend(chars.next()),
+                c => s.push(c),
+            }
+        }
+        strings.push(s);
+    }
+    strings
+}
+
+#[test]
+fn check_matches_build() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("daemon");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("app.hb");
+    let mut daemon = Daemon::start();
+    for source in [SOURCE.to_string(), format!("\n# moved down\n\n{}", SOURCE)] {
+        fs::write(&path, &source).unwrap();
+        let mut built = daemon.build(&path);
+        let mut checked = daemon.check(&path, &source);
+        built.sort();
+        checked.sort();
+        assert_eq!(built.len(), 5, "expected W004-W008, got {:?}", built);
+        assert_eq!(checked, built);
+    }
+}
+
+#[test]
+fn build_names_imported_modules() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("daemon-imports");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("main.hb"), "import \"./shared.hb\"\nprint shared.total\n").unwrap();
+    fs::write(dir.join("shared.hb"), "total = \"count: \" + 1\n").unwrap();
+    let mut daemon = Daemon::start();
+    let built = daemon.build(&dir.join("main.hb"));
+    assert_eq!(built.len(), 1, "expected W007, got {:?}", built);
+    let prefix = format!("{}: Warning[W007]: ", dir.join("shared.hb").display());
+    assert!(built[0].starts_with(&prefix), "expected {:?} to name shared.hb", built[0]);
+}
+
+#[test]
+fn compile_matches_build() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("daemon-compile");
+    fs::create_dir_all(&dir).unwrap();
+    let source = "import \"./shared.hb\"\nif DEBUG:\n    print \"debugging\"\nprint shared.total\n";
+    fs::write(dir.join("main.hb"), source).unwrap();
+    fs::write(dir.join("shared.hb"), "total = 1\n").unwrap();
+    let mut daemon = Daemon::start_with(&["--define", "DEBUG=false"]);
+    let path = dir.join("main.hb");
+    daemon.build(&path);
+    let written = fs::read_to_string(dir.join("main.js")).unwrap();
+    assert!(!written.contains("debugging"), "the define should drop the branch");
+    let reply = daemon.request(&format!("compile {} {}\n{}", path.display(), source.len(), source));
+    assert_eq!(reply.trim_end(), format!("{{\"js\": {}, \"diagnostics\": []}}", harbor::json_string(&written)));
+}
+
+#[test]
+fn comparisons_are_conditions() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("daemon-strict");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("app.hb");
+    let source = "def f(c, rows):\n    boundary = c == \" \" or not c == \"-\"\n    if boundary:\n        return 1\n    found = rows\n    if found:\n        return 2\n    return 0\n\nprint f(\"a\", [])\n";
+    fs::write(&path, source).unwrap();
+    let mut daemon = Daemon::start_with(&["--strict"]);
+    let checked = daemon.check(&path, source);
+    assert_eq!(checked.len(), 1, "expected W002 for 'found' only, got {:?}", checked);
+    assert!(checked[0].contains("'found'"), "expected W002 for 'found', got {:?}", checked[0]);
+}
+
+#[test]
+fn check_follows_warning_settings() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("daemon-settings");
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("harbor.toml"), "[warnings]\nW007 = false\nW002 = true  # off by default\n").unwrap();
+    let path = dir.join("app.hb");
+    let source = "total = \"count: \" + 1\nrows = []\nif rows:  # harbor: ignore[W002]\n    print 1\n# harbor: ignore\nif rows:\n    print 2\nif rows:\n    print 3\n";
+    fs::write(&path, source).unwrap();
+    let mut daemon = Daemon::start();
+    let checked = daemon.check(&path, source);
+    assert_eq!(checked.len(), 1, "expected one W002, got {:?}", checked);
+    assert!(checked[0].starts_with("Warning[W002]") && checked[0].ends_with("at line 8, col 4"), "got {:?}", checked[0]);
+    assert_eq!(daemon.build(&path), checked);
+}
@@ -0,0 +1,174 @@
+//! Golden-file tests for the compiler.
+//!
+//! Every `tests/cases/NAME.hb` is compiled and checked against snapshots
+//! beside it:
+//!
+//! - `NAME.js`: the generated program, minus the runtime prelude (which is
+//!   the same for every case and would otherwise bloat each snapshot)
+//! - `NAME.err`: the diagnostic, for cases that are meant to fail
+//! - `NAME.out`: if present, the program is built, with the modules it
+//!   imports, and run under node, with `HARBOR_TEST` set as `harbor test`
+//!   does, and its stdout, with request timings masked, must match
+//!   (skipped when node isn't installed)
+//! - `NAME.fmt`: if present, what `harbor fmt` turns the source into
+//!
+//! Every case that compiles must also survive formatting: the formatted
+//! source compiles to the same JavaScript and formats to itself. And it must
+//! compile to the same bytes every time, with CRLF line endings too, so
+//! cached builds and diffs of the output only change when the source does.
+//!
+//! Run `HARBOR_BLESS=1 cargo test --test golden` to rewrite the snapshots
+//! after an intended change, then review the diff. To start checking a
+//! case's output or formatting, create an empty `NAME.out` or `NAME.fmt`
+//! and bless.
+
+use harbor::codegen::{Backend, NodeBackend};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+fn cases_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("cases")
+}
+
+fn blessing() -> bool {
+    std::env::var_os("HARBOR_BLESS").is_some_and(|v| v != "0")
+}
+
+fn node_available() -> bool {
+    Command::new("node").arg("--version").output().is_ok_and(|o| o.status.success())
+}
+
+/// Compare `actual` with the snapshot at `path`, or overwrite it when
+/// blessing. Returns a description of the mismatch.
+fn check_snapshot(path: &Path, actual: &str) -> Result<(), String> {
+    if blessing() {
+        fs::write(path, actual).map_err(|e| format!("{}: could not write: {}", path.display(), e))?;
+        return Ok(());
+    }
+    let expected = fs::read_to_string(path)
+        .map_err(|_| format!("{}: missing snapshot (run with HARBOR_BLESS=1 to create it)", path.display()))?;
+    if expected == actual {
+        return Ok(());
+    }
+    // Point at the first differing line rather than dumping both files
+    let line = expected.lines().zip(actual.lines()).position(|(e, a)| e != a)
+        .unwrap_or_else(|| expected.lines().count().min(actual.lines().count()));
+    Err(format!(
+        "{}: differs at line {}\n  expected: {}\n  actual:   {}",
+        path.display(),
+        line + 1,
+        expected.lines().nth(line).unwrap_or("<end of file>"),
+        actual.lines().nth(line).unwrap_or("<end of file>"),
+    ))
+}
+
+/// Build `source` as its own project, so the modules it imports are built
+/// beside it, and return the path of the program.
+fn build_case(name: &str, source: &str) -> Result<PathBuf, String> {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join(format!("golden-{}", name));
+    fs::create_dir_all(&dir).map_err(|e| format!("{}: could not create {}: {}", name, dir.display(), e))?;
+    let input = dir.join(format!("{}.hb", name));
+    let script = dir.join(format!("{}.js", name));
+    fs::write(&input, source).map_err(|e| format!("{}: could not write source: {}", name, e))?;
+    harbor::build_file(&input.to_string_lossy(), &script.to_string_lossy(), None, &harbor::BuildOptions::default())
+        .map_err(|e| format!("{}: build failed: {}", name, e))?;
+    Ok(script)
+}
+
+/// Run the program at `script` under node and return its stdout.
+fn run_node(name: &str, script: &Path) -> Result<String, String> {
+    // As under `harbor test`, servers don't listen, so __inject can drive
+    // them and the program exits when it's done
+    let output = Command::new("node").arg(script).env("HARBOR_TEST", "1").output()
+        .map_err(|e| format!("{}: could not run node: {}", name, e))?;
+    if !output.status.success() {
+        return Err(format!("{}: node exited with {}\n{}", name, output.status,
+            String::from_utf8_lossy(&output.stderr)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `out` with the timings the default request log ends its lines with,
+/// like `12.3ms`, masked, since they change from run to run.
+fn mask_timings(out: &str) -> String {
+    out.lines().map(|line| match line.rsplit_once(' ') {
+        Some((rest, last)) if last.strip_suffix("ms").is_some_and(|ms| ms.parse::<f64>().is_ok()) => format!("{} <ms>\n", rest),
+        _ => format!("{}\n", line),
+    }).collect()
+}
+
+/// Formatting `source` must keep its meaning and be stable.
+fn check_round_trip(name: &str, source: &str, js: &str) -> Result<(), String> {
+    let formatted = harbor::format_source(source)
+        .map_err(|diag| format!("{}: lossless parse failed: {}", name, diag))?;
+    match harbor::compile(&formatted).js {
+        Some(reformatted_js) if reformatted_js == js => {}
+        Some(_) => return Err(format!("{}: formatted source compiles differently:\n{}", name, formatted)),
+        None => return Err(format!("{}: formatted source doesn't compile:\n{}", name, formatted)),
+    }
+    match harbor::format_source(&formatted) {
+        Ok(again) if again == formatted => Ok(()),
+        _ => Err(format!("{}: formatting isn't idempotent:\n{}", name, formatted)),
+    }
+}
+
+/// Compiling `source` again, on another thread and with CRLF line endings,
+/// must give exactly `js`.
+fn check_deterministic(name: &str, source: &str, js: &str) -> Result<(), String> {
+    let again = std::thread::scope(|scope| scope.spawn(|| harbor::compile(source).js).join().unwrap());
+    if again.as_deref() != Some(js) {
+        return Err(format!("{}: compiling twice gives different JavaScript", name));
+    }
+    let crlf = source.replace('\n', "\r\n");
+    if harbor::compile(&crlf).js.as_deref() != Some(js) {
+        return Err(format!("{}: compiles differently with CRLF line endings", name));
+    }
+    Ok(())
+}
+
+fn check_case(source_path: &Path, run: bool) -> Result<(), String> {
+    let name = source_path.file_stem().unwrap().to_string_lossy().into_owned();
+    let source = fs::read_to_string(source_path)
+        .map_err(|e| format!("{}: could not read: {}", source_path.display(), e))?;
+    let snapshot = |ext: &str| source_path.with_extension(ext);
+
+    let output = harbor::compile(&source);
+    let Some(js) = output.js else {
+        let errors: Vec<String> = output.diagnostics.iter().map(|d| d.to_string()).collect();
+        return check_snapshot(&snapshot("err"), &(errors.join("\n") + "\n"));
+    };
+    let prelude = NodeBackend.runtime_prelude();
+    let program = js.strip_prefix(prelude.as_str())
+        .ok_or_else(|| format!("{}: output doesn't start with the runtime prelude", name))?;
+    check_snapshot(&snapshot("js"), program)?;
+    check_round_trip(&name, &source, &js)?;
+    check_deterministic(&name, &source, &js)?;
+    if snapshot("fmt").exists() {
+        check_snapshot(&snapshot("fmt"), &harbor::format_source(&source).unwrap())?;
+    }
+
+    if run && snapshot("out").exists() {
+        check_snapshot(&snapshot("out"), &mask_timings(&run_node(&name, &build_case(&name, &source)?)?))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn golden_cases() {
+    let mut sources: Vec<PathBuf> = fs::read_dir(cases_dir())
+        .expect("tests/cases exists")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "hb"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty(), "no cases in tests/cases");
+
+    let run = node_available();
+    if !run {
+        eprintln!("node not found; skipping .out checks");
+    }
+    let failures: Vec<String> = sources.iter().filter_map(|path| check_case(path, run).err()).collect();
+    assert!(failures.is_empty(), "{} of {} golden cases failed:\n\n{}",
+        failures.len(), sources.len(), failures.join("\n\n"));
+}
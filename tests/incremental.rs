@@ -0,0 +1,50 @@
+//! Parsing by chunks must give the program a parse of the whole file does.
+//!
+//! Each source is parsed whole and by an `IncrementalParser`, then again
+//! after lines are added above it, which the parser serves from the chunks
+//! it cached the first time.
+
+use harbor::incremental::IncrementalParser;
+use std::fs;
+use std::path::Path;
+
+/// Sources whose statements run onto later lines in ways that chunking
+/// has to follow.
+const SOURCES: &[&str] = &[
+    "x = \"a\\\nb\"\nprint x\n",
+    "x = 'a\\\n# not a comment\nb'\ny = 2\n",
+    "x = \"a\\\\\"\ny = f\"{x}\n{x}\"\nprint y\n",
+    "items = [\n    1,\n    2,\n]\nprint items\n",
+    "if True:\n    print 1\nelse:\n    print 2\n",
+    "@sync\ndef f():\n    return 1\n\nprint f()\n",
+];
+
+fn assert_same(source: &str, parser: &mut IncrementalParser, name: &str) {
+    let whole = harbor::parse_source_with_lines(source);
+    let chunked = parser.parse(source);
+    assert_eq!(format!("{:?}", chunked), format!("{:?}", whole), "{}: chunked parse differs", name);
+}
+
+fn check(source: &str, name: &str) {
+    let mut parser = IncrementalParser::new();
+    assert_same(source, &mut parser, name);
+    assert_same(&format!("\n# moved down\n\n{}", source), &mut parser, name);
+}
+
+#[test]
+fn chunked_parse_matches_whole() {
+    for (i, source) in SOURCES.iter().enumerate() {
+        check(source, &format!("source {}", i));
+    }
+}
+
+#[test]
+fn chunked_parse_matches_whole_for_cases() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("cases");
+    let mut paths: Vec<_> = fs::read_dir(&dir).unwrap().map(|e| e.unwrap().path()).collect();
+    paths.sort();
+    for path in paths.iter().filter(|p| p.extension().is_some_and(|e| e == "hb")) {
+        let source = fs::read_to_string(path).unwrap();
+        check(&source, &path.display().to_string());
+    }
+}